@@ -1,4 +1,4 @@
-use super::deterministic_aes;
+use utv_token::deterministic_aes;
 use ldap3::{Scope, SearchEntry};
 use utv_token::VerifiedClaims;
 
@@ -33,6 +33,12 @@ impl Person {
 
         let encrypted_eid = deterministic_aes::encrypt(eid.as_bytes(), encryption_key);
 
+        let name = entry
+            .attrs
+            .remove("displayName")
+            .and_then(|a| a.into_iter().next())
+            .ok_or(LookupError::MissingDirectoryInfo("name"))?;
+
         let claims = VerifiedClaims {
             encrypted_eid,
             major: entry
@@ -47,15 +53,15 @@ impl Person {
                 .attrs
                 .remove("utexasEduPersonPubAffiliation")
                 .ok_or(LookupError::MissingDirectoryInfo("affiliation"))?,
+            expires_at: None,
+            issued_at: None,
+            nonce: None,
+            name: Some(name.clone()),
         };
 
         let person = Person {
             claims,
-            name: entry
-                .attrs
-                .remove("displayName")
-                .and_then(|a| a.into_iter().next())
-                .ok_or(LookupError::MissingDirectoryInfo("name"))?,
+            name,
             email: entry
                 .attrs
                 .remove("mail")
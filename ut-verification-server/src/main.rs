@@ -6,12 +6,11 @@ use lazy_static::lazy_static;
 use aws_sdk_sqs::{self, model::DeleteMessageBatchRequestEntry};
 use ldap3::{LdapConnAsync, Ldap};
 
-use utv_token;
+use utv_token::{self, deterministic_aes};
 
-use crate::directory::Person;
+use crate::directory::{LookupError, Person};
 use mail_sender::MailSender;
 
-mod deterministic_aes;
 mod directory;
 mod mail_sender;
 
@@ -30,10 +29,15 @@ lazy_static! {
     static ref SQS_VERIFICATION_REQUEST_URL: String = {
         "https://sqs.us-east-1.amazonaws.com/402762806873/eid_verification_requests".to_owned()
     };
+    static ref SQS_RECHECK_RESULT_URL: String = {
+        "https://sqs.us-east-1.amazonaws.com/402762806873/eid_recheck_results".to_owned()
+    };
 }
 
 static TEMPLATE: &'static str = include_str!("./email.hbs");
 const REQUESTS_PER_SECOND: i32 = 10;
+/// Emailed tokens expire, so an old email someone else gets hold of can't be redeemed
+const TOKEN_VALIDITY_SECS: i64 = 24 * 60 * 60;
 
 #[derive(Deserialize)]
 struct VerificationRequest<'a> {
@@ -44,8 +48,15 @@ async fn request_verification<'a>(mail_sender: &MailSender, ldap: &mut Ldap, req
     let eid = req.eid;
     let res = Person::lookup(ldap, eid, &ENCRYPTION_KEY).await;
     match res {
-        Ok(person) => {
+        Ok(mut person) => {
             let email = format!("{}@eid.utexas.edu", eid);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            person.claims.expires_at = Some(now + TOKEN_VALIDITY_SECS);
+            person.claims.issued_at = Some(now);
+            person.claims.nonce = Some(rand::random::<[u8; 16]>().to_vec());
             let token = utv_token::encode_token(&person.claims, &SHARED_KEY);
 
             let reg = Handlebars::new();
@@ -79,6 +90,58 @@ async fn request_verification<'a>(mail_sender: &MailSender, ldap: &mut Ldap, req
 
 }
 
+/// Sent by the bot to look a verified user's EID up again
+#[derive(Deserialize)]
+struct RecheckRequest {
+    discord_id: String,
+    /// base64 of the encrypted EID the bot stored
+    encrypted_eid: String,
+}
+
+async fn recheck(client: &aws_sdk_sqs::Client, ldap: &mut Ldap, req: RecheckRequest) {
+    let eid = base64::decode(&req.encrypted_eid)
+        .ok()
+        .and_then(|ciphertext| deterministic_aes::decrypt(&ciphertext, &ENCRYPTION_KEY).ok())
+        .and_then(|eid| String::from_utf8(eid).ok());
+    let eid = match eid {
+        Some(eid) => eid,
+        None => {
+            eprintln!("cannot decrypt eid of {} for recheck", req.discord_id);
+            return;
+        }
+    };
+    let result = match Person::lookup(ldap, &eid, &ENCRYPTION_KEY).await {
+        Ok(person) => json!({
+            "discord_id": req.discord_id,
+            "departed": false,
+            "claims": {
+                "major": person.claims.major,
+                "school": person.claims.school,
+                "affiliation": person.claims.affiliation,
+                "name": person.name,
+            }
+        }),
+        // gone from the directory, or no longer listed with the attributes verification needs
+        Err(LookupError::NotFound) | Err(LookupError::MissingDirectoryInfo(_)) => json!({
+            "discord_id": req.discord_id,
+            "departed": true,
+        }),
+        Err(err) => {
+            eprintln!("had a lookup error rechecking {}: {:#?}", req.discord_id, err);
+            return;
+        }
+    };
+    let sent = client
+        .send_message()
+        .queue_url(SQS_RECHECK_RESULT_URL.as_str())
+        .message_body(result.to_string())
+        .send()
+        .await;
+    if let Err(err) = sent {
+        eprintln!("cannot send recheck result of {}: {}", req.discord_id, err);
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let config = aws_config::load_from_env().await;
@@ -108,8 +171,12 @@ async fn main() {
 
         for msg in messages {
             let body = msg.body.expect("invalid message received");
-            let req: VerificationRequest = serde_json::from_str(&body).expect("invalid message received");
-            request_verification(&mail_sender, &mut ldap, req).await;
+            if let Ok(req) = serde_json::from_str::<RecheckRequest>(&body) {
+                recheck(&client, &mut ldap, req).await;
+            } else {
+                let req: VerificationRequest = serde_json::from_str(&body).expect("invalid message received");
+                request_verification(&mail_sender, &mut ldap, req).await;
+            }
             entries.push(
                 DeleteMessageBatchRequestEntry::builder()
                 .set_id(Some(entries.len().to_string()))
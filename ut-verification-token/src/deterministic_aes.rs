@@ -1,6 +1,7 @@
 //! Deterministic aes-gcm-siv aead encryption
 //! 
 //! ```
+//! # use utv_token::deterministic_aes::{decrypt, encrypt};
 //! let key: [u8; 32] = rand::random();
 //!
 //! let msg = b"bha366";
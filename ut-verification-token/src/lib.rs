@@ -1,6 +1,9 @@
+pub mod deterministic_aes;
+
 use ring::hmac;
+use ring::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct VerifiedClaims {
@@ -8,30 +11,137 @@ pub struct VerifiedClaims {
     pub major: Vec<String>,
     pub school: Vec<String>,
     pub affiliation: Vec<String>,
+    /// Unix time after which the token is refused. Last and defaulted, so tokens from before it
+    /// still decode.
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    /// Unix time the token was issued, for refusing tokens older than the redeemer allows
+    #[serde(default)]
+    pub issued_at: Option<i64>,
+    /// Random bytes unique to the token, which the redeemer records so a captured token can't
+    /// verify a second account
+    #[serde(default)]
+    pub nonce: Option<Vec<u8>>,
+    /// The directory's display name, for guilds that put real names in nicknames
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
-pub fn encode_token(claims: &VerifiedClaims, shared_key: &[u8]) -> String {
-    let mut data = rmp_serde::to_vec(claims).unwrap();
-
-    let hmac_key = hmac::Key::new(ring::hmac::HMAC_SHA256, shared_key);
-    let hmac_tag = hmac::sign(&hmac_key, &data[..]);
+/// Proof of verification a member can present to other systems, signed with the bot's Ed25519
+/// key so anyone holding its public key can check it. Carries no EID, major or school.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Certificate {
+    pub discord_id: u64,
+    pub affiliation: Vec<String>,
+    pub issued_at: i64,
+    pub expires_at: i64,
+}
 
-    data.extend_from_slice(hmac_tag.as_ref());
+/// The purpose verification tokens are signed for
+pub const VERIFICATION: &str = "verification";
 
-    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+pub fn encode_token(claims: &VerifiedClaims, shared_key: &[u8]) -> String {
+    sign(VERIFICATION, claims, shared_key)
 }
 
 #[derive(Debug)]
 pub struct InvalidToken;
 
 pub fn decode_token(token: &str, shared_key: &[u8]) -> Result<VerifiedClaims, InvalidToken> {
+    verify(VERIFICATION, token, shared_key)
+}
+
+/// The HMAC-SHA256 tag of a payload signed for `purpose`. The purpose and a zero byte come
+/// before the payload, so a value signed for one purpose is never accepted as another even when
+/// their msgpack happens to decode alike.
+fn tag(purpose: &str, data: &[u8], shared_key: &[u8]) -> hmac::Tag {
     let hmac_key = hmac::Key::new(ring::hmac::HMAC_SHA256, shared_key);
+    let mut context = hmac::Context::with_key(&hmac_key);
+    context.update(purpose.as_bytes());
+    context.update(&[0]);
+    context.update(data);
+    context.sign()
+}
+
+/// Serializes any payload with msgpack and appends an HMAC-SHA256 tag over it and the purpose
+/// it's signed for
+pub fn sign<T: Serialize>(purpose: &str, payload: &T, shared_key: &[u8]) -> String {
+    let mut data = rmp_serde::to_vec(payload).unwrap();
+
+    let hmac_tag = tag(purpose, &data, shared_key);
 
+    data.extend_from_slice(hmac_tag.as_ref());
+
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+/// Checks the HMAC tag of a token produced by [`sign`] for the same purpose and deserializes
+/// its payload
+pub fn verify<T: DeserializeOwned>(
+    purpose: &str,
+    token: &str,
+    shared_key: &[u8],
+) -> Result<T, InvalidToken> {
     let data = base64::decode_config(token, base64::URL_SAFE_NO_PAD).map_err(|_| InvalidToken)?;
     if data.len() <= 32 { return Err(InvalidToken) };
 
     let (claims_raw, hmac_tag) = data.split_at(data.len() - 32);
-    hmac::verify(&hmac_key, claims_raw, hmac_tag).map_err(|_| InvalidToken)?;
+    let valid = tag(purpose, claims_raw, shared_key);
+    ring::constant_time::verify_slices_are_equal(valid.as_ref(), hmac_tag)
+        .map_err(|_| InvalidToken)?;
 
     rmp_serde::from_read(claims_raw).map_err(|_| InvalidToken)
 }
+
+/// Detached HMAC-SHA256 signature of arbitrary data, for exports that must stay human readable
+pub fn signature(data: &[u8], shared_key: &[u8]) -> String {
+    let hmac_key = hmac::Key::new(ring::hmac::HMAC_SHA256, shared_key);
+    base64::encode_config(hmac::sign(&hmac_key, data), base64::URL_SAFE_NO_PAD)
+}
+
+/// Serializes any payload with msgpack and appends an Ed25519 signature over it, for tokens
+/// checked by third parties that only have the public key
+pub fn sign_public<T: Serialize>(payload: &T, key_pair: &Ed25519KeyPair) -> String {
+    let mut data = rmp_serde::to_vec(payload).unwrap();
+    let signature = key_pair.sign(&data[..]);
+    data.extend_from_slice(signature.as_ref());
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+/// Detached Ed25519 signature of arbitrary data, for exports that must stay human readable and
+/// that third parties check with the public key
+pub fn signature_public(data: &[u8], key_pair: &Ed25519KeyPair) -> String {
+    base64::encode_config(key_pair.sign(data), base64::URL_SAFE_NO_PAD)
+}
+
+/// Checks the signature of a token produced by [`sign_public`] and deserializes its payload
+pub fn verify_public<T: DeserializeOwned>(
+    token: &str,
+    public_key: &[u8],
+) -> Result<T, InvalidToken> {
+    let data = base64::decode_config(token, base64::URL_SAFE_NO_PAD).map_err(|_| InvalidToken)?;
+    if data.len() <= 64 {
+        return Err(InvalidToken);
+    };
+
+    let (payload, signature) = data.split_at(data.len() - 64);
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(payload, signature)
+        .map_err(|_| InvalidToken)?;
+
+    rmp_serde::from_read(payload).map_err(|_| InvalidToken)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn purposes_dont_mix() {
+        let key: [u8; 32] = rand::random();
+        let token = sign("ticket", &(1u64, 2i64), &key);
+
+        assert_eq!(verify::<(u64, i64)>("ticket", &token, &key).unwrap(), (1, 2));
+        assert!(verify::<(u64, i64)>("session", &token, &key).is_err());
+    }
+}
@@ -0,0 +1,771 @@
+//! `/admin`: maintenance commands for guild administrators.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use serde_json::json;
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+    ApplicationCommandInteractionDataOptionValue,
+};
+use serenity::utils::Color;
+
+use crate::{
+    analytics, audit, certificate, db, handlers, jobs, members, nicknames, offboard, owner,
+    ratelimits, response, rush, selftest, settings, snapshots, transfer, webhooks, SHARED_KEY,
+};
+
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+/// rosters are a few hundred lines; each EID is a separate index query
+const MAX_LOOKUP_EIDS: usize = 1000;
+const MAX_LOOKUP_BYTES: u64 = 64 * 1024;
+/// tokens issued by hand are meant to be handed over right away
+const ISSUED_TOKEN_SECS: i64 = 30 * 60;
+
+/// One line of an audit export
+#[derive(Serialize)]
+struct ExportedEntry<'a> {
+    guild_id: String,
+    at: i64,
+    actor: String,
+    action: &'a str,
+    target: Option<String>,
+    detail: &'a str,
+}
+
+pub async fn admin(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    if let Some(("ratelimits", options)) = handlers::subcommand(&command) {
+        return ratelimits(&command, options, &ctx).await;
+    }
+    if let Some(("selftest", _)) = handlers::subcommand(&command) {
+        return selftest::selftest(db_client, &command, &ctx).await;
+    }
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    // subcommand groups nest their subcommand as the only option
+    let (group, options) = handlers::subcommand(&command).unwrap_or(("", &[][..]));
+    match (group, options.first()) {
+        ("audit", Some(sub)) if sub.name == "export" => {
+            audit_export(db_client, &command, guild_id, &sub.options, &ctx).await
+        }
+        ("analytics", _) => analytics(db_client, &command, guild_id, options, &ctx).await,
+        ("bulk-lookup", _) => bulk_lookup(db_client, &command, guild_id, options, &ctx).await,
+        ("export", _) => transfer::export(db_client, &command, guild_id, options, &ctx).await,
+        ("import", _) => transfer::import(db_client, &command, guild_id, options, &ctx, jobs).await,
+        ("issue-token", _) => issue_token(db_client, &command, guild_id, options, &ctx).await,
+        ("jobs", _) => jobs_status(&command, guild_id, options, &ctx, jobs).await,
+        ("offboard", _) => offboard::prompt(&command, &ctx).await,
+        ("rollback", _) => rollback(db_client, &command, guild_id, options, &ctx, jobs).await,
+        ("rush-mode", _) => rush_mode(db_client, &command, guild_id, options, &ctx).await,
+        ("webhook", Some(sub)) => webhooks::webhook(db_client, &command, guild_id, sub, &ctx).await,
+        _ => {
+            response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    }
+}
+
+/// Shows rate limit bucket utilization and recent exhaustion to the bot's owner, since it
+/// covers every guild the bot is in
+async fn ratelimits(
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let owner = owner::owner(&ctx.http).await?;
+    if command.user.id != owner {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Only the bot's owner can run this command.",
+        )
+        .await;
+    }
+    let buckets = ratelimits::buckets();
+    let buckets_json = buckets
+        .iter()
+        .map(|b| json!({ "route": b.route, "limit": b.limit, "remaining": b.remaining }))
+        .collect::<Vec<_>>();
+    let buckets = buckets
+        .iter()
+        .take(10)
+        .map(|b| format!("`{}`: {}/{} used", b.route, b.limit - b.remaining, b.limit))
+        .collect::<Vec<_>>();
+    let exhausted = ratelimits::exhausted_since(response::unix_now() - HOUR);
+    let mut by_route: HashMap<&str, usize> = HashMap::new();
+    let mut by_job: HashMap<(GuildId, &str), usize> = HashMap::new();
+    for event in &exhausted {
+        *by_route.entry(event.route.as_str()).or_default() += 1;
+        for job in &event.jobs {
+            *by_job.entry(*job).or_default() += 1;
+        }
+    }
+    let mut by_route = by_route.into_iter().collect::<Vec<_>>();
+    by_route.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    let mut by_job = by_job.into_iter().collect::<Vec<_>>();
+    by_job.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+    let running = ratelimits::running();
+    if handlers::wants_json(options) {
+        return response::respond_json(
+            ctx,
+            command,
+            "ratelimits",
+            &json!({
+                "buckets": buckets_json,
+                "exhausted_last_hour": by_route
+                    .iter()
+                    .map(|(route, n)| json!({ "route": route, "times": n }))
+                    .collect::<Vec<_>>(),
+                "jobs_while_exhausted": by_job
+                    .iter()
+                    .map(|((guild, kind), n)| {
+                        json!({ "guild_id": guild.0.to_string(), "job": kind, "times": n })
+                    })
+                    .collect::<Vec<_>>(),
+                "running": running
+                    .iter()
+                    .map(|(guild, kind)| json!({ "guild_id": guild.0.to_string(), "job": kind }))
+                    .collect::<Vec<_>>(),
+            }),
+        )
+        .await;
+    }
+
+    let list = |lines: Vec<String>| {
+        if lines.is_empty() {
+            "None".to_string()
+        } else {
+            lines.join("\n")
+        }
+    };
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Rate Limits")
+            .description(
+                "Exhausted buckets make requests on the same route wait, including verification \
+                 responses.",
+            )
+            .field("Busiest buckets", list(buckets), false)
+            .field(
+                "Exhausted in the last hour",
+                list(
+                    by_route
+                        .iter()
+                        .take(10)
+                        .map(|(route, n)| format!("`{}`: {} times", route, n))
+                        .collect(),
+                ),
+                false,
+            )
+            .field(
+                "Jobs running while buckets were exhausted",
+                list(
+                    by_job
+                        .iter()
+                        .take(10)
+                        .map(|((guild, kind), n)| format!("{} in {}: {} times", kind, guild, n))
+                        .collect(),
+                ),
+                false,
+            )
+            .field(
+                "Running jobs",
+                list(
+                    running
+                        .iter()
+                        .map(|(guild, kind)| format!("{} in {}", kind, guild))
+                        .collect(),
+                ),
+                false,
+            )
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+/// Reports which EIDs of an uploaded roster are verified, and as which members of this guild.
+/// EIDs are masked in the report, and accounts are only named for members of this guild.
+async fn bulk_lookup(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let key = match settings::encryption_key() {
+        Ok(Some(key)) => key,
+        _ => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Bulk lookups are not enabled on this instance of the bot.",
+            )
+            .await
+        }
+    };
+    let file = match handlers::option(options, "file") {
+        Some(ApplicationCommandInteractionDataOptionValue::Attachment(file))
+            if file.size <= MAX_LOOKUP_BYTES =>
+        {
+            file
+        }
+        _ => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Attach a text file with one EID per line, up to 64 KB",
+            )
+            .await
+        }
+    };
+    response::defer(ctx, command, true).await?;
+    let text = match file.download().await.map(String::from_utf8) {
+        Ok(Ok(text)) => text,
+        _ => {
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message.content("Couldn't read that file, attach a plain text file.")
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+    // EIDs are stored as entered at verification, which is almost always lower case
+    let eids = text
+        .lines()
+        .map(|line| line.trim().trim_matches(',').to_lowercase())
+        .filter(|eid| !eid.is_empty() && eid != "eid")
+        .take(MAX_LOOKUP_EIDS)
+        .collect::<Vec<_>>();
+    let encrypted = eids
+        .iter()
+        .map(|eid| base64::encode(utv_token::deterministic_aes::encrypt(eid.as_bytes(), &key)))
+        .collect::<Vec<_>>();
+    let users = db_client.users_by_encrypted_eid(&encrypted).await;
+    let in_guild = members::fetch_all(&ctx.http, guild_id)
+        .await?
+        .into_iter()
+        .map(|m| m.user.id)
+        .collect::<HashSet<_>>();
+
+    let (mut verified, mut elsewhere) = (0, 0);
+    let mut csv = "line,eid,status,discord_id\n".to_string();
+    for (i, (eid, encrypted)) in eids.iter().zip(&encrypted).enumerate() {
+        let (status, discord_id) = match users.get(encrypted) {
+            Some(user) if in_guild.contains(user) => {
+                verified += 1;
+                ("verified", user.0.to_string())
+            }
+            Some(_) => {
+                elsewhere += 1;
+                ("verified, not in this server", String::new())
+            }
+            None => ("not verified", String::new()),
+        };
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            i + 1,
+            mask_eid(eid),
+            status,
+            discord_id
+        ));
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "admin.bulk-lookup",
+        None,
+        format!("{} EIDs, {} verified members", eids.len(), verified),
+    )
+    .await;
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(csv.into_bytes()),
+                    filename: "bulk-lookup.csv".to_string(),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title("Bulk EID Lookup")
+                        .description(format!(
+                            "Of {} EIDs, {} are verified members of this server and {} are \
+                             verified but not in it. Lines match the uploaded file, ignoring \
+                             blank lines; EIDs are masked.",
+                            eids.len(),
+                            verified,
+                            elsewhere
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Mints a short-lived verification token for an EID and DMs it to the admin, for when the
+/// portal is down. Only the bot's owner and `TRUSTED_ADMIN_IDS` may, as the EID isn't checked
+/// against the directory; the token carries only the affiliation given.
+async fn issue_token(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let owner = owner::owner(&ctx.http).await?;
+    let trusted = settings::trusted_admins().unwrap_or_default();
+    if command.user.id != owner && !trusted.contains(&command.user.id) {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Only the bot's owner and trusted admins can issue tokens.",
+        )
+        .await;
+    }
+    let key = match settings::encryption_key() {
+        Ok(Some(key)) => key,
+        _ => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Issuing tokens is not enabled on this instance of the bot.",
+            )
+            .await
+        }
+    };
+    let eid = match handlers::option_str(options, "eid") {
+        Some(eid) if !eid.trim().is_empty() => eid.trim().to_lowercase(),
+        _ => return response::respond_title(ctx, command, true, "Enter an EID").await,
+    };
+    let affiliation = handlers::option_str(options, "affiliation").unwrap_or("student");
+    let now = response::unix_now();
+    let expires_at = now + ISSUED_TOKEN_SECS;
+    let token = utv_token::encode_token(
+        &utv_token::VerifiedClaims {
+            encrypted_eid: utv_token::deterministic_aes::encrypt(eid.as_bytes(), &key),
+            major: Vec::new(),
+            school: Vec::new(),
+            affiliation: vec![affiliation.to_string()],
+            expires_at: Some(expires_at),
+            issued_at: Some(now),
+            nonce: Some(rand::random::<[u8; 16]>().to_vec()),
+            name: None,
+        },
+        &SHARED_KEY,
+    );
+    let sent = command
+        .user
+        .direct_message(&ctx.http, |message| {
+            message.content(format!(
+                "Verification token for `{}` ({}), valid until {}. Hand it over privately; it's \
+                 redeemed with `/redeem`.\n```{}```",
+                eid,
+                affiliation,
+                response::timestamp(expires_at, response::TimestampStyle::ShortDateTime),
+                token
+            ))
+        })
+        .await;
+    if sent.is_err() {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Couldn't DM you the token, allow DMs from this server's members and try again.",
+        )
+        .await;
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "admin.issue-token",
+        None,
+        format!(
+            "{} ({}), expires at {}",
+            mask_eid(&eid),
+            affiliation,
+            expires_at
+        ),
+    )
+    .await;
+    response::respond_title(ctx, command, true, "Sent you the token in a DM").await
+}
+
+/// Keeps the first two and the last character, e.g. `ab***4` for `abc1234`
+pub fn mask_eid(eid: &str) -> String {
+    let chars = eid.chars().collect::<Vec<_>>();
+    if chars.len() <= 3 {
+        return "*".repeat(chars.len());
+    }
+    format!(
+        "{}{}{}",
+        chars[..2].iter().collect::<String>(),
+        "*".repeat(chars.len() - 3),
+        chars[chars.len() - 1]
+    )
+}
+
+/// Shows the guild's running and queued jobs, and the nickname edits waiting for their turn
+async fn jobs_status(
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    let running = ratelimits::running()
+        .into_iter()
+        .filter(|(guild, _)| *guild == guild_id)
+        .map(|(_, kind)| kind.to_string())
+        .collect::<Vec<_>>();
+    let pending = jobs.pending_in(guild_id);
+    let edits = nicknames::queued(guild_id);
+    let mut by_source: Vec<(nicknames::Source, usize, i64)> = Vec::new();
+    for edit in &edits {
+        match by_source
+            .iter_mut()
+            .find(|(source, _, _)| *source == edit.source)
+        {
+            Some((_, count, _)) => *count += 1,
+            None => by_source.push((edit.source, 1, edit.queued_at)),
+        }
+    }
+    if handlers::wants_json(options) {
+        return response::respond_json(
+            ctx,
+            command,
+            "jobs",
+            &json!({
+                "running": running,
+                "queued": pending,
+                "queued_nickname_edits": by_source
+                    .iter()
+                    .map(|(source, count, oldest)| {
+                        json!({
+                            "source": source.name(),
+                            "interactive": source.interactive(),
+                            "waiting": count,
+                            "oldest_queued_at": oldest,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            }),
+        )
+        .await;
+    }
+    let edit_lines = by_source
+        .iter()
+        .map(|(source, count, oldest)| {
+            format!(
+                "{}: {} waiting{}, oldest since {}",
+                source.name(),
+                count,
+                if source.interactive() {
+                    ""
+                } else {
+                    " behind member-facing edits"
+                },
+                response::timestamp(*oldest, response::TimestampStyle::Relative)
+            )
+        })
+        .collect::<Vec<_>>();
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Jobs")
+            .description(
+                "Nickname edits are rate limited per server; edits for members who just verified, \
+                 joined or renamed themselves go before those of scans and other bulk jobs.",
+            )
+            .field("Running", response::field_lines(&running, "None"), false)
+            .field("Queued", response::field_lines(&pending, "None"), false)
+            .field(
+                "Queued nickname edits",
+                response::field_lines(&edit_lines, "None"),
+                false,
+            )
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+/// Reports the verification funnel and where users drop off
+async fn analytics(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let days = handlers::option_int(options, "days")
+        .unwrap_or(30)
+        .clamp(1, 365);
+    let steps = analytics::funnel(db_client, guild_id, response::unix_now() - days * DAY).await;
+    if handlers::wants_json(options) {
+        return response::respond_json(
+            ctx,
+            command,
+            "analytics",
+            &json!({
+                "days": days,
+                "funnel": steps
+                    .iter()
+                    .map(|step| {
+                        json!({
+                            "stage": step.stage.name(),
+                            "reached": step.reached,
+                            "continued": step.continued,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            }),
+        )
+        .await;
+    }
+    let report = steps
+        .iter()
+        .map(|step| {
+            let drop_off = if step.reached == 0 || step.stage == analytics::Stage::Verified {
+                String::new()
+            } else {
+                format!(
+                    ", {}% drop off",
+                    (step.reached - step.continued) * 100 / step.reached
+                )
+            };
+            format!("**{}**: {}{}", step.stage.label(), step.reached, drop_off)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Verification Funnel")
+            .description(format!(
+                "Users who started verifying in the last {} days.\n\n{}",
+                days, report
+            ))
+            .footer(|footer| {
+                footer.text(
+                    "Members verifying through the portal skip the earlier stages, so later \
+                     stages can be larger.",
+                )
+            })
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+/// Starts, extends or ends (with 0 hours) the guild's rush mode
+async fn rush_mode(
+    db_client: &'static db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let hours = handlers::option_int(options, "hours")
+        .unwrap_or(0)
+        .clamp(0, rush::MAX_HOURS);
+    if hours == 0 {
+        rush::end(guild_id);
+        audit::record(
+            db_client,
+            guild_id,
+            command.user.id,
+            "admin.rush-mode",
+            None,
+            "ended",
+        )
+        .await;
+        return response::respond_title(ctx, command, true, "Rush mode ended").await;
+    }
+    let channel = handlers::option_channel(options, "channel")
+        .map(|c| c.id)
+        .unwrap_or(command.channel_id);
+    let ends_at = response::unix_now() + hours * 60 * 60;
+    response::defer(ctx, command, true).await?;
+    let title = match rush::start(db_client, ctx.http.clone(), guild_id, ends_at, channel).await {
+        Ok(()) => {
+            audit::record(
+                db_client,
+                guild_id,
+                command.user.id,
+                "admin.rush-mode",
+                None,
+                format!("for {} hours", hours),
+            )
+            .await;
+            format!(
+                "Rush mode is on until {}, with progress posted in <#{}>",
+                response::timestamp(ends_at, response::TimestampStyle::ShortDateTime),
+                channel
+            )
+        }
+        Err(why) => why,
+    };
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message.create_embed(|embed| embed.title(title))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Queues the rollback of a bulk job from the snapshot taken before it ran
+async fn rollback(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    let job_id = handlers::option_str(options, "job-id")
+        .unwrap_or_default()
+        .trim();
+    if !snapshots::exists(db_client, guild_id, job_id).await {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "No snapshot found for that job, snapshots are kept for a week",
+        )
+        .await;
+    }
+    jobs.push(jobs::Job::Rollback {
+        guild_id,
+        job_id: job_id.to_string(),
+        actor: command.user.id,
+    });
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Rollback Queued")
+            .description(format!(
+                "Members changed by `{}` will get their previous roles and nicknames back. \
+                 Deleted roles can't be restored.",
+                job_id
+            ))
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+/// Exports the guild's audit ledger as NDJSON. The last line is `{"signature": ...}`, an Ed25519
+/// signature with the certificate key over every byte before it, which compliance officers check
+/// against the public key served at `/certificate-key`.
+async fn audit_export(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let since = match handlers::option_date(options, "since") {
+        Some(since) => since,
+        None => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Enter the start date as YYYY-MM-DD",
+            )
+            .await
+        }
+    };
+    let key_pair = match certificate::key_pair() {
+        Some(key_pair) => key_pair,
+        None => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Exports are signed with the certificate key, which this bot doesn't have.",
+            )
+            .await
+        }
+    };
+    // the end date is inclusive
+    let until = handlers::option_date(options, "until").map(|until| until + DAY);
+    response::defer(ctx, command, true).await?;
+
+    let entries = db_client
+        .get_audit(guild_id, since)
+        .await
+        .into_iter()
+        .filter(|e| until.map_or(true, |until| e.at < until))
+        .collect::<Vec<_>>();
+    let mut export = String::new();
+    for entry in &entries {
+        let line = ExportedEntry {
+            guild_id: entry.guild_id.0.to_string(),
+            at: entry.at,
+            actor: entry.actor.0.to_string(),
+            action: &entry.action,
+            target: entry.target.map(|t| t.0.to_string()),
+            detail: &entry.detail,
+        };
+        export.push_str(&serde_json::to_string(&line).unwrap());
+        export.push('\n');
+    }
+    let signature = utv_token::signature_public(export.as_bytes(), key_pair);
+    export.push_str(&serde_json::json!({ "signature": signature }).to_string());
+    export.push('\n');
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(export.into_bytes()),
+                    filename: format!("audit-{}-{}.ndjson", guild_id, since),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title("Audit Ledger Export")
+                        .description(format!(
+                            "{} entries since {}{}. The last line signs the rest of the file.",
+                            entries.len(),
+                            response::timestamp(since, response::TimestampStyle::LongDate),
+                            until
+                                .map(|until| format!(
+                                    " until {}",
+                                    response::timestamp(
+                                        until - DAY,
+                                        response::TimestampStyle::LongDate
+                                    )
+                                ))
+                                .unwrap_or_default()
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+//! Alumni transition: members whose claims no longer include the student affiliation are moved
+//! from the Student role to the guild's Alumni role instead of simply losing their status.
+
+use std::collections::HashMap;
+
+use serenity::client::Context;
+use serenity::model::guild::Member;
+use serenity::model::id::RoleId;
+use tracing::error;
+
+use crate::{audit, cache, db, sheets};
+
+/// Handles a verified member who isn't a student. Returns the nickname suffix to use if they are
+/// an alumnus, i.e. they held the Student role until now or already hold the Alumni role.
+pub async fn former_student(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    mem: &mut Member,
+    role_mappings: &HashMap<String, u64>,
+) -> Option<String> {
+    let config = db_client.get_guild_config(mem.guild_id).await;
+    let student_role = role_mappings.get("student").map(|r| RoleId(*r));
+    let was_student = student_role.map_or(false, |r| mem.roles.contains(&r));
+    let is_alumnus = config.alumni_role.map_or(false, |r| mem.roles.contains(&r));
+    if !was_student && !is_alumnus {
+        return None;
+    }
+
+    if let Some(student_role) = student_role.filter(|_| was_student) {
+        if let Err(why) = mem.remove_role(&ctx.http, student_role).await {
+            error!(
+                "Failed to remove student role from {}: {}",
+                mem.user.id, why
+            );
+        }
+        if let Some(alumni_role) = config.alumni_role {
+            if let Err(why) = mem.add_role(&ctx.http, alumni_role).await {
+                error!("Failed to add alumni role to {}: {}", mem.user.id, why);
+            }
+        }
+        if let Ok(bot_id) = cache::bot_id(&ctx.http).await {
+            audit::record(
+                db_client,
+                mem.guild_id,
+                bot_id,
+                "alumni.transition",
+                Some(mem.user.id),
+                match config.alumni_role {
+                    Some(role) => format!("<@&{}> replaced by <@&{}>", student_role, role),
+                    None => format!("<@&{}> removed", student_role),
+                },
+            )
+            .await;
+        }
+        sheets::append(db_client, mem.guild_id, "alumni", mem.user.id).await;
+        let guild_name = mem
+            .guild_id
+            .to_partial_guild(&ctx.http)
+            .await
+            .map(|g| g.name)
+            .unwrap_or_else(|_| "the server".to_string());
+        let notice = format!(
+            "Congratulations on graduating! Your UT records no longer list you as a student, so \
+             your roles in {} were updated{}.",
+            guild_name,
+            if config.alumni_role.is_some() {
+                " to reflect your alumni status"
+            } else {
+                ""
+            }
+        );
+        if let Err(why) = mem.user.direct_message(ctx, |m| m.content(notice)).await {
+            error!(
+                "Failed to notify {} of alumni transition: {}",
+                mem.user.id, why
+            );
+        }
+    }
+    Some(config.alumni_suffix.unwrap_or_default())
+}
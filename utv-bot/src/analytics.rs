@@ -0,0 +1,112 @@
+//! Verification funnel: how far users get through verifying in each guild.
+//!
+//! Each stage records the first time a user reached it, so `/admin analytics` can show where
+//! students give up, e.g. requesting an email but never redeeming the token.
+
+use serenity::model::id::{GuildId, UserId};
+use tracing::error;
+
+use crate::{db, response};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stage {
+    /// `/verify` sent the verification email
+    EmailRequested,
+    /// the `/redeem` modal was opened
+    ModalOpened,
+    /// a token was submitted to `/redeem`, valid or not
+    TokenSubmitted,
+    /// the member was verified, through `/redeem` or the portal
+    Verified,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::EmailRequested,
+        Stage::ModalOpened,
+        Stage::TokenSubmitted,
+        Stage::Verified,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::EmailRequested => "email_requested",
+            Stage::ModalOpened => "modal_opened",
+            Stage::TokenSubmitted => "token_submitted",
+            Stage::Verified => "verified",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::EmailRequested => "Requested an email",
+            Stage::ModalOpened => "Opened the token form",
+            Stage::TokenSubmitted => "Submitted a token",
+            Stage::Verified => "Verified",
+        }
+    }
+}
+
+/// Records that a user reached a stage. Interactions outside of a guild aren't tracked.
+pub async fn record(
+    db_client: &db::DynamoDB,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    stage: Stage,
+) {
+    let guild_id = match guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+    if !db_client
+        .record_funnel_stage(guild_id, user_id, stage.name(), response::unix_now())
+        .await
+    {
+        error!("Failed to record {} for {}", stage.name(), user_id);
+    }
+}
+
+/// How many users reached a stage, and how many of them went on to the next one
+#[derive(Debug)]
+pub struct Step {
+    pub stage: Stage,
+    pub reached: usize,
+    pub continued: usize,
+}
+
+/// The funnel of users who entered it at or after `since`
+pub async fn funnel(db_client: &db::DynamoDB, guild_id: GuildId, since: i64) -> Vec<Step> {
+    let entries = db_client
+        .get_funnel(guild_id)
+        .await
+        .into_iter()
+        .filter(|e| {
+            e.stages
+                .values()
+                .min()
+                .map_or(false, |first| *first >= since)
+        })
+        .collect::<Vec<_>>();
+    Stage::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, stage)| {
+            let reached = entries
+                .iter()
+                .filter(|e| e.stages.contains_key(stage.name()))
+                .collect::<Vec<_>>();
+            let continued = match Stage::ALL.get(i + 1) {
+                Some(next) => reached
+                    .iter()
+                    .filter(|e| e.stages.contains_key(next.name()))
+                    .count(),
+                None => reached.len(),
+            };
+            Step {
+                stage: *stage,
+                reached: reached.len(),
+                continued,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,150 @@
+//! Narrow, read-only API for other org bots (ticketing, elections, ...).
+//!
+//! Each API key is scoped to a network of guilds and only answers for users who are members of
+//! one of them, so a key cannot be used to enumerate every verified Discord account.
+//!
+//! The only endpoint without a key is a guild's aggregate stats, for badges on org websites,
+//! and only for guilds that publish them with `/config public-stats`.
+
+use axum::async_trait;
+use axum::extract::{Extension, FromRequest, Path, RequestParts};
+use axum::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CACHE_CONTROL};
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use axum::response::Headers;
+use axum::Json;
+use serde::Serialize;
+use serenity::model::id::GuildId;
+
+use crate::{db, http, stats};
+
+/// Endpoints an API key can be scoped to
+pub const ENDPOINTS: &[&str] = &["is-verified", "metrics"];
+/// Endpoints only keys scoped to them can call, rather than every unscoped key
+const EXPLICIT_ENDPOINTS: &[&str] = &["metrics"];
+
+#[derive(Serialize)]
+pub struct VerificationStatus {
+    discord_id: String,
+    verified: bool,
+}
+
+/// A request authorized by an API key that is scoped to the endpoint being called.
+///
+/// The endpoint is the first path segment after `/v1/`, e.g. `is-verified`, or `metrics` for
+/// `/metrics`, which only keys scoped to it may read.
+pub struct Authorized(pub db::ApiKey);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Authorized {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let endpoint = req
+            .uri()
+            .path()
+            .trim_start_matches("/v1/")
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let headers = HeaderMap::from_request(req)
+            .await
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid headers."))?;
+        let Extension(state) = Extension::<http::State>::from_request(req)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Missing state."))?;
+
+        let key = headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or((StatusCode::UNAUTHORIZED, "Missing API key."))?;
+        let key = state
+            .db_client
+            .get_api_key(&db::sha256_hex(key.trim().as_bytes()))
+            .await
+            .ok_or((StatusCode::UNAUTHORIZED, "Invalid API key."))?;
+        let scoped = key.endpoints.contains(&endpoint);
+        let unscoped = key.endpoints.is_empty() && !EXPLICIT_ENDPOINTS.contains(&endpoint.as_str());
+        if !scoped && !unscoped {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "This API key is not allowed to call this endpoint.",
+            ));
+        }
+        Ok(Authorized(key))
+    }
+}
+
+/// `GET /v1/is-verified/:discord_id`
+pub async fn is_verified(
+    Authorized(key): Authorized,
+    Path(discord_id): Path<u64>,
+    Extension(state): Extension<http::State>,
+) -> Result<Json<VerificationStatus>, (StatusCode, &'static str)> {
+    let mut in_scope = false;
+    for guild_id in &key.guild_ids {
+        if state.http.get_member(guild_id.0, discord_id).await.is_ok() {
+            in_scope = true;
+            break;
+        }
+    }
+    if !in_scope {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "User is not a member of any guild this key is scoped to.",
+        ));
+    }
+
+    Ok(Json(VerificationStatus {
+        discord_id: discord_id.to_string(),
+        verified: state.db_client.is_verified(discord_id).await,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct PublicStats {
+    guild_id: String,
+    verified: usize,
+    counted_at: i64,
+    /// when the bot last checked every member, unset when it hasn't since it started
+    last_scan_at: Option<i64>,
+}
+
+/// `GET /v1/guilds/:guild_id/stats`, public for guilds that opted in
+pub async fn guild_stats(
+    Path(guild_id): Path<u64>,
+    Extension(state): Extension<http::State>,
+) -> Result<(Headers<[(HeaderName, String); 2]>, Json<PublicStats>), (StatusCode, &'static str)> {
+    let guild_id = GuildId(guild_id);
+    // the same answer for guilds that don't publish stats and guilds the bot isn't in
+    let not_found = (StatusCode::NOT_FOUND, "This guild doesn't publish stats.");
+    if !state
+        .db_client
+        .get_guild_config(guild_id)
+        .await
+        .public_stats
+    {
+        return Err(not_found);
+    }
+    let (counted_at, guild_stats) = stats::public_stats(state.db_client, &state.http, guild_id)
+        .await
+        .map_err(|_| not_found)?;
+    Ok((
+        Headers([
+            (
+                CACHE_CONTROL,
+                format!("public, max-age={}", stats::PUBLIC_TTL_SECS),
+            ),
+            // fetched by org websites from the browser
+            (ACCESS_CONTROL_ALLOW_ORIGIN, "*".to_string()),
+        ]),
+        Json(PublicStats {
+            guild_id: guild_id.0.to_string(),
+            verified: guild_stats.verified,
+            counted_at,
+            last_scan_at: stats::last_scan(guild_id),
+        }),
+    ))
+}
@@ -0,0 +1,164 @@
+//! `utv-bot api-key ...`: management of HTTP API keys by the bot's operator.
+//!
+//! ```text
+//! utv-bot api-key create <label> --guild <id>... [--endpoint <name>...]
+//! utv-bot api-key list
+//! utv-bot api-key rotate <key id>
+//! utv-bot api-key revoke <key id>
+//! ```
+//!
+//! Only the SHA-256 hash of a key is stored, so a key is printed once when it's created or
+//! rotated and cannot be recovered afterwards.
+
+use rand::Rng;
+use serenity::model::id::GuildId;
+
+use crate::{api, db, response};
+
+const USAGE: &str = "usage: utv-bot api-key create <label> --guild <id>... [--endpoint <name>...]
+       utv-bot api-key list
+       utv-bot api-key rotate <key id>
+       utv-bot api-key revoke <key id>";
+
+/// Runs an `api-key` subcommand, returning whether it succeeded
+pub async fn run(args: &[String]) -> bool {
+    let db_client = db::DynamoDB::new("users").await;
+    let result = match args.first().map(String::as_str) {
+        Some("create") => create(&db_client, &args[1..]).await,
+        Some("list") => {
+            list(&db_client).await;
+            Ok(())
+        }
+        Some("rotate") => rotate(&db_client, args.get(1)).await,
+        Some("revoke") => revoke(&db_client, args.get(1)).await,
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(why) = &result {
+        eprintln!("{}", why);
+    }
+    result.is_ok()
+}
+
+/// A new random key and its hash
+fn generate() -> (String, String) {
+    let key = base64::encode_config(
+        rand::thread_rng().gen::<[u8; 32]>(),
+        base64::URL_SAFE_NO_PAD,
+    );
+    let hash = db::sha256_hex(key.as_bytes());
+    (key, hash)
+}
+
+async fn create(db_client: &db::DynamoDB, args: &[String]) -> Result<(), String> {
+    let label = args.first().filter(|l| !l.starts_with("--")).ok_or(USAGE)?;
+    let mut guild_ids = Vec::new();
+    let mut endpoints = Vec::new();
+    let mut flags = args[1..].iter();
+    while let Some(flag) = flags.next() {
+        let value = flags
+            .next()
+            .ok_or_else(|| format!("{} is missing a value", flag))?;
+        match flag.as_str() {
+            "--guild" => guild_ids.push(GuildId(
+                value
+                    .parse()
+                    .map_err(|_| format!("{} is not a guild id", value))?,
+            )),
+            "--endpoint" if api::ENDPOINTS.contains(&value.as_str()) => {
+                endpoints.push(value.clone())
+            }
+            "--endpoint" => {
+                return Err(format!(
+                    "Unknown endpoint {}, expected one of {}",
+                    value,
+                    api::ENDPOINTS.join(", ")
+                ))
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if guild_ids.is_empty() {
+        return Err("A key must be scoped to at least one guild".to_string());
+    }
+
+    let (key, key_hash) = generate();
+    let api_key = db::ApiKey {
+        key_id: format!("{:08x}", rand::thread_rng().gen::<u32>()),
+        key_hash,
+        label: label.clone(),
+        guild_ids,
+        endpoints,
+        created_at: response::unix_now(),
+    };
+    if !db_client.put_api_key(&api_key).await {
+        return Err("Failed to save the key".to_string());
+    }
+    println!(
+        "Created key {} ({}):\n{}",
+        api_key.key_id, api_key.label, key
+    );
+    Ok(())
+}
+
+async fn list(db_client: &db::DynamoDB) {
+    let mut keys = db_client.api_keys().await;
+    keys.sort_by_key(|k| k.created_at);
+    for key in keys {
+        println!(
+            "{}  {}  guilds: {}  endpoints: {}",
+            key.key_id,
+            key.label,
+            key.guild_ids
+                .iter()
+                .map(|g| g.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            if key.endpoints.is_empty() {
+                "all".to_string()
+            } else {
+                key.endpoints.join(",")
+            }
+        );
+    }
+}
+
+async fn find(db_client: &db::DynamoDB, key_id: Option<&String>) -> Result<db::ApiKey, String> {
+    let key_id = key_id.ok_or(USAGE)?;
+    db_client
+        .api_keys()
+        .await
+        .into_iter()
+        .find(|k| &k.key_id == key_id)
+        .ok_or_else(|| format!("No key with id {}", key_id))
+}
+
+/// Replaces a key's secret, keeping its id and scope. The old secret stops working immediately.
+async fn rotate(db_client: &db::DynamoDB, key_id: Option<&String>) -> Result<(), String> {
+    let mut api_key = find(db_client, key_id).await?;
+    let old_hash = api_key.key_hash.clone();
+    let (key, key_hash) = generate();
+    api_key.key_hash = key_hash;
+    if !db_client.put_api_key(&api_key).await {
+        return Err("Failed to save the new key".to_string());
+    }
+    if !db_client.delete_api_key(&old_hash).await {
+        return Err(format!(
+            "Saved the new key but failed to delete the old one, which still works:\n{}",
+            key
+        ));
+    }
+    println!(
+        "Rotated key {} ({}):\n{}",
+        api_key.key_id, api_key.label, key
+    );
+    Ok(())
+}
+
+async fn revoke(db_client: &db::DynamoDB, key_id: Option<&String>) -> Result<(), String> {
+    let api_key = find(db_client, key_id).await?;
+    if !db_client.delete_api_key(&api_key.key_hash).await {
+        return Err("Failed to revoke the key".to_string());
+    }
+    println!("Revoked key {} ({})", api_key.key_id, api_key.label);
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! Officer/committee attestations.
+//!
+//! Designated approvers vouch that a verified member holds one of the guild's officer roles for
+//! a semester. The role is removed automatically once the term ends, and every grant and
+//! expiry is written to the audit ledger.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+use tracing::error;
+
+use crate::config::GuildConfig;
+use crate::{audit, db, handlers, response};
+
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Parses terms such as "Fall 2026" into their canonical name and the unix timestamp they end at
+pub fn parse_term(term: &str) -> Option<(String, i64)> {
+    let mut parts = term.split_whitespace();
+    let season = parts.next()?.to_lowercase();
+    let year: i32 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let (name, month, day) = match season.as_str() {
+        "spring" => ("Spring", 5, 31),
+        "summer" => ("Summer", 8, 31),
+        "fall" => ("Fall", 12, 31),
+        _ => return None,
+    };
+    let end = Utc.ymd(year, month, day).and_hms(23, 59, 59).timestamp();
+    Some((format!("{} {}", name, year), end))
+}
+
+fn is_approver(command: &ApplicationCommandInteraction, config: &GuildConfig) -> bool {
+    handlers::is_admin(command)
+        || match (&command.member, config.attest_approver_role) {
+            (Some(member), Some(role)) => member.roles.contains(&role),
+            _ => false,
+        }
+}
+
+pub async fn attest(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let config = db_client.get_guild_config(guild_id).await;
+    if !is_approver(&command, &config) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Only designated approvers can attest officer roles.",
+        )
+        .await;
+    }
+    let options = &command.data.options;
+    let (user, role, term) = match (
+        handlers::option_user(options, "user"),
+        handlers::option_role(options, "role"),
+        handlers::option_str(options, "term"),
+    ) {
+        (Some(user), Some(role), Some(term)) => (user, role, term),
+        _ => {
+            return response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    };
+    if !config.officer_roles.contains(&role.id) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            format!(
+                "{} is not an officer role. An administrator can add it with `/config officer-role`.",
+                role.name
+            ),
+        )
+        .await;
+    }
+    let (term, expires_at) = match parse_term(term) {
+        Some((term, end)) if end > response::unix_now() => (term, end),
+        _ => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "Enter a current or upcoming term, such as \"Fall 2026\".",
+            )
+            .await
+        }
+    };
+    if !db_client.is_verified(user.id.0).await {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Only verified members can hold officer roles.",
+        )
+        .await;
+    }
+
+    let attestation = db::Attestation {
+        guild_id,
+        user_id: user.id,
+        role_id: role.id,
+        term: term.clone(),
+        expires_at,
+        attested_by: command.user.id,
+        attested_at: response::unix_now(),
+    };
+    if !db_client.put_attestation(&attestation).await {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Failed to save the attestation, please try again",
+        )
+        .await;
+    }
+    if let Err(why) = ctx
+        .http
+        .add_member_role(guild_id.0, user.id.0, role.id.0)
+        .await
+    {
+        error!("Failed to add attested role to {}: {}", user.id, why);
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "attest.grant",
+        Some(user.id),
+        format!("<@&{}> for {}", role.id, term),
+    )
+    .await;
+
+    response::respond_embed(&ctx, &command, false, |embed| {
+        embed
+            .title("Officer Role Attested")
+            .description(format!(
+                "<@{}> holds <@&{}> for {}, attested by <@{}>. The role is removed {}.",
+                user.id,
+                role.id,
+                term,
+                command.user.id,
+                response::datetime(expires_at)
+            ))
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+/// Lists a member's current attestations and their attestation history
+pub async fn attestations(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let config = db_client.get_guild_config(guild_id).await;
+    if !is_approver(&command, &config) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Only designated approvers can view attestations.",
+        )
+        .await;
+    }
+    let user = match handlers::option_user(&command.data.options, "user") {
+        Some(user) => user.clone(),
+        None => command.user.clone(),
+    };
+
+    let current = db_client
+        .get_attestations(guild_id, user.id)
+        .await
+        .iter()
+        .map(|a| {
+            format!(
+                "<@&{}> for {}, until {}",
+                a.role_id,
+                a.term,
+                response::timestamp(a.expires_at, response::TimestampStyle::ShortDate)
+            )
+        })
+        .collect::<Vec<_>>();
+    let history = db_client
+        .get_audit(guild_id, 0)
+        .await
+        .into_iter()
+        .filter(|e| e.target == Some(user.id) && e.action.starts_with("attest."))
+        .rev()
+        .map(|e| {
+            format!(
+                "{} `{}` {} by <@{}>",
+                response::timestamp(e.at, response::TimestampStyle::ShortDate),
+                e.action,
+                e.detail,
+                e.actor
+            )
+        })
+        .collect::<Vec<_>>();
+
+    response::respond_embed(&ctx, &command, true, |embed| {
+        embed
+            .title(format!("Attestations for {}", user.tag()))
+            .color(Color::from_rgb(191, 87, 0))
+            .field("Current", response::field_lines(&current, "None"), false)
+            .field(
+                "Ledger (newest first)",
+                response::field_lines(&history, "No entries"),
+                false,
+            )
+    })
+    .await
+}
+
+/// Removes attested roles once their term has ended
+pub async fn expire_loop(db_client: &'static db::DynamoDB, http: Arc<Http>) {
+    let bot_id = match http.get_current_user().await {
+        Ok(user) => user.id,
+        Err(why) => {
+            error!(
+                "Attestation expiry disabled, cannot fetch current user: {}",
+                why
+            );
+            return;
+        }
+    };
+    loop {
+        for attestation in db_client.expired_attestations(response::unix_now()).await {
+            if let Err(why) = http
+                .remove_member_role(
+                    attestation.guild_id.0,
+                    attestation.user_id.0,
+                    attestation.role_id.0,
+                )
+                .await
+            {
+                error!(
+                    "Failed to remove expired role from {}: {}",
+                    attestation.user_id, why
+                );
+            }
+            db_client
+                .delete_attestation(
+                    attestation.guild_id,
+                    attestation.user_id,
+                    attestation.role_id,
+                )
+                .await;
+            audit::record(
+                db_client,
+                attestation.guild_id,
+                bot_id,
+                "attest.expire",
+                Some(attestation.user_id),
+                format!("<@&{}> for {}", attestation.role_id, attestation.term),
+            )
+            .await;
+        }
+        tokio::time::sleep(EXPIRY_CHECK_INTERVAL).await;
+    }
+}
@@ -0,0 +1,75 @@
+//! Per-guild ledger of actions taken by or through the bot
+
+use serenity::http::Http;
+use serenity::model::id::{GuildId, UserId};
+use serenity::utils::Color;
+use tracing::{error, warn};
+
+use crate::{db, response};
+
+/// Appends an entry to the guild's audit ledger, logging instead of failing the caller
+pub async fn record(
+    db_client: &db::DynamoDB,
+    guild_id: GuildId,
+    actor: UserId,
+    action: &str,
+    target: Option<UserId>,
+    detail: impl Into<String>,
+) {
+    let entry = db::AuditEntry {
+        guild_id,
+        at: response::unix_now(),
+        actor,
+        action: action.to_string(),
+        target,
+        detail: detail.into(),
+    };
+    if !db_client.append_audit(&entry).await {
+        error!("Failed to record audit entry {:?}", entry);
+    }
+}
+
+/// An action on a member posted to the guild's audit channel
+pub struct Post<'a> {
+    pub action: &'a str,
+    pub member: UserId,
+    /// who the bot acted for, when not on its own
+    pub actor: Option<UserId>,
+    pub reason: String,
+    pub failed: bool,
+}
+
+/// Posts an action to the guild's audit channel, if it has one. Unlike [`record`] these aren't
+/// kept in the ledger, since a scan can change thousands of members.
+pub async fn post(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId, post: Post<'_>) {
+    let channel = match db_client.get_guild_config(guild_id).await.audit_channel {
+        Some(channel) => channel,
+        None => return,
+    };
+    let sent = channel
+        .send_message(http, |message| {
+            message.embed(|embed| {
+                embed
+                    .title(post.action)
+                    .field("Member", format!("<@{}>", post.member), true)
+                    .field(
+                        "By",
+                        match post.actor {
+                            Some(actor) => format!("<@{}>", actor),
+                            None => "The bot".to_string(),
+                        },
+                        true,
+                    )
+                    .field("Reason", post.reason, false)
+                    .color(if post.failed {
+                        Color::from_rgb(255, 0, 0)
+                    } else {
+                        Color::from_rgb(191, 87, 0)
+                    })
+            })
+        })
+        .await;
+    if let Err(why) = sent {
+        warn!("Cannot post to the audit channel of {}: {}", guild_id, why);
+    }
+}
@@ -0,0 +1,328 @@
+//! Backups of the bot's tables, taken periodically by the stable instance and by hand with
+//! `utv-bot db backup`, and restored with `utv-bot db restore`.
+//!
+//! A backup is a JSON lines file: a header with the schema version and time it was taken, then
+//! one line per item of the tables in [`Table::BACKED_UP`], in DynamoDB's JSON format. Next to it
+//! is a `.sha256` file in `sha256sum` format, which restoring checks before writing anything.
+//! Backups go to `BACKUP_TARGET`, a directory or an `s3://bucket/prefix`. The latest
+//! `BACKUP_KEEP` are kept in a directory; S3 buckets should expire old ones with a lifecycle rule.
+//!
+//! Restoring writes every item of the backup over the one with the same key, and leaves items
+//! that aren't in the backup alone. A backup from an older schema version is migrated at the
+//! next startup, as restoring it brings back its version too.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aws_sdk_dynamodb::model::AttributeValue;
+use aws_sdk_dynamodb::Blob;
+use serde_json::{json, Map, Value};
+use tracing::{error, info, warn};
+
+use crate::db::{self, Item, Table};
+use crate::{migrations, shutdown};
+
+const NAME_PREFIX: &str = "utv-backup-";
+const NAME_SUFFIX: &str = ".jsonl";
+
+/// Where backups are written, from `BACKUP_TARGET`
+#[derive(Clone, Debug)]
+pub enum Target {
+    Dir(PathBuf),
+    S3 { bucket: String, prefix: String },
+}
+
+impl Target {
+    pub fn parse(target: &str) -> Result<Target, String> {
+        match target.strip_prefix("s3://") {
+            Some(rest) => {
+                let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+                if bucket.is_empty() {
+                    return Err(format!("{} has no bucket", target));
+                }
+                Ok(Target::S3 {
+                    bucket: bucket.to_string(),
+                    prefix: prefix.trim_matches('/').to_string(),
+                })
+            }
+            None if target.is_empty() => Err("The backup target is empty".to_string()),
+            None => Ok(Target::Dir(PathBuf::from(target))),
+        }
+    }
+
+    /// Where a backup with the name is stored, as `utv-bot db restore` takes it
+    fn location(&self, name: &str) -> String {
+        match self {
+            Target::Dir(dir) => dir.join(name).display().to_string(),
+            Target::S3 { bucket, prefix } if prefix.is_empty() => {
+                format!("s3://{}/{}", bucket, name)
+            }
+            Target::S3 { bucket, prefix } => format!("s3://{}/{}/{}", bucket, prefix, name),
+        }
+    }
+}
+
+/// Takes a backup every interval, keeping the latest `keep` of those in a directory
+pub async fn backup_loop(
+    db_client: &'static db::DynamoDB,
+    target: Target,
+    interval: Duration,
+    keep: usize,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if shutdown::draining() {
+            return;
+        }
+        match backup(db_client, &target).await {
+            Ok((location, items)) => info!("Backed up {} items to {}", items, location),
+            Err(why) => {
+                // old backups are only pruned once a complete one replaces them
+                error!("Backup failed: {}", why);
+                continue;
+            }
+        }
+        if let Target::Dir(dir) = &target {
+            if let Err(why) = prune(dir, keep) {
+                warn!("Cannot remove old backups from {}: {}", dir.display(), why);
+            }
+        }
+    }
+}
+
+/// Takes a backup, returning where it was written and how many items it holds. Fails without
+/// writing anything if any table can't be read in full.
+pub async fn backup(db_client: &db::DynamoDB, target: &Target) -> Result<(String, usize), String> {
+    let now = chrono::Utc::now();
+    let name = format!(
+        "{}{}{}",
+        NAME_PREFIX,
+        now.format("%Y%m%dT%H%M%SZ"),
+        NAME_SUFFIX
+    );
+    let schema_version = db_client
+        .schema_version()
+        .await
+        .ok_or("Couldn't read the schema version")?;
+    let header = json!({
+        "schema_version": schema_version,
+        "taken_at": now.timestamp(),
+        "tables": Table::BACKED_UP.iter().map(|t| t.name()).collect::<Vec<_>>(),
+    });
+    let mut lines = vec![header.to_string()];
+    for table in Table::BACKED_UP {
+        for item in db_client.raw_items(table).await? {
+            lines.push(json!({ "table": table.name(), "item": item_to_json(&item) }).to_string());
+        }
+    }
+    let items = lines.len() - 1;
+    let mut data = lines.join("\n");
+    data.push('\n');
+    let checksum = format!("{}  {}\n", db::sha256_hex(data.as_bytes()), name);
+
+    let location = target.location(&name);
+    write(&location, data.into_bytes()).await?;
+    write(&format!("{}.sha256", location), checksum.into_bytes()).await?;
+    Ok((location, items))
+}
+
+/// Restores a backup from a file or an `s3://` URL, returning how many items it wrote
+pub async fn restore(db_client: &db::DynamoDB, location: &str) -> Result<usize, String> {
+    let data = read(location).await?;
+    let checksum = String::from_utf8(read(&format!("{}.sha256", location)).await?)
+        .map_err(|_| format!("The checksum of {} is not text", location))?;
+    if checksum.split_whitespace().next() != Some(db::sha256_hex(&data).as_str()) {
+        return Err(format!(
+            "{} doesn't match its checksum, it's damaged or incomplete",
+            location
+        ));
+    }
+    let data = String::from_utf8(data).map_err(|_| format!("{} is not a backup", location))?;
+
+    let mut lines = data.lines();
+    let header = lines
+        .next()
+        .and_then(|line| serde_json::from_str::<Value>(line).ok())
+        .ok_or_else(|| format!("{} has no header", location))?;
+    let schema_version = header["schema_version"]
+        .as_u64()
+        .ok_or_else(|| format!("{} has no schema version", location))?;
+    if schema_version > migrations::SCHEMA_VERSION as u64 {
+        return Err(format!(
+            "The backup is at schema version {}, newer than this build's {}",
+            schema_version,
+            migrations::SCHEMA_VERSION
+        ));
+    }
+    // every line is read before anything is written, so a bad backup changes nothing
+    let mut tables: HashMap<&str, Vec<Item>> = HashMap::new();
+    for (i, line) in lines.enumerate() {
+        let bad = || format!("Line {} of {} is not an item", i + 2, location);
+        let line = serde_json::from_str::<Value>(line).map_err(|_| bad())?;
+        let table = Table::BACKED_UP
+            .iter()
+            .find(|t| Some(t.name()) == line["table"].as_str())
+            .ok_or_else(bad)?;
+        let item = item_from_json(&line["item"]).ok_or_else(bad)?;
+        tables.entry(table.name()).or_default().push(item);
+    }
+
+    let mut written = 0;
+    for table in Table::BACKED_UP {
+        let items = tables.remove(table.name()).unwrap_or_default();
+        let count = items.len();
+        if !db_client.put_items(table, items).await {
+            return Err(format!(
+                "Couldn't write the {} table, run the restore again",
+                table.name()
+            ));
+        }
+        info!("Restored {} items of the {} table", count, table.name());
+        written += count;
+    }
+    Ok(written)
+}
+
+/// Removes all but the latest `keep` backups from a directory
+fn prune(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut names = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with(NAME_PREFIX) && name.ends_with(NAME_SUFFIX))
+        .collect::<Vec<_>>();
+    // the names sort by when they were taken
+    names.sort();
+    for name in names.iter().rev().skip(keep) {
+        std::fs::remove_file(dir.join(name))?;
+        let _ = std::fs::remove_file(dir.join(format!("{}.sha256", name)));
+    }
+    Ok(())
+}
+
+async fn write(location: &str, data: Vec<u8>) -> Result<(), String> {
+    match s3_location(location) {
+        Some((bucket, key)) => s3_client()
+            .await
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .body(aws_sdk_s3::ByteStream::from(data))
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|why| format!("Couldn't upload {}: {}", location, why)),
+        None => {
+            if let Some(dir) = Path::new(location).parent() {
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .map_err(|why| format!("Couldn't create {}: {}", dir.display(), why))?;
+            }
+            tokio::fs::write(location, data)
+                .await
+                .map_err(|why| format!("Couldn't write {}: {}", location, why))
+        }
+    }
+}
+
+async fn read(location: &str) -> Result<Vec<u8>, String> {
+    match s3_location(location) {
+        Some((bucket, key)) => {
+            let object = s3_client()
+                .await
+                .get_object()
+                .bucket(bucket)
+                .key(key)
+                .send()
+                .await
+                .map_err(|why| format!("Couldn't download {}: {}", location, why))?;
+            object
+                .body
+                .collect()
+                .await
+                .map(|data| data.into_bytes().to_vec())
+                .map_err(|why| format!("Couldn't download {}: {}", location, why))
+        }
+        None => tokio::fs::read(location)
+            .await
+            .map_err(|why| format!("Couldn't read {}: {}", location, why)),
+    }
+}
+
+/// The bucket and key of an `s3://` URL
+fn s3_location(location: &str) -> Option<(&str, &str)> {
+    location.strip_prefix("s3://")?.split_once('/')
+}
+
+async fn s3_client() -> aws_sdk_s3::Client {
+    aws_sdk_s3::Client::new(&aws_config::load_from_env().await)
+}
+
+fn item_to_json(item: &Item) -> Value {
+    Value::Object(
+        item.iter()
+            .map(|(name, value)| (name.clone(), value_to_json(value)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+fn value_to_json(value: &AttributeValue) -> Value {
+    let blob = |b: &Blob| base64::encode(b.as_ref());
+    match value {
+        AttributeValue::S(s) => json!({ "S": s }),
+        AttributeValue::N(n) => json!({ "N": n }),
+        AttributeValue::Bool(b) => json!({ "BOOL": b }),
+        AttributeValue::Null(n) => json!({ "NULL": n }),
+        AttributeValue::B(b) => json!({ "B": blob(b) }),
+        AttributeValue::Ss(s) => json!({ "SS": s }),
+        AttributeValue::Ns(n) => json!({ "NS": n }),
+        AttributeValue::Bs(b) => json!({ "BS": b.iter().map(blob).collect::<Vec<_>>() }),
+        AttributeValue::L(l) => json!({ "L": l.iter().map(value_to_json).collect::<Vec<_>>() }),
+        AttributeValue::M(m) => json!({ "M": item_to_json(m) }),
+        // the bot writes none of the others, and restoring refuses the item
+        _ => Value::Null,
+    }
+}
+
+fn item_from_json(item: &Value) -> Option<Item> {
+    item.as_object()?
+        .iter()
+        .map(|(name, value)| Some((name.clone(), value_from_json(value)?)))
+        .collect()
+}
+
+fn value_from_json(value: &Value) -> Option<AttributeValue> {
+    let (kind, value) = value.as_object()?.iter().next()?;
+    let blob = |b: &str| base64::decode(b).ok().map(Blob::new);
+    Some(match kind.as_str() {
+        "S" => AttributeValue::S(value.as_str()?.to_string()),
+        "N" => AttributeValue::N(value.as_str()?.to_string()),
+        "BOOL" => AttributeValue::Bool(value.as_bool()?),
+        "NULL" => AttributeValue::Null(value.as_bool()?),
+        "B" => AttributeValue::B(blob(value.as_str()?)?),
+        "SS" => AttributeValue::Ss(strings(value)?),
+        "NS" => AttributeValue::Ns(strings(value)?),
+        "BS" => AttributeValue::Bs(
+            strings(value)?
+                .iter()
+                .map(|b| blob(b))
+                .collect::<Option<_>>()?,
+        ),
+        "L" => AttributeValue::L(
+            value
+                .as_array()?
+                .iter()
+                .map(value_from_json)
+                .collect::<Option<_>>()?,
+        ),
+        "M" => AttributeValue::M(item_from_json(value)?),
+        _ => return None,
+    })
+}
+
+fn strings(value: &Value) -> Option<Vec<String>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|s| s.as_str().map(str::to_string))
+        .collect()
+}
@@ -0,0 +1,109 @@
+//! Ban evasion: a banned member's EID is remembered, so a new account verifying with it is
+//! caught.
+//!
+//! When a verified member is banned, the guild records the hash of their EID. Another account
+//! redeeming a token for that EID in the guild is refused, and one that verified elsewhere is
+//! treated as unverified in the guild, without roles or ✓. Either way moderators are alerted
+//! once per account, in the audit channel and the ledger. Unbanning the member forgets the ban.
+//! Needs the `ban-evasion` feature; bans from before it was enabled, or of members who weren't
+//! verified, aren't known.
+
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, UserId};
+use tracing::{error, info};
+
+use crate::{audit, cache, db};
+
+/// Records the EID of a member banned from the guild
+pub async fn banned(db_client: &db::DynamoDB, guild_id: GuildId, user_id: UserId) {
+    let encrypted_eid = match db_client.get_encrypted_eid(user_id.0).await {
+        Some(encrypted_eid) => encrypted_eid,
+        None => return,
+    };
+    if db_client
+        .put_ban(guild_id, &db::eid_hash(&encrypted_eid), user_id)
+        .await
+    {
+        info!("Recorded the EID of {} banned from {}", user_id, guild_id);
+    } else {
+        error!(
+            "Failed to record the EID of {} banned from {}",
+            user_id, guild_id
+        );
+    }
+}
+
+pub async fn unbanned(db_client: &db::DynamoDB, guild_id: GuildId, user_id: UserId) {
+    if !db_client.delete_bans(guild_id, user_id).await {
+        error!("Failed to forget the ban of {} from {}", user_id, guild_id);
+    }
+}
+
+/// Whether an account verifying with the EID evades a ban from the guild, alerting moderators
+/// the first time it's caught
+pub async fn evades(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    user_id: UserId,
+    encrypted_eid: &str,
+) -> bool {
+    let eid_hash = db::eid_hash(encrypted_eid);
+    let banned = match db_client.get_ban(guild_id, &eid_hash).await {
+        Some(banned) if banned != user_id => banned,
+        _ => return false,
+    };
+    if !db_client.record_evasion(guild_id, &eid_hash, user_id).await {
+        return true;
+    }
+    info!(
+        "{} verified in {} with the EID of banned {}",
+        user_id, guild_id, banned
+    );
+    if let Ok(bot_id) = cache::bot_id(http).await {
+        audit::record(
+            db_client,
+            guild_id,
+            bot_id,
+            "ban.evasion",
+            Some(user_id),
+            format!("EID of banned <@{}>", banned),
+        )
+        .await;
+    }
+    audit::post(
+        db_client,
+        http,
+        guild_id,
+        audit::Post {
+            action: "Possible ban evasion",
+            member: user_id,
+            actor: None,
+            reason: format!(
+                "Verified with the EID of <@{}>, who is banned from this server",
+                banned
+            ),
+            failed: false,
+        },
+    )
+    .await;
+    true
+}
+
+/// Whether a verified member evades a ban from their guild, see [`evades`]
+pub async fn evading(db_client: &db::DynamoDB, http: &Http, member: &Member) -> bool {
+    match db_client.get_encrypted_eid(member.user.id.0).await {
+        Some(encrypted_eid) => {
+            evades(
+                db_client,
+                http,
+                member.guild_id,
+                member.user.id,
+                &encrypted_eid,
+            )
+            .await
+        }
+        None => false,
+    }
+}
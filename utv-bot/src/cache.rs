@@ -0,0 +1,72 @@
+//! Guild data fetched from Discord, kept so per-member work doesn't refetch it.
+//!
+//! The bot runs without serenity's cache, so role lists are cached here: seeded when a guild is
+//! created, dropped whenever a role event arrives for the guild and otherwise kept for [`TTL`],
+//! which only covers events missed while disconnected. The bot's own id never changes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serenity::http::Http;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+
+const TTL: Duration = Duration::from_secs(10 * 60);
+
+lazy_static! {
+    static ref ROLES: Mutex<HashMap<GuildId, (Instant, HashMap<RoleId, Role>)>> =
+        Mutex::new(HashMap::new());
+    static ref BOT_ID: Mutex<Option<UserId>> = Mutex::new(None);
+}
+
+/// The guild's roles, fetched at most once per [`TTL`]
+pub async fn roles(http: &Http, guild_id: GuildId) -> serenity::Result<HashMap<RoleId, Role>> {
+    if let Some((at, roles)) = ROLES.lock().unwrap().get(&guild_id) {
+        if at.elapsed() < TTL {
+            return Ok(roles.clone());
+        }
+    }
+    let roles = guild_id.roles(http).await?;
+    set_roles(guild_id, roles.clone());
+    Ok(roles)
+}
+
+/// Stores roles received with a gateway event
+pub fn set_roles(guild_id: GuildId, roles: HashMap<RoleId, Role>) {
+    ROLES
+        .lock()
+        .unwrap()
+        .insert(guild_id, (Instant::now(), roles));
+}
+
+/// A role as last fetched, without fetching; what a role event changed can still be read here
+/// before the event drops the guild's roles
+pub fn cached_role(guild_id: GuildId, role_id: RoleId) -> Option<Role> {
+    ROLES
+        .lock()
+        .unwrap()
+        .get(&guild_id)
+        .and_then(|(_, roles)| roles.get(&role_id).cloned())
+}
+
+/// Drops the guild's roles after one was created, changed or deleted
+pub fn invalidate_roles(guild_id: GuildId) {
+    ROLES.lock().unwrap().remove(&guild_id);
+}
+
+/// Drops every guild's roles, for `/shutdown reload:True`
+pub fn clear() {
+    ROLES.lock().unwrap().clear();
+}
+
+/// The bot's user id
+pub async fn bot_id(http: &Http) -> serenity::Result<UserId> {
+    if let Some(bot_id) = *BOT_ID.lock().unwrap() {
+        return Ok(bot_id);
+    }
+    let bot_id = http.get_current_user().await?.id;
+    *BOT_ID.lock().unwrap() = Some(bot_id);
+    Ok(bot_id)
+}
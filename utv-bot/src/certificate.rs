@@ -0,0 +1,174 @@
+//! `/certificate`: portable proof of verification.
+//!
+//! Members get a token signed with the bot's Ed25519 key that they can present to other UT
+//! systems and bots. It names their Discord account and affiliation, never their EID. Anyone can
+//! check it offline against the public key served at `/certificate-key`, or ask
+//! `/certificate/:token`, which also reports certificates of accounts that were unverified since.
+
+use std::borrow::Cow;
+
+use axum::extract::{Extension, Path};
+use axum::http::StatusCode;
+use axum::Json;
+use lazy_static::lazy_static;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::Serialize;
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::InteractionApplicationCommandCallbackDataFlags;
+use serenity::utils::Color;
+use utv_token::Certificate;
+
+use crate::{db, events, handlers, http, response, settings, PUBLIC_URL};
+
+/// How long a certificate is valid, after which the member asks for a new one
+const CERTIFICATE_SECS: i64 = 30 * 24 * 60 * 60;
+const QR_FILENAME: &str = "certificate.png";
+
+lazy_static! {
+    static ref KEY_PAIR: Option<Ed25519KeyPair> = settings::certificate_key().ok().flatten();
+}
+
+/// The key certificates are signed with, which also signs exports meant to be checked by others
+pub fn key_pair() -> Option<&'static Ed25519KeyPair> {
+    KEY_PAIR.as_ref()
+}
+
+pub async fn certificate(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let key_pair = match KEY_PAIR.as_ref() {
+        Some(key_pair) => key_pair,
+        None => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "Certificates aren't enabled on this bot.",
+            )
+            .await
+        }
+    };
+    let claims = match db_client.get_user(command.user.id.0).await {
+        Some(claims) => claims,
+        None => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "Verify with `/verify` before asking for a certificate.",
+            )
+            .await
+        }
+    };
+    let now = response::unix_now();
+    let token = utv_token::sign_public(
+        &Certificate {
+            discord_id: command.user.id.0,
+            affiliation: claims.affiliation,
+            issued_at: now,
+            expires_at: now + CERTIFICATE_SECS,
+        },
+        key_pair,
+    );
+    let link = format!("{}/certificate/{}", PUBLIC_URL.as_str(), token);
+    let qr = if handlers::option_bool(&command.data.options, "qr").unwrap_or(false) {
+        events::qr_png(&link)
+    } else {
+        None
+    };
+
+    response::defer(&ctx, &command, true).await?;
+    command
+        .create_followup_message(&ctx.http, |message| {
+            if let Some(png) = qr {
+                message.add_file(AttachmentType::Bytes {
+                    data: Cow::from(png),
+                    filename: QR_FILENAME.to_string(),
+                });
+            }
+            message
+                .create_embed(|embed| {
+                    embed
+                        .title("Verification Certificate")
+                        .description(format!(
+                            "Present this token to other UT systems and bots. It shows your Discord \
+                             account and affiliation, never your EID, and expires <t:{}:R>.",
+                            now + CERTIFICATE_SECS
+                        ))
+                        .field("Token", format!("```{}```", token), false)
+                        .field(
+                            "Checking it",
+                            format!(
+                                "[Open the certificate]({}) or check it against the public key \
+                                 at {}/certificate-key.",
+                                link,
+                                PUBLIC_URL.as_str()
+                            ),
+                            false,
+                        )
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+        .await?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct PublicKey {
+    algorithm: &'static str,
+    /// unpadded URL-safe base64, like the certificates
+    public_key: String,
+}
+
+/// `GET /certificate-key`
+pub async fn public_key() -> Result<Json<PublicKey>, (StatusCode, &'static str)> {
+    let key_pair = KEY_PAIR
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Certificates are not enabled."))?;
+    Ok(Json(PublicKey {
+        algorithm: "Ed25519",
+        public_key: base64::encode_config(key_pair.public_key(), base64::URL_SAFE_NO_PAD),
+    }))
+}
+
+#[derive(Serialize)]
+pub struct CertificateStatus {
+    discord_id: String,
+    affiliation: Vec<String>,
+    issued_at: i64,
+    expires_at: i64,
+    /// `valid`, `expired`, or `revoked` when the account was unverified after it was issued
+    status: &'static str,
+}
+
+/// `GET /certificate/:token`
+pub async fn check(
+    Path(token): Path<String>,
+    Extension(state): Extension<http::State>,
+) -> Result<Json<CertificateStatus>, (StatusCode, &'static str)> {
+    let key_pair = KEY_PAIR
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Certificates are not enabled."))?;
+    let certificate: Certificate =
+        utv_token::verify_public(token.trim(), key_pair.public_key().as_ref())
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid certificate."))?;
+    let status = if certificate.expires_at <= response::unix_now() {
+        "expired"
+    } else if !state.db_client.is_verified(certificate.discord_id).await {
+        "revoked"
+    } else {
+        "valid"
+    };
+    Ok(Json(CertificateStatus {
+        discord_id: certificate.discord_id.to_string(),
+        affiliation: certificate.affiliation,
+        issued_at: certificate.issued_at,
+        expires_at: certificate.expires_at,
+        status,
+    }))
+}
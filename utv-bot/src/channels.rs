@@ -0,0 +1,129 @@
+//! Channel policies: permission overwrites the bot keeps in place on configured channels.
+//!
+//! Voice gating denies Connect to @everyone and allows it for the verified role on the
+//! channels in `GuildConfig::voice_gated_channels`. The overwrites are re-applied periodically,
+//! so changes made by hand in Discord are reverted, as are the Unverified role's overwrites
+//! when quarantine limits it to a welcome channel, see `quarantine`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::http::Http;
+use serenity::model::channel::{PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::Permissions;
+use tracing::warn;
+
+use crate::{db, quarantine, roles, sharding};
+
+const ENFORCE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Re-applies every guild's channel policies
+pub async fn enforce_loop(db_client: &'static db::DynamoDB, http: Arc<Http>) {
+    loop {
+        tokio::time::sleep(ENFORCE_INTERVAL).await;
+        let guilds = match sharding::guilds(&http).await {
+            Ok(guilds) => guilds,
+            Err(why) => {
+                warn!("Cannot list guilds to enforce channel policies: {}", why);
+                continue;
+            }
+        };
+        for guild_id in guilds {
+            if let Err(why) = enforce(db_client, &http, guild_id).await {
+                warn!("Cannot enforce channel policies of {}: {}", guild_id, why);
+            }
+        }
+    }
+}
+
+/// Applies the guild's channel policies, only editing overwrites that drifted
+pub async fn enforce(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<()> {
+    let config = db_client.get_guild_config(guild_id).await;
+    if let (true, Some(role_id), Some(channel)) = (
+        config.quarantine,
+        config.quarantine_role,
+        config.quarantine_channel,
+    ) {
+        quarantine::gate_channels(http, guild_id, role_id, channel).await?;
+    }
+    if config.voice_gated_channels.is_empty() {
+        return Ok(());
+    }
+    let verified_role = roles::verified_role(db_client, http, guild_id).await?;
+    for channel_id in config.voice_gated_channels {
+        set_voice_gate(http, guild_id, channel_id, verified_role, true).await?;
+    }
+    Ok(())
+}
+
+/// Gates or ungates a single channel to match the guild's config
+pub async fn sync_channel(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> serenity::Result<()> {
+    let gated = db_client
+        .get_guild_config(guild_id)
+        .await
+        .voice_gated_channels
+        .contains(&channel_id);
+    let verified_role = roles::verified_role(db_client, http, guild_id).await?;
+    set_voice_gate(http, guild_id, channel_id, verified_role, gated).await
+}
+
+async fn set_voice_gate(
+    http: &Http,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    verified_role: Option<RoleId>,
+    gated: bool,
+) -> serenity::Result<()> {
+    if gated && verified_role.is_none() {
+        // denying @everyone without an allowed role would lock out every member
+        return Err(serenity::Error::Other(
+            "the server has no UTexas Verified role",
+        ));
+    }
+    let channel = match channel_id.to_channel(http).await?.guild() {
+        Some(channel) => channel,
+        None => return Ok(()),
+    };
+    // the @everyone role shares the guild's id
+    let mut targets = vec![(PermissionOverwriteType::Role(RoleId(guild_id.0)), false)];
+    if let Some(role) = verified_role {
+        targets.push((PermissionOverwriteType::Role(role), true));
+    }
+    for (kind, allowed) in targets {
+        let current = channel
+            .permission_overwrites
+            .iter()
+            .find(|o| o.kind == kind);
+        let (mut allow, mut deny) = current
+            .map_or((Permissions::empty(), Permissions::empty()), |o| {
+                (o.allow, o.deny)
+            });
+        allow.remove(Permissions::CONNECT);
+        deny.remove(Permissions::CONNECT);
+        if gated && allowed {
+            allow.insert(Permissions::CONNECT);
+        } else if gated {
+            deny.insert(Permissions::CONNECT);
+        }
+        let unchanged = match current {
+            Some(o) => o.allow == allow && o.deny == deny,
+            None => allow.is_empty() && deny.is_empty(),
+        };
+        if !unchanged {
+            channel
+                .create_permission(http, &PermissionOverwrite { allow, deny, kind })
+                .await?;
+        }
+    }
+    Ok(())
+}
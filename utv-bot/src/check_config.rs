@@ -0,0 +1,100 @@
+//! `utv-bot check-config [--offline]`: validates the configuration and prints a report, for
+//! use in deploy pipelines. Exits non-zero if anything is wrong.
+//!
+//! The environment is always checked. Unless `--offline` is passed, every guild's stored
+//! configuration is also checked against the roles that exist in Discord.
+
+use std::collections::HashMap;
+
+use serenity::http::Http;
+use serenity::model::id::RoleId;
+
+use crate::config::GuildConfig;
+use crate::settings::Settings;
+use crate::{db, sharding};
+
+/// Runs the checks, returning whether the configuration is valid
+pub async fn run(offline: bool) -> bool {
+    let settings = match Settings::from_env() {
+        Ok(settings) => {
+            println!("✓ environment");
+            settings
+        }
+        Err(problems) => {
+            println!("✗ environment");
+            for problem in problems {
+                println!("    {}", problem);
+            }
+            return false;
+        }
+    };
+    if offline {
+        println!("- guild configuration (skipped, offline)");
+        return true;
+    }
+
+    let http = Http::new_with_token(&settings.discord_token);
+    let guilds = match sharding::guild_infos(&http).await {
+        Ok(guilds) => guilds,
+        Err(why) => {
+            println!("✗ cannot reach Discord with DISCORD_TOKEN: {}", why);
+            return false;
+        }
+    };
+    let db_client = db::DynamoDB::new("users").await;
+    let mut valid = true;
+    for guild in guilds {
+        let problems = match guild.id.roles(&http).await {
+            Ok(roles) => {
+                let mappings = db_client.get_role_config(guild.id).await;
+                let config = db_client.get_guild_config(guild.id).await;
+                let mut problems =
+                    missing_roles(&mappings, &config, |role| roles.contains_key(&role));
+                for channel in &config.voice_gated_channels {
+                    if http.get_channel(channel.0).await.is_err() {
+                        problems.push(format!("voice-gated channel {} does not exist", channel));
+                    }
+                }
+                problems
+            }
+            Err(why) => vec![format!("cannot fetch roles: {}", why)],
+        };
+        if problems.is_empty() {
+            println!("✓ guild {} ({})", guild.name, guild.id);
+        } else {
+            valid = false;
+            println!("✗ guild {} ({})", guild.name, guild.id);
+            for problem in problems {
+                println!("    {}", problem);
+            }
+        }
+    }
+    valid
+}
+
+/// Describes every role referenced by the guild's mappings or config that doesn't exist
+fn missing_roles(
+    mappings: &HashMap<String, u64>,
+    config: &GuildConfig,
+    exists: impl Fn(RoleId) -> bool,
+) -> Vec<String> {
+    let mut problems = mappings
+        .iter()
+        .filter(|(_, role)| !exists(RoleId(**role)))
+        .map(|(tag, role)| format!("role {} mapped to \"{}\" does not exist", role, tag))
+        .collect::<Vec<_>>();
+    problems.sort();
+    if let Some(role) = config.attest_approver_role.filter(|r| !exists(*r)) {
+        problems.push(format!("attestation approver role {} does not exist", role));
+    }
+    if let Some(role) = config.alumni_role.filter(|r| !exists(*r)) {
+        problems.push(format!("alumni role {} does not exist", role));
+    }
+    if let Some(role) = config.guest_role.filter(|r| !exists(*r)) {
+        problems.push(format!("guest role {} does not exist", role));
+    }
+    for role in config.officer_roles.iter().filter(|r| !exists(**r)) {
+        problems.push(format!("officer role {} does not exist", role));
+    }
+    problems
+}
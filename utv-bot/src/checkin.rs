@@ -0,0 +1,270 @@
+//! Event attendance that only verified members can check in to.
+//!
+//! Attendance is keyed by a hash of the member's EID, so one person checking in from two
+//! Discord accounts is only counted once, and exports never contain raw EIDs. With `qr`, the
+//! check-in also gets a QR code linking to its message, for projecting at the event: scanning it
+//! opens Discord on the button, so checking in still needs the member's own account.
+
+use std::borrow::Cow;
+
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::utils::Color;
+
+use crate::db::{self, CheckinResult};
+use crate::i18n::{self, Locale};
+use crate::{components, events, handlers, response};
+
+/// Custom id prefix of the check-in buttons, followed by the check-in id
+pub const COMPONENT_PREFIX: &str = "checkin:";
+/// Check-ins are for a single event, their buttons are disabled after this
+const BUTTON_LIFETIME_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub async fn checkin(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    match handlers::subcommand(&command) {
+        Some(("create", options)) => create(db_client, &command, options, guild_id, &ctx).await,
+        Some(("export", options)) => export(db_client, &command, options, guild_id, &ctx).await,
+        _ => {
+            response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    }
+}
+
+async fn create(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    guild_id: GuildId,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let checkin = db::Checkin {
+        checkin_id: format!("{:08x}", rand::random::<u32>()),
+        guild_id,
+        name: handlers::option_str(options, "event")
+            .unwrap_or("Event")
+            .to_string(),
+        created_at: response::unix_now(),
+        attendees: Vec::new(),
+    };
+    if !db_client.create_checkin(&checkin).await {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Failed to create the check-in, please try again",
+        )
+        .await;
+    }
+
+    // posted for the members checking in, so in the guild's language
+    let locale = i18n::locale(db_client, Some(guild_id)).await;
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title(i18n::format(
+                                    locale,
+                                    "checkin.title",
+                                    &[("event", &checkin.name)],
+                                ))
+                                .description(i18n::text(locale, "checkin.description"))
+                                .footer(|footer| {
+                                    footer.text(i18n::format(
+                                        locale,
+                                        "checkin.id",
+                                        &[("id", &checkin.checkin_id)],
+                                    ))
+                                })
+                                .color(Color::from_rgb(191, 87, 0))
+                        })
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button
+                                        .style(ButtonStyle::Success)
+                                        .label(i18n::text(locale, "checkin.button"))
+                                        .custom_id(format!(
+                                            "{}{}",
+                                            COMPONENT_PREFIX, checkin.checkin_id
+                                        ))
+                                })
+                            })
+                        })
+                })
+        })
+        .await?;
+    components::track_response(
+        db_client,
+        command,
+        guild_id,
+        ctx,
+        Some(BUTTON_LIFETIME_SECS),
+    )
+    .await;
+    if handlers::option_bool(options, "qr").unwrap_or(false) {
+        post_qr(command, guild_id, ctx, locale).await?;
+    }
+    Ok(())
+}
+
+/// Follows up with a QR code of the link to the check-in message
+async fn post_qr(
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: &Context,
+    locale: Locale,
+) -> serenity::Result<()> {
+    let message = command.get_interaction_response(&ctx.http).await?;
+    let url = format!(
+        "https://discord.com/channels/{}/{}/{}",
+        guild_id, message.channel_id, message.id
+    );
+    let png = match events::qr_png(&url) {
+        Some(png) => png,
+        None => {
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message.content(
+                        "Failed to generate the QR code, members can still use the button.",
+                    )
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(png),
+                    filename: "checkin.png".to_string(),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .description(i18n::text(locale, "checkin.qr"))
+                        .url(&url)
+                        .image("attachment://checkin.png")
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Handles a press of a check-in button
+pub async fn redeem(
+    db_client: &'static db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let checkin_id = component
+        .data
+        .custom_id
+        .trim_start_matches(COMPONENT_PREFIX);
+    let locale = i18n::locale(db_client, component.guild_id).await;
+    let encrypted_eid = match db_client.get_encrypted_eid(component.user.id.0).await {
+        Some(encrypted_eid) => encrypted_eid,
+        None => {
+            return response::respond_component_title(
+                &ctx,
+                &component,
+                true,
+                i18n::text(locale, "checkin.not-verified"),
+            )
+            .await
+        }
+    };
+    let key = match db_client
+        .record_attendance(
+            checkin_id,
+            &db::eid_hash(&encrypted_eid),
+            component.user.id,
+            response::unix_now(),
+        )
+        .await
+    {
+        CheckinResult::Recorded => "checkin.recorded",
+        CheckinResult::AlreadyCheckedIn => "checkin.already",
+        CheckinResult::Failed => "checkin.failed",
+    };
+    response::respond_component_title(&ctx, &component, true, i18n::text(locale, key)).await
+}
+
+async fn export(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    guild_id: GuildId,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let checkin = match handlers::option_str(options, "event") {
+        Some(checkin_id) => db_client.get_checkin(checkin_id).await,
+        None => None,
+    };
+    let mut checkin = match checkin {
+        Some(checkin) if checkin.guild_id == guild_id => checkin,
+        _ => {
+            return response::respond_title(ctx, command, true, "No such check-in in this guild")
+                .await
+        }
+    };
+
+    response::defer(ctx, command, true).await?;
+
+    checkin.attendees.sort_by_key(|a| a.checked_in_at);
+    let mut csv = "eid_hash,discord_id,checked_in_at\n".to_string();
+    for attendance in &checkin.attendees {
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            attendance.eid_hash, attendance.discord_id, attendance.checked_in_at
+        ));
+    }
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(csv.into_bytes()),
+                    filename: format!("checkin-{}.csv", checkin.checkin_id),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title(format!("Attendance: {}", checkin.name))
+                        .description(format!(
+                            "{} verified attendees, check-in opened {}",
+                            checkin.attendees.len(),
+                            response::datetime(checkin.created_at)
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
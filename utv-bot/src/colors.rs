@@ -0,0 +1,84 @@
+//! Contrast checks for role colors.
+//!
+//! Members' names are drawn in their role's color on both of Discord's themes, so a color that
+//! reads well on one can be nearly invisible on the other. Colors are checked with the WCAG
+//! contrast ratio against each theme's background, and failing colors get a suggestion with
+//! the same hue at a lightness that works on both.
+
+use serenity::utils::Colour;
+
+/// WCAG's minimum contrast for large text; names are bold enough to count as such
+const MIN_CONTRAST: f64 = 3.0;
+const DARK_BACKGROUND: Colour = Colour(0x313338);
+const LIGHT_BACKGROUND: Colour = Colour(0xFFFFFF);
+
+/// Parses `#rrggbb` or `rrggbb`
+pub fn parse(hex: &str) -> Option<Colour> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(Colour)
+}
+
+pub fn hex(color: Colour) -> String {
+    format!("#{:06X}", color.0)
+}
+
+fn luminance(color: Colour) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(color.r()) + 0.7152 * channel(color.g()) + 0.0722 * channel(color.b())
+}
+
+fn contrast(a: Colour, b: Colour) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Describes each theme the color is hard to read on, empty when it passes both
+pub fn problems(color: Colour) -> Vec<String> {
+    // Discord shows names in the default color when the role has none
+    if color.0 == 0 {
+        return Vec::new();
+    }
+    [("dark", DARK_BACKGROUND), ("light", LIGHT_BACKGROUND)]
+        .iter()
+        .filter_map(|(theme, background)| {
+            let ratio = contrast(color, *background);
+            (ratio < MIN_CONTRAST).then(|| {
+                format!(
+                    "{} has a contrast of {:.1}:1 on the {} theme, below {:.0}:1",
+                    hex(color),
+                    ratio,
+                    theme,
+                    MIN_CONTRAST
+                )
+            })
+        })
+        .collect()
+}
+
+fn mix(color: Colour, target: u8, amount: f64) -> Colour {
+    let channel = |c: u8| (c as f64 + (target as f64 - c as f64) * amount).round() as u8;
+    Colour::from_rgb(channel(color.r()), channel(color.g()), channel(color.b()))
+}
+
+/// The closest color of the same hue that passes on both themes, found by mixing in black or
+/// white in small steps
+pub fn suggest(color: Colour) -> Option<Colour> {
+    let target = if contrast(color, DARK_BACKGROUND) < MIN_CONTRAST {
+        255
+    } else {
+        0
+    };
+    (1..=20)
+        .map(|step| mix(color, target, step as f64 * 0.05))
+        .find(|c| problems(*c).is_empty())
+}
@@ -0,0 +1,1571 @@
+//! Slash command definitions and their registration with Discord.
+//!
+//! Commands are only re-registered when the definitions differ from what Discord already has,
+//! since overwriting them on every reconnect churns propagation and burns rate limits.
+
+use serde_json::{json, Value};
+use serenity::builder::{CreateApplicationCommandOption, CreateApplicationCommands};
+use serenity::http::Http;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommand, ApplicationCommandOptionType,
+};
+use tracing::{info, warn};
+
+use crate::i18n::Locale;
+use crate::settings;
+
+/// Versioned commands trialed next to their stable counterparts. They are registered as guild
+/// commands only in pilot guilds that enabled them with `/config beta`.
+pub const BETA_COMMANDS: &[&str] = &["verify-beta"];
+
+/// Who may run a command, and so who `/help` lists it for, from everyone up
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Audience {
+    Everyone,
+    /// administrators and members who can kick
+    Moderators,
+    Administrators,
+    /// the bot's owner, never listed by `/help`
+    Owner,
+}
+
+/// A command as `/help` describes it
+pub struct CommandHelp {
+    pub name: &'static str,
+    /// what it does, also its description in Discord
+    pub summary: &'static str,
+    /// how to use it, shown by `/help command:<name>`
+    pub detail: &'static str,
+    pub audience: Audience,
+    /// only runs in servers, not in DMs
+    pub guild_only: bool,
+}
+
+/// Every global command, in the order `/help` lists them
+pub const REGISTRY: &[CommandHelp] = &[
+    CommandHelp {
+        name: "verify",
+        summary: "Verify your Discord Account",
+        detail:
+            "`/verify eid:<your EID>` emails a token to your UT address. Finish with `/redeem` \
+                 once it arrives. Where UT login is enabled, `/verify` gives you a link to log in \
+                 with your EID instead.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "redeem",
+        summary: "Finish verifying with the token from your verification email",
+        detail: "`/redeem token:<token>`, or `/redeem` alone to paste it into a form. A text file \
+                 with the token works too, with `file:`.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "help",
+        summary: "Learn more about the bot and its commands",
+        detail: "`/help` lists the commands you can run, `/help command:<name>` explains one.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "support",
+        summary: "Report a problem with the bot to its maintainers",
+        detail: "`/support` opens a form for a summary and details; the maintainers get it with \
+                 the server it came from.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "forgetme",
+        summary: "Delete your verification and everything the bot stores about it",
+        detail: "`/forgetme` asks for confirmation, then unlinks your EID and takes the verified \
+                 role off in every server.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "status",
+        summary: "Show the bot's version and how its shards are connected",
+        detail: "`/status` shows the version, the deployment and each shard's connection.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "certificate",
+        summary: "Get a signed proof of your verification to show other UT services",
+        detail: "`/certificate` replies with a signed token naming your account and affiliation, \
+                 never your EID. `qr:True` adds a QR code linking to it.",
+        audience: Audience::Everyone,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "roles",
+        summary: "Pick roles like your courses or major",
+        detail: "`/roles` shows the roles this server lets verified members give themselves. \
+                 Pick yours and unpick the ones you no longer want.",
+        audience: Audience::Everyone,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "instructions",
+        summary: "Post how to verify in this server",
+        detail: "`/instructions` posts the steps in this channel, or in `channel:`. `card:True` \
+                 adds a QR code linking to the verification website.",
+        audience: Audience::Moderators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "note",
+        summary: "Moderator notes about members",
+        detail: "`/note add user:<member> text:<note>` records a note only moderators see, \
+                 `/note list user:<member>` shows them.",
+        audience: Audience::Moderators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "whois",
+        summary: "Show a member's verification status",
+        detail: "`/whois user:<member>` shows whether they're verified, since when, their \
+                 affiliation and their masked EID. It's recorded in the audit ledger.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "lookup",
+        summary: "Find the account that verified with an EID",
+        detail: "`/lookup eid:<EID>` names the account that verified with it, if it's a member \
+                 of this server. It's recorded in the audit ledger.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "unverify",
+        summary: "Revoke a member's verification",
+        detail: "`/unverify user:<member>` unlinks their EID and takes their verified and mapped \
+                 roles off.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "rescan",
+        summary: "Check all users in the guild for nickname compliance and role assignment",
+        detail: "`/rescan` walks every member, fixing nicknames and roles that drifted, and \
+                 reports its progress. `dry-run:True` lists what it would change instead.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "stats",
+        summary: "How many of this server's members are verified, and recently",
+        detail: "`/stats` shows the verified count, verifications in the last 7 and 30 days, the \
+                 top affiliations and the last sweep.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "setup-panel",
+        summary: "Post a message with buttons members can use to verify",
+        detail: "`/setup-panel` posts Verify, Enter Token and Verify on the Web buttons in this \
+                 channel. They never expire.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "merge-roles",
+        summary: "Find duplicate verified or mapped roles and merge them into one",
+        detail: "`/merge-roles` finds duplicates of the verified and mapped roles and lets you \
+                 pick the one their members are moved onto.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "guest",
+        summary: "Give someone who can't verify the guest role for a limited time",
+        detail: "`/guest user:<member> hours:<hours>` gives the role set with \
+                 `/config guest-role` until it expires.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "attest",
+        summary: "Attest that a verified member holds an officer role",
+        detail: "`/attest user:<member> role:<officer role> term:<term>` grants the role until \
+                 the term ends. Members with the `/config attest-approver` role may run it too.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "attestations",
+        summary: "Show a member's officer attestations",
+        detail: "`/attestations user:<member>` lists who attested which roles, and until when.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "checkin",
+        summary: "Attendance check-ins for verified members",
+        detail: "`/checkin create event:<name> [qr]` posts a check-in button for verified members, \
+                 `/checkin export event:<name>` sends the attendance.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "event-qr",
+        summary: "Verification QR codes for in-person events",
+        detail: "`/event-qr create name:<name> hours:<hours>` makes a QR code that opens the \
+                 portal for this server, `cap:` limits its uses. `/event-qr report` shows how \
+                 many verified with it.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "eligible-voters",
+        summary: "Election eligibility: verified members who joined before a date",
+        detail: "`/eligible-voters export joined-before:<YYYY-MM-DD>` sends the list, \
+                 `/eligible-voters panel` posts a button members check themselves with.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "config",
+        summary: "Configure the bot for this guild",
+        detail: "`/config show` shows every setting. Each other subcommand changes one, e.g. \
+                 `/config affiliation-role`, `/config nickname`, `/config verify-age`, \
+                 `/config review` or `/config quarantine`; changes to who gets verified are \
+                 previewed before they apply.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "admin",
+        summary: "Maintenance commands for administrators",
+        detail: "`/admin audit export`, `/admin analytics`, `/admin export`, `/admin jobs`, \
+                 `/admin rollback`, `/admin rush-mode`, `/admin webhook` and `/admin offboard`. `bulk-lookup`, \
+                 `import` and `issue-token` are for the bot's owner and trusted admins.",
+        audience: Audience::Administrators,
+        guild_only: true,
+    },
+    CommandHelp {
+        name: "tenant",
+        summary: "Approve the servers the bot serves (bot owner only)",
+        detail: "In a DM with the bot, `/tenant add guild:<id>` approves a server, \
+                 `/tenant remove guild:<id>` removes it and leaves it, and `/tenant list` shows \
+                 the approved servers and those the bot would leave. Only matters with \
+                 `TENANCY=allowlist`.",
+        audience: Audience::Owner,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "guilds",
+        summary: "List the bot's servers (bot owner only)",
+        detail: "In a DM with the bot, shows how many servers the bot is in and their members, \
+                 with the largest servers' member and verified counts.",
+        audience: Audience::Owner,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "announce",
+        summary: "Post an announcement in every server (bot owner only)",
+        detail: "In a DM with the bot, `/announce title:<title> message:<message>` posts an \
+                 embed in the audit channel of every server that set one with \
+                 `/config audit-channel`.",
+        audience: Audience::Owner,
+        guild_only: false,
+    },
+    CommandHelp {
+        name: "shutdown",
+        summary: "Stop the bot, or reload its settings (bot owner only)",
+        detail: "In a DM with the bot, `/shutdown` stops the bot cleanly like SIGTERM, for its \
+                 supervisor to restart it. `/shutdown reload:True` instead applies the \
+                 `--profile` again and drops cached roles and stats.",
+        audience: Audience::Owner,
+        guild_only: false,
+    },
+];
+
+/// The registry entry of a command
+pub fn command_help(name: &str) -> Option<&'static CommandHelp> {
+    REGISTRY.iter().find(|command| command.name == name)
+}
+
+fn summary(name: &str) -> &'static str {
+    command_help(name).map_or("", |command| command.summary)
+}
+
+/// Builds the beta commands a guild enabled
+pub fn create_beta<'a>(
+    commands: &'a mut CreateApplicationCommands,
+    enabled: &[String],
+) -> &'a mut CreateApplicationCommands {
+    if enabled.iter().any(|name| name == "verify-beta") {
+        commands.create_application_command(|command| {
+            command
+                .name("verify-beta")
+                .description("Verify your Discord account (new flow, in testing)")
+                .create_option(|option| {
+                    option
+                        .name("eid")
+                        .description("Your UT EID")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        });
+    }
+    commands
+}
+
+/// Builds the global command set
+pub fn create(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|command| {
+            command
+                .name("verify")
+                .description(summary("verify"))
+                .create_option(|option| {
+                    option
+                        .name("eid")
+                        .description("Your UT EID")
+                        .kind(ApplicationCommandOptionType::String)
+                        // logging in with UT asks for it instead
+                        .required(!matches!(settings::sso(), Ok(Some(_))))
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("redeem")
+                .description(summary("redeem"))
+                .create_option(|option| {
+                    option
+                        .name("token")
+                        .description("The token, or leave empty to paste it into a form")
+                        .kind(ApplicationCommandOptionType::String)
+                })
+                .create_option(|option| {
+                    option
+                        .name("file")
+                        .description("A text file containing the token")
+                        .kind(ApplicationCommandOptionType::Attachment)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("help")
+                .description(summary("help"))
+                .create_option(|option| {
+                    option
+                        .name("command")
+                        .description("The command to explain, like verify")
+                        .kind(ApplicationCommandOptionType::String)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("support")
+                .description(summary("support"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("forgetme")
+                .description(summary("forgetme"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("status")
+                .description(summary("status"))
+        })
+        .create_application_command(|command| {
+            command.name("roles").description(summary("roles"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("certificate")
+                .description(summary("certificate"))
+                .create_option(|option| {
+                    option
+                        .name("qr")
+                        .description("Include a QR code linking to the certificate")
+                        .kind(ApplicationCommandOptionType::Boolean)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("tenant")
+                .description(summary("tenant"))
+                .create_option(|option| {
+                    option
+                        .name("add")
+                        .description("Approve a server")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("guild")
+                                .description("The server's id")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("remove")
+                        .description("Remove a server's approval and leave it")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("guild")
+                                .description("The server's id")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("list")
+                        .description("Show the approved servers")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                })
+        })
+        .create_application_command(|command| {
+            command.name("guilds").description(summary("guilds"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("announce")
+                .description(summary("announce"))
+                .create_option(|option| {
+                    option
+                        .name("title")
+                        .description("The announcement's title")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+                .create_option(|option| {
+                    option
+                        .name("message")
+                        .description("The announcement")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("shutdown")
+                .description(summary("shutdown"))
+                .create_option(|option| {
+                    option
+                        .name("reload")
+                        .description("Reload the settings instead of stopping")
+                        .kind(ApplicationCommandOptionType::Boolean)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("merge-roles")
+                .description(summary("merge-roles"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("setup-panel")
+                .description(summary("setup-panel"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("rescan")
+                .description(summary("rescan"))
+                .create_option(|option| {
+                    option
+                        .name("dry-run")
+                        .description("List what the scan would change without changing it")
+                        .kind(ApplicationCommandOptionType::Boolean)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("unverify")
+                .description(summary("unverify"))
+                .create_option(|option| {
+                    option
+                        .name("user")
+                        .description("The member to unverify")
+                        .kind(ApplicationCommandOptionType::User)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("whois")
+                .description(summary("whois"))
+                .create_option(|option| {
+                    option
+                        .name("user")
+                        .description("The member to look up")
+                        .kind(ApplicationCommandOptionType::User)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("stats")
+                .description(summary("stats"))
+        })
+        .create_application_command(|command| {
+            command
+                .name("lookup")
+                .description(summary("lookup"))
+                .create_option(|option| {
+                    option
+                        .name("eid")
+                        .description("The EID to look up")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("admin")
+                .description(summary("admin"))
+                .create_option(|option| {
+                    option
+                        .name("audit")
+                        .description("The guild's audit ledger")
+                        .kind(ApplicationCommandOptionType::SubCommandGroup)
+                        .create_sub_option(|option| {
+                            option
+                                .name("export")
+                                .description("Export the ledger as signed NDJSON")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("since")
+                                        .description("First day to include, YYYY-MM-DD")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(true)
+                                })
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("until")
+                                        .description("Last day to include, YYYY-MM-DD")
+                                        .kind(ApplicationCommandOptionType::String)
+                                })
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("analytics")
+                        .description("Where members drop off while verifying")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("days")
+                                .description("How far back to look, 30 days by default")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                        .create_sub_option(format_option)
+                })
+                .create_option(|option| {
+                    option
+                        .name("bulk-lookup")
+                        .description("Check which EIDs of a roster are verified members")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("file")
+                                .description("Text file with one EID per line")
+                                .kind(ApplicationCommandOptionType::Attachment)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("export")
+                        .description("Export this server's verified members as CSV or JSON")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("format")
+                                .description("File format, CSV by default")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("CSV", "csv")
+                                .add_string_choice("JSON", "json")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("import")
+                        .description("Link verified members from a CSV or JSON file (owner and trusted admins)")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("file")
+                                .description("Rows of discord_id,eid,affiliation")
+                                .kind(ApplicationCommandOptionType::Attachment)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("dry-run")
+                                .description("Only report what would be imported")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("issue-token")
+                        .description("Issue a verification token by hand (owner and trusted admins)")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("eid")
+                                .description("The member's UT EID")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("affiliation")
+                                .description("Affiliation the token grants, student by default")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("Student", "student")
+                                .add_string_choice("Faculty", "faculty")
+                                .add_string_choice("Staff", "staff")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("jobs")
+                        .description("Running and queued jobs, including waiting nickname edits")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(format_option)
+                })
+                .create_option(|option| {
+                    option
+                        .name("offboard")
+                        .description("Remove the bot and everything it stored from this server")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                })
+                .create_option(|option| {
+                    option
+                        .name("ratelimits")
+                        .description("Rate limit usage across all guilds (bot owner only)")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(format_option)
+                })
+                .create_option(|option| {
+                    option
+                        .name("rollback")
+                        .description("Revert the role and nickname changes of a bulk job")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("job-id")
+                                .description("Job id shown when the job was started")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("rush-mode")
+                        .description("Boost verification for a few hours, e.g. during orientation")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("hours")
+                                .description("How long to boost for, up to 72; 0 ends rush mode")
+                                .kind(ApplicationCommandOptionType::Integer)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Channel to post progress in, this one by default")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("selftest")
+                        .description("Check every subsystem against the test server (bot owner only)")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                })
+                .create_option(|option| {
+                    option
+                        .name("webhook")
+                        .description("URLs verification events are sent to")
+                        .kind(ApplicationCommandOptionType::SubCommandGroup)
+                        .create_sub_option(|option| {
+                            option
+                                .name("add")
+                                .description("Send verification events to a URL")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("url")
+                                        .description("An https URL")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(true)
+                                })
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("remove")
+                                .description("Stop sending events to a URL")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("url")
+                                        .description("The URL to remove")
+                                        .kind(ApplicationCommandOptionType::String)
+                                        .required(true)
+                                })
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("list")
+                                .description("Show the URLs and recent deliveries")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("instructions")
+                .description(summary("instructions"))
+                .create_option(|option| {
+                    option
+                        .name("channel")
+                        .description("Channel to post in, this one by default")
+                        .kind(ApplicationCommandOptionType::Channel)
+                })
+                .create_option(|option| {
+                    option
+                        .name("card")
+                        .description("Include a QR code linking to the verification website")
+                        .kind(ApplicationCommandOptionType::Boolean)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("note")
+                .description(summary("note"))
+                .create_option(|option| {
+                    option
+                        .name("add")
+                        .description("Add a note about a member")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("user")
+                                .description("The member")
+                                .kind(ApplicationCommandOptionType::User)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("text")
+                                .description("The note, only moderators can read it")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("list")
+                        .description("Show the notes about a member")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("user")
+                                .description("The member")
+                                .kind(ApplicationCommandOptionType::User)
+                                .required(true)
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("attest")
+                .description(summary("attest"))
+                .create_option(|option| {
+                    option
+                        .name("user")
+                        .description("The officer")
+                        .kind(ApplicationCommandOptionType::User)
+                        .required(true)
+                })
+                .create_option(|option| {
+                    option
+                        .name("role")
+                        .description("The officer role")
+                        .kind(ApplicationCommandOptionType::Role)
+                        .required(true)
+                })
+                .create_option(|option| {
+                    option
+                        .name("term")
+                        .description("Semester the position is held, e.g. Fall 2026")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("guest")
+                .description(summary("guest"))
+                .create_option(|option| {
+                    option
+                        .name("user")
+                        .description("The guest")
+                        .kind(ApplicationCommandOptionType::User)
+                        .required(true)
+                })
+                .create_option(|option| {
+                    option
+                        .name("hours")
+                        .description("How long the pass lasts")
+                        .kind(ApplicationCommandOptionType::Integer)
+                        .required(true)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("attestations")
+                .description(summary("attestations"))
+                .create_option(|option| {
+                    option
+                        .name("user")
+                        .description("The member")
+                        .kind(ApplicationCommandOptionType::User)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("config")
+                .description(summary("config"))
+                .create_option(|option| {
+                    option
+                        .name("show")
+                        .description("Show the current configuration")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                })
+                .create_option(|option| {
+                    option
+                        .name("alumni")
+                        .description("How members who are no longer students are shown")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("role")
+                                .description("Role replacing the Student role, leave empty to only remove it")
+                                .kind(ApplicationCommandOptionType::Role)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("suffix")
+                                .description("Nickname decoration in place of ✓, e.g. 🎓")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("attest-approver")
+                        .description("Role allowed to attest officer roles")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("role")
+                                .description("Leave empty to only allow administrators")
+                                .kind(ApplicationCommandOptionType::Role)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("beta")
+                        .description("Enable or disable a beta command in this server")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("command")
+                                .description("The beta command")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true);
+                            for name in BETA_COMMANDS {
+                                option.add_string_choice(name, name);
+                            }
+                            option
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("milestones")
+                        .description("Announce verified member milestones (100, 500, every 1000)")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Where to announce them, leave empty to stop")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("message")
+                                .description("Announcement, {count} and {server} are filled in")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("on-join")
+                        .description("DM unverified members who join how to verify")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("dm")
+                                .description("Send the DM")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Mention them here instead when their DMs are closed")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("message")
+                                .description("Message, {user}, {server} and {link} are filled in")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("reminders")
+                                .description("Remind them this many days after joining, like 1, 7, or off")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("on-verify")
+                        .description("What members see after verifying, leave all empty for the default")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("ephemeral")
+                                .description("Add the message to the reply only they see")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Welcome them publicly in this channel")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("dm")
+                                .description("Send them the message as a DM")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("message")
+                                .description("Message, {user} and {server} are filled in")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("role")
+                        .description("Set or check the name, color and display of the verified role")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("color")
+                                .description("Hex color, e.g. #BF5700; leave empty to check the current one")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("name")
+                                .description("New name of the role")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("hoist")
+                                .description("Show verified members separately in the member list")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("mentionable")
+                                .description("Let everyone mention the role")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("marker")
+                        .description("Set the marker after verified students' nicknames")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("symbol")
+                                .description("Emoji or non-ASCII symbol, e.g. ★; leave empty for ✓")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("nickname")
+                        .description("Set the template verified members' nicknames follow")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("template")
+                                .description(
+                                    "e.g. {real_name} ({eid}); leave empty for {display_name} {marker} {decoration}",
+                                )
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("dues")
+                        .description("Role for verified members on a dues roster")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("role")
+                                .description("Role to grant, leave empty to stop tracking dues")
+                                .kind(ApplicationCommandOptionType::Role)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("url")
+                                .description("HTTPS link to the roster")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("kind")
+                                .description("What the link returns, a CSV file by default")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("CSV file with an eid column", "csv")
+                                .add_string_choice("JSON list of EIDs", "api")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("token")
+                                .description("Bearer token for the API, if it needs one")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("locale")
+                        .description("The language of the replies members get while verifying")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("language")
+                                .description("The language")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true);
+                            for locale in Locale::ALL {
+                                option.add_string_choice(locale.name(), locale.code());
+                            }
+                            option
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("selfrole")
+                        .description("Roles verified members can give themselves with /roles")
+                        .kind(ApplicationCommandOptionType::SubCommandGroup)
+                        .create_sub_option(|option| {
+                            option
+                                .name("add")
+                                .description("Let verified members pick a role")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("role")
+                                        .description("The role, like a course or major")
+                                        .kind(ApplicationCommandOptionType::Role)
+                                        .required(true)
+                                })
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("remove")
+                                .description("Stop offering a role")
+                                .kind(ApplicationCommandOptionType::SubCommand)
+                                .create_sub_option(|option| {
+                                    option
+                                        .name("role")
+                                        .description("The role")
+                                        .kind(ApplicationCommandOptionType::Role)
+                                        .required(true)
+                                })
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("sheet")
+                        .description("Google spreadsheet verification events are appended to")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("spreadsheet")
+                                .description("Link or id of the spreadsheet, leave empty to stop syncing")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("verify-age")
+                        .description("Minimum account and membership age to verify")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("account-days")
+                                .description("Minimum Discord account age in days")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("member-days")
+                                .description("Minimum days since joining this server")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("decoration")
+                        .description("Temporary emoji in verified members' nicknames, e.g. for gameday")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("text")
+                                .description("Emoji to add, leave empty to remove the decoration")
+                                .kind(ApplicationCommandOptionType::String)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("hours")
+                                .description("How long to show it, 48 hours by default")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("guest-role")
+                        .description("Role granted by /guest")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("role")
+                                .description("Leave empty to disable guest passes")
+                                .kind(ApplicationCommandOptionType::Role)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("public-stats")
+                        .description("Publish the verified member count for badges on your website")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("enabled")
+                                .description("Whether the count is public")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("quarantine")
+                        .description("Give unverified members an Unverified role, optionally limited to one channel")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("action")
+                                .description("Enable or disable the role")
+                                .kind(ApplicationCommandOptionType::String)
+                                .add_string_choice("Enable", "enable")
+                                .add_string_choice("Disable", "disable")
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Welcome channel, the only one unverified members can see")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("review")
+                        .description("Hold suspicious verifications until a moderator approves them")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Channel held verifications are posted in, leave empty to stop reviewing")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("account-days")
+                                .description("Hold Discord accounts younger than this many days")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("name-mismatch")
+                                .description("Hold members whose name shares no word with their UT Directory name")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("unrenamable")
+                        .description("Choose what happens with verified members the bot can't rename, like moderators")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("policy")
+                                .description("What the bot does")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                                .add_string_choice("Skip them silently", "skip")
+                                .add_string_choice("DM them once to set their nickname themselves", "notify")
+                                .add_string_choice("List them in the audit ledger after scans", "report")
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("affiliation-role")
+                        .description("Give verified members a role for their UT affiliation")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("affiliation")
+                                .description("Affiliation from the UT directory")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                                .add_string_choice("Student", "student")
+                                .add_string_choice("Faculty", "faculty")
+                                .add_string_choice("Staff", "staff")
+                                .add_string_choice("Employee", "employee")
+                                .add_string_choice("Affiliate", "affiliate")
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("role")
+                                .description("Role to give them, leave empty to stop")
+                                .kind(ApplicationCommandOptionType::Role)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("audit-channel")
+                        .description("Post the bot's role and nickname changes in a channel")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("Where to post them, leave empty to stop")
+                                .kind(ApplicationCommandOptionType::Channel)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("voice-gate")
+                        .description("Allow only verified members to join a voice or stage channel, or undo it")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("channel")
+                                .description("The voice or stage channel")
+                                .kind(ApplicationCommandOptionType::Channel)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("officer-role")
+                        .description("Allow or disallow attesting a role")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("role")
+                                .description("The officer role")
+                                .kind(ApplicationCommandOptionType::Role)
+                                .required(true)
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("checkin")
+                .description(summary("checkin"))
+                .create_option(|option| {
+                    option
+                        .name("create")
+                        .description("Post a check-in button for an event")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("event")
+                                .description("Name of the event")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("qr")
+                                .description("Also post a QR code that opens the check-in")
+                                .kind(ApplicationCommandOptionType::Boolean)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("export")
+                        .description("Export a check-in's attendance as CSV")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("event")
+                                .description("Check-in id")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("eligible-voters")
+                .description(summary("eligible-voters"))
+                .create_option(|option| {
+                    option
+                        .name("export")
+                        .description("Export a signed list of eligible voters")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("joined-before")
+                                .description("Cutoff date, YYYY-MM-DD")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("panel")
+                        .description("Post a button members can use to check their eligibility")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("joined-before")
+                                .description("Cutoff date, YYYY-MM-DD")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("event-qr")
+                .description(summary("event-qr"))
+                .create_option(|option| {
+                    option
+                        .name("create")
+                        .description("Create an event and its QR code")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("name")
+                                .description("Name of the event")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("hours")
+                                .description("How long the QR code stays valid")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                        .create_sub_option(|option| {
+                            option
+                                .name("cap")
+                                .description("Maximum number of redemptions")
+                                .kind(ApplicationCommandOptionType::Integer)
+                        })
+                })
+                .create_option(|option| {
+                    option
+                        .name("report")
+                        .description("Summarize an event's redemptions")
+                        .kind(ApplicationCommandOptionType::SubCommand)
+                        .create_sub_option(|option| {
+                            option
+                                .name("event")
+                                .description("Event id")
+                                .kind(ApplicationCommandOptionType::String)
+                                .required(true)
+                        })
+                })
+        })
+}
+
+/// Whether the global commands are registered in the guild, see `settings::command_guild`
+pub fn registers_in(guild_id: GuildId) -> bool {
+    settings::command_guild().ok().flatten() == Some(guild_id)
+}
+
+/// The global command set, empty while `COMMAND_GUILD_ID` registers it in one guild instead
+fn create_global(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    if matches!(settings::command_guild(), Ok(None)) {
+        create(commands);
+    }
+    commands
+}
+
+/// The commands of a guild: its beta commands, and the global ones in the command guild
+fn create_guild<'a>(
+    commands: &'a mut CreateApplicationCommands,
+    guild_id: GuildId,
+    enabled: &[String],
+) -> &'a mut CreateApplicationCommands {
+    if registers_in(guild_id) {
+        create(commands);
+    }
+    create_beta(commands, enabled)
+}
+
+/// Registers the global commands if they differ from the ones Discord has, logging the changes
+pub async fn sync(http: &Http) {
+    let mut builder = CreateApplicationCommands::default();
+    create_global(&mut builder);
+    let existing = ApplicationCommand::get_global_application_commands(http).await;
+    if !needs_update("Global", existing, &builder) {
+        return;
+    }
+    match ApplicationCommand::set_global_application_commands(http, create_global).await {
+        Ok(commands) => info!("Registered {} global slash commands", commands.len()),
+        Err(why) => warn!("Cannot register global slash commands: {}", why),
+    }
+}
+
+/// Registers the beta commands a guild enabled, removing the ones it disabled
+pub async fn sync_guild(http: &Http, guild_id: GuildId, enabled: &[String]) {
+    let mut builder = CreateApplicationCommands::default();
+    create_guild(&mut builder, guild_id, enabled);
+    let existing = guild_id.get_application_commands(http).await;
+    if !needs_update(&format!("Guild {}", guild_id), existing, &builder) {
+        return;
+    }
+    match guild_id
+        .set_application_commands(http, |commands| create_guild(commands, guild_id, enabled))
+        .await
+    {
+        Ok(commands) => info!(
+            "Registered {} slash commands in guild {}",
+            commands.len(),
+            guild_id
+        ),
+        Err(why) => warn!(
+            "Cannot register slash commands in guild {}: {}",
+            guild_id, why
+        ),
+    }
+}
+
+/// Compares registered commands with the builder, logging any changes
+/// `format:json` of admin commands, see `response::respond_json`
+fn format_option(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .name("format")
+        .description("Reply with a JSON file for scripts instead of a message")
+        .kind(ApplicationCommandOptionType::String)
+        .add_string_choice("Message", "embed")
+        .add_string_choice("JSON", "json")
+}
+
+fn needs_update(
+    scope: &str,
+    existing: serenity::Result<Vec<ApplicationCommand>>,
+    builder: &CreateApplicationCommands,
+) -> bool {
+    let desired = builder.0.iter().map(normalize).collect::<Vec<_>>();
+    let existing = match existing {
+        Ok(commands) => commands
+            .iter()
+            .map(|c| normalize(&serde_json::to_value(c).unwrap_or_default()))
+            .collect::<Vec<_>>(),
+        Err(why) => {
+            warn!(
+                "Cannot fetch {} slash commands, re-registering: {}",
+                scope.to_lowercase(),
+                why
+            );
+            Vec::new()
+        }
+    };
+
+    let changes = diff(&existing, &desired);
+    if changes.is_empty() {
+        info!(
+            "{} slash commands are up to date ({} commands)",
+            scope,
+            desired.len()
+        );
+        return false;
+    }
+    info!("{} slash commands changed:\n{}", scope, changes.join("\n"));
+    true
+}
+
+/// Reduces a command or option to the fields we define, filling in Discord's defaults, so
+/// builder output and fetched commands compare equal
+fn normalize(value: &Value) -> Value {
+    let nested = |key| {
+        value
+            .get(key)
+            .and_then(Value::as_array)
+            .map(|items| items.iter().map(normalize).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    json!({
+        "name": value.get("name"),
+        "description": value.get("description"),
+        "type": value.get("type"),
+        "required": value.get("required").and_then(Value::as_bool).unwrap_or(false),
+        "choices": value.get("choices").cloned().unwrap_or_else(|| json!([])),
+        "options": nested("options"),
+    })
+}
+
+fn diff(existing: &[Value], desired: &[Value]) -> Vec<String> {
+    let name = |command: &Value| command["name"].as_str().unwrap_or_default().to_string();
+    let mut changes = Vec::new();
+    for command in desired {
+        match existing.iter().find(|c| name(c) == name(command)) {
+            None => changes.push(format!("  + /{}", name(command))),
+            Some(old) if old != command => changes.push(format!("  ~ /{}", name(command))),
+            Some(_) => {}
+        }
+    }
+    for command in existing {
+        if !desired.iter().any(|c| name(c) == name(command)) {
+            changes.push(format!("  - /{}", name(command)));
+        }
+    }
+    changes
+}
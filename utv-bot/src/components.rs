@@ -0,0 +1,169 @@
+//! Garbage collection of message components.
+//!
+//! Panels posted to channels keep their buttons forever, and pressing one whose handler was
+//! removed or whose event is over only shows "This interaction failed". Posted panels are
+//! tracked, and a sweeper disables their components once they expire or no handler recognizes
+//! their custom ids any more.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::client::Context;
+use serenity::http::{Http, HttpError};
+use serenity::model::channel::Message;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::ActionRowComponent;
+use tracing::{error, warn};
+
+use crate::{
+    checkin, db, dry_run, elections, forget, offboard, panel, preview, redeem, response, review,
+    roles, self_roles,
+};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Whether an interaction handler recognizes a custom id; keep in sync with the routing of
+/// message components in `main`
+pub fn handled(custom_id: &str) -> bool {
+    custom_id.starts_with(checkin::COMPONENT_PREFIX)
+        || custom_id.starts_with(elections::COMPONENT_PREFIX)
+        || custom_id.starts_with(preview::COMPONENT_PREFIX)
+        || custom_id.starts_with(dry_run::COMPONENT_PREFIX)
+        || custom_id.starts_with(roles::COMPONENT_PREFIX)
+        || custom_id == self_roles::COMPONENT_ID
+        || custom_id == redeem::OPEN_BUTTON_ID
+        || custom_id == offboard::CONFIRM_ID
+        || custom_id == forget::CONFIRM_ID
+        || custom_id.starts_with(panel::COMPONENT_PREFIX)
+        || custom_id.starts_with(review::COMPONENT_PREFIX)
+}
+
+/// Tracks the panel posted as the response to a command. `lifetime_secs` is how long its
+/// components stay useful, or `None` to keep them until their handler goes away.
+pub async fn track_response(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: &Context,
+    lifetime_secs: Option<i64>,
+) {
+    let message = match command.get_interaction_response(&ctx.http).await {
+        Ok(message) => message,
+        Err(why) => {
+            warn!("Cannot fetch the posted panel to track it: {}", why);
+            return;
+        }
+    };
+    let now = response::unix_now();
+    let tracked = db::TrackedMessage {
+        message_id: message.id,
+        channel_id: message.channel_id,
+        guild_id,
+        posted_at: now,
+        expires_at: lifetime_secs.map(|secs| now + secs),
+    };
+    if !db_client.track_message(&tracked).await {
+        error!("Failed to track message {}", message.id);
+    }
+}
+
+pub async fn sweep_loop(db_client: &'static db::DynamoDB, http: Arc<Http>) {
+    loop {
+        tokio::time::sleep(SWEEP_INTERVAL).await;
+        sweep(db_client, &http).await;
+    }
+}
+
+/// Disables the components of tracked messages that expired or lost their handler, and stops
+/// tracking messages that were deleted
+async fn sweep(db_client: &db::DynamoDB, http: &Http) {
+    let now = response::unix_now();
+    for tracked in db_client.tracked_messages().await {
+        let message = match tracked.channel_id.message(http, tracked.message_id).await {
+            Ok(message) => message,
+            Err(serenity::Error::Http(why)) if matches!(&*why, HttpError::UnsuccessfulRequest(r) if r.status_code.as_u16() == 404) =>
+            {
+                db_client.untrack_message(tracked.message_id).await;
+                continue;
+            }
+            Err(why) => {
+                warn!(
+                    "Cannot fetch tracked message {}: {}",
+                    tracked.message_id, why
+                );
+                continue;
+            }
+        };
+        let custom_ids = custom_ids(&message);
+        let expired = tracked.expires_at.map_or(false, |at| at <= now);
+        if !custom_ids.is_empty() && !expired && custom_ids.iter().all(|id| handled(id)) {
+            continue;
+        }
+        if !custom_ids.is_empty() {
+            if let Err(why) = disable(http, message).await {
+                warn!(
+                    "Cannot disable components of {}: {}",
+                    tracked.message_id, why
+                );
+                continue;
+            }
+        }
+        db_client.untrack_message(tracked.message_id).await;
+    }
+}
+
+fn custom_ids(message: &Message) -> Vec<String> {
+    message
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .filter_map(|component| match component {
+            ActionRowComponent::Button(button) => button.custom_id.clone(),
+            ActionRowComponent::SelectMenu(menu) => menu.custom_id.clone(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Re-renders the message's buttons disabled, keeping their labels so the panel still reads
+/// the same, and drops select menus
+async fn disable(http: &Http, mut message: Message) -> serenity::Result<()> {
+    let rows = message.components.clone();
+    message
+        .edit(http, |edit| {
+            edit.components(|components| {
+                for row in &rows {
+                    let buttons = row
+                        .components
+                        .iter()
+                        .filter_map(|component| match component {
+                            ActionRowComponent::Button(button) => Some(button),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    if buttons.is_empty() {
+                        continue;
+                    }
+                    components.create_action_row(|new_row| {
+                        for button in buttons {
+                            new_row.create_button(|new_button| {
+                                new_button.style(button.style).disabled(true);
+                                if let Some(label) = &button.label {
+                                    new_button.label(label);
+                                }
+                                match (&button.custom_id, &button.url) {
+                                    (_, Some(url)) => new_button.url(url),
+                                    (Some(custom_id), None) => new_button.custom_id(custom_id),
+                                    (None, None) => new_button,
+                                }
+                            });
+                        }
+                        new_row
+                    });
+                }
+                components
+            })
+        })
+        .await
+}
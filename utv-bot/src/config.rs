@@ -0,0 +1,1202 @@
+//! Per-guild settings, changed by admins with `/config`
+
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::channel::ChannelType;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::utils::Color;
+
+use crate::i18n::Locale;
+use crate::membership::{self, DuesConfig};
+use crate::onboarding::{self, OnboardingActions};
+use crate::review::ReviewConfig;
+use crate::success::SuccessActions;
+use crate::webhooks::Webhook;
+use crate::{
+    audit, channels, colors, commands, db, handlers, jobs, nickname_policy, preview, quarantine,
+    response, roles, scheduler, sheets, stats, unrenamable, PUBLIC_URL,
+};
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct GuildConfig {
+    /// members with this role may attest officer positions, in addition to admins
+    pub attest_approver_role: Option<RoleId>,
+    /// roles that can be granted with `/attest`
+    pub officer_roles: Vec<RoleId>,
+    /// roles verified members can give themselves with `/roles`
+    pub self_roles: Vec<RoleId>,
+    /// minimum age of a Discord account before it may `/verify`
+    pub min_account_age_days: u32,
+    /// minimum days since joining the guild before a member may `/verify`
+    pub min_membership_days: u32,
+    /// voice and stage channels only verified members may join
+    pub voice_gated_channels: Vec<ChannelId>,
+    /// role granted by `/guest`, kept visibly distinct from verified members
+    pub guest_role: Option<RoleId>,
+    /// role that replaces the Student role once a member is no longer a student
+    pub alumni_role: Option<RoleId>,
+    /// nickname decoration for alumni in place of ✓, none when unset
+    pub alumni_suffix: Option<String>,
+    /// Google spreadsheet verification events are appended to
+    pub sheet_id: Option<String>,
+    /// beta commands registered in this guild, from `commands::BETA_COMMANDS`
+    pub beta_commands: Vec<String>,
+    /// temporary decoration appended to verified members' nicknames, e.g. for gameday
+    pub decoration: Option<Decoration>,
+    /// channel verified-count milestones are announced in
+    pub milestone_channel: Option<ChannelId>,
+    /// announcement with `{count}` and `{server}` placeholders, a default one when unset
+    pub milestone_template: Option<String>,
+    /// the last milestone reached, unset until the count is first seen after enabling
+    pub last_milestone: Option<u64>,
+    /// what members see after verifying, besides the default confirmation
+    pub success_actions: SuccessActions,
+    /// what unverified members get when they join
+    pub onboarding: OnboardingActions,
+    /// role for verified members on an external dues roster
+    pub dues: Option<DuesConfig>,
+    /// the verified role has an icon, which replaces ✓ in nicknames; kept in sync by `marker`
+    pub native_marker: bool,
+    /// name the verified role is found by, `roles::VERIFIED_ROLE_NAME` when unset
+    pub verified_role_name: Option<String>,
+    /// nickname marker of verified students in place of ✓
+    pub marker: Option<String>,
+    /// how verified members' nicknames are written, see `nickname_policy`
+    pub nickname_template: Option<String>,
+    /// the verified count is served without an API key, see `api::guild_stats`
+    pub public_stats: bool,
+    /// what happens with verified members the bot can't rename
+    pub unrenamable: unrenamable::Policy,
+    /// members already asked to set their nickname themselves, see `unrenamable`
+    pub unrenamable_notified: Vec<UserId>,
+    /// channel the bot's role and nickname changes are posted in, see `audit::post`
+    pub audit_channel: Option<ChannelId>,
+    /// unverified members get the Unverified role, see `quarantine`
+    pub quarantine: bool,
+    /// the Unverified role the bot created, kept until quarantine is disabled
+    pub quarantine_role: Option<RoleId>,
+    /// the only channel unverified members can see, all of them when unset
+    pub quarantine_channel: Option<ChannelId>,
+    /// verifications matching these rules wait for a moderator, see `review`
+    pub review: Option<ReviewConfig>,
+    /// language of the replies members get while verifying, see `i18n`
+    pub locale: Locale,
+    /// where verification events are POSTed to, see `webhooks`
+    pub webhooks: Vec<Webhook>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Decoration {
+    pub text: String,
+    pub ends_at: i64,
+}
+
+impl GuildConfig {
+    pub fn verified_role_name(&self) -> &str {
+        self.verified_role_name
+            .as_deref()
+            .unwrap_or(roles::VERIFIED_ROLE_NAME)
+    }
+
+    /// The marker after verified students' nicknames
+    pub fn marker_symbol(&self) -> &str {
+        self.marker.as_deref().unwrap_or(DEFAULT_MARKER)
+    }
+
+    /// The template verified members' nicknames follow
+    pub fn nickname_template(&self) -> &str {
+        self.nickname_template
+            .as_deref()
+            .unwrap_or(nickname_policy::DEFAULT_TEMPLATE)
+    }
+
+    /// The decoration to show right now, if any
+    pub fn active_decoration(&self, now: i64) -> Option<&str> {
+        self.decoration
+            .as_ref()
+            .filter(|d| d.ends_at > now)
+            .map(|d| d.text.as_str())
+    }
+}
+
+pub const DEFAULT_MARKER: &str = "✓";
+/// Markers are short so they fit after nicknames, and contain no ASCII since nickname cleanup
+/// removes all non-ASCII characters, which takes an old marker off when it changes
+const MAX_MARKER_CHARS: usize = 8;
+/// Discord's limit on role names
+const MAX_ROLE_NAME_CHARS: usize = 100;
+
+/// Decorations last two days unless given a duration, and at most two weeks
+const DEFAULT_DECORATION_HOURS: i64 = 48;
+const MAX_DECORATION_HOURS: i64 = 14 * 24;
+/// Discord's limit on the options of a select menu, which `/roles` shows them in
+const MAX_SELF_ROLES: usize = 25;
+/// settings that change members' nicknames, which go through `preview`
+const POLICY_SETTINGS: &[&str] = &["alumni", "decoration", "marker", "nickname"];
+/// affiliations the directory gives, which `/config affiliation-role` maps to roles
+pub const AFFILIATIONS: &[&str] = &["student", "faculty", "staff", "employee", "affiliate"];
+
+pub type Setter =
+    fn(&mut GuildConfig, &[ApplicationCommandInteractionDataOption]) -> Option<String>;
+
+pub async fn config(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let (name, options) = match handlers::subcommand(&command) {
+        Some(sub) => sub,
+        None => ("show", &[][..]),
+    };
+    let setter: Setter = match name {
+        "show" => {
+            let config = db_client.get_guild_config(guild_id).await;
+            return show(&command, &config, &ctx).await;
+        }
+        "role" => return verified_role(db_client, &command, options, guild_id, &ctx).await,
+        "affiliation-role" => {
+            return affiliation_role(db_client, &command, options, guild_id, &ctx, jobs).await
+        }
+        "alumni" => set_alumni,
+        "attest-approver" => set_attest_approver,
+        "audit-channel" => set_audit_channel,
+        "beta" => toggle_beta_command,
+        "decoration" => set_decoration,
+        "dues" => set_dues,
+        "guest-role" => set_guest_role,
+        "locale" => set_locale,
+        "marker" => set_marker,
+        "milestones" => set_milestones,
+        "nickname" => set_nickname,
+        "public-stats" => set_public_stats,
+        "quarantine" => set_quarantine,
+        "review" => set_review,
+        "unrenamable" => set_unrenamable,
+        "officer-role" => toggle_officer_role,
+        "on-join" => set_onboarding,
+        "on-verify" => set_success_actions,
+        "selfrole" => set_self_roles,
+        "sheet" => set_sheet,
+        "verify-age" => set_verify_age,
+        "voice-gate" => toggle_voice_gate,
+        _ => {
+            return response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    };
+    // changes to the nickname policy are previewed and confirmed first
+    if POLICY_SETTINGS.contains(&name) {
+        return preview::offer(db_client, &command, guild_id, &ctx, name, setter, options).await;
+    }
+    let title = match update(db_client, guild_id, command.user.id, |config| {
+        setter(config, options)
+    })
+    .await
+    {
+        Ok(summary) => {
+            apply_side_effects(db_client, &ctx.http, guild_id, name, options, jobs, summary).await
+        }
+        Err(why) => why.to_string(),
+    };
+    response::respond_title(&ctx, &command, true, title).await
+}
+
+/// Carries a saved change over to Discord where it needs more than the config, returning the
+/// change's summary with any problem doing so
+pub async fn apply_side_effects(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    name: &str,
+    options: &[ApplicationCommandInteractionDataOption],
+    jobs: &jobs::Queue,
+    summary: String,
+) -> String {
+    match (name, handlers::option_channel(options, "channel")) {
+        ("voice-gate", Some(channel)) => {
+            match channels::sync_channel(db_client, http, guild_id, channel.id).await {
+                Ok(()) => summary,
+                Err(why) => format!("{}, but applying it failed: {}", summary, why),
+            }
+        }
+        ("decoration", _) => {
+            let config = db_client.get_guild_config(guild_id).await;
+            if let Some(decoration) = &config.decoration {
+                scheduler::schedule(
+                    db_client,
+                    format!("decoration:{}", guild_id),
+                    decoration.ends_at,
+                    &scheduler::Task::EndDecoration { guild_id },
+                )
+                .await;
+            }
+            // nicknames only pick up the change when members are checked again
+            jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+            summary
+        }
+        ("alumni" | "marker" | "nickname", _) => {
+            jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+            summary
+        }
+        ("dues", _) => {
+            membership::forget(guild_id);
+            if db_client.get_guild_config(guild_id).await.dues.is_some() {
+                // the first sync runs right away
+                membership::schedule(db_client, guild_id, response::unix_now()).await;
+            }
+            summary
+        }
+        ("public-stats", _) => {
+            stats::forget_public(guild_id);
+            if db_client.get_guild_config(guild_id).await.public_stats {
+                format!(
+                    "{}, at {}/v1/guilds/{}/stats",
+                    summary,
+                    PUBLIC_URL.as_str(),
+                    guild_id
+                )
+            } else {
+                summary
+            }
+        }
+        ("quarantine", _) => {
+            let config = db_client.get_guild_config(guild_id).await;
+            if !config.quarantine {
+                return match quarantine::disable(db_client, http, guild_id).await {
+                    Ok(()) => summary,
+                    Err(why) => format!("{}, but deleting the role failed: {}", summary, why),
+                };
+            }
+            let role_id = match quarantine::enable(db_client, http, guild_id).await {
+                Ok(role_id) => role_id,
+                Err(why) => return format!("{}, but creating the role failed: {}", summary, why),
+            };
+            // members get the role when they're checked again
+            jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+            match config.quarantine_channel {
+                Some(channel) => {
+                    match quarantine::gate_channels(http, guild_id, role_id, channel).await {
+                        Ok(()) => summary,
+                        Err(why) => {
+                            format!("{}, but hiding the other channels failed: {}", summary, why)
+                        }
+                    }
+                }
+                None => summary,
+            }
+        }
+        ("beta", _) => {
+            let config = db_client.get_guild_config(guild_id).await;
+            commands::sync_guild(http, guild_id, &config.beta_commands).await;
+            summary
+        }
+        _ => summary,
+    }
+}
+
+/// Maps a directory affiliation to a role verified members with it are given. Alumni aren't in
+/// the directory, they're handled by `/config alumni`.
+async fn affiliation_role(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    guild_id: GuildId,
+    ctx: &Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    let affiliation = match handlers::option_str(options, "affiliation") {
+        Some(affiliation) if AFFILIATIONS.contains(&affiliation) => affiliation,
+        _ => {
+            return response::respond_title(ctx, command, true, "Missing or invalid setting").await
+        }
+    };
+    let role = handlers::option_role(options, "role").map(|r| r.id);
+    if !db_client
+        .set_affiliation_role(guild_id, affiliation, role)
+        .await
+    {
+        return response::respond_title(ctx, command, true, "Failed to save the change").await;
+    }
+    let summary = match role {
+        Some(role) => format!("Verified {} members now get <@&{}>", affiliation, role),
+        None => format!("Verified {} members no longer get a role", affiliation),
+    };
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "config.update",
+        None,
+        summary.clone(),
+    )
+    .await;
+    if role.is_some() {
+        // members who verified earlier get the role too
+        jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+    }
+    response::respond_title(ctx, command, true, summary).await
+}
+
+/// Sets or checks the verified role's name, color and member list settings, warning about
+/// colors that are hard to read
+async fn verified_role(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    guild_id: GuildId,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let role_id = match roles::verified_role(db_client, &ctx.http, guild_id).await? {
+        Some(role_id) => role_id,
+        None => {
+            return response::respond_title(ctx, command, true, "This server has no verified role")
+                .await
+        }
+    };
+    let color = match handlers::option_str(options, "color") {
+        Some(input) => match colors::parse(input) {
+            Some(color) => Some(color),
+            None => {
+                return response::respond_title(
+                    ctx,
+                    command,
+                    true,
+                    "Enter the color as a hex code, e.g. #BF5700",
+                )
+                .await
+            }
+        },
+        None => None,
+    };
+    let name = match handlers::option_str(options, "name").map(str::trim) {
+        Some(name) if name.is_empty() || name.chars().count() > MAX_ROLE_NAME_CHARS => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Role names must be between 1 and 100 characters",
+            )
+            .await
+        }
+        name => name,
+    };
+    let hoist = handlers::option_bool(options, "hoist");
+    let mentionable = handlers::option_bool(options, "mentionable");
+
+    let mut changes = Vec::new();
+    if let Some(color) = color {
+        changes.push(format!("color set to {}", colors::hex(color)));
+    }
+    if let Some(hoist) = hoist {
+        changes.push(if hoist {
+            "shown separately in the member list".to_string()
+        } else {
+            "no longer shown separately in the member list".to_string()
+        });
+    }
+    if let Some(mentionable) = mentionable {
+        changes.push(if mentionable {
+            "mentionable by everyone".to_string()
+        } else {
+            "no longer mentionable by everyone".to_string()
+        });
+    }
+    if name.is_some() || !changes.is_empty() {
+        if name.is_some() {
+            // saved below, so the rename isn't followed as if an admin made it by hand
+            roles::expect_change(role_id);
+        }
+        guild_id
+            .edit_role(&ctx.http, role_id, |role| {
+                if let Some(name) = name {
+                    role.name(name);
+                }
+                if let Some(color) = color {
+                    role.colour(color.0 as u64);
+                }
+                if let Some(hoist) = hoist {
+                    role.hoist(hoist);
+                }
+                if let Some(mentionable) = mentionable {
+                    role.mentionable(mentionable);
+                }
+                role
+            })
+            .await?;
+    }
+    if let Some(name) = name {
+        // the bot finds its role by name, so the new one is saved before anything looks again
+        let renamed = update(db_client, guild_id, command.user.id, |config| {
+            config.verified_role_name =
+                Some(name.to_string()).filter(|name| name != roles::VERIFIED_ROLE_NAME);
+            Some(format!("<@&{}> renamed to {}", role_id, name))
+        })
+        .await;
+        if let Err(why) = renamed {
+            return response::respond_title(ctx, command, true, why).await;
+        }
+    }
+    if !changes.is_empty() {
+        audit::record(
+            db_client,
+            guild_id,
+            command.user.id,
+            "config.update",
+            None,
+            format!("<@&{}> {}", role_id, changes.join(", ")),
+        )
+        .await;
+    }
+
+    let role = match guild_id.roles(&ctx.http).await?.remove(&role_id) {
+        Some(role) => role,
+        None => return Ok(()),
+    };
+    let problems = colors::problems(role.colour);
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title(if name.is_some() || !changes.is_empty() {
+                "Verified role updated".to_string()
+            } else {
+                format!("The verified role's color is {}", colors::hex(role.colour))
+            })
+            .color(role.colour)
+            .field("Name", &role.name, true)
+            .field("Color", colors::hex(role.colour), true)
+            .field(
+                "Shown Separately",
+                if role.hoist { "Yes" } else { "No" },
+                true,
+            )
+            .field(
+                "Mentionable",
+                if role.mentionable { "Yes" } else { "No" },
+                true,
+            );
+        if problems.is_empty() {
+            embed.description("Names in this color are readable on both light and dark themes.");
+        } else {
+            embed.description(format!(
+                "⚠️ Members may have trouble reading names in this color:\n{}",
+                problems.join("\n")
+            ));
+            if let Some(suggestion) = colors::suggest(role.colour) {
+                embed.field(
+                    "Suggestion",
+                    format!(
+                        "{} keeps the hue and is readable on both themes: \
+                         `/config role color:{}`",
+                        colors::hex(suggestion),
+                        colors::hex(suggestion)
+                    ),
+                    false,
+                );
+            }
+        }
+        embed
+    })
+    .await
+}
+
+/// Applies a change to the guild's config, saves it and records it in the audit ledger,
+/// returning the change's summary. Used by both `/config` and the dashboard.
+pub async fn update(
+    db_client: &db::DynamoDB,
+    guild_id: GuildId,
+    actor: UserId,
+    change: impl FnOnce(&mut GuildConfig) -> Option<String>,
+) -> Result<String, &'static str> {
+    let mut config = db_client.get_guild_config(guild_id).await;
+    let summary = change(&mut config).ok_or("Missing or invalid setting")?;
+    if !db_client.set_guild_config(guild_id, &config).await {
+        return Err("Failed to save the configuration, please try again");
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        actor,
+        "config.update",
+        None,
+        summary.clone(),
+    )
+    .await;
+    Ok(summary)
+}
+
+fn set_attest_approver(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.attest_approver_role = handlers::option_role(options, "role").map(|r| r.id);
+    Some(match config.attest_approver_role {
+        Some(role) => format!("Members with <@&{}> can now attest officer roles", role),
+        None => "Only administrators can now attest officer roles".to_string(),
+    })
+}
+
+fn set_alumni(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.alumni_role = handlers::option_role(options, "role").map(|r| r.id);
+    config.alumni_suffix = handlers::option_str(options, "suffix")
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    Some(format!(
+        "Former students now {} and {}",
+        match config.alumni_role {
+            Some(role) => format!("move to <@&{}>", role),
+            None => "lose the Student role".to_string(),
+        },
+        match &config.alumni_suffix {
+            Some(suffix) => format!("have their nickname end with \"{}\"", suffix),
+            None => "have no nickname decoration".to_string(),
+        }
+    ))
+}
+
+fn set_marker(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.marker = match handlers::option_str(options, "symbol").map(str::trim) {
+        Some(symbol) if symbol.is_empty() || symbol == DEFAULT_MARKER => None,
+        Some(symbol)
+            if symbol.chars().count() <= MAX_MARKER_CHARS
+                && !symbol.contains(|c: char| c.is_ascii()) =>
+        {
+            Some(symbol.to_string())
+        }
+        Some(_) => return None,
+        None => None,
+    };
+    Some(format!(
+        "Verified students' nicknames now end with {}{}",
+        config.marker_symbol(),
+        if config.native_marker {
+            " once the verified role has no icon"
+        } else {
+            ""
+        }
+    ))
+}
+
+fn set_nickname(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.nickname_template = match handlers::option_str(options, "template").map(str::trim) {
+        Some(template) if template.is_empty() || template == nickname_policy::DEFAULT_TEMPLATE => {
+            None
+        }
+        Some(template) if nickname_policy::is_valid(template) => Some(template.to_string()),
+        Some(_) => return None,
+        None => None,
+    };
+    let example = nickname_policy::render(
+        config.nickname_template(),
+        &nickname_policy::Values {
+            display_name: "Bevo",
+            real_name: Some("Bevo Longhorn"),
+            eid: Some("bl1883"),
+            marker: config.marker_symbol(),
+            decoration: config.active_decoration(response::unix_now()),
+        },
+    );
+    Some(format!(
+        "Verified members' nicknames now follow `{}`, e.g. `{}`",
+        config.nickname_template(),
+        example
+    ))
+}
+
+fn set_guest_role(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.guest_role = handlers::option_role(options, "role").map(|r| r.id);
+    Some(match config.guest_role {
+        Some(role) => format!("`/guest` now grants <@&{}>", role),
+        None => "`/guest` is now disabled".to_string(),
+    })
+}
+
+fn set_sheet(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.sheet_id = match handlers::option_str(options, "spreadsheet") {
+        Some(input) => Some(sheets::spreadsheet_id(input)?),
+        None => None,
+    };
+    Some(match (&config.sheet_id, sheets::service_account()) {
+        (Some(_), Some(account)) => format!(
+            "Verification events will be appended to the spreadsheet. Share it with `{}` as an editor.",
+            account.client_email
+        ),
+        (Some(_), None) => {
+            "Saved, but the bot has no Google service account configured, so nothing will be synced"
+                .to_string()
+        }
+        (None, _) => "Verification events are no longer synced to a spreadsheet".to_string(),
+    })
+}
+
+fn set_dues(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let role = match handlers::option_role(options, "role") {
+        Some(role) => role.id,
+        None => {
+            config.dues = None;
+            return Some("Dues are no longer tracked".to_string());
+        }
+    };
+    let url = handlers::option_str(options, "url")?.trim().to_string();
+    if !url.starts_with("https://") {
+        return None;
+    }
+    let source = match handlers::option_str(options, "kind") {
+        Some("api") => membership::Source::Api {
+            url,
+            token: handlers::option_str(options, "token").map(|t| t.trim().to_string()),
+        },
+        _ => membership::Source::Csv { url },
+    };
+    let summary = format!(
+        "Verified members on the roster from the {} get <@&{}>, checked every {} hours",
+        source.describe(),
+        role,
+        membership::SYNC_INTERVAL_SECS / 3600
+    );
+    config.dues = Some(DuesConfig { role, source });
+    Some(summary)
+}
+
+fn set_decoration(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let text = match handlers::option_str(options, "text") {
+        Some(text) => text.trim(),
+        None => {
+            config.decoration = None;
+            return Some("Decorations are removed from nicknames".to_string());
+        }
+    };
+    // nickname cleanup strips non-ASCII characters, which is what takes decorations off again
+    if text.is_empty() || text.chars().count() > 4 || text.chars().any(|c| c.is_ascii()) {
+        return None;
+    }
+    let hours = handlers::option_int(options, "hours")
+        .unwrap_or(DEFAULT_DECORATION_HOURS)
+        .clamp(1, MAX_DECORATION_HOURS);
+    let ends_at = response::unix_now() + hours * 60 * 60;
+    config.decoration = Some(Decoration {
+        text: text.to_string(),
+        ends_at,
+    });
+    Some(format!(
+        "Verified members get {} in their nickname until {}",
+        text,
+        response::timestamp(ends_at, response::TimestampStyle::ShortDateTime)
+    ))
+}
+
+fn set_milestones(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let channel = handlers::option_channel(options, "channel").map(|c| c.id);
+    if channel != config.milestone_channel {
+        // don't announce milestones the guild passed before enabling them
+        config.last_milestone = None;
+    }
+    config.milestone_channel = channel;
+    if let Some(template) = handlers::option_str(options, "message") {
+        config.milestone_template = Some(template.to_string()).filter(|t| !t.trim().is_empty());
+    }
+    Some(match channel {
+        Some(channel) => format!(
+            "Verified member milestones will be announced in <#{}>",
+            channel
+        ),
+        None => "Milestones are no longer announced".to_string(),
+    })
+}
+
+fn set_audit_channel(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.audit_channel = handlers::option_channel(options, "channel").map(|c| c.id);
+    Some(match config.audit_channel {
+        Some(channel) => format!("The bot's actions will be posted in <#{}>", channel),
+        None => "The bot's actions are no longer posted".to_string(),
+    })
+}
+
+fn set_public_stats(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.public_stats = handlers::option_bool(options, "enabled")?;
+    Some(if config.public_stats {
+        "The verified member count is now public".to_string()
+    } else {
+        "The verified member count is no longer public".to_string()
+    })
+}
+
+fn set_quarantine(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.quarantine = match handlers::option_str(options, "action")? {
+        "enable" => true,
+        "disable" => false,
+        _ => return None,
+    };
+    config.quarantine_channel = handlers::option_channel(options, "channel")
+        .map(|c| c.id)
+        .filter(|_| config.quarantine);
+    Some(match (config.quarantine, config.quarantine_channel) {
+        (true, Some(channel)) => format!(
+            "Unverified members get the {} role and only see <#{}>",
+            quarantine::ROLE_NAME,
+            channel
+        ),
+        (true, None) => format!("Unverified members get the {} role", quarantine::ROLE_NAME),
+        (false, _) => format!("The {} role is no longer used", quarantine::ROLE_NAME),
+    })
+}
+
+fn set_review(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let channel = match handlers::option_channel(options, "channel") {
+        Some(channel) => channel.id,
+        None => {
+            config.review = None;
+            return Some("Verifications are no longer held for review".to_string());
+        }
+    };
+    let review = ReviewConfig {
+        channel,
+        min_account_age_days: handlers::option_int(options, "account-days")
+            .unwrap_or(0)
+            .max(0) as u32,
+        name_mismatch: handlers::option_bool(options, "name-mismatch").unwrap_or(false),
+        // changing the rules doesn't hold members let through since review was enabled
+        since: config
+            .review
+            .as_ref()
+            .map_or_else(response::unix_now, |review| review.since),
+    };
+    let summary = review.describe();
+    config.review = Some(review);
+    Some(summary)
+}
+
+fn set_unrenamable(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.unrenamable = unrenamable::Policy::parse(handlers::option_str(options, "policy")?)?;
+    if config.unrenamable != unrenamable::Policy::Notify {
+        // switching back to notify asks everyone again
+        config.unrenamable_notified.clear();
+    }
+    Some(format!(
+        "Verified members the bot can't rename: {}",
+        config.unrenamable.describe().to_lowercase()
+    ))
+}
+
+fn set_onboarding(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let actions = &mut config.onboarding;
+    actions.dm = handlers::option_bool(options, "dm")?;
+    actions.fallback_channel = handlers::option_channel(options, "channel").map(|c| c.id);
+    if let Some(template) = handlers::option_str(options, "message") {
+        actions.template = Some(template.to_string()).filter(|t| !t.trim().is_empty());
+    }
+    if let Some(days) = handlers::option_str(options, "reminders") {
+        actions.reminder_days = onboarding::parse_reminder_days(days)?;
+    }
+    Some(format!(
+        "Unverified members who join will get: {}",
+        actions.describe().to_lowercase()
+    ))
+}
+
+fn set_success_actions(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let actions = &mut config.success_actions;
+    actions.ephemeral = handlers::option_bool(options, "ephemeral").unwrap_or(false);
+    actions.welcome_channel = handlers::option_channel(options, "channel").map(|c| c.id);
+    actions.dm = handlers::option_bool(options, "dm").unwrap_or(false);
+    if let Some(template) = handlers::option_str(options, "message") {
+        actions.template = Some(template.to_string()).filter(|t| !t.trim().is_empty());
+    }
+    Some(format!(
+        "Members who verify will see {}",
+        actions.describe()
+    ))
+}
+
+fn toggle_beta_command(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let name = handlers::option_str(options, "command")?;
+    if !commands::BETA_COMMANDS.contains(&name) {
+        return None;
+    }
+    Some(
+        if let Some(i) = config.beta_commands.iter().position(|c| c == name) {
+            config.beta_commands.remove(i);
+            format!("`/{}` is disabled in this server", name)
+        } else {
+            config.beta_commands.push(name.to_string());
+            format!(
+                "`/{}` is enabled in this server, it may take a moment to show up",
+                name
+            )
+        },
+    )
+}
+
+/// Whether a guild enabled a beta command, in case Discord still shows it after it was disabled
+pub async fn beta_enabled(db_client: &db::DynamoDB, guild_id: GuildId, name: &str) -> bool {
+    db_client
+        .get_guild_config(guild_id)
+        .await
+        .beta_commands
+        .iter()
+        .any(|c| c == name)
+}
+
+fn toggle_officer_role(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let role = handlers::option_role(options, "role")?.id;
+    Some(
+        if let Some(i) = config.officer_roles.iter().position(|r| *r == role) {
+            config.officer_roles.remove(i);
+            format!("<@&{}> can no longer be attested", role)
+        } else {
+            config.officer_roles.push(role);
+            format!("<@&{}> can now be attested", role)
+        },
+    )
+}
+
+fn set_locale(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    config.locale = Locale::parse(handlers::option_str(options, "language")?)?;
+    Some(format!(
+        "Members now get replies in {} while verifying",
+        config.locale.name()
+    ))
+}
+
+/// `/config selfrole add|remove role:<role>`
+fn set_self_roles(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let sub = options.first()?;
+    let role = handlers::option_role(&sub.options, "role")?;
+    let listed = config.self_roles.iter().position(|r| *r == role.id);
+    // members shouldn't be able to hand themselves moderation powers or the verified role
+    let privileged = role.permissions.administrator()
+        || role.permissions.manage_guild()
+        || role.permissions.manage_roles()
+        || role.permissions.manage_channels()
+        || role.permissions.kick_members()
+        || role.permissions.ban_members()
+        || role.permissions.manage_messages();
+    Some(match (sub.name.as_str(), listed) {
+        ("add", Some(_)) => format!("<@&{}> is already self-assignable", role.id),
+        ("add", None) if role.managed => format!(
+            "<@&{}> is managed by an integration and can't be assigned",
+            role.id
+        ),
+        ("add", None) if privileged => format!(
+            "<@&{}> grants moderation permissions, so it can't be self-assignable",
+            role.id
+        ),
+        ("add", None) if role.name == config.verified_role_name() => {
+            "The verified role can't be self-assignable".to_string()
+        }
+        ("add", None) if config.self_roles.len() >= MAX_SELF_ROLES => format!(
+            "There can be at most {} self-assignable roles",
+            MAX_SELF_ROLES
+        ),
+        ("add", None) => {
+            config.self_roles.push(role.id);
+            format!(
+                "Verified members can now give themselves <@&{}> with `/roles`",
+                role.id
+            )
+        }
+        ("remove", Some(i)) => {
+            config.self_roles.remove(i);
+            format!("<@&{}> is no longer self-assignable", role.id)
+        }
+        ("remove", None) => format!("<@&{}> wasn't self-assignable", role.id),
+        _ => return None,
+    })
+}
+
+fn set_verify_age(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    if let Some(days) = handlers::option_int(options, "account-days") {
+        config.min_account_age_days = days.max(0) as u32;
+    }
+    if let Some(days) = handlers::option_int(options, "member-days") {
+        config.min_membership_days = days.max(0) as u32;
+    }
+    Some(verify_age_summary(config))
+}
+
+fn toggle_voice_gate(
+    config: &mut GuildConfig,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> Option<String> {
+    let channel = handlers::option_channel(options, "channel")?;
+    if channel.kind != ChannelType::Voice && channel.kind != ChannelType::Stage {
+        return None;
+    }
+    Some(
+        if let Some(i) = config
+            .voice_gated_channels
+            .iter()
+            .position(|c| *c == channel.id)
+        {
+            config.voice_gated_channels.remove(i);
+            format!("Anyone can now join <#{}>", channel.id)
+        } else {
+            config.voice_gated_channels.push(channel.id);
+            format!("Only verified members can now join <#{}>", channel.id)
+        },
+    )
+}
+
+pub fn verify_age_summary(config: &GuildConfig) -> String {
+    format!(
+        "Verification now requires accounts at least {} days old and {} days of membership",
+        config.min_account_age_days, config.min_membership_days
+    )
+}
+
+async fn show(
+    command: &ApplicationCommandInteraction,
+    config: &GuildConfig,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let roles = |roles: &[RoleId]| {
+        if roles.is_empty() {
+            "None".to_string()
+        } else {
+            roles
+                .iter()
+                .map(|r| format!("<@&{}>", r))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    };
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Guild Configuration")
+            .color(Color::from_rgb(191, 87, 0))
+            .field(
+                "Attestation Approvers",
+                match config.attest_approver_role {
+                    Some(role) => format!("Administrators and <@&{}>", role),
+                    None => "Administrators".to_string(),
+                },
+                false,
+            )
+            .field("Officer Roles", roles(&config.officer_roles), false)
+            .field("Self-Assignable Roles", roles(&config.self_roles), false)
+            .field("Verified Role", config.verified_role_name(), false)
+            .field("Language", config.locale.name(), false)
+            .field(
+                "Quarantine",
+                match (config.quarantine_role, config.quarantine_channel) {
+                    (Some(role), Some(channel)) if config.quarantine => {
+                        format!("<@&{}>, only sees <#{}>", role, channel)
+                    }
+                    (Some(role), None) if config.quarantine => format!("<@&{}>", role),
+                    _ => "Disabled".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Verification Review",
+                match &config.review {
+                    Some(review) => review.describe(),
+                    None => "Off".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Alumni",
+                format!(
+                    "Role: {}, nickname suffix: {}",
+                    match config.alumni_role {
+                        Some(role) => format!("<@&{}>", role),
+                        None => "None".to_string(),
+                    },
+                    config.alumni_suffix.as_deref().unwrap_or("None")
+                ),
+                false,
+            )
+            .field(
+                "Spreadsheet",
+                match &config.sheet_id {
+                    Some(id) => format!("https://docs.google.com/spreadsheets/d/{}", id),
+                    None => "None".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Milestone Announcements",
+                match config.milestone_channel {
+                    Some(channel) => format!("<#{}>", channel),
+                    None => "None".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Audit Channel",
+                match config.audit_channel {
+                    Some(channel) => format!("<#{}>", channel),
+                    None => "None".to_string(),
+                },
+                false,
+            )
+            .field("On Join", config.onboarding.describe(), false)
+            .field("On Verification", config.success_actions.describe(), false)
+            .field(
+                "Members the Bot Can't Rename",
+                config.unrenamable.describe(),
+                false,
+            )
+            .field(
+                "Public Stats",
+                if config.public_stats { "On" } else { "Off" },
+                false,
+            )
+            .field(
+                "Verified Marker",
+                if config.native_marker {
+                    "The verified role's icon".to_string()
+                } else {
+                    format!("{} after nicknames", config.marker_symbol())
+                },
+                false,
+            )
+            .field(
+                "Nickname Template",
+                format!("`{}`", config.nickname_template()),
+                false,
+            )
+            .field(
+                "Dues",
+                match &config.dues {
+                    Some(dues) => format!("<@&{}> from the {}", dues.role, dues.source.describe()),
+                    None => "None".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Decoration",
+                match &config.decoration {
+                    Some(d) if d.ends_at > response::unix_now() => format!(
+                        "{} until {}",
+                        d.text,
+                        response::timestamp(d.ends_at, response::TimestampStyle::ShortDateTime)
+                    ),
+                    _ => "None".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Beta Commands",
+                if config.beta_commands.is_empty() {
+                    "None".to_string()
+                } else {
+                    config
+                        .beta_commands
+                        .iter()
+                        .map(|c| format!("`/{}`", c))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+                false,
+            )
+            .field(
+                "Guest Role",
+                match config.guest_role {
+                    Some(role) => format!("<@&{}>", role),
+                    None => "None".to_string(),
+                },
+                false,
+            )
+            .field(
+                "Verified-Only Voice Channels",
+                if config.voice_gated_channels.is_empty() {
+                    "None".to_string()
+                } else {
+                    config
+                        .voice_gated_channels
+                        .iter()
+                        .map(|c| format!("<#{}>", c))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                },
+                false,
+            )
+            .field(
+                "Verification Age Requirements",
+                format!(
+                    "Account: {} days, membership: {} days",
+                    config.min_account_age_days, config.min_membership_days
+                ),
+                false,
+            )
+    })
+    .await
+}
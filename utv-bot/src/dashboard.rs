@@ -0,0 +1,387 @@
+//! Web dashboard for guild admins.
+//!
+//! Admins log in with Discord OAuth. The session is a signed cookie holding the guilds the user
+//! administers, snapshotted at login and valid for an hour. Actions go through the same
+//! functions the slash commands use, so they are validated and audited the same way.
+
+use axum::extract::{Extension, Form, Path, Query};
+use axum::http::header::{COOKIE, SET_COOKIE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, Uri};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use rand::Rng;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+
+use crate::{config, http, response, settings, sharding, stats, PUBLIC_URL, SHARED_KEY};
+
+const SESSION_COOKIE: &str = "utv_session";
+const STATE_COOKIE: &str = "utv_oauth_state";
+const SESSION_SECS: i64 = 60 * 60;
+/// What session cookies are signed for, so no other signed value passes as one
+const SESSION_PURPOSE: &str = "dashboard-session";
+const API_URL: &str = "https://discord.com/api/v9";
+/// `ADMINISTRATOR` permission bit
+const ADMINISTRATOR: u64 = 1 << 3;
+/// how far back the activity section reaches
+const ACTIVITY_SECS: i64 = 7 * 24 * 60 * 60;
+const ACTIVITY_LIMIT: usize = 50;
+
+type Error = (StatusCode, &'static str);
+
+#[derive(Serialize, Deserialize)]
+struct Session {
+    user_id: u64,
+    /// guilds the user administers that the bot is in, as (id, name)
+    guilds: Vec<(u64, String)>,
+    expires_at: i64,
+}
+
+impl Session {
+    fn administers(&self, guild_id: u64) -> bool {
+        self.guilds.iter().any(|(id, _)| *id == guild_id)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Callback {
+    code: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthToken {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthUser {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthGuild {
+    id: String,
+    owner: bool,
+    permissions: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyAge {
+    account_days: u32,
+    member_days: u32,
+}
+
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn set_cookie(headers: &mut HeaderMap, name: &str, value: &str, max_age: i64) {
+    let cookie = format!(
+        "{}={}; Path=/dashboard; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        name, value, max_age
+    );
+    if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+        headers.append(SET_COOKIE, cookie);
+    }
+}
+
+fn session(headers: &HeaderMap) -> Option<Session> {
+    let session: Session = utv_token::verify(
+        SESSION_PURPOSE,
+        cookie(headers, SESSION_COOKIE)?,
+        &SHARED_KEY,
+    )
+    .ok()?;
+    if session.expires_at > response::unix_now() {
+        Some(session)
+    } else {
+        None
+    }
+}
+
+fn redirect_uri() -> String {
+    format!("{}/dashboard/callback", PUBLIC_URL.as_str())
+}
+
+pub fn redirect(to: &str) -> Result<Redirect, Error> {
+    to.parse::<Uri>()
+        .map(Redirect::to)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Invalid redirect."))
+}
+
+fn login_failed<E>(_: E) -> Error {
+    (StatusCode::BAD_GATEWAY, "Discord login failed.")
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A page styled like the rest of the bot's web pages
+pub fn page(title: &str, body: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{} - UT Verified</title>\
+         <style>body{{font-family:sans-serif;max-width:48em;margin:2em auto}}\
+         h1{{color:#bf5700}}td,th{{padding:.2em .8em;text-align:left}}</style></head>\
+         <body><h1>{}</h1>{}</body></html>",
+        escape(title),
+        escape(title),
+        body
+    ))
+}
+
+/// `GET /dashboard`: the guilds the user administers, or the Discord login
+pub async fn index(headers: HeaderMap) -> Result<Response, Error> {
+    if settings::client_secret().is_none() {
+        return Err((StatusCode::NOT_FOUND, "The dashboard is disabled."));
+    }
+    let session = match session(&headers) {
+        Some(session) => session,
+        None => return login(),
+    };
+    let guilds = session
+        .guilds
+        .iter()
+        .map(|(id, name)| {
+            format!(
+                "<li><a href=\"/dashboard/{}\">{}</a></li>",
+                id,
+                escape(name)
+            )
+        })
+        .collect::<String>();
+    let body = if guilds.is_empty() {
+        "<p>You don't administer any server the bot is in.</p>".to_string()
+    } else {
+        format!("<ul>{}</ul>", guilds)
+    };
+    Ok(page("Dashboard", &body).into_response())
+}
+
+fn login() -> Result<Response, Error> {
+    let state = format!("{:032x}", rand::thread_rng().gen::<u128>());
+    let url = Url::parse_with_params(
+        "https://discord.com/api/oauth2/authorize",
+        &[
+            (
+                "client_id",
+                settings::application_id().unwrap_or_default().to_string(),
+            ),
+            ("redirect_uri", redirect_uri()),
+            ("response_type", "code".to_string()),
+            ("scope", "identify guilds".to_string()),
+            ("state", state.clone()),
+        ],
+    )
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Invalid OAuth url."))?;
+    let mut headers = HeaderMap::new();
+    set_cookie(&mut headers, STATE_COOKIE, &state, 600);
+    Ok((headers, redirect(url.as_str())?).into_response())
+}
+
+/// `GET /dashboard/callback`: completes the Discord login
+pub async fn callback(
+    Query(callback): Query<Callback>,
+    headers: HeaderMap,
+    Extension(state): Extension<http::State>,
+) -> Result<Response, Error> {
+    let client_secret =
+        settings::client_secret().ok_or((StatusCode::NOT_FOUND, "The dashboard is disabled."))?;
+    if cookie(&headers, STATE_COOKIE) != Some(callback.state.as_str()) {
+        return Err((StatusCode::BAD_REQUEST, "Login expired, please try again."));
+    }
+    let client = reqwest::Client::new();
+    let token: OAuthToken = client
+        .post(format!("{}/oauth2/token", API_URL))
+        .form(&[
+            (
+                "client_id",
+                settings::application_id().unwrap_or_default().to_string(),
+            ),
+            ("client_secret", client_secret),
+            ("grant_type", "authorization_code".to_string()),
+            ("code", callback.code),
+            ("redirect_uri", redirect_uri()),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(login_failed)?
+        .json()
+        .await
+        .map_err(login_failed)?;
+    let user: OAuthUser = client
+        .get(format!("{}/users/@me", API_URL))
+        .bearer_auth(&token.access_token)
+        .send()
+        .await
+        .map_err(login_failed)?
+        .json()
+        .await
+        .map_err(login_failed)?;
+    let administered: Vec<OAuthGuild> = client
+        .get(format!("{}/users/@me/guilds", API_URL))
+        .bearer_auth(&token.access_token)
+        .send()
+        .await
+        .map_err(login_failed)?
+        .json()
+        .await
+        .map_err(login_failed)?;
+    let administered = administered
+        .into_iter()
+        .filter(|g| g.owner || g.permissions.parse::<u64>().unwrap_or(0) & ADMINISTRATOR != 0)
+        .filter_map(|g| g.id.parse::<u64>().ok())
+        .collect::<Vec<_>>();
+    let guilds = sharding::guild_infos(&state.http)
+        .await
+        .map_err(|_| (StatusCode::BAD_GATEWAY, "Cannot list the bot's servers."))?
+        .into_iter()
+        .filter(|g| administered.contains(&g.id.0))
+        .map(|g| (g.id.0, g.name))
+        .collect();
+
+    let session = Session {
+        user_id: user.id.parse().map_err(login_failed)?,
+        guilds,
+        expires_at: response::unix_now() + SESSION_SECS,
+    };
+    let mut headers = HeaderMap::new();
+    set_cookie(
+        &mut headers,
+        SESSION_COOKIE,
+        &utv_token::sign(SESSION_PURPOSE, &session, &SHARED_KEY),
+        SESSION_SECS,
+    );
+    set_cookie(&mut headers, STATE_COOKIE, "", 0);
+    Ok((headers, redirect("/dashboard")?).into_response())
+}
+
+/// `GET /dashboard/:guild_id`: stats, configuration and recent activity of a guild
+pub async fn guild(
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+    Extension(state): Extension<http::State>,
+) -> Result<Html<String>, Error> {
+    let session = session(&headers).ok_or((StatusCode::UNAUTHORIZED, "Please log in again."))?;
+    if !session.administers(guild_id) {
+        return Err((StatusCode::FORBIDDEN, "You don't administer this server."));
+    }
+    let name = session
+        .guilds
+        .iter()
+        .find(|(id, _)| *id == guild_id)
+        .map(|(_, name)| name.as_str())
+        .unwrap_or_default();
+    let guild_id = GuildId(guild_id);
+    let db_client = state.db_client;
+
+    let stats = stats::guild_stats(db_client, &state.http, guild_id)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::BAD_GATEWAY,
+                "Cannot fetch the server's members.",
+            )
+        })?;
+    let mappings = db_client.get_role_config(guild_id).await;
+    let config = db_client.get_guild_config(guild_id).await;
+    let activity = db_client
+        .get_audit(guild_id, response::unix_now() - ACTIVITY_SECS)
+        .await
+        .into_iter()
+        .rev()
+        .take(ACTIVITY_LIMIT)
+        .map(|e| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td><code>{}</code></td><td>{}</td></tr>",
+                chrono::NaiveDateTime::from_timestamp(e.at, 0).format("%Y-%m-%d %H:%M UTC"),
+                e.actor,
+                escape(&e.action),
+                escape(&e.detail)
+            )
+        })
+        .collect::<String>();
+
+    let body = format!(
+        "<p><a href=\"/dashboard\">All servers</a></p>\
+         <h2>Stats</h2><table>\
+         <tr><th>Members</th><td>{members}</td></tr>\
+         <tr><th>Verified</th><td>{verified}</td></tr>\
+         <tr><th>Mapped roles</th><td>{mapped}</td></tr></table>\
+         <h2>Configuration</h2><table>\
+         <tr><th>Attestation approver role</th><td>{approver}</td></tr>\
+         <tr><th>Officer roles</th><td>{officers}</td></tr></table>\
+         <form method=\"post\" action=\"/dashboard/{guild_id}/verify-age\">\
+         <p>Accounts must be <input type=\"number\" min=\"0\" name=\"account_days\" value=\"{account}\"> \
+         days old and members must have joined <input type=\"number\" min=\"0\" name=\"member_days\" \
+         value=\"{membership}\"> days ago to verify. <button>Save</button></p></form>\
+         <h2>Recent activity</h2>{activity}",
+        members = stats.members,
+        verified = stats.verified,
+        mapped = mappings.len(),
+        approver = config
+            .attest_approver_role
+            .map_or("None".to_string(), |r| r.to_string()),
+        officers = if config.officer_roles.is_empty() {
+            "None".to_string()
+        } else {
+            config
+                .officer_roles
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        },
+        guild_id = guild_id,
+        account = config.min_account_age_days,
+        membership = config.min_membership_days,
+        activity = if activity.is_empty() {
+            "<p>None in the last week.</p>".to_string()
+        } else {
+            format!(
+                "<table><tr><th>When</th><th>Actor</th><th>Action</th><th>Detail</th></tr>{}</table>",
+                activity
+            )
+        },
+    );
+    Ok(page(name, &body))
+}
+
+/// `POST /dashboard/:guild_id/verify-age`, the dashboard's `/config verify-age`
+pub async fn set_verify_age(
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+    Extension(state): Extension<http::State>,
+    Form(form): Form<VerifyAge>,
+) -> Result<Redirect, Error> {
+    // the session cookie is SameSite=Lax, so it isn't sent with cross-site form posts
+    let session = session(&headers).ok_or((StatusCode::UNAUTHORIZED, "Please log in again."))?;
+    if !session.administers(guild_id) {
+        return Err((StatusCode::FORBIDDEN, "You don't administer this server."));
+    }
+    config::update(
+        state.db_client,
+        GuildId(guild_id),
+        UserId(session.user_id),
+        |config| {
+            config.min_account_age_days = form.account_days;
+            config.min_membership_days = form.member_days;
+            Some(config::verify_age_summary(config))
+        },
+    )
+    .await
+    .map_err(|why| (StatusCode::INTERNAL_SERVER_ERROR, why))?;
+    redirect(&format!("/dashboard/{}", guild_id))
+}
@@ -1,14 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Instant;
 
-use aws_sdk_dynamodb::{model::AttributeValue, Client};
-use serde::Deserialize;
-use serenity::model::id::{GuildId, RoleId};
+use aws_sdk_dynamodb::model::{
+    AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, ReturnValue, WriteRequest,
+};
+use aws_sdk_dynamodb::{Client, SdkError};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use tracing::error;
 
-#[derive(Deserialize, Debug)]
+use crate::config::GuildConfig;
+use crate::{migrations, response, settings, telemetry};
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Claims {
     pub major: Vec<String>,
     pub school: Vec<String>,
     pub affiliation: Vec<String>,
+    /// display name from the directory, unset for members verified before it was stored
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -17,30 +31,364 @@ pub struct UserData {
     pub claims: Claims,
 }
 
+/// An in-person verification event created with `/event-qr`
+#[derive(Debug)]
+pub struct Event {
+    pub event_id: String,
+    pub guild_id: GuildId,
+    pub name: String,
+    pub cap: u64,
+    pub created_at: i64,
+    pub expires_at: i64,
+    /// unix timestamps of every accepted redemption
+    pub redeemed_at: Vec<i64>,
+}
+
+/// A check-in created with `/checkin create`
+#[derive(Debug)]
+pub struct Checkin {
+    pub checkin_id: String,
+    pub guild_id: GuildId,
+    pub name: String,
+    pub created_at: i64,
+    pub attendees: Vec<Attendance>,
+}
+
+#[derive(Debug)]
+pub struct Attendance {
+    pub eid_hash: String,
+    pub discord_id: UserId,
+    pub checked_in_at: i64,
+}
+
+pub enum CheckinResult {
+    Recorded,
+    AlreadyCheckedIn,
+    Failed,
+}
+
+/// An entry in the per-guild audit ledger
+#[derive(Debug)]
+pub struct AuditEntry {
+    pub guild_id: GuildId,
+    pub at: i64,
+    /// the user who caused the action, or the bot itself
+    pub actor: UserId,
+    /// dotted action name, e.g. `attest.grant`
+    pub action: String,
+    pub target: Option<UserId>,
+    pub detail: String,
+}
+
+/// A designated approver's attestation that a member holds an officer role for a term
+#[derive(Debug)]
+pub struct Attestation {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub role_id: RoleId,
+    pub term: String,
+    pub expires_at: i64,
+    pub attested_by: UserId,
+    pub attested_at: i64,
+}
+
+/// A key for the HTTP API, only valid for users in its guilds
+#[derive(Debug)]
+pub struct ApiKey {
+    /// stable identifier, kept when the key is rotated
+    pub key_id: String,
+    pub key_hash: String,
+    pub label: String,
+    pub guild_ids: Vec<GuildId>,
+    /// endpoints the key may call, all of them when empty
+    pub endpoints: Vec<String>,
+    pub created_at: i64,
+}
+
+/// A task the scheduler runs once `due_at` has passed
+#[derive(Debug)]
+pub struct ScheduledTask {
+    pub task_id: String,
+    pub due_at: i64,
+    /// JSON of `scheduler::Task`
+    pub task: String,
+}
+
+/// Outcome of claiming an EID for an account
+#[derive(Clone, Copy)]
+pub enum EidClaim {
+    New,
+    /// the account already held it
+    Held,
+    /// another account holds it
+    Taken,
+    Failed,
+}
+
+/// Outcome of recording that an account redeemed a token
+pub enum TokenUse {
+    /// first redeemed now, or earlier by the same account
+    Unused,
+    UsedByOther,
+    Failed,
+}
+
+pub enum LinkResult {
+    Linked,
+    AlreadyLinked,
+    /// the EID is claimed by another account
+    EidInUse,
+    Failed,
+}
+
+/// A user's record, see `get_user_record`
+pub struct UserRecord {
+    pub encrypted_eid: Option<String>,
+    pub claims: Option<Claims>,
+    pub verified_at: Option<i64>,
+    pub departed_at: Option<i64>,
+    pub expired_at: Option<i64>,
+}
+
+pub enum UnlinkResult {
+    Unlinked,
+    NotLinked,
+    Failed,
+}
+
+/// A member's roles and nickname before a bulk job changed them, for `/admin rollback`
+#[derive(Debug)]
+pub struct MemberSnapshot {
+    pub job_id: String,
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub roles: Vec<RoleId>,
+    pub nick: Option<String>,
+    /// unix timestamp after which DynamoDB may delete the snapshot
+    pub expires_at: i64,
+}
+
+/// When a user first reached each stage of verification in a guild
+#[derive(Debug)]
+pub struct FunnelEntry {
+    pub user_id: UserId,
+    /// stage name to unix timestamp
+    pub stages: HashMap<String, i64>,
+}
+
+/// A posted message with buttons or menus, tracked so stale components can be disabled
+#[derive(Debug)]
+pub struct TrackedMessage {
+    pub message_id: MessageId,
+    pub channel_id: ChannelId,
+    pub guild_id: GuildId,
+    pub posted_at: i64,
+    /// when the components stop being useful, tracked until their handler goes away otherwise
+    pub expires_at: Option<i64>,
+}
+
+/// A verification held for the guild's moderators by `review`
+#[derive(Debug)]
+pub struct Review {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    /// `eid_hash` of the EID the member verified with
+    pub eid_hash: String,
+    /// "pending", "approved" or "denied"
+    pub decision: String,
+    /// the rules the verification matched, empty when it matched none
+    pub reasons: Vec<String>,
+    pub requested_at: i64,
+    /// the moderator who decided, unset for pending reviews and ones that matched no rule
+    pub decided_by: Option<UserId>,
+}
+
+/// A guild approved with `/tenant add`, see `tenancy`
+#[derive(Debug)]
+pub struct Tenant {
+    pub guild_id: GuildId,
+    pub added_by: UserId,
+    pub added_at: i64,
+}
+
+/// A member's or guild's budget of verification emails, see `throttle`
+#[derive(Clone, Default, Debug)]
+pub struct RateLimit {
+    pub tokens: f64,
+    pub updated_at: i64,
+    /// times the member ran out within a day of the last time
+    pub strikes: u32,
+    pub struck_at: i64,
+    pub blocked_until: i64,
+    /// when moderators were last alerted about the member
+    pub alerted_at: i64,
+}
+
+/// Tables read as stored, by `utv-bot fsck` and backups
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Table {
+    Users,
+    Guilds,
+    Audit,
+    Attestations,
+    Scheduled,
+    Eids,
+    Events,
+    Checkins,
+    ApiKeys,
+    Notes,
+    Memberships,
+    Reviews,
+    Bans,
+    Meta,
+    Tenants,
+}
+
+impl Table {
+    /// Tables a backup holds. The others only hold short-lived or derived data: token nonces,
+    /// DM sessions, role snapshots, tracked components, the onboarding funnel and rate limits.
+    pub const BACKED_UP: [Table; 15] = [
+        Table::Users,
+        Table::Guilds,
+        Table::Audit,
+        Table::Attestations,
+        Table::Scheduled,
+        Table::Eids,
+        Table::Events,
+        Table::Checkins,
+        Table::ApiKeys,
+        Table::Notes,
+        Table::Memberships,
+        Table::Reviews,
+        Table::Bans,
+        Table::Meta,
+        Table::Tenants,
+    ];
+
+    /// Attributes making up the table's primary key
+    pub fn key_names(self) -> &'static [&'static str] {
+        match self {
+            Table::Users => &["discord_id"],
+            Table::Guilds => &["guild_id"],
+            Table::Audit => &["guild_id", "entry_id"],
+            Table::Attestations => &["guild_id", "attestation_id"],
+            Table::Scheduled => &["task_id"],
+            Table::Eids => &["encrypted_eid"],
+            Table::Events => &["event_id"],
+            Table::Checkins => &["checkin_id"],
+            Table::ApiKeys => &["key_hash"],
+            Table::Notes => &["guild_id", "note_id"],
+            Table::Memberships => &["discord_id", "guild_id"],
+            Table::Reviews => &["guild_id", "user_id"],
+            Table::Bans => &["eid_hash", "guild_id"],
+            Table::Meta => &["name"],
+            Table::Tenants => &["guild_id"],
+        }
+    }
+
+    /// The table's name without `TABLE_PREFIX`
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Users => "users",
+            Table::Guilds => "guilds",
+            Table::Audit => "audit",
+            Table::Attestations => "attestations",
+            Table::Scheduled => "scheduled",
+            Table::Eids => "eids",
+            Table::Events => "events",
+            Table::Checkins => "checkins",
+            Table::ApiKeys => "api_keys",
+            Table::Notes => "notes",
+            Table::Memberships => "memberships",
+            Table::Reviews => "reviews",
+            Table::Bans => "bans",
+            Table::Meta => "meta",
+            Table::Tenants => "tenants",
+        }
+    }
+}
+
+/// Global secondary index of the users table keyed by `encrypted_eid`
+const ENCRYPTED_EID_INDEX: &str = "encrypted_eid-index";
+
+pub type Item = HashMap<String, AttributeValue>;
+
+/// A moderator's note about a member, from `/note add`
+#[derive(Debug)]
+pub struct Note {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub author: UserId,
+    pub at: i64,
+    pub text: String,
+}
+
 pub struct DynamoDB {
     client: Client,
     users_table_name: String,
     guilds_table_name: String,
+    events_table_name: String,
+    checkins_table_name: String,
+    audit_table_name: String,
+    attestations_table_name: String,
+    api_keys_table_name: String,
+    scheduled_table_name: String,
+    snapshots_table_name: String,
+    funnel_table_name: String,
+    components_table_name: String,
+    notes_table_name: String,
+    eids_table_name: String,
+    token_nonces_table_name: String,
+    dm_sessions_table_name: String,
+    memberships_table_name: String,
+    reviews_table_name: String,
+    bans_table_name: String,
+    meta_table_name: String,
+    tenants_table_name: String,
+    rate_limits_table_name: String,
 }
 
 impl DynamoDB {
     pub async fn new(table_name: &str) -> Self {
         let shared_config = aws_config::load_from_env().await;
         let client = Client::new(&shared_config);
+        let prefix = settings::table_prefix();
+        let table = |name: &str| format!("{}{}", prefix, name);
         Self {
             client,
-            users_table_name: table_name.to_string(),
-            guilds_table_name: "guilds".to_string(),
+            users_table_name: table(table_name),
+            guilds_table_name: table("guilds"),
+            events_table_name: table("events"),
+            checkins_table_name: table("checkins"),
+            audit_table_name: table("audit"),
+            attestations_table_name: table("attestations"),
+            api_keys_table_name: table("api_keys"),
+            scheduled_table_name: table("scheduled"),
+            snapshots_table_name: table("snapshots"),
+            funnel_table_name: table("funnel"),
+            components_table_name: table("components"),
+            notes_table_name: table("notes"),
+            eids_table_name: table("eids"),
+            token_nonces_table_name: table("token_nonces"),
+            dm_sessions_table_name: table("dm_sessions"),
+            memberships_table_name: table("memberships"),
+            reviews_table_name: table("reviews"),
+            bans_table_name: table("bans"),
+            meta_table_name: table("meta"),
+            tenants_table_name: table("tenants"),
+            rate_limits_table_name: table("rate_limits"),
         }
     }
 
     /// Gets claims data under the condition that DB access succeeds and it exists
     pub async fn get_user(&self, discord_id: u64) -> Option<Claims> {
-        self.client
+        let request = self
+            .client
             .get_item()
             .table_name(self.users_table_name.as_str())
             .key("discord_id", AttributeValue::S(discord_id.to_string()))
-            .send()
+            .send();
+        timed("get_user", request)
             .await
             .ok()
             .map(|o| o.item().cloned())
@@ -50,20 +398,59 @@ impl DynamoDB {
             .map(|v| match v {
                 Some(AttributeValue::S(s)) => serde_json::from_str(s.as_str()).ok(),
                 _ => {
-                    eprintln!("Failed to Get Claims Data on Discord ID: {}", discord_id);
+                    error!("Failed to Get Claims Data on Discord ID: {}", discord_id);
                     None
                 }
             })
             .flatten()
     }
 
+    /// Everything stored about a user, whether or not they're verified
+    pub async fn get_user_record(&self, discord_id: UserId) -> Option<UserRecord> {
+        let item = timed(
+            "get_user_record",
+            self.client
+                .get_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        Some(user_record_from_item(&item))
+    }
+
+    /// The base64 encoded, deterministically encrypted EID of a verified user
+    pub async fn get_encrypted_eid(&self, discord_id: u64) -> Option<String> {
+        timed(
+            "get_encrypted_eid",
+            self.client
+                .get_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item
+        .and_then(|item| attr_string(&item, "encrypted_eid"))
+    }
+
+    /// Whether the user has linked an EID, regardless of whether their claims are filled in
+    pub async fn is_verified(&self, discord_id: u64) -> bool {
+        self.get_encrypted_eid(discord_id).await.is_some()
+    }
+
     /// Maps majors/affiliation to a role
     pub async fn get_role_config(&self, guild_id: GuildId) -> HashMap<String, u64> {
-        self.client
+        let request = self
+            .client
             .get_item()
             .table_name(self.guilds_table_name.as_str())
             .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
-            .send()
+            .send();
+        timed("get_role_config", request)
             .await
             .ok()
             .map(|o| o.item().cloned())
@@ -78,7 +465,7 @@ impl DynamoDB {
                             serde_json::from_str(data).unwrap_or(HashMap::new())
                         }
                         _ => {
-                            eprintln!("{} does not exist in guild {}'s attributes", key, guild_id);
+                            error!("{} does not exist in guild {}'s attributes", key, guild_id);
                             HashMap::new()
                         }
                     };
@@ -88,11 +475,2373 @@ impl DynamoDB {
             })
             .unwrap_or(HashMap::new())
     }
+
+    /// Points every role mapping that uses `from` at `to` instead, or removes them without `to`
+    pub async fn replace_mapped_role(
+        &self,
+        guild_id: GuildId,
+        from: RoleId,
+        to: Option<RoleId>,
+    ) -> bool {
+        let item = match timed(
+            "replace_mapped_role",
+            self.client
+                .get_item()
+                .table_name(self.guilds_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .send(),
+        )
+        .await
+        {
+            Ok(out) => out.item.unwrap_or_default(),
+            Err(_) => return false,
+        };
+        let mut ok = true;
+        for key in ["affiliation_roles", "school_roles", "major_roles"] {
+            let mut mappings: HashMap<String, u64> = match item.get(key) {
+                Some(AttributeValue::S(data)) => serde_json::from_str(data).unwrap_or_default(),
+                _ => continue,
+            };
+            if !mappings.values().any(|r| *r == from.0) {
+                continue;
+            }
+            match to {
+                Some(to) => {
+                    for role in mappings.values_mut() {
+                        if *role == from.0 {
+                            *role = to.0;
+                        }
+                    }
+                }
+                None => mappings.retain(|_, role| *role != from.0),
+            }
+            ok &= timed(
+                "replace_mapped_role",
+                self.client
+                    .update_item()
+                    .table_name(self.guilds_table_name.as_str())
+                    .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                    .update_expression("SET #mappings = :mappings")
+                    .expression_attribute_names("#mappings", key)
+                    .expression_attribute_values(
+                        ":mappings",
+                        AttributeValue::S(serde_json::to_string(&mappings).unwrap()),
+                    )
+                    .send(),
+            )
+            .await
+            .is_ok();
+        }
+        ok
+    }
+
+    /// Maps an affiliation to a role, or removes its mapping
+    pub async fn set_affiliation_role(
+        &self,
+        guild_id: GuildId,
+        affiliation: &str,
+        role: Option<RoleId>,
+    ) -> bool {
+        let item = match timed(
+            "set_affiliation_role",
+            self.client
+                .get_item()
+                .table_name(self.guilds_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .send(),
+        )
+        .await
+        {
+            Ok(out) => out.item.unwrap_or_default(),
+            Err(_) => return false,
+        };
+        let mut mappings: HashMap<String, u64> = match item.get("affiliation_roles") {
+            Some(AttributeValue::S(data)) => serde_json::from_str(data).unwrap_or_default(),
+            _ => HashMap::new(),
+        };
+        match role {
+            Some(role) => mappings.insert(affiliation.to_string(), role.0),
+            None => mappings.remove(affiliation),
+        };
+        let mappings = match serde_json::to_string(&mappings) {
+            Ok(mappings) => mappings,
+            Err(_) => return false,
+        };
+        timed(
+            "set_affiliation_role",
+            self.client
+                .update_item()
+                .table_name(self.guilds_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .update_expression("SET affiliation_roles = :mappings")
+                .expression_attribute_values(":mappings", AttributeValue::S(mappings))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn create_event(&self, event: &Event) -> bool {
+        timed(
+            "create_event",
+            self.client
+                .put_item()
+                .table_name(self.events_table_name.as_str())
+                .item("event_id", AttributeValue::S(event.event_id.clone()))
+                .item("guild_id", AttributeValue::S(event.guild_id.0.to_string()))
+                .item("name", AttributeValue::S(event.name.clone()))
+                .item("cap", AttributeValue::N(event.cap.to_string()))
+                .item(
+                    "created_at",
+                    AttributeValue::N(event.created_at.to_string()),
+                )
+                .item(
+                    "expires_at",
+                    AttributeValue::N(event.expires_at.to_string()),
+                )
+                .item("redeemed_at", AttributeValue::L(Vec::new()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn get_event(&self, event_id: &str) -> Option<Event> {
+        let item = timed(
+            "get_event",
+            self.client
+                .get_item()
+                .table_name(self.events_table_name.as_str())
+                .key("event_id", AttributeValue::S(event_id.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        Some(Event {
+            event_id: event_id.to_string(),
+            guild_id: GuildId(attr_number(&item, "guild_id")?),
+            name: attr_string(&item, "name")?,
+            cap: attr_number(&item, "cap")?,
+            created_at: attr_number(&item, "created_at")?,
+            expires_at: attr_number(&item, "expires_at")?,
+            redeemed_at: match item.get("redeemed_at") {
+                Some(AttributeValue::L(list)) => list
+                    .iter()
+                    .filter_map(|v| match v {
+                        AttributeValue::N(n) => n.parse().ok(),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            },
+        })
+    }
+
+    /// Atomically records a redemption, failing once the event is expired or at its cap
+    pub async fn redeem_event(&self, event_id: &str, now: i64) -> bool {
+        timed(
+            "redeem_event",
+            self.client
+                .update_item()
+                .table_name(self.events_table_name.as_str())
+                .key("event_id", AttributeValue::S(event_id.to_string()))
+                .update_expression("SET redeemed_at = list_append(redeemed_at, :redemption)")
+                .condition_expression("size(redeemed_at) < cap AND expires_at > :now")
+                .expression_attribute_values(
+                    ":redemption",
+                    AttributeValue::L(vec![AttributeValue::N(now.to_string())]),
+                )
+                .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn create_checkin(&self, checkin: &Checkin) -> bool {
+        timed(
+            "create_checkin",
+            self.client
+                .put_item()
+                .table_name(self.checkins_table_name.as_str())
+                .item("checkin_id", AttributeValue::S(checkin.checkin_id.clone()))
+                .item(
+                    "guild_id",
+                    AttributeValue::S(checkin.guild_id.0.to_string()),
+                )
+                .item("name", AttributeValue::S(checkin.name.clone()))
+                .item(
+                    "created_at",
+                    AttributeValue::N(checkin.created_at.to_string()),
+                )
+                .item("attendees", AttributeValue::M(HashMap::new()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn get_checkin(&self, checkin_id: &str) -> Option<Checkin> {
+        let item = timed(
+            "get_checkin",
+            self.client
+                .get_item()
+                .table_name(self.checkins_table_name.as_str())
+                .key("checkin_id", AttributeValue::S(checkin_id.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        let attendees = match item.get("attendees") {
+            Some(AttributeValue::M(attendees)) => attendees
+                .iter()
+                .filter_map(|(eid_hash, entry)| match entry {
+                    AttributeValue::M(entry) => Some(Attendance {
+                        eid_hash: eid_hash.clone(),
+                        discord_id: UserId(attr_number(entry, "discord_id")?),
+                        checked_in_at: attr_number(entry, "checked_in_at")?,
+                    }),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Some(Checkin {
+            checkin_id: checkin_id.to_string(),
+            guild_id: GuildId(attr_number(&item, "guild_id")?),
+            name: attr_string(&item, "name")?,
+            created_at: attr_number(&item, "created_at")?,
+            attendees,
+        })
+    }
+
+    /// Records attendance once per EID, no matter how many accounts it is linked to
+    pub async fn record_attendance(
+        &self,
+        checkin_id: &str,
+        eid_hash: &str,
+        discord_id: UserId,
+        now: i64,
+    ) -> CheckinResult {
+        let entry = HashMap::from([
+            (
+                "discord_id".to_string(),
+                AttributeValue::S(discord_id.0.to_string()),
+            ),
+            (
+                "checked_in_at".to_string(),
+                AttributeValue::N(now.to_string()),
+            ),
+        ]);
+        let res = timed(
+            "record_attendance",
+            self.client
+                .update_item()
+                .table_name(self.checkins_table_name.as_str())
+                .key("checkin_id", AttributeValue::S(checkin_id.to_string()))
+                .update_expression("SET attendees.#eid = :entry")
+                .condition_expression(
+                    "attribute_exists(checkin_id) AND attribute_not_exists(attendees.#eid)",
+                )
+                .expression_attribute_names("#eid", eid_hash)
+                .expression_attribute_values(":entry", AttributeValue::M(entry))
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(_) => CheckinResult::Recorded,
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                CheckinResult::AlreadyCheckedIn
+            }
+            Err(e) => {
+                error!("Failed to record attendance for {}: {}", checkin_id, e);
+                CheckinResult::Failed
+            }
+        }
+    }
+
+    pub async fn get_guild_config(&self, guild_id: GuildId) -> GuildConfig {
+        let request = self
+            .client
+            .get_item()
+            .table_name(self.guilds_table_name.as_str())
+            .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+            .send();
+        timed("get_guild_config", request)
+            .await
+            .ok()
+            .and_then(|o| o.item)
+            .and_then(|item| attr_string(&item, "config"))
+            .and_then(|config| serde_json::from_str(&config).ok())
+            .unwrap_or_default()
+    }
+
+    pub async fn set_guild_config(&self, guild_id: GuildId, config: &GuildConfig) -> bool {
+        let config = match serde_json::to_string(config) {
+            Ok(config) => config,
+            Err(_) => return false,
+        };
+        timed(
+            "set_guild_config",
+            self.client
+                .update_item()
+                .table_name(self.guilds_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .update_expression("SET config = :config")
+                .expression_attribute_values(":config", AttributeValue::S(config))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn append_audit(&self, entry: &AuditEntry) -> bool {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(self.audit_table_name.as_str())
+            .item("guild_id", AttributeValue::S(entry.guild_id.0.to_string()))
+            // zero padded so entries sort chronologically
+            .item(
+                "entry_id",
+                AttributeValue::S(format!("{:012}-{:08x}", entry.at, rand::random::<u32>())),
+            )
+            .item("at", AttributeValue::N(entry.at.to_string()))
+            .item("actor", AttributeValue::S(entry.actor.0.to_string()))
+            .item("action", AttributeValue::S(entry.action.clone()))
+            .item("detail", AttributeValue::S(entry.detail.clone()));
+        if let Some(target) = entry.target {
+            request = request.item("target", AttributeValue::S(target.0.to_string()));
+        }
+        timed("append_audit", request.send()).await.is_ok()
+    }
+
+    /// Audit entries of a guild at or after `since`, oldest first
+    pub async fn get_audit(&self, guild_id: GuildId, since: i64) -> Vec<AuditEntry> {
+        self.query_items(
+            self.audit_table_name.as_str(),
+            "guild_id = :guild_id AND entry_id >= :since",
+            vec![
+                (":guild_id", AttributeValue::S(guild_id.0.to_string())),
+                (":since", AttributeValue::S(format!("{:012}", since.max(0)))),
+            ],
+        )
+        .await
+        .iter()
+        .filter_map(|item| {
+            Some(AuditEntry {
+                guild_id,
+                at: attr_number(item, "at")?,
+                actor: UserId(attr_number(item, "actor")?),
+                action: attr_string(item, "action")?,
+                target: attr_number(item, "target").map(UserId),
+                detail: attr_string(item, "detail").unwrap_or_default(),
+            })
+        })
+        .collect()
+    }
+
+    pub async fn put_attestation(&self, attestation: &Attestation) -> bool {
+        timed(
+            "put_attestation",
+            self.client
+                .put_item()
+                .table_name(self.attestations_table_name.as_str())
+                .item(
+                    "guild_id",
+                    AttributeValue::S(attestation.guild_id.0.to_string()),
+                )
+                .item(
+                    "attestation_id",
+                    AttributeValue::S(format!("{}:{}", attestation.user_id, attestation.role_id)),
+                )
+                .item("term", AttributeValue::S(attestation.term.clone()))
+                .item(
+                    "expires_at",
+                    AttributeValue::N(attestation.expires_at.to_string()),
+                )
+                .item(
+                    "attested_by",
+                    AttributeValue::S(attestation.attested_by.0.to_string()),
+                )
+                .item(
+                    "attested_at",
+                    AttributeValue::N(attestation.attested_at.to_string()),
+                )
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn get_attestations(&self, guild_id: GuildId, user_id: UserId) -> Vec<Attestation> {
+        self.query_items(
+            self.attestations_table_name.as_str(),
+            "guild_id = :guild_id AND begins_with(attestation_id, :user_id)",
+            vec![
+                (":guild_id", AttributeValue::S(guild_id.0.to_string())),
+                (":user_id", AttributeValue::S(format!("{}:", user_id))),
+            ],
+        )
+        .await
+        .iter()
+        .filter_map(attestation_from_item)
+        .collect()
+    }
+
+    /// Attestations across all guilds whose term has ended
+    pub async fn expired_attestations(&self, now: i64) -> Vec<Attestation> {
+        self.scan_items(
+            self.attestations_table_name.as_str(),
+            "expires_at <= :now",
+            vec![(":now", AttributeValue::N(now.to_string()))],
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(attestation_from_item)
+        .collect()
+    }
+
+    pub async fn delete_attestation(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    ) -> bool {
+        timed(
+            "delete_attestation",
+            self.client
+                .delete_item()
+                .table_name(self.attestations_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .key(
+                    "attestation_id",
+                    AttributeValue::S(format!("{}:{}", user_id, role_id)),
+                )
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Runs a query, following pagination until every matching item is read
+    async fn query_items(
+        &self,
+        table_name: &str,
+        key_condition: &str,
+        values: Vec<(&str, AttributeValue)>,
+    ) -> Vec<HashMap<String, AttributeValue>> {
+        let mut items = Vec::new();
+        let mut start_key = None;
+        loop {
+            let mut request = self
+                .client
+                .query()
+                .table_name(table_name)
+                .key_condition_expression(key_condition)
+                .set_exclusive_start_key(start_key);
+            for (name, value) in &values {
+                request = request.expression_attribute_values(*name, value.clone());
+            }
+            match timed("query", request.send()).await {
+                Ok(out) => {
+                    items.extend(out.items.unwrap_or_default());
+                    start_key = out.last_evaluated_key;
+                    if start_key.is_none() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to query {}: {}", table_name, e);
+                    break;
+                }
+            }
+        }
+        items
+    }
+
+    /// Scans a whole table, following pagination and keeping items matching `filter`. Fails if
+    /// any page can't be read, rather than returning part of the table.
+    async fn scan_items(
+        &self,
+        table_name: &str,
+        filter: &str,
+        values: Vec<(&str, AttributeValue)>,
+    ) -> Result<Vec<HashMap<String, AttributeValue>>, String> {
+        let mut items = Vec::new();
+        let mut start_key = None;
+        loop {
+            let mut request = self
+                .client
+                .scan()
+                .table_name(table_name)
+                .filter_expression(filter)
+                .set_exclusive_start_key(start_key);
+            for (name, value) in &values {
+                request = request.expression_attribute_values(*name, value.clone());
+            }
+            match timed("scan", request.send()).await {
+                Ok(out) => {
+                    items.extend(out.items.unwrap_or_default());
+                    start_key = out.last_evaluated_key;
+                    if start_key.is_none() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to scan {}: {}", table_name, e);
+                    return Err(format!("Couldn't read the {} table: {}", table_name, e));
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    pub async fn get_api_key(&self, key_hash: &str) -> Option<ApiKey> {
+        let item = timed(
+            "get_api_key",
+            self.client
+                .get_item()
+                .table_name(self.api_keys_table_name.as_str())
+                .key("key_hash", AttributeValue::S(key_hash.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        api_key_from_item(&item)
+    }
+
+    pub async fn api_keys(&self) -> Vec<ApiKey> {
+        self.scan_items(
+            self.api_keys_table_name.as_str(),
+            "attribute_exists(key_hash)",
+            Vec::new(),
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(api_key_from_item)
+        .collect()
+    }
+
+    pub async fn put_api_key(&self, key: &ApiKey) -> bool {
+        let strings = |values: Vec<String>| {
+            AttributeValue::L(values.into_iter().map(AttributeValue::S).collect())
+        };
+        timed(
+            "put_api_key",
+            self.client
+                .put_item()
+                .table_name(self.api_keys_table_name.as_str())
+                .item("key_hash", AttributeValue::S(key.key_hash.clone()))
+                .item("key_id", AttributeValue::S(key.key_id.clone()))
+                .item("label", AttributeValue::S(key.label.clone()))
+                .item(
+                    "guild_ids",
+                    strings(key.guild_ids.iter().map(|g| g.0.to_string()).collect()),
+                )
+                .item("endpoints", strings(key.endpoints.clone()))
+                .item("created_at", AttributeValue::N(key.created_at.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn delete_api_key(&self, key_hash: &str) -> bool {
+        timed(
+            "delete_api_key",
+            self.client
+                .delete_item()
+                .table_name(self.api_keys_table_name.as_str())
+                .key("key_hash", AttributeValue::S(key_hash.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Which of the given users have linked an EID
+    pub async fn verified_among(&self, discord_ids: &[UserId]) -> HashSet<UserId> {
+        self.batch_get_users(discord_ids, "discord_id, encrypted_eid")
+            .await
+            .iter()
+            .filter(|item| item.contains_key("encrypted_eid"))
+            .filter_map(|item| attr_number(item, "discord_id").map(UserId))
+            .collect()
+    }
+
+    /// The records of the given users who have one, for `/stats`
+    pub async fn records_among(&self, discord_ids: &[UserId]) -> HashMap<UserId, UserRecord> {
+        self.batch_get_users(
+            discord_ids,
+            "discord_id, encrypted_eid, claims, verified_at, departed_at, expired_at",
+        )
+        .await
+        .iter()
+        .filter_map(|item| {
+            Some((
+                UserId(attr_number(item, "discord_id")?),
+                user_record_from_item(item),
+            ))
+        })
+        .collect()
+    }
+
+    async fn batch_get_users(&self, discord_ids: &[UserId], projection: &str) -> Vec<Item> {
+        let mut items = Vec::new();
+        // BatchGetItem accepts at most 100 keys per request
+        for chunk in discord_ids.chunks(100) {
+            let mut keys: Vec<HashMap<String, AttributeValue>> = chunk
+                .iter()
+                .map(|id| {
+                    HashMap::from([(
+                        "discord_id".to_string(),
+                        AttributeValue::S(id.0.to_string()),
+                    )])
+                })
+                .collect();
+            while !keys.is_empty() {
+                let request = KeysAndAttributes::builder()
+                    .set_keys(Some(keys))
+                    .projection_expression(projection)
+                    .build();
+                let out = match timed(
+                    "batch_get_users",
+                    self.client
+                        .batch_get_item()
+                        .request_items(self.users_table_name.as_str(), request)
+                        .send(),
+                )
+                .await
+                {
+                    Ok(out) => out,
+                    Err(e) => {
+                        error!("Failed to batch get users: {}", e);
+                        break;
+                    }
+                };
+                items.extend(
+                    out.responses
+                        .and_then(|mut r| r.remove(self.users_table_name.as_str()))
+                        .unwrap_or_default(),
+                );
+                // retry whatever DynamoDB could not process this round
+                keys = out
+                    .unprocessed_keys
+                    .and_then(|mut u| u.remove(self.users_table_name.as_str()))
+                    .and_then(|k| k.keys)
+                    .unwrap_or_default();
+            }
+        }
+        items
+    }
+
+    /// Stores a task, replacing any task with the same id
+    pub async fn put_scheduled(&self, task: &ScheduledTask) -> bool {
+        timed(
+            "put_scheduled",
+            self.client
+                .put_item()
+                .table_name(self.scheduled_table_name.as_str())
+                .item("task_id", AttributeValue::S(task.task_id.clone()))
+                .item("due_at", AttributeValue::N(task.due_at.to_string()))
+                .item("task", AttributeValue::S(task.task.clone()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn due_scheduled(&self, now: i64) -> Vec<ScheduledTask> {
+        self.scan_items(
+            self.scheduled_table_name.as_str(),
+            "due_at <= :now",
+            vec![(":now", AttributeValue::N(now.to_string()))],
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some(ScheduledTask {
+                task_id: attr_string(item, "task_id")?,
+                due_at: attr_number(item, "due_at")?,
+                task: attr_string(item, "task")?,
+            })
+        })
+        .collect()
+    }
+
+    pub async fn delete_scheduled(&self, task_id: &str) -> bool {
+        timed(
+            "delete_scheduled",
+            self.client
+                .delete_item()
+                .table_name(self.scheduled_table_name.as_str())
+                .key("task_id", AttributeValue::S(task_id.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Links a Discord account to the EID and claims from a verification token, unless another
+    /// account holds the EID's claim
+    pub async fn link_user(
+        &self,
+        discord_id: UserId,
+        encrypted_eid: &str,
+        claims: &Claims,
+    ) -> LinkResult {
+        let claims = match serde_json::to_string(claims) {
+            Ok(claims) => claims,
+            Err(_) => return LinkResult::Failed,
+        };
+        let claim = self.claim_eid(encrypted_eid, discord_id).await;
+        match claim {
+            EidClaim::New | EidClaim::Held => {}
+            EidClaim::Taken => return LinkResult::EidInUse,
+            EidClaim::Failed => return LinkResult::Failed,
+        }
+        let result = self.put_link(discord_id, encrypted_eid, claims).await;
+        if matches!(claim, EidClaim::New) && !matches!(result, LinkResult::Linked) {
+            self.release_eid(encrypted_eid, discord_id).await;
+        }
+        result
+    }
+
+    /// Claims an EID for an account. The users table's `encrypted_eid` index can't be unique, so
+    /// the eids table, keyed by the EID, holds the account that may link it.
+    pub async fn claim_eid(&self, encrypted_eid: &str, discord_id: UserId) -> EidClaim {
+        let res = timed(
+            "claim_eid",
+            self.client
+                .put_item()
+                .table_name(self.eids_table_name.as_str())
+                .item(
+                    "encrypted_eid",
+                    AttributeValue::S(encrypted_eid.to_string()),
+                )
+                .item("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .condition_expression(
+                    "attribute_not_exists(encrypted_eid) OR discord_id = :discord_id",
+                )
+                .expression_attribute_values(
+                    ":discord_id",
+                    AttributeValue::S(discord_id.0.to_string()),
+                )
+                .return_values(ReturnValue::AllOld)
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(out) if out.attributes.as_ref().map_or(true, |old| old.is_empty()) => EidClaim::New,
+            Ok(_) => EidClaim::Held,
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                EidClaim::Taken
+            }
+            Err(e) => {
+                error!("Failed to claim an EID for {}: {}", discord_id, e);
+                EidClaim::Failed
+            }
+        }
+    }
+
+    /// Records that an account redeemed the token with this nonce, unless another account already
+    /// did. The record is kept until the token would be refused anyway.
+    pub async fn use_token(&self, nonce: &str, discord_id: UserId, expires_at: i64) -> TokenUse {
+        let res = timed(
+            "use_token",
+            self.client
+                .put_item()
+                .table_name(self.token_nonces_table_name.as_str())
+                .item("nonce", AttributeValue::S(nonce.to_string()))
+                .item("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .item("expires_at", AttributeValue::N(expires_at.to_string()))
+                .condition_expression("attribute_not_exists(nonce) OR discord_id = :discord_id")
+                .expression_attribute_values(
+                    ":discord_id",
+                    AttributeValue::S(discord_id.0.to_string()),
+                )
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(_) => TokenUse::Unused,
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                TokenUse::UsedByOther
+            }
+            Err(e) => {
+                error!("Failed to record a token redeemed by {}: {}", discord_id, e);
+                TokenUse::Failed
+            }
+        }
+    }
+
+    /// The JSON of a user's `dm::Stage`, unless the conversation went quiet
+    pub async fn get_dm_session(&self, discord_id: UserId) -> Option<String> {
+        let item = timed(
+            "get_dm_session",
+            self.client
+                .get_item()
+                .table_name(self.dm_sessions_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        // the TTL deletes expired sessions some time after they expire
+        if attr_number::<i64>(&item, "expires_at")? <= response::unix_now() {
+            return None;
+        }
+        attr_string(&item, "stage")
+    }
+
+    pub async fn put_dm_session(&self, discord_id: UserId, stage: &str, expires_at: i64) -> bool {
+        timed(
+            "put_dm_session",
+            self.client
+                .put_item()
+                .table_name(self.dm_sessions_table_name.as_str())
+                .item("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .item("stage", AttributeValue::S(stage.to_string()))
+                .item("expires_at", AttributeValue::N(expires_at.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn delete_dm_session(&self, discord_id: UserId) -> bool {
+        timed(
+            "delete_dm_session",
+            self.client
+                .delete_item()
+                .table_name(self.dm_sessions_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Records that a user is a member of the guild, see `members::mutual`
+    pub async fn put_membership(&self, guild_id: GuildId, discord_id: UserId) -> bool {
+        timed(
+            "put_membership",
+            self.client
+                .put_item()
+                .table_name(self.memberships_table_name.as_str())
+                .item("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .item("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn delete_membership(&self, guild_id: GuildId, discord_id: UserId) -> bool {
+        timed(
+            "delete_membership",
+            self.client
+                .delete_item()
+                .table_name(self.memberships_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// The guilds a user was last seen in
+    pub async fn member_guilds(&self, discord_id: UserId) -> Vec<GuildId> {
+        self.query_items(
+            self.memberships_table_name.as_str(),
+            "discord_id = :discord_id",
+            vec![(":discord_id", AttributeValue::S(discord_id.0.to_string()))],
+        )
+        .await
+        .iter()
+        .filter_map(|item| Some(GuildId(attr_number(item, "guild_id")?)))
+        .collect()
+    }
+
+    /// Stores a review unless the member already has one for the same EID, so a verification is
+    /// only held and posted once. Returns whether it was stored.
+    pub async fn put_review(&self, review: &Review) -> bool {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(self.reviews_table_name.as_str())
+            .item("guild_id", AttributeValue::S(review.guild_id.0.to_string()))
+            .item("user_id", AttributeValue::S(review.user_id.0.to_string()))
+            .item("eid_hash", AttributeValue::S(review.eid_hash.clone()))
+            .item("decision", AttributeValue::S(review.decision.clone()))
+            .item(
+                "reasons",
+                AttributeValue::L(
+                    review
+                        .reasons
+                        .iter()
+                        .map(|r| AttributeValue::S(r.clone()))
+                        .collect(),
+                ),
+            )
+            .item(
+                "requested_at",
+                AttributeValue::N(review.requested_at.to_string()),
+            )
+            .condition_expression("attribute_not_exists(user_id) OR eid_hash <> :eid_hash")
+            .expression_attribute_values(":eid_hash", AttributeValue::S(review.eid_hash.clone()));
+        if let Some(decided_by) = review.decided_by {
+            request = request.item("decided_by", AttributeValue::S(decided_by.0.to_string()));
+        }
+        match timed("put_review", request.send()).await {
+            Ok(_) => true,
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                false
+            }
+            Err(e) => {
+                error!("Failed to store the review of {}: {}", review.user_id, e);
+                false
+            }
+        }
+    }
+
+    pub async fn get_review(&self, guild_id: GuildId, user_id: UserId) -> Option<Review> {
+        let item = timed(
+            "get_review",
+            self.client
+                .get_item()
+                .table_name(self.reviews_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .key("user_id", AttributeValue::S(user_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        review_from_item(&item)
+    }
+
+    /// Every review in a guild, decided or not
+    pub async fn guild_reviews(&self, guild_id: GuildId) -> Vec<Review> {
+        self.query_items(
+            self.reviews_table_name.as_str(),
+            "guild_id = :guild_id",
+            vec![(":guild_id", AttributeValue::S(guild_id.0.to_string()))],
+        )
+        .await
+        .iter()
+        .filter_map(review_from_item)
+        .collect()
+    }
+
+    /// Approves or denies a pending review. Returns false when it was already decided, e.g. by
+    /// another moderator pressing the other button at the same time.
+    pub async fn decide_review(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        decision: &str,
+        decided_by: UserId,
+    ) -> bool {
+        timed(
+            "decide_review",
+            self.client
+                .update_item()
+                .table_name(self.reviews_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .key("user_id", AttributeValue::S(user_id.0.to_string()))
+                .update_expression("SET decision = :decision, decided_by = :decided_by")
+                .condition_expression("decision = :pending")
+                .expression_attribute_values(":decision", AttributeValue::S(decision.to_string()))
+                .expression_attribute_values(
+                    ":decided_by",
+                    AttributeValue::S(decided_by.0.to_string()),
+                )
+                .expression_attribute_values(":pending", AttributeValue::S("pending".to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Records that the account verified with an EID was banned from the guild, see `bans`
+    pub async fn put_ban(&self, guild_id: GuildId, eid_hash: &str, discord_id: UserId) -> bool {
+        timed(
+            "put_ban",
+            self.client
+                .put_item()
+                .table_name(self.bans_table_name.as_str())
+                .item("eid_hash", AttributeValue::S(eid_hash.to_string()))
+                .item("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .item("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .item(
+                    "banned_at",
+                    AttributeValue::N(response::unix_now().to_string()),
+                )
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// The banned account an EID was verified with, if it was banned from the guild
+    pub async fn get_ban(&self, guild_id: GuildId, eid_hash: &str) -> Option<UserId> {
+        let item = timed(
+            "get_ban",
+            self.client
+                .get_item()
+                .table_name(self.bans_table_name.as_str())
+                .key("eid_hash", AttributeValue::S(eid_hash.to_string()))
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?
+        .item?;
+        attr_number(&item, "discord_id").map(UserId)
+    }
+
+    /// Forgets the bans of an account in a guild, once it's unbanned
+    pub async fn delete_bans(&self, guild_id: GuildId, discord_id: UserId) -> bool {
+        let table = self.bans_table_name.as_str();
+        let items = self
+            .scan_items(
+                table,
+                "guild_id = :guild_id AND discord_id = :discord_id",
+                vec![
+                    (":guild_id", AttributeValue::S(guild_id.0.to_string())),
+                    (":discord_id", AttributeValue::S(discord_id.0.to_string())),
+                ],
+            )
+            .await;
+        match items {
+            Ok(items) => {
+                self.batch_delete(table, &["eid_hash", "guild_id"], items)
+                    .await
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Adds an account to the ones caught verifying with a banned EID. Returns false when it
+    /// was caught before, so moderators are only alerted once.
+    pub async fn record_evasion(
+        &self,
+        guild_id: GuildId,
+        eid_hash: &str,
+        discord_id: UserId,
+    ) -> bool {
+        let user = AttributeValue::S(discord_id.0.to_string());
+        timed(
+            "record_evasion",
+            self.client
+                .update_item()
+                .table_name(self.bans_table_name.as_str())
+                .key("eid_hash", AttributeValue::S(eid_hash.to_string()))
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .update_expression(
+                    "SET evaders = list_append(if_not_exists(evaders, :empty), :user)",
+                )
+                .condition_expression(
+                    "attribute_exists(eid_hash) AND NOT contains(evaders, :discord_id)",
+                )
+                .expression_attribute_values(":empty", AttributeValue::L(Vec::new()))
+                .expression_attribute_values(":user", AttributeValue::L(vec![user.clone()]))
+                .expression_attribute_values(":discord_id", user)
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Releases an account's claim on an EID, once it's no longer linked to it
+    async fn release_eid(&self, encrypted_eid: &str, discord_id: UserId) -> bool {
+        timed(
+            "release_eid",
+            self.client
+                .delete_item()
+                .table_name(self.eids_table_name.as_str())
+                .key(
+                    "encrypted_eid",
+                    AttributeValue::S(encrypted_eid.to_string()),
+                )
+                .condition_expression("discord_id = :discord_id")
+                .expression_attribute_values(
+                    ":discord_id",
+                    AttributeValue::S(discord_id.0.to_string()),
+                )
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    async fn put_link(
+        &self,
+        discord_id: UserId,
+        encrypted_eid: &str,
+        claims: String,
+    ) -> LinkResult {
+        let res = timed(
+            "put_link",
+            self.client
+                .update_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .update_expression(
+                    "SET encrypted_eid = :encrypted_eid, claims = :claims, verified_at = :now, \
+                 schema_version = :version REMOVE expired_at",
+                )
+                .condition_expression("attribute_not_exists(encrypted_eid)")
+                .expression_attribute_values(
+                    ":version",
+                    AttributeValue::N(migrations::SCHEMA_VERSION.to_string()),
+                )
+                .expression_attribute_values(
+                    ":encrypted_eid",
+                    AttributeValue::S(encrypted_eid.to_string()),
+                )
+                .expression_attribute_values(":claims", AttributeValue::S(claims))
+                .expression_attribute_values(
+                    ":now",
+                    AttributeValue::N(response::unix_now().to_string()),
+                )
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(_) => LinkResult::Linked,
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                LinkResult::AlreadyLinked
+            }
+            Err(e) => {
+                error!("Failed to link {}: {}", discord_id, e);
+                LinkResult::Failed
+            }
+        }
+    }
+
+    /// Stores the snapshots of a job's members, returning whether every write succeeded
+    pub async fn put_snapshots(&self, snapshots: &[MemberSnapshot]) -> bool {
+        let mut ok = true;
+        // BatchWriteItem accepts at most 25 items per request
+        for chunk in snapshots.chunks(25) {
+            let mut requests: Vec<WriteRequest> = chunk
+                .iter()
+                .map(|snapshot| {
+                    let mut item = HashMap::from([
+                        (
+                            "job_id".to_string(),
+                            AttributeValue::S(snapshot.job_id.clone()),
+                        ),
+                        (
+                            "user_id".to_string(),
+                            AttributeValue::S(snapshot.user_id.0.to_string()),
+                        ),
+                        (
+                            "guild_id".to_string(),
+                            AttributeValue::S(snapshot.guild_id.0.to_string()),
+                        ),
+                        (
+                            "roles".to_string(),
+                            AttributeValue::L(
+                                snapshot
+                                    .roles
+                                    .iter()
+                                    .map(|r| AttributeValue::S(r.0.to_string()))
+                                    .collect(),
+                            ),
+                        ),
+                        (
+                            "expires_at".to_string(),
+                            AttributeValue::N(snapshot.expires_at.to_string()),
+                        ),
+                    ]);
+                    if let Some(nick) = &snapshot.nick {
+                        item.insert("nick".to_string(), AttributeValue::S(nick.clone()));
+                    }
+                    WriteRequest::builder()
+                        .put_request(PutRequest::builder().set_item(Some(item)).build())
+                        .build()
+                })
+                .collect();
+            while !requests.is_empty() {
+                let out = match timed(
+                    "put_snapshots",
+                    self.client
+                        .batch_write_item()
+                        .request_items(self.snapshots_table_name.as_str(), requests)
+                        .send(),
+                )
+                .await
+                {
+                    Ok(out) => out,
+                    Err(e) => {
+                        error!("Failed to store snapshots: {}", e);
+                        ok = false;
+                        break;
+                    }
+                };
+                // retry whatever DynamoDB could not process this round
+                requests = out
+                    .unprocessed_items
+                    .and_then(|mut u| u.remove(self.snapshots_table_name.as_str()))
+                    .unwrap_or_default();
+            }
+        }
+        ok
+    }
+
+    pub async fn get_snapshots(&self, job_id: &str) -> Vec<MemberSnapshot> {
+        self.query_items(
+            self.snapshots_table_name.as_str(),
+            "job_id = :job_id",
+            vec![(":job_id", AttributeValue::S(job_id.to_string()))],
+        )
+        .await
+        .iter()
+        .filter_map(|item| {
+            Some(MemberSnapshot {
+                job_id: attr_string(item, "job_id")?,
+                guild_id: GuildId(attr_number(item, "guild_id")?),
+                user_id: UserId(attr_number(item, "user_id")?),
+                roles: match item.get("roles") {
+                    Some(AttributeValue::L(roles)) => roles
+                        .iter()
+                        .filter_map(|r| match r {
+                            AttributeValue::S(id) => id.parse().ok().map(RoleId),
+                            _ => None,
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                },
+                nick: attr_string(item, "nick"),
+                expires_at: attr_number(item, "expires_at")?,
+            })
+        })
+        .collect()
+    }
+
+    /// Records the first time a user reached a funnel stage; later visits keep the original time
+    pub async fn record_funnel_stage(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        stage: &str,
+        at: i64,
+    ) -> bool {
+        timed(
+            "record_funnel_stage",
+            self.client
+                .update_item()
+                .table_name(self.funnel_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .key("user_id", AttributeValue::S(user_id.0.to_string()))
+                .update_expression("SET #stage = if_not_exists(#stage, :at)")
+                .expression_attribute_names("#stage", stage)
+                .expression_attribute_values(":at", AttributeValue::N(at.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn get_funnel(&self, guild_id: GuildId) -> Vec<FunnelEntry> {
+        self.query_items(
+            self.funnel_table_name.as_str(),
+            "guild_id = :guild_id",
+            vec![(":guild_id", AttributeValue::S(guild_id.0.to_string()))],
+        )
+        .await
+        .iter()
+        .filter_map(|item| {
+            Some(FunnelEntry {
+                user_id: UserId(attr_number(item, "user_id")?),
+                stages: item
+                    .iter()
+                    .filter(|(key, _)| *key != "guild_id" && *key != "user_id")
+                    .filter_map(|(key, _)| Some((key.clone(), attr_number(item, key)?)))
+                    .collect(),
+            })
+        })
+        .collect()
+    }
+
+    pub async fn track_message(&self, message: &TrackedMessage) -> bool {
+        let mut request = self
+            .client
+            .put_item()
+            .table_name(self.components_table_name.as_str())
+            .item(
+                "message_id",
+                AttributeValue::S(message.message_id.0.to_string()),
+            )
+            .item(
+                "channel_id",
+                AttributeValue::S(message.channel_id.0.to_string()),
+            )
+            .item(
+                "guild_id",
+                AttributeValue::S(message.guild_id.0.to_string()),
+            )
+            .item(
+                "posted_at",
+                AttributeValue::N(message.posted_at.to_string()),
+            );
+        if let Some(expires_at) = message.expires_at {
+            request = request.item("expires_at", AttributeValue::N(expires_at.to_string()));
+        }
+        timed("track_message", request.send()).await.is_ok()
+    }
+
+    pub async fn tracked_messages(&self) -> Vec<TrackedMessage> {
+        self.scan_items(
+            self.components_table_name.as_str(),
+            "attribute_exists(message_id)",
+            Vec::new(),
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some(TrackedMessage {
+                message_id: MessageId(attr_number(item, "message_id")?),
+                channel_id: ChannelId(attr_number(item, "channel_id")?),
+                guild_id: GuildId(attr_number(item, "guild_id")?),
+                posted_at: attr_number(item, "posted_at")?,
+                expires_at: attr_number(item, "expires_at"),
+            })
+        })
+        .collect()
+    }
+
+    pub async fn untrack_message(&self, message_id: MessageId) -> bool {
+        timed(
+            "untrack_message",
+            self.client
+                .delete_item()
+                .table_name(self.components_table_name.as_str())
+                .key("message_id", AttributeValue::S(message_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    fn table_name(&self, table: Table) -> &str {
+        match table {
+            Table::Users => self.users_table_name.as_str(),
+            Table::Guilds => self.guilds_table_name.as_str(),
+            Table::Audit => self.audit_table_name.as_str(),
+            Table::Attestations => self.attestations_table_name.as_str(),
+            Table::Scheduled => self.scheduled_table_name.as_str(),
+            Table::Eids => self.eids_table_name.as_str(),
+            Table::Events => self.events_table_name.as_str(),
+            Table::Checkins => self.checkins_table_name.as_str(),
+            Table::ApiKeys => self.api_keys_table_name.as_str(),
+            Table::Notes => self.notes_table_name.as_str(),
+            Table::Memberships => self.memberships_table_name.as_str(),
+            Table::Reviews => self.reviews_table_name.as_str(),
+            Table::Bans => self.bans_table_name.as_str(),
+            Table::Meta => self.meta_table_name.as_str(),
+            Table::Tenants => self.tenants_table_name.as_str(),
+        }
+    }
+
+    /// Every item of a table as stored, so consistency checks also see records that don't parse.
+    /// Fails if the table couldn't be read in full.
+    pub async fn raw_items(&self, table: Table) -> Result<Vec<Item>, String> {
+        let key = table.key_names()[0];
+        self.scan_items(
+            self.table_name(table),
+            &format!("attribute_exists({})", key),
+            Vec::new(),
+        )
+        .await
+    }
+
+    /// Removes attributes from an item, or deletes the whole item when `attributes` is empty
+    pub async fn repair_item(&self, table: Table, item: &Item, attributes: &[&str]) -> bool {
+        let key = table
+            .key_names()
+            .iter()
+            .filter_map(|name| Some((name.to_string(), item.get(*name)?.clone())))
+            .collect::<Item>();
+        if attributes.is_empty() {
+            return timed(
+                "repair_item",
+                self.client
+                    .delete_item()
+                    .table_name(self.table_name(table))
+                    .set_key(Some(key))
+                    .send(),
+            )
+            .await
+            .is_ok();
+        }
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(self.table_name(table))
+            .set_key(Some(key))
+            .update_expression(format!(
+                "REMOVE {}",
+                (0..attributes.len())
+                    .map(|i| format!("#a{}", i))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        for (i, attribute) in attributes.iter().enumerate() {
+            request = request.expression_attribute_names(format!("#a{}", i), *attribute);
+        }
+        timed("repair_item", request.send()).await.is_ok()
+    }
+
+    /// Writes items as they are, replacing any with the same key, e.g. when restoring a backup
+    pub async fn put_items(&self, table: Table, items: Vec<Item>) -> bool {
+        let table_name = self.table_name(table);
+        let mut ok = true;
+        for chunk in items.chunks(25) {
+            let mut requests: Vec<WriteRequest> = chunk
+                .iter()
+                .map(|item| {
+                    WriteRequest::builder()
+                        .put_request(PutRequest::builder().set_item(Some(item.clone())).build())
+                        .build()
+                })
+                .collect();
+            while !requests.is_empty() {
+                let out = match timed(
+                    "put_items",
+                    self.client
+                        .batch_write_item()
+                        .request_items(table_name, requests)
+                        .send(),
+                )
+                .await
+                {
+                    Ok(out) => out,
+                    Err(e) => {
+                        error!("Failed to write to {}: {}", table_name, e);
+                        ok = false;
+                        break;
+                    }
+                };
+                // retry whatever DynamoDB could not process this round
+                requests = out
+                    .unprocessed_items
+                    .and_then(|mut u| u.remove(table_name))
+                    .unwrap_or_default();
+            }
+        }
+        ok
+    }
+
+    pub async fn guild_attestations(&self, guild_id: GuildId) -> Vec<Attestation> {
+        self.query_items(
+            self.attestations_table_name.as_str(),
+            "guild_id = :guild_id",
+            vec![(":guild_id", AttributeValue::S(guild_id.0.to_string()))],
+        )
+        .await
+        .iter()
+        .filter_map(attestation_from_item)
+        .collect()
+    }
+
+    /// Deletes everything stored about a guild, for offboarding. Users' links are global and
+    /// are kept.
+    pub async fn delete_guild_data(&self, guild_id: GuildId) -> bool {
+        let guild = AttributeValue::S(guild_id.0.to_string());
+        let by_guild = vec![(":guild_id", guild.clone())];
+        let mut ok = timed(
+            "delete_guild_data",
+            self.client
+                .delete_item()
+                .table_name(self.guilds_table_name.as_str())
+                .key("guild_id", guild.clone())
+                .send(),
+        )
+        .await
+        .is_ok();
+        for (table, key_names) in [
+            (self.audit_table_name.as_str(), &["guild_id", "entry_id"]),
+            (
+                self.attestations_table_name.as_str(),
+                &["guild_id", "attestation_id"],
+            ),
+            (self.funnel_table_name.as_str(), &["guild_id", "user_id"]),
+            (self.notes_table_name.as_str(), &["guild_id", "note_id"]),
+            (self.reviews_table_name.as_str(), &["guild_id", "user_id"]),
+        ] {
+            let items = self
+                .query_items(table, "guild_id = :guild_id", by_guild.clone())
+                .await;
+            ok &= self.batch_delete(table, key_names, items).await;
+        }
+        for (table, key_names, filter, values) in [
+            (
+                self.snapshots_table_name.as_str(),
+                &["job_id", "user_id"][..],
+                "guild_id = :guild_id",
+                by_guild.clone(),
+            ),
+            (
+                self.components_table_name.as_str(),
+                &["message_id"][..],
+                "guild_id = :guild_id",
+                by_guild.clone(),
+            ),
+            (
+                self.memberships_table_name.as_str(),
+                &["discord_id", "guild_id"][..],
+                "guild_id = :guild_id",
+                by_guild.clone(),
+            ),
+            (
+                self.bans_table_name.as_str(),
+                &["eid_hash", "guild_id"][..],
+                "guild_id = :guild_id",
+                by_guild.clone(),
+            ),
+            (
+                self.scheduled_table_name.as_str(),
+                &["task_id"][..],
+                "begins_with(task_id, :prefix)",
+                vec![(":prefix", AttributeValue::S(format!("guest:{}:", guild_id)))],
+            ),
+        ] {
+            ok &= match self.scan_items(table, filter, values).await {
+                Ok(items) => self.batch_delete(table, key_names, items).await,
+                Err(_) => false,
+            };
+        }
+        ok
+    }
+
+    /// Deletes items by the given key attributes, 25 per request
+    async fn batch_delete(&self, table_name: &str, key_names: &[&str], items: Vec<Item>) -> bool {
+        let mut ok = true;
+        for chunk in items.chunks(25) {
+            let mut requests: Vec<WriteRequest> = chunk
+                .iter()
+                .map(|item| {
+                    let key = key_names
+                        .iter()
+                        .filter_map(|name| Some((name.to_string(), item.get(*name)?.clone())))
+                        .collect::<Item>();
+                    WriteRequest::builder()
+                        .delete_request(DeleteRequest::builder().set_key(Some(key)).build())
+                        .build()
+                })
+                .collect();
+            while !requests.is_empty() {
+                let out = match timed(
+                    "batch_delete",
+                    self.client
+                        .batch_write_item()
+                        .request_items(table_name, requests)
+                        .send(),
+                )
+                .await
+                {
+                    Ok(out) => out,
+                    Err(e) => {
+                        error!("Failed to delete from {}: {}", table_name, e);
+                        ok = false;
+                        break;
+                    }
+                };
+                // retry whatever DynamoDB could not process this round
+                requests = out
+                    .unprocessed_items
+                    .and_then(|mut u| u.remove(table_name))
+                    .unwrap_or_default();
+            }
+        }
+        ok
+    }
+
+    pub async fn add_note(&self, note: &Note) -> bool {
+        timed(
+            "add_note",
+            self.client
+                .put_item()
+                .table_name(self.notes_table_name.as_str())
+                .item("guild_id", AttributeValue::S(note.guild_id.0.to_string()))
+                .item(
+                    "note_id",
+                    AttributeValue::S(format!(
+                        "{}:{:012}-{:08x}",
+                        note.user_id,
+                        note.at,
+                        rand::random::<u32>()
+                    )),
+                )
+                .item("author", AttributeValue::S(note.author.0.to_string()))
+                .item("at", AttributeValue::N(note.at.to_string()))
+                .item("text", AttributeValue::S(note.text.clone()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Notes about a member, oldest first
+    pub async fn get_notes(&self, guild_id: GuildId, user_id: UserId) -> Vec<Note> {
+        self.query_items(
+            self.notes_table_name.as_str(),
+            "guild_id = :guild_id AND begins_with(note_id, :user_id)",
+            vec![
+                (":guild_id", AttributeValue::S(guild_id.0.to_string())),
+                (":user_id", AttributeValue::S(format!("{}:", user_id))),
+            ],
+        )
+        .await
+        .iter()
+        .filter_map(|item| note_from_item(guild_id, item))
+        .collect()
+    }
+
+    /// Every note in a guild, grouped by member
+    pub async fn guild_notes(&self, guild_id: GuildId) -> Vec<Note> {
+        self.query_items(
+            self.notes_table_name.as_str(),
+            "guild_id = :guild_id",
+            vec![(":guild_id", AttributeValue::S(guild_id.0.to_string()))],
+        )
+        .await
+        .iter()
+        .filter_map(|item| note_from_item(guild_id, item))
+        .collect()
+    }
+
+    /// Every linked user with their base64 encoded, encrypted EID
+    pub async fn linked_users(&self) -> Vec<(UserId, String)> {
+        self.scan_items(
+            self.users_table_name.as_str(),
+            "attribute_exists(encrypted_eid)",
+            Vec::new(),
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some((
+                UserId(attr_number(item, "discord_id")?),
+                attr_string(item, "encrypted_eid")?,
+            ))
+        })
+        .collect()
+    }
+
+    /// Every linked user with their record, for `/admin export`
+    pub async fn linked_records(&self) -> Vec<(UserId, UserRecord)> {
+        self.scan_items(
+            self.users_table_name.as_str(),
+            "attribute_exists(encrypted_eid)",
+            Vec::new(),
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some((
+                UserId(attr_number(item, "discord_id")?),
+                user_record_from_item(item),
+            ))
+        })
+        .collect()
+    }
+
+    /// Every linked user with when they verified, unknown for links from before it was recorded
+    pub async fn verified_since(&self) -> Vec<(UserId, Option<i64>)> {
+        self.scan_items(
+            self.users_table_name.as_str(),
+            "attribute_exists(encrypted_eid)",
+            Vec::new(),
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some((
+                UserId(attr_number(item, "discord_id")?),
+                attr_number(item, "verified_at"),
+            ))
+        })
+        .collect()
+    }
+
+    /// Tags a user record with the schema version its attributes follow, see `migrations`
+    pub async fn set_user_schema_version(&self, discord_id: UserId, version: u32) -> bool {
+        timed(
+            "set_user_schema_version",
+            self.client
+                .update_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .update_expression("SET schema_version = :version")
+                .condition_expression("attribute_exists(discord_id)")
+                .expression_attribute_values(":version", AttributeValue::N(version.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// The schema version the tables were last migrated to, 0 before the first migration
+    pub async fn schema_version(&self) -> Option<u32> {
+        let out = timed(
+            "schema_version",
+            self.client
+                .get_item()
+                .table_name(self.meta_table_name.as_str())
+                .key("name", AttributeValue::S("schema_version".to_string()))
+                .send(),
+        )
+        .await
+        .ok()?;
+        Some(
+            out.item
+                .and_then(|item| attr_number(&item, "version"))
+                .unwrap_or(0),
+        )
+    }
+
+    pub async fn set_schema_version(&self, version: u32) -> bool {
+        timed(
+            "set_schema_version",
+            self.client
+                .put_item()
+                .table_name(self.meta_table_name.as_str())
+                .item("name", AttributeValue::S("schema_version".to_string()))
+                .item("version", AttributeValue::N(version.to_string()))
+                .item(
+                    "migrated_at",
+                    AttributeValue::N(response::unix_now().to_string()),
+                )
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// A rate limit bucket by its key, e.g. `user:<id>`, none when it couldn't be read
+    pub async fn get_rate_limit(&self, key: &str) -> Option<RateLimit> {
+        let out = timed(
+            "get_rate_limit",
+            self.client
+                .get_item()
+                .table_name(self.rate_limits_table_name.as_str())
+                .key("key", AttributeValue::S(key.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?;
+        let item = match out.item {
+            Some(item) => item,
+            None => return Some(RateLimit::default()),
+        };
+        let number = |name: &str| attr_number(&item, name).unwrap_or(0);
+        Some(RateLimit {
+            tokens: attr_number(&item, "tokens").unwrap_or(0.0),
+            updated_at: number("updated_at"),
+            strikes: attr_number(&item, "strikes").unwrap_or(0),
+            struck_at: number("struck_at"),
+            blocked_until: number("blocked_until"),
+            alerted_at: number("alerted_at"),
+        })
+    }
+
+    pub async fn put_rate_limit(&self, key: &str, limit: &RateLimit, expires_at: i64) -> bool {
+        timed(
+            "put_rate_limit",
+            self.client
+                .put_item()
+                .table_name(self.rate_limits_table_name.as_str())
+                .item("key", AttributeValue::S(key.to_string()))
+                .item("tokens", AttributeValue::N(limit.tokens.to_string()))
+                .item(
+                    "updated_at",
+                    AttributeValue::N(limit.updated_at.to_string()),
+                )
+                .item("strikes", AttributeValue::N(limit.strikes.to_string()))
+                .item("struck_at", AttributeValue::N(limit.struck_at.to_string()))
+                .item(
+                    "blocked_until",
+                    AttributeValue::N(limit.blocked_until.to_string()),
+                )
+                .item(
+                    "alerted_at",
+                    AttributeValue::N(limit.alerted_at.to_string()),
+                )
+                .item("expires_at", AttributeValue::N(expires_at.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    pub async fn put_tenant(&self, tenant: &Tenant) -> bool {
+        timed(
+            "put_tenant",
+            self.client
+                .put_item()
+                .table_name(self.tenants_table_name.as_str())
+                .item("guild_id", AttributeValue::S(tenant.guild_id.0.to_string()))
+                .item("added_by", AttributeValue::S(tenant.added_by.0.to_string()))
+                .item("added_at", AttributeValue::N(tenant.added_at.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Removes an approved guild, returning whether it was approved
+    pub async fn delete_tenant(&self, guild_id: GuildId) -> Option<bool> {
+        let out = timed(
+            "delete_tenant",
+            self.client
+                .delete_item()
+                .table_name(self.tenants_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .return_values(ReturnValue::AllOld)
+                .send(),
+        )
+        .await
+        .ok()?;
+        Some(out.attributes.is_some())
+    }
+
+    /// Whether a guild was approved, none when it couldn't be read
+    pub async fn is_tenant(&self, guild_id: GuildId) -> Option<bool> {
+        let out = timed(
+            "is_tenant",
+            self.client
+                .get_item()
+                .table_name(self.tenants_table_name.as_str())
+                .key("guild_id", AttributeValue::S(guild_id.0.to_string()))
+                .send(),
+        )
+        .await
+        .ok()?;
+        Some(out.item.is_some())
+    }
+
+    /// Every approved guild
+    pub async fn tenants(&self) -> Vec<Tenant> {
+        self.scan_items(
+            self.tenants_table_name.as_str(),
+            "attribute_exists(guild_id)",
+            Vec::new(),
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some(Tenant {
+                guild_id: GuildId(attr_number(item, "guild_id")?),
+                added_by: UserId(attr_number(item, "added_by")?),
+                added_at: attr_number(item, "added_at")?,
+            })
+        })
+        .collect()
+    }
+
+    /// Records when a user verified, for links from before it was recorded
+    pub async fn set_verified_at(&self, discord_id: UserId, at: i64) -> bool {
+        timed(
+            "set_verified_at",
+            self.client
+                .update_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .update_expression("SET verified_at = :at")
+                .condition_expression(
+                    "attribute_exists(encrypted_eid) AND attribute_not_exists(verified_at)",
+                )
+                .expression_attribute_values(":at", AttributeValue::N(at.to_string()))
+                .send(),
+        )
+        .await
+        .is_ok()
+    }
+
+    /// Removes an expired link's EID and claims, keeping the record with `expired_at`
+    pub async fn expire_user(&self, discord_id: UserId, at: i64) -> bool {
+        let res = timed(
+            "expire_user",
+            self.client
+                .update_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .update_expression(
+                    "SET expired_at = :at REMOVE encrypted_eid, claims, verified_at, departed_at",
+                )
+                .condition_expression("attribute_exists(encrypted_eid)")
+                .expression_attribute_values(":at", AttributeValue::N(at.to_string()))
+                .return_values(ReturnValue::AllOld)
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(out) => {
+                if let Some(encrypted_eid) = out
+                    .attributes
+                    .as_ref()
+                    .and_then(|old| attr_string(old, "encrypted_eid"))
+                {
+                    self.release_eid(&encrypted_eid, discord_id).await;
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Replaces a linked user's claims after a directory re-check, recording when an affiliation
+    /// was found to be gone
+    pub async fn update_claims(
+        &self,
+        discord_id: UserId,
+        claims: &Claims,
+        departed_at: Option<i64>,
+    ) -> bool {
+        let claims = match serde_json::to_string(claims) {
+            Ok(claims) => claims,
+            Err(_) => return false,
+        };
+        let mut request = self
+            .client
+            .update_item()
+            .table_name(self.users_table_name.as_str())
+            .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+            .condition_expression("attribute_exists(encrypted_eid)")
+            .expression_attribute_values(":claims", AttributeValue::S(claims))
+            .expression_attribute_values(
+                ":version",
+                AttributeValue::N(migrations::SCHEMA_VERSION.to_string()),
+            );
+        request = match departed_at {
+            Some(at) => request
+                .update_expression(
+                    "SET claims = :claims, schema_version = :version, departed_at = :departed_at",
+                )
+                .expression_attribute_values(":departed_at", AttributeValue::N(at.to_string())),
+            None => request.update_expression("SET claims = :claims, schema_version = :version"),
+        };
+        timed("update_claims", request.send()).await.is_ok()
+    }
+
+    /// Tasks whose id starts with `prefix`, for tasks that reschedule themselves under new ids
+    pub async fn scheduled_with_prefix(&self, prefix: &str) -> Vec<ScheduledTask> {
+        self.scan_items(
+            self.scheduled_table_name.as_str(),
+            "begins_with(task_id, :prefix)",
+            vec![(":prefix", AttributeValue::S(prefix.to_string()))],
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|item| {
+            Some(ScheduledTask {
+                task_id: attr_string(item, "task_id")?,
+                due_at: attr_number(item, "due_at")?,
+                task: attr_string(item, "task")?,
+            })
+        })
+        .collect()
+    }
+
+    /// Maps encrypted EIDs (base64, as stored) to the users who linked them, using the users
+    /// table's `encrypted_eid` index
+    pub async fn users_by_encrypted_eid(
+        &self,
+        encrypted_eids: &[String],
+    ) -> HashMap<String, UserId> {
+        let mut users = HashMap::new();
+        for encrypted_eid in encrypted_eids {
+            let result = timed(
+                "users_by_encrypted_eid",
+                self.client
+                    .query()
+                    .table_name(self.users_table_name.as_str())
+                    .index_name(ENCRYPTED_EID_INDEX)
+                    .key_condition_expression("encrypted_eid = :encrypted_eid")
+                    .expression_attribute_values(
+                        ":encrypted_eid",
+                        AttributeValue::S(encrypted_eid.clone()),
+                    )
+                    .send(),
+            )
+            .await;
+            match result {
+                Ok(out) => {
+                    if let Some(user) = out
+                        .items
+                        .unwrap_or_default()
+                        .iter()
+                        .find_map(|item| attr_number(item, "discord_id"))
+                    {
+                        users.insert(encrypted_eid.clone(), UserId(user));
+                    }
+                }
+                Err(e) => error!("Failed to look up an encrypted EID: {}", e),
+            }
+        }
+        users
+    }
+
+    /// Removes a Discord account's link to its EID, e.g. when a moderator revokes it
+    pub async fn unlink_user(&self, discord_id: UserId) -> UnlinkResult {
+        let res = timed(
+            "unlink_user",
+            self.client
+                .delete_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .condition_expression("attribute_exists(encrypted_eid)")
+                .return_values(ReturnValue::AllOld)
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(out) => {
+                if let Some(encrypted_eid) = out
+                    .attributes
+                    .as_ref()
+                    .and_then(|old| attr_string(old, "encrypted_eid"))
+                {
+                    self.release_eid(&encrypted_eid, discord_id).await;
+                }
+                UnlinkResult::Unlinked
+            }
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                UnlinkResult::NotLinked
+            }
+            Err(e) => {
+                error!("Failed to unlink {}: {}", discord_id, e);
+                UnlinkResult::Failed
+            }
+        }
+    }
+
+    /// Deletes a Discord account's record, linked or expired, at the account's own request
+    pub async fn forget_user(&self, discord_id: UserId) -> UnlinkResult {
+        self.delete_dm_session(discord_id).await;
+        let res = timed(
+            "forget_user",
+            self.client
+                .delete_item()
+                .table_name(self.users_table_name.as_str())
+                .key("discord_id", AttributeValue::S(discord_id.0.to_string()))
+                .return_values(ReturnValue::AllOld)
+                .send(),
+        )
+        .await;
+        match res {
+            Ok(out) => {
+                let old = out.attributes.unwrap_or_default();
+                if old.is_empty() {
+                    return UnlinkResult::NotLinked;
+                }
+                if let Some(encrypted_eid) = attr_string(&old, "encrypted_eid") {
+                    self.release_eid(&encrypted_eid, discord_id).await;
+                }
+                UnlinkResult::Unlinked
+            }
+            Err(e) => {
+                error!("Failed to forget {}: {}", discord_id, e);
+                UnlinkResult::Failed
+            }
+        }
+    }
+}
+
+fn user_record_from_item(item: &HashMap<String, AttributeValue>) -> UserRecord {
+    UserRecord {
+        encrypted_eid: attr_string(item, "encrypted_eid"),
+        claims: attr_string(item, "claims").and_then(|c| serde_json::from_str(&c).ok()),
+        verified_at: attr_number(item, "verified_at"),
+        departed_at: attr_number(item, "departed_at"),
+        expired_at: attr_number(item, "expired_at"),
+    }
+}
+
+fn note_from_item(guild_id: GuildId, item: &HashMap<String, AttributeValue>) -> Option<Note> {
+    // note ids are "{user_id}:{at}-{random}"
+    let note_id = attr_string(item, "note_id")?;
+    Some(Note {
+        guild_id,
+        user_id: UserId(note_id.split(':').next()?.parse().ok()?),
+        author: UserId(attr_number(item, "author")?),
+        at: attr_number(item, "at")?,
+        text: attr_string(item, "text")?,
+    })
+}
+
+/// Stable identifier for an EID that does not reveal it, derived from its encrypted form
+pub fn eid_hash(encrypted_eid: &str) -> String {
+    sha256_hex(encrypted_eid.as_bytes())
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    digest::digest(&digest::SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Awaits a request, recording its latency and whether it failed under `op` in the metrics.
+/// Every DynamoDB request goes through this, named after the method making it.
+async fn timed<T, E>(
+    op: &'static str,
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let result = request.await;
+    telemetry::record_db(op, started.elapsed(), result.is_ok());
+    result
+}
+
+pub fn attr_string(item: &HashMap<String, AttributeValue>, key: &str) -> Option<String> {
+    match item.get(key) {
+        Some(AttributeValue::S(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Reads a number, also accepting numbers stored as strings (e.g. snowflake ids)
+pub fn attr_number<T: FromStr>(item: &HashMap<String, AttributeValue>, key: &str) -> Option<T> {
+    match item.get(key) {
+        Some(AttributeValue::N(n)) | Some(AttributeValue::S(n)) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+fn review_from_item(item: &HashMap<String, AttributeValue>) -> Option<Review> {
+    Some(Review {
+        guild_id: GuildId(attr_number(item, "guild_id")?),
+        user_id: UserId(attr_number(item, "user_id")?),
+        eid_hash: attr_string(item, "eid_hash")?,
+        decision: attr_string(item, "decision")?,
+        reasons: match item.get("reasons") {
+            Some(AttributeValue::L(reasons)) => reasons
+                .iter()
+                .filter_map(|r| match r {
+                    AttributeValue::S(reason) => Some(reason.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        },
+        requested_at: attr_number(item, "requested_at").unwrap_or_default(),
+        decided_by: attr_number(item, "decided_by").map(UserId),
+    })
+}
+
+fn api_key_from_item(item: &HashMap<String, AttributeValue>) -> Option<ApiKey> {
+    let strings = |name: &str| match item.get(name) {
+        Some(AttributeValue::L(list)) => list
+            .iter()
+            .filter_map(|v| match v {
+                AttributeValue::S(value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+    let key_hash = attr_string(item, "key_hash")?;
+    Some(ApiKey {
+        // keys created before ids existed are identified by a prefix of their hash
+        key_id: attr_string(item, "key_id").unwrap_or_else(|| key_hash.chars().take(8).collect()),
+        label: attr_string(item, "label").unwrap_or_default(),
+        guild_ids: strings("guild_ids")
+            .iter()
+            .filter_map(|id| id.parse().ok().map(GuildId))
+            .collect(),
+        endpoints: strings("endpoints"),
+        created_at: attr_number(item, "created_at").unwrap_or(0),
+        key_hash,
+    })
+}
+
+fn attestation_from_item(item: &HashMap<String, AttributeValue>) -> Option<Attestation> {
+    let attestation_id = attr_string(item, "attestation_id")?;
+    let (user_id, role_id) = attestation_id.split_once(':')?;
+    Some(Attestation {
+        guild_id: GuildId(attr_number(item, "guild_id")?),
+        user_id: UserId(user_id.parse().ok()?),
+        role_id: RoleId(role_id.parse().ok()?),
+        term: attr_string(item, "term")?,
+        expires_at: attr_number(item, "expires_at")?,
+        attested_by: UserId(attr_number(item, "attested_by")?),
+        attested_at: attr_number(item, "attested_at")?,
+    })
 }
 
 // Guild Data:
 // guild_id (primary key): u64
+// config: JSON of config::GuildConfig
 // affiliation_roles: JSON {"student": 2322324243, "member": 4089904238094}
 // major_roles: JSON {"Computer Science, Entry-Level": 32094209878097, "Computer Science":
 // 348023984093}
 // school_roles: JSON {"College of Natural Science": 340580932480}
+//
+// Event Data:
+// event_id (primary key): String
+// guild_id: String
+// name: String
+// cap: u64, maximum number of redemptions
+// created_at, expires_at: unix timestamps
+// redeemed_at: List of unix timestamps
+//
+// Checkin Data:
+// checkin_id (primary key): String
+// guild_id: String
+// name: String
+// created_at: unix timestamp
+// attendees: Map of EID hash to {discord_id: String, checked_in_at: unix timestamp}
+//
+// Audit Data:
+// guild_id (primary key): String
+// entry_id (sort key): String, zero padded unix timestamp followed by a random suffix
+// at: unix timestamp
+// actor, target (optional): String discord ids
+// action: String, e.g. "attest.grant"
+// detail: String
+//
+// Attestation Data:
+// guild_id (primary key): String
+// attestation_id (sort key): String, "{discord_id}:{role_id}"
+// term: String, e.g. "Fall 2026"
+// expires_at, attested_at: unix timestamps
+// attested_by: String discord id
+//
+// API Key Data:
+// key_hash (primary key): String, hex SHA-256 of the key
+// key_id: String, stable across rotations
+// label: String
+// guild_ids: List of String guild ids the key may query
+// endpoints: List of String endpoint names the key may call, all when empty
+// created_at: unix timestamp
+//
+// Scheduled Task Data:
+// task_id (primary key): String, e.g. "guest:{guild_id}:{discord_id}"
+// due_at: unix timestamp
+// task: JSON of scheduler::Task
+//
+// Snapshot Data:
+// job_id (primary key): String, e.g. "rescan-1a2b3c4d"
+// user_id (sort key): String discord id
+// guild_id: String
+// roles: List of String role ids the member held before the job
+// nick (optional): String
+// expires_at: unix timestamp, the table's TTL attribute
+//
+// Funnel Data:
+// guild_id (primary key): String
+// user_id (sort key): String discord id
+// one unix timestamp per stage the user reached, named after analytics::Stage, e.g. "verified"
+//
+// Component Message Data:
+// message_id (primary key): String
+// channel_id, guild_id: String
+// posted_at: unix timestamp
+// expires_at (optional): unix timestamp after which the message's components are disabled
+//
+// Note Data:
+// guild_id (primary key): String
+// note_id (sort key): String, "{discord_id}:{zero padded unix timestamp}-{random suffix}"
+// author: String discord id
+// at: unix timestamp
+// text: String
+//
+// User Data:
+// discord_id (primary key): String
+// encrypted_eid: String, base64 of the deterministically encrypted EID; global secondary index
+// "encrypted_eid-index" with it as the partition key
+// claims: JSON of Claims
+// schema_version (optional): Number, the `migrations` version the record was written or migrated
+// in; records without one predate versions
+// departed_at (optional): unix timestamp a directory re-check found an affiliation gone
+// verified_at (optional): unix timestamp the EID was linked, see `expiry`
+// expired_at (optional): unix timestamp the link expired; the record then has no EID or claims
+//
+// EID Claims:
+// encrypted_eid (primary key): String, as in User Data
+// discord_id: String, the only account that may link the EID; written before the user record
+// and deleted when it loses the EID, so one EID never verifies two accounts
+//
+// Token Nonce Data:
+// nonce (primary key): String, base64 of the token's nonce, or the SHA-256 of tokens without one
+// discord_id: String, the account that redeemed it
+// expires_at: unix timestamp the token is refused after, the table's TTL attribute
+//
+// DM Session Data:
+// discord_id (primary key): String
+// stage: JSON of dm::Stage
+// expires_at: unix timestamp the conversation is forgotten after, the table's TTL attribute
+//
+// Membership Data:
+// discord_id (primary key): String
+// guild_id (sort key): String, a guild the user was seen in and hadn't been seen leaving
+//
+// Review Data:
+// guild_id (primary key): String
+// user_id (sort key): String discord id
+// eid_hash: String, `eid_hash` of the EID the member verified with
+// decision: String, "pending", "approved" or "denied"
+// reasons: List of String, the `review` rules the verification matched
+// requested_at: unix timestamp
+// decided_by (optional): String discord id of the moderator who decided
+//
+// Ban Data:
+// eid_hash (primary key): String, `eid_hash` of the EID the banned account verified with
+// guild_id (sort key): String
+// discord_id: String, the banned account
+// banned_at: unix timestamp
+// evaders (optional): List of String discord ids caught verifying with the EID since
+//
+// Meta Data:
+// name (primary key): String, "schema_version"
+// version: Number, the last migration applied, see `migrations`
+// migrated_at: unix timestamp
+//
+// Rate Limit Data:
+// key (primary key): String, `user:` or `guild:` followed by the id, see `throttle`
+// tokens: Number of verification emails left, fractional while refilling
+// updated_at: unix timestamp the tokens were counted at
+// strikes: Number of times the member ran out within a day of the previous time
+// struck_at: unix timestamp of the last strike
+// blocked_until: unix timestamp the member's cooldown ends at
+// alerted_at: unix timestamp moderators were last alerted at
+// expires_at: unix timestamp the bucket is forgotten after, the table's TTL attribute
+//
+// Tenant Data:
+// guild_id (primary key): String, a guild approved with `/tenant add`
+// added_by: String discord id of the bot's owner
+// added_at: unix timestamp
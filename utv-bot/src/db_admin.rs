@@ -0,0 +1,199 @@
+//! `utv-bot db ...`: inspection and maintenance of the user and guild tables by the bot's
+//! operator, without connecting to the gateway.
+//!
+//! ```text
+//! utv-bot db list
+//! utv-bot db remove <discord id>
+//! utv-bot db stats
+//! utv-bot db migrate
+//! utv-bot db backup [<directory or s3://bucket/prefix>]
+//! utv-bot db restore <backup file or s3:// URL>
+//! ```
+//!
+//! `remove` unlinks an account like `/unverify` does, taking the verified role off in every
+//! server through the REST API when `DISCORD_TOKEN` is set. `migrate` applies the pending
+//! `migrations`, like the stable instance does at startup; it's safe to run more than once.
+//! `backup` takes a `backup` to `BACKUP_TARGET` unless another target is given, and `restore`
+//! checks one against its checksum before writing it back. Stop the bot while restoring, or it
+//! may write over restored items.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDateTime;
+use serenity::http::Http;
+use serenity::model::id::UserId;
+
+use crate::db::{self, attr_string, Table};
+use crate::{audit, backup, cache, migrations, roles, settings};
+
+const USAGE: &str = "usage: utv-bot db list
+       utv-bot db remove <discord id>
+       utv-bot db stats
+       utv-bot db migrate
+       utv-bot db backup [<directory or s3://bucket/prefix>]
+       utv-bot db restore <backup file or s3:// URL>";
+
+/// Runs a `db` subcommand, returning whether it succeeded
+pub async fn run(args: &[String]) -> bool {
+    let db_client = db::DynamoDB::new("users").await;
+    let result = match args.first().map(String::as_str) {
+        Some("list") => {
+            list(&db_client).await;
+            Ok(())
+        }
+        Some("remove") => remove(&db_client, args.get(1)).await,
+        Some("stats") => stats(&db_client).await,
+        Some("migrate") => migrate(&db_client).await,
+        Some("backup") => backup(&db_client, args.get(1)).await,
+        Some("restore") => restore(&db_client, args.get(1)).await,
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(why) = &result {
+        eprintln!("{}", why);
+    }
+    result.is_ok()
+}
+
+/// Prints every linked account with when it verified, its affiliation and a hash of its EID
+async fn list(db_client: &db::DynamoDB) {
+    let mut records = db_client.linked_records().await;
+    records.sort_by_key(|(user_id, _)| *user_id);
+    for (user_id, record) in records {
+        println!(
+            "{}  {}  {}  {}",
+            user_id,
+            record
+                .verified_at
+                .map(date)
+                .unwrap_or_else(|| "unknown".to_string()),
+            record
+                .claims
+                .map(|c| c.affiliation.join(","))
+                .unwrap_or_else(|| "-".to_string()),
+            record
+                .encrypted_eid
+                .map(|eid| db::eid_hash(&eid)[..16].to_string())
+                .unwrap_or_default()
+        );
+    }
+}
+
+async fn remove(db_client: &db::DynamoDB, discord_id: Option<&String>) -> Result<(), String> {
+    let discord_id = discord_id.ok_or(USAGE)?;
+    let user_id = UserId(
+        discord_id
+            .parse()
+            .map_err(|_| format!("{} is not a Discord id", discord_id))?,
+    );
+    match db_client.unlink_user(user_id).await {
+        db::UnlinkResult::Unlinked => println!("Unlinked {}", user_id),
+        db::UnlinkResult::NotLinked => return Err(format!("{} is not verified", user_id)),
+        db::UnlinkResult::Failed => return Err(format!("Couldn't unlink {}", user_id)),
+    }
+    let token = match settings::discord_token() {
+        Ok(token) => token,
+        Err(_) => {
+            println!("DISCORD_TOKEN is not set, so their verified roles are left as they are");
+            return Ok(());
+        }
+    };
+    let http = Http::new_with_token(&token);
+    let guilds = roles::unverify_everywhere(db_client, &http, user_id).await;
+    // the bot itself is the actor, as the operator has no account to record
+    if let Ok(bot_id) = cache::bot_id(&http).await {
+        for guild_id in &guilds {
+            audit::record(
+                db_client,
+                *guild_id,
+                bot_id,
+                "unverify",
+                Some(user_id),
+                "by the operator with utv-bot db remove",
+            )
+            .await;
+        }
+    }
+    println!(
+        "Took the verified role off in the {} servers they're a member of",
+        guilds.len()
+    );
+    Ok(())
+}
+
+async fn stats(db_client: &db::DynamoDB) -> Result<(), String> {
+    let users = db_client.raw_items(Table::Users).await?;
+    let linked = users
+        .iter()
+        .filter(|u| u.contains_key("encrypted_eid"))
+        .collect::<Vec<_>>();
+    let expired = users
+        .iter()
+        .filter(|u| !u.contains_key("encrypted_eid") && u.contains_key("expired_at"))
+        .count();
+    let departed = linked
+        .iter()
+        .filter(|u| u.contains_key("departed_at"))
+        .count();
+    let mut affiliations = BTreeMap::new();
+    for user in &linked {
+        let claims =
+            attr_string(user, "claims").and_then(|c| serde_json::from_str::<db::Claims>(&c).ok());
+        for affiliation in claims.map(|c| c.affiliation).unwrap_or_default() {
+            *affiliations.entry(affiliation).or_insert(0) += 1;
+        }
+    }
+    println!("users: {} linked, {} expired", linked.len(), expired);
+    println!("  departed per the directory: {}", departed);
+    for (affiliation, n) in &affiliations {
+        println!("  {}: {}", affiliation, n);
+    }
+
+    let guilds = db_client.raw_items(Table::Guilds).await?;
+    let mappings = guilds
+        .iter()
+        .map(|guild| {
+            ["affiliation_roles", "school_roles", "major_roles"]
+                .iter()
+                .filter_map(|key| attr_string(guild, key))
+                .filter_map(|data| serde_json::from_str::<HashMap<String, u64>>(&data).ok())
+                .map(|roles| roles.len())
+                .sum::<usize>()
+        })
+        .collect::<Vec<_>>();
+    println!(
+        "guilds: {} configured, {} with role mappings, {} mappings",
+        guilds.len(),
+        mappings.iter().filter(|n| **n > 0).count(),
+        mappings.iter().sum::<usize>()
+    );
+    Ok(())
+}
+
+async fn migrate(db_client: &db::DynamoDB) -> Result<(), String> {
+    let version = migrations::run(db_client).await?;
+    println!("The tables are at schema version {}", version);
+    Ok(())
+}
+
+async fn backup(db_client: &db::DynamoDB, target: Option<&String>) -> Result<(), String> {
+    let target = match target {
+        Some(target) => backup::Target::parse(target)?,
+        None => settings::backup_target()?.ok_or("BACKUP_TARGET is not set, give a target")?,
+    };
+    let (location, items) = backup::backup(db_client, &target).await?;
+    println!("Backed up {} items to {}", items, location);
+    Ok(())
+}
+
+async fn restore(db_client: &db::DynamoDB, location: Option<&String>) -> Result<(), String> {
+    let location = location.ok_or(USAGE)?;
+    let items = backup::restore(db_client, location).await?;
+    println!("Restored {} items from {}", items, location);
+    Ok(())
+}
+
+fn date(at: i64) -> String {
+    NaiveDateTime::from_timestamp(at, 0)
+        .format("%Y-%m-%d")
+        .to_string()
+}
@@ -0,0 +1,172 @@
+//! The Discord requests that change members, behind a trait so the code deciding them can be
+//! tested against [`mock::MockDiscord`] instead of a live connection.
+
+use serenity::async_trait;
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::id::RoleId;
+
+#[async_trait]
+pub trait Discord: Send + Sync {
+    /// Adds roles to the member, keeping the ones they have
+    async fn add_roles(&self, member: &Member, roles: &[RoleId]) -> serenity::Result<()>;
+    async fn set_nickname(&self, member: &Member, nickname: &str) -> serenity::Result<()>;
+}
+
+#[async_trait]
+impl Discord for Http {
+    async fn add_roles(&self, member: &Member, roles: &[RoleId]) -> serenity::Result<()> {
+        let mut all = member.roles.clone();
+        all.extend(roles.iter().filter(|r| !member.roles.contains(r)));
+        member
+            .guild_id
+            .edit_member(self, member.user.id, |m| m.roles(&all))
+            .await
+            .map(|_| ())
+    }
+
+    async fn set_nickname(&self, member: &Member, nickname: &str) -> serenity::Result<()> {
+        member
+            .guild_id
+            .edit_member(self, member.user.id, |m| m.nickname(nickname))
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Adds roles to the member, and to `member.roles` once Discord accepted them
+pub async fn add_roles(
+    discord: &dyn Discord,
+    member: &mut Member,
+    roles: &[RoleId],
+) -> serenity::Result<()> {
+    discord.add_roles(member, roles).await?;
+    for role in roles {
+        if !member.roles.contains(role) {
+            member.roles.push(*role);
+        }
+    }
+    Ok(())
+}
+
+/// Sets the member's nickname, and `member.nick` once Discord accepted it
+pub async fn set_nickname(
+    discord: &dyn Discord,
+    member: &mut Member,
+    nickname: &str,
+) -> serenity::Result<()> {
+    discord.set_nickname(member, nickname).await?;
+    member.nick = Some(nickname.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::sync::Mutex;
+
+    use serde_json::json;
+    use serenity::model::guild::Role;
+    use serenity::model::id::{GuildId, UserId};
+
+    use super::*;
+
+    pub const GUILD: GuildId = GuildId(10);
+
+    /// Records the requests made instead of sending them, refusing them all with `fail`
+    #[derive(Default)]
+    pub struct MockDiscord {
+        pub fail: bool,
+        pub requests: Mutex<Vec<String>>,
+    }
+
+    impl MockDiscord {
+        pub fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn request(&self, request: String) -> serenity::Result<()> {
+            self.requests.lock().unwrap().push(request);
+            if self.fail {
+                return Err(serenity::Error::Other("refused by the mock"));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Discord for MockDiscord {
+        async fn add_roles(&self, member: &Member, roles: &[RoleId]) -> serenity::Result<()> {
+            self.request(format!("add_roles {} {:?}", member.user.id, roles))
+        }
+
+        async fn set_nickname(&self, member: &Member, nickname: &str) -> serenity::Result<()> {
+            self.request(format!("set_nickname {} {}", member.user.id, nickname))
+        }
+    }
+
+    pub fn member(user_id: u64, nick: Option<&str>, roles: &[RoleId]) -> Member {
+        serde_json::from_value(json!({
+            "guild_id": GUILD.to_string(),
+            "user": {
+                "id": UserId(user_id).to_string(),
+                "username": "Bevo",
+                "discriminator": "0001",
+                "avatar": null,
+            },
+            "nick": nick,
+            "roles": roles.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+            "joined_at": "2021-08-23T00:00:00Z",
+            "deaf": false,
+            "mute": false,
+            "pending": false,
+        }))
+        .expect("a member")
+    }
+
+    pub fn role(role_id: u64, name: &str) -> Role {
+        serde_json::from_value(json!({
+            "id": RoleId(role_id).to_string(),
+            "guild_id": GUILD.to_string(),
+            "name": name,
+            "color": 0,
+            "hoist": false,
+            "managed": false,
+            "mentionable": false,
+            "permissions": "0",
+            "position": 1,
+        }))
+        .expect("a role")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::mock::*;
+    use super::*;
+
+    #[tokio::test]
+    async fn added_roles_are_kept_on_the_member() {
+        let discord = MockDiscord::default();
+        let mut member = member(1, None, &[RoleId(5)]);
+        add_roles(&discord, &mut member, &[RoleId(6)])
+            .await
+            .unwrap();
+        assert_eq!(member.roles, vec![RoleId(5), RoleId(6)]);
+        assert_eq!(discord.requests(), vec!["add_roles 1 [RoleId(6)]"]);
+    }
+
+    #[tokio::test]
+    async fn refused_requests_leave_the_member() {
+        let discord = MockDiscord {
+            fail: true,
+            ..Default::default()
+        };
+        let mut member = member(1, Some("Bevo"), &[]);
+        assert!(add_roles(&discord, &mut member, &[RoleId(6)])
+            .await
+            .is_err());
+        assert!(set_nickname(&discord, &mut member, "Bevo ✓").await.is_err());
+        assert!(member.roles.is_empty());
+        assert_eq!(member.nick.as_deref(), Some("Bevo"));
+    }
+}
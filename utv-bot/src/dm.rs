@@ -0,0 +1,154 @@
+//! Verification by DM, for users who can't use slash commands: screen readers on mobile
+//! clients, or servers that don't let members use application commands.
+//!
+//! Any DM to the bot starts a guided conversation. The bot asks for the EID, or `web` for a link
+//! to the portal, sends the email like `/verify` and waits for the token, which it redeems like
+//! `/redeem`; a token sent right away is redeemed without asking. Each user's stage is stored in
+//! the DM sessions table and forgotten after [`SESSION_SECS`] of silence, and `cancel` ends the
+//! conversation early. Turned off with the `direct-messages` feature.
+
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::UserId;
+use tracing::error;
+
+use crate::i18n::Locale;
+use crate::{db, handlers, onboarding, redeem, response, throttle};
+
+/// How long the bot waits for the next message
+const SESSION_SECS: i64 = 60 * 60;
+/// Tokens are much longer than any EID, so a first message this long is taken as one
+const MIN_TOKEN_CHARS: usize = 40;
+
+/// Where a user is in the conversation
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+enum Stage {
+    /// asked for the EID
+    Eid,
+    /// sent the email, waiting for the token
+    Token,
+}
+
+/// Answers a DM to the bot
+pub async fn handle(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    message: &Message,
+) -> serenity::Result<()> {
+    let user_id = message.author.id;
+    let text = message.content.trim();
+    if text.eq_ignore_ascii_case("cancel") {
+        db_client.delete_dm_session(user_id).await;
+        return reply(
+            ctx,
+            message,
+            "Verification cancelled. Message me again any time to start over.",
+        )
+        .await;
+    }
+    let stage = db_client
+        .get_dm_session(user_id)
+        .await
+        .and_then(|stage| serde_json::from_str(&stage).ok());
+    match stage {
+        Some(Stage::Token) => {
+            if redeem::redeem_message(db_client, ctx, message).await? {
+                db_client.delete_dm_session(user_id).await;
+            }
+            Ok(())
+        }
+        None if text.len() >= MIN_TOKEN_CHARS => {
+            redeem::redeem_message(db_client, ctx, message).await?;
+            Ok(())
+        }
+        None => {
+            if db_client.is_verified(user_id.0).await {
+                return reply(ctx, message, "You're already verified.").await;
+            }
+            save(db_client, user_id, &Stage::Eid).await;
+            reply(
+                ctx,
+                message,
+                "Let's verify your UT EID. Reply with your EID, like `abc123`, and I'll email you a \
+                 token. Reply `web` instead to verify on the web with your UT login, or `cancel` \
+                 to stop.",
+            )
+            .await
+        }
+        Some(Stage::Eid) if text.eq_ignore_ascii_case("web") => {
+            db_client.delete_dm_session(user_id).await;
+            reply(
+                ctx,
+                message,
+                format!(
+                    "Open {} and log in with your UT EID. The link is made for your account, \
+                     don't share it.",
+                    onboarding::portal_link(None, user_id)
+                ),
+            )
+            .await
+        }
+        Some(Stage::Eid) => {
+            if let Some(guidance) = handlers::eid_input_problem(Locale::En, text) {
+                return reply(
+                    ctx,
+                    message,
+                    format!(
+                        "{} Reply with your EID, `web` to verify on the web or `cancel` to stop.",
+                        guidance
+                    ),
+                )
+                .await;
+            }
+            if let Some(retry_at) = throttle::take(db_client, &ctx.http, None, user_id).await {
+                return reply(
+                    ctx,
+                    message,
+                    throttle::limited_message(Locale::En, retry_at),
+                )
+                .await;
+            }
+            if !handlers::request_email(db_client, None, user_id, text).await {
+                return reply(
+                    ctx,
+                    message,
+                    "Couldn't send the email. Check you entered your EID correctly and reply with \
+                     it again.",
+                )
+                .await;
+            }
+            save(db_client, user_id, &Stage::Token).await;
+            reply(
+                ctx,
+                message,
+                "We sent a token to the email address the UT Directory has for your EID. Reply \
+                 with the token once it arrives.",
+            )
+            .await
+        }
+    }
+}
+
+async fn save(db_client: &db::DynamoDB, user_id: UserId, stage: &Stage) {
+    let saved = match serde_json::to_string(stage) {
+        Ok(stage) => {
+            db_client
+                .put_dm_session(user_id, &stage, response::unix_now() + SESSION_SECS)
+                .await
+        }
+        Err(_) => false,
+    };
+    if !saved {
+        error!("Failed to save the DM session of {}", user_id);
+    }
+}
+
+async fn reply(ctx: &Context, message: &Message, text: impl ToString) -> serenity::Result<()> {
+    message
+        .channel_id
+        .say(&ctx.http, text.to_string())
+        .await
+        .map(|_| ())
+}
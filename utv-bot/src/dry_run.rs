@@ -0,0 +1,423 @@
+//! `/rescan dry-run:True`: what a scan would change, without changing anything.
+//!
+//! Every member is checked like `handle_member_status` does, reading their verification and the
+//! guild's policies but making no requests that change roles or nicknames. The proposed changes
+//! are listed [`PAGE_LINES`] members to a page, with buttons to move between pages. Banned EIDs
+//! and verifications held for review aren't looked at, so members caught by them are listed as
+//! the verified members they'd be otherwise. Reports are kept in memory for [`REPORT_SECS`].
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures::stream::{self, StreamExt};
+use lazy_static::lazy_static;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::utils::Color;
+
+use crate::config::GuildConfig;
+use crate::{db, members, nickname_policy, response, roles, unrenamable, SCAN_CONCURRENCY};
+
+pub const COMPONENT_PREFIX: &str = "dry-run:";
+const PAGE_LINES: usize = 20;
+/// as long as the interaction token the report is edited with
+const REPORT_SECS: i64 = 15 * 60;
+
+struct Report {
+    checked: usize,
+    renames: usize,
+    roles: usize,
+    lines: Vec<String>,
+    created_at: i64,
+}
+
+lazy_static! {
+    static ref REPORTS: Mutex<HashMap<String, Report>> = Mutex::new(HashMap::new());
+}
+
+/// What a scan would change for one member
+#[derive(Default)]
+struct Plan {
+    nickname: Option<(String, String)>,
+    roles_added: Vec<RoleId>,
+    roles_removed: Vec<RoleId>,
+}
+
+pub async fn run(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    response::defer(&ctx, &command, true).await?;
+    let guild_members = match members::fetch_all(&ctx.http, guild_id).await {
+        Ok(guild_members) => guild_members,
+        Err(why) => {
+            command
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    response.create_embed(|embed| {
+                        embed.title(format!("Command Failed: could not list members: {}", why))
+                    })
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+    let role_mappings = db_client.get_role_config(guild_id).await;
+    let config = db_client.get_guild_config(guild_id).await;
+    let owner = unrenamable::owner(&ctx.http, guild_id).await;
+
+    let (role_mappings, config) = (&role_mappings, &config);
+    let mut plans = stream::iter(guild_members.iter())
+        .map(|member| async move {
+            let plan = plan_member(db_client, member, role_mappings, config, owner).await;
+            (member, plan)
+        })
+        .buffer_unordered(SCAN_CONCURRENCY)
+        .filter(|(_, plan)| {
+            let changes = plan.nickname.is_some()
+                || !plan.roles_added.is_empty()
+                || !plan.roles_removed.is_empty();
+            async move { changes }
+        })
+        .collect::<Vec<_>>()
+        .await;
+    plans.sort_by_key(|(member, _)| member.display_name().to_lowercase());
+
+    let report = Report {
+        checked: guild_members.len(),
+        renames: plans.iter().filter(|(_, p)| p.nickname.is_some()).count(),
+        roles: plans
+            .iter()
+            .map(|(_, p)| p.roles_added.len() + p.roles_removed.len())
+            .sum(),
+        lines: plans
+            .iter()
+            .map(|(member, plan)| line(member.user.id, plan))
+            .collect(),
+        created_at: response::unix_now(),
+    };
+    let id = command.id.to_string();
+    command
+        .edit_original_interaction_response(&ctx.http, |response| {
+            response
+                .create_embed(|embed| page(embed, &report, 0))
+                .components(|components| buttons(components, &id, &report, 0))
+        })
+        .await?;
+    let mut reports = REPORTS.lock().unwrap();
+    reports.retain(|_, r| report.created_at - r.created_at < REPORT_SECS);
+    reports.insert(id, report);
+    Ok(())
+}
+
+/// Handles the page buttons of a report
+pub async fn pressed(component: MessageComponentInteraction, ctx: Context) -> serenity::Result<()> {
+    let rest = component
+        .data
+        .custom_id
+        .strip_prefix(COMPONENT_PREFIX)
+        .unwrap_or_default();
+    let (id, number) = match rest.rsplit_once(':') {
+        Some((id, number)) => (id.to_string(), number.parse().unwrap_or(0)),
+        None => return Ok(()),
+    };
+    // the lock can't be held across the response
+    let shown = REPORTS.lock().unwrap().get(&id).map(|report| {
+        let mut embed = CreateEmbed::default();
+        let mut components = CreateComponents::default();
+        page(&mut embed, report, number);
+        buttons(&mut components, &id, report, number);
+        (embed, components)
+    });
+    match shown {
+        Some((embed, components)) => {
+            component
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|message| {
+                            message.add_embed(embed).set_components(components)
+                        })
+                })
+                .await
+        }
+        None => {
+            response::respond_component_title(
+                &ctx,
+                &component,
+                true,
+                "This report expired, run `/rescan dry-run:True` again",
+            )
+            .await
+        }
+    }
+}
+
+/// Looks up what [`plan`] needs about the member
+async fn plan_member(
+    db_client: &db::DynamoDB,
+    member: &Member,
+    role_mappings: &HashMap<String, u64>,
+    config: &GuildConfig,
+    owner: Option<UserId>,
+) -> Plan {
+    let claims = db_client.get_user(member.user.id.0).await;
+    let eid = match claims {
+        Some(_) => nickname_policy::shown_eid(db_client, member.user.id, config).await,
+        None => None,
+    };
+    plan(
+        member,
+        claims.as_ref(),
+        eid.as_deref(),
+        role_mappings,
+        config,
+        owner,
+        response::unix_now(),
+    )
+}
+
+/// Works out what `handle_member_status` would do to the member; keep in sync with
+/// `apply_member_status`
+fn plan(
+    member: &Member,
+    claims: Option<&db::Claims>,
+    eid: Option<&str>,
+    role_mappings: &HashMap<String, u64>,
+    config: &GuildConfig,
+    owner: Option<UserId>,
+    now: i64,
+) -> Plan {
+    let mut plan = Plan::default();
+    let original = member.display_name().to_string();
+    let mut nickname = nickname_policy::clean(&original);
+    if let Some(claims) = claims {
+        plan.roles_added = roles::mapped_roles(claims, role_mappings, &member.roles);
+        let student_role = role_mappings.get("student").map(|r| RoleId(*r));
+        let was_student = student_role.map_or(false, |r| member.roles.contains(&r));
+        let is_alumnus = config
+            .alumni_role
+            .map_or(false, |r| member.roles.contains(&r));
+        let marker = if claims.affiliation.contains(&"student".to_string()) {
+            Some(if config.native_marker {
+                String::new()
+            } else {
+                config.marker_symbol().to_string()
+            })
+        } else if was_student || is_alumnus {
+            if let Some(student_role) = student_role.filter(|_| was_student) {
+                plan.roles_removed.push(student_role);
+                plan.roles_added.extend(config.alumni_role);
+            }
+            Some(config.alumni_suffix.clone().unwrap_or_default())
+        } else {
+            None
+        };
+        nickname = match marker {
+            Some(marker) => nickname_policy::compose(config, &nickname, claims, eid, &marker, now),
+            // verified members with no marker keep their nickname
+            None => original.clone(),
+        };
+    }
+    // Discord never lets bots rename the owner
+    if original != nickname && owner != Some(member.user.id) {
+        plan.nickname = Some((original, nickname));
+    }
+    plan
+}
+
+fn line(user_id: UserId, plan: &Plan) -> String {
+    let mut changes = Vec::new();
+    if let Some((from, to)) = &plan.nickname {
+        changes.push(format!("`{}` → `{}`", from, to));
+    }
+    changes.extend(plan.roles_added.iter().map(|r| format!("+<@&{}>", r)));
+    changes.extend(plan.roles_removed.iter().map(|r| format!("-<@&{}>", r)));
+    format!("<@{}>: {}", user_id, changes.join(", "))
+}
+
+fn pages(report: &Report) -> usize {
+    ((report.lines.len() + PAGE_LINES - 1) / PAGE_LINES).max(1)
+}
+
+fn page<'a>(embed: &'a mut CreateEmbed, report: &Report, number: usize) -> &'a mut CreateEmbed {
+    let number = number.min(pages(report) - 1);
+    let lines = report
+        .lines
+        .iter()
+        .skip(number * PAGE_LINES)
+        .take(PAGE_LINES)
+        .cloned()
+        .collect::<Vec<_>>();
+    embed
+        .title(format!(
+            "Dry Run: {} of {} Members Would Change",
+            response::count(report.lines.len()),
+            response::count(report.checked)
+        ))
+        .description(if lines.is_empty() {
+            "Every member's nickname and roles are already in place.".to_string()
+        } else {
+            lines.join("\n")
+        })
+        .field("Nicknames", response::count(report.renames), true)
+        .field("Role changes", response::count(report.roles), true)
+        .footer(|footer| {
+            footer.text(format!(
+                "Page {}/{}. Nothing was changed, run /rescan without dry-run to apply this.",
+                number + 1,
+                pages(report)
+            ))
+        })
+        .color(Color::from_rgb(191, 87, 0))
+}
+
+fn buttons<'a>(
+    components: &'a mut CreateComponents,
+    id: &str,
+    report: &Report,
+    number: usize,
+) -> &'a mut CreateComponents {
+    let last = pages(report) - 1;
+    if last == 0 {
+        return components;
+    }
+    let number = number.min(last);
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!(
+                    "{}{}:{}",
+                    COMPONENT_PREFIX,
+                    id,
+                    number.saturating_sub(1)
+                ))
+                .label("Previous")
+                .style(ButtonStyle::Secondary)
+                .disabled(number == 0)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!(
+                    "{}{}:{}",
+                    COMPONENT_PREFIX,
+                    id,
+                    (number + 1).min(last)
+                ))
+                .label("Next")
+                .style(ButtonStyle::Secondary)
+                .disabled(number == last)
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::discord::mock::member;
+
+    const STUDENT: RoleId = RoleId(20);
+    const ALUMNI: RoleId = RoleId(21);
+    const ECE: RoleId = RoleId(22);
+
+    fn claims(affiliation: &str) -> db::Claims {
+        db::Claims {
+            major: vec!["ece".to_string()],
+            school: vec![],
+            affiliation: vec![affiliation.to_string()],
+            name: None,
+        }
+    }
+
+    fn mappings() -> HashMap<String, u64> {
+        HashMap::from([
+            ("student".to_string(), STUDENT.0),
+            ("ece".to_string(), ECE.0),
+        ])
+    }
+
+    fn plan_for(member: &Member, claims: Option<&db::Claims>, config: &GuildConfig) -> Plan {
+        plan(member, claims, None, &mappings(), config, None, 0)
+    }
+
+    #[test]
+    fn verified_student_gets_roles_and_marker() {
+        let member = member(1, Some("Bevo"), &[]);
+        let plan = plan_for(&member, Some(&claims("student")), &GuildConfig::default());
+        assert_eq!(plan.roles_added, vec![STUDENT, ECE]);
+        assert_eq!(
+            plan.nickname,
+            Some(("Bevo".to_string(), "Bevo ✓".to_string()))
+        );
+    }
+
+    #[test]
+    fn compliant_member_is_left_alone() {
+        let member = member(1, Some("Bevo ✓"), &[STUDENT, ECE]);
+        let plan = plan_for(&member, Some(&claims("student")), &GuildConfig::default());
+        assert!(plan.roles_added.is_empty() && plan.nickname.is_none());
+    }
+
+    #[test]
+    fn unverified_member_loses_fake_marker() {
+        let member = member(1, Some("Bevo ✅"), &[]);
+        let plan = plan_for(&member, None, &GuildConfig::default());
+        assert!(plan.roles_added.is_empty());
+        assert_eq!(
+            plan.nickname,
+            Some(("Bevo ✅".to_string(), "Bevo".to_string()))
+        );
+    }
+
+    #[test]
+    fn owner_is_not_renamed() {
+        let member = member(1, Some("Bevo ✅"), &[]);
+        let config = GuildConfig::default();
+        let plan = plan(
+            &member,
+            None,
+            None,
+            &mappings(),
+            &config,
+            Some(UserId(1)),
+            0,
+        );
+        assert!(plan.nickname.is_none());
+    }
+
+    #[test]
+    fn former_student_moves_to_alumni() {
+        let member = member(1, Some("Bevo ✓"), &[STUDENT, ECE]);
+        let config = GuildConfig {
+            alumni_role: Some(ALUMNI),
+            alumni_suffix: Some("🎓".to_string()),
+            ..Default::default()
+        };
+        let plan = plan_for(&member, Some(&claims("affiliate")), &config);
+        assert_eq!(plan.roles_removed, vec![STUDENT]);
+        assert_eq!(plan.roles_added, vec![ALUMNI]);
+        assert_eq!(
+            plan.nickname,
+            Some(("Bevo ✓".to_string(), "Bevo 🎓".to_string()))
+        );
+    }
+
+    #[test]
+    fn report_lines_list_every_change() {
+        let plan = Plan {
+            nickname: Some(("Bevo".to_string(), "Bevo ✓".to_string())),
+            roles_added: vec![ECE],
+            roles_removed: vec![STUDENT],
+        };
+        assert_eq!(
+            line(UserId(1), &plan),
+            "<@1>: `Bevo` → `Bevo ✓`, +<@&22>, -<@&20>"
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Voter eligibility for org elections: verified members who joined before a cutoff date.
+//!
+//! Admins can export a list of eligible voters, signed with the certificate key so election
+//! officials can check it against `/certificate-key`, or post a button that lets each member
+//! check their own eligibility.
+
+use std::borrow::Cow;
+
+use serde::Serialize;
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::utils::Color;
+
+use crate::{certificate, components, db, handlers, members, response};
+
+/// Custom id prefix of the eligibility check buttons, followed by the cutoff timestamp
+pub const COMPONENT_PREFIX: &str = "eligibility:";
+
+#[derive(Serialize)]
+struct EligibilityList {
+    guild_id: String,
+    joined_before: i64,
+    generated_at: i64,
+    voters: Vec<String>,
+}
+
+/// The export, with an Ed25519 signature over the exact `payload` string so tampering can be
+/// detected by anyone with the public key
+#[derive(Serialize)]
+struct SignedEligibilityList {
+    payload: String,
+    signature: String,
+}
+
+pub async fn eligible_voters(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let (name, options) = match handlers::subcommand(&command) {
+        Some(sub) => sub,
+        None => ("", &[][..]),
+    };
+    let cutoff = match parse_cutoff(options) {
+        Some(cutoff) => cutoff,
+        None => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "Enter the cutoff date as YYYY-MM-DD",
+            )
+            .await
+        }
+    };
+    match name {
+        "export" => export(db_client, &command, guild_id, cutoff, &ctx).await,
+        "panel" => panel(db_client, &command, guild_id, cutoff, &ctx).await,
+        _ => {
+            response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    }
+}
+
+fn parse_cutoff(options: &[ApplicationCommandInteractionDataOption]) -> Option<i64> {
+    handlers::option_date(options, "joined-before")
+}
+
+async fn export(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    cutoff: i64,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let key_pair = match certificate::key_pair() {
+        Some(key_pair) => key_pair,
+        None => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Exports are signed with the certificate key, which this bot doesn't have.",
+            )
+            .await
+        }
+    };
+    // walking the member list takes longer than the interaction deadline in large guilds
+    response::defer(ctx, command, true).await?;
+
+    let candidates = members::fetch_all(&ctx.http, guild_id)
+        .await?
+        .into_iter()
+        .filter(|m| !m.user.bot && m.joined_at.map_or(false, |j| j.timestamp() < cutoff))
+        .map(|m| m.user.id)
+        .collect::<Vec<_>>();
+    let mut voters = db_client
+        .verified_among(&candidates)
+        .await
+        .into_iter()
+        .map(|id| id.0.to_string())
+        .collect::<Vec<_>>();
+    voters.sort();
+
+    let list = EligibilityList {
+        guild_id: guild_id.0.to_string(),
+        joined_before: cutoff,
+        generated_at: response::unix_now(),
+        voters,
+    };
+    let payload = serde_json::to_string(&list).unwrap();
+    let signed = SignedEligibilityList {
+        signature: utv_token::signature_public(payload.as_bytes(), key_pair),
+        payload,
+    };
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(serde_json::to_vec_pretty(&signed).unwrap()),
+                    filename: "eligible-voters.json".to_string(),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title("Eligible Voters")
+                        .description(format!(
+                            "{} of {} members who joined before {} are verified and eligible.",
+                            list.voters.len(),
+                            candidates.len(),
+                            response::timestamp(cutoff, response::TimestampStyle::LongDate)
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn panel(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    cutoff: i64,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title("Voter Eligibility")
+                                .description(format!(
+                                    "Verified members who joined before {} can vote. \
+                                     Press the button to check your eligibility.",
+                                    response::timestamp(cutoff, response::TimestampStyle::LongDate)
+                                ))
+                                .color(Color::from_rgb(191, 87, 0))
+                        })
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button
+                                        .style(ButtonStyle::Primary)
+                                        .label("Am I eligible?")
+                                        .custom_id(format!("{}{}", COMPONENT_PREFIX, cutoff))
+                                })
+                            })
+                        })
+                })
+        })
+        .await?;
+    // members may keep checking their eligibility after the cutoff, so panels don't expire
+    components::track_response(db_client, command, guild_id, ctx, None).await;
+    Ok(())
+}
+
+/// Handles a press of an eligibility check button
+pub async fn check(
+    db_client: &'static db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let cutoff: i64 = component
+        .data
+        .custom_id
+        .trim_start_matches(COMPONENT_PREFIX)
+        .parse()
+        .unwrap_or(0);
+    let joined_in_time = component
+        .member
+        .as_ref()
+        .and_then(|m| m.joined_at)
+        .map_or(false, |j| j.timestamp() < cutoff);
+    let title = if !joined_in_time {
+        "You are not eligible: you joined this server after the cutoff date."
+    } else if !db_client.is_verified(component.user.id.0).await {
+        "You are not eligible yet: verify your account with `/verify` first."
+    } else {
+        "You are eligible to vote!"
+    };
+    response::respond_component_title(&ctx, &component, true, title).await
+}
@@ -0,0 +1,23 @@
+//! Errors of the event handlers.
+//!
+//! Handlers that work through many members or queue messages return these instead of
+//! unwrapping, and their loops log them per member or message so one missing permission or
+//! deleted role doesn't stop the rest of a scan.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Discord request failed: {0}")]
+    Discord(#[from] serenity::Error),
+    #[error("couldn't add roles: {0}")]
+    AddRoles(serenity::Error),
+    #[error("couldn't set the nickname: {0}")]
+    Nickname(serenity::Error),
+    #[error("SQS request failed: {0}")]
+    Queue(String),
+    #[error("invalid queue message: {0}")]
+    InvalidMessage(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
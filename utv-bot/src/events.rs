@@ -0,0 +1,270 @@
+//! In-person verification events.
+//!
+//! `/event-qr create` hands out a QR code pointing at a signed, short-lived link on our HTTP
+//! server. Visiting the link forwards the visitor to the verification portal for the event's
+//! guild, passing the ticket along, while the event is running and under its cap. A redemption
+//! is only counted once the portal links a new account with the ticket, see [`attribute`], so
+//! prefetched or reopened links don't use up the cap.
+
+use std::borrow::Cow;
+
+use axum::extract::{Extension, Path};
+use axum::http::{StatusCode, Uri};
+use axum::response::Redirect;
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::utils::Color;
+use tracing::info;
+
+use crate::response::{self, TimestampStyle};
+use crate::{db, handlers, http, PORTAL_URL, PUBLIC_URL, SHARED_KEY};
+
+/// What event links are signed for, so no other signed value passes as one
+const TICKET_PURPOSE: &str = "event-ticket";
+const DEFAULT_HOURS: i64 = 4;
+const MAX_HOURS: i64 = 72;
+const DEFAULT_CAP: i64 = 100;
+
+/// Signed into the QR code link, binding it to an event and guild
+#[derive(Serialize, Deserialize)]
+struct Ticket {
+    event_id: String,
+    guild_id: u64,
+    expires_at: i64,
+}
+
+pub async fn event_qr(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    match handlers::subcommand(&command) {
+        Some(("create", options)) => create(db_client, &command, options, guild_id, &ctx).await,
+        Some(("report", options)) => report(db_client, &command, options, guild_id, &ctx).await,
+        _ => {
+            response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    }
+}
+
+async fn create(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    guild_id: GuildId,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let name = handlers::option_str(options, "name").unwrap_or("Tabling Event");
+    let hours = handlers::option_int(options, "hours")
+        .unwrap_or(DEFAULT_HOURS)
+        .clamp(1, MAX_HOURS);
+    let cap = handlers::option_int(options, "cap")
+        .unwrap_or(DEFAULT_CAP)
+        .max(1);
+
+    // generating the image and saving the event can take a moment
+    response::defer(ctx, command, true).await?;
+
+    let now = response::unix_now();
+    let event = db::Event {
+        event_id: format!("{:08x}", rand::random::<u32>()),
+        guild_id,
+        name: name.to_string(),
+        cap: cap as u64,
+        created_at: now,
+        expires_at: now + hours * 60 * 60,
+        redeemed_at: Vec::new(),
+    };
+    let ticket = Ticket {
+        event_id: event.event_id.clone(),
+        guild_id: guild_id.0,
+        expires_at: event.expires_at,
+    };
+    let url = format!(
+        "{}/events/{}",
+        PUBLIC_URL.as_str(),
+        utv_token::sign(TICKET_PURPOSE, &ticket, &SHARED_KEY)
+    );
+
+    let png = qr_png(&url);
+    let created = png.is_some() && db_client.create_event(&event).await;
+    let png = match png {
+        Some(png) if created => png,
+        _ => {
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    message.create_embed(|embed| {
+                        embed
+                            .title("Failed to create the event, please try again")
+                            .color(Color::from_rgb(255, 0, 0))
+                    })
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(png),
+                    filename: "event.png".to_string(),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title(format!("{} ({})", event.name, event.event_id))
+                        .description(format!(
+                            "Scan to verify. Accepts up to {} redemptions until {}.\n\
+                             Run `/event-qr report event:{}` afterwards for a summary.",
+                            event.cap,
+                            response::datetime(event.expires_at),
+                            event.event_id
+                        ))
+                        .url(&url)
+                        .image("attachment://event.png")
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn report(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    options: &[ApplicationCommandInteractionDataOption],
+    guild_id: GuildId,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let event = match handlers::option_str(options, "event") {
+        Some(event_id) => db_client.get_event(event_id).await,
+        None => None,
+    };
+    let event = match event {
+        Some(event) if event.guild_id == guild_id => event,
+        _ => {
+            return response::respond_title(ctx, command, true, "No such event in this guild").await
+        }
+    };
+
+    let first = event.redeemed_at.iter().min();
+    let last = event.redeemed_at.iter().max();
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title(format!("Event Report: {}", event.name))
+            .color(Color::from_rgb(191, 87, 0))
+            .field(
+                "Redemptions",
+                format!("{}/{}", event.redeemed_at.len(), event.cap),
+                true,
+            )
+            .field("Created", response::datetime(event.created_at), true)
+            .field(
+                if event.expires_at > response::unix_now() {
+                    "Ends"
+                } else {
+                    "Ended"
+                },
+                response::datetime(event.expires_at),
+                true,
+            );
+        if let (Some(first), Some(last)) = (first, last) {
+            embed.field(
+                "Redemption Window",
+                format!(
+                    "{} to {}",
+                    response::timestamp(*first, TimestampStyle::ShortTime),
+                    response::timestamp(*last, TimestampStyle::ShortTime)
+                ),
+                false,
+            );
+        }
+        embed
+    })
+    .await
+}
+
+/// Route handler for the links encoded in event QR codes
+pub async fn redeem(
+    Path(signed): Path<String>,
+    Extension(state): Extension<http::State>,
+) -> Result<Redirect, (StatusCode, &'static str)> {
+    let ticket: Ticket = utv_token::verify(TICKET_PURPOSE, &signed, &SHARED_KEY)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid event link."))?;
+    let open = match state.db_client.get_event(&ticket.event_id).await {
+        Some(event) => {
+            event.expires_at > response::unix_now() && (event.redeemed_at.len() as u64) < event.cap
+        }
+        None => false,
+    };
+    if !open {
+        return Err((
+            StatusCode::GONE,
+            "This event has ended or reached its limit. Please verify from the website instead.",
+        ));
+    }
+    let portal: Uri = format!(
+        "{}/app?guild_id={}&event={}",
+        PORTAL_URL.as_str(),
+        ticket.guild_id,
+        signed
+    )
+    .parse()
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Misconfigured portal url.",
+        )
+    })?;
+    Ok(Redirect::temporary(portal))
+}
+
+/// Counts a verification the portal completed for someone who arrived with the `signed` ticket,
+/// unless the event has ended or is full by now
+pub async fn attribute(db_client: &db::DynamoDB, signed: &str) {
+    let ticket: Ticket = match utv_token::verify(TICKET_PURPOSE, signed, &SHARED_KEY) {
+        Ok(ticket) => ticket,
+        Err(_) => return,
+    };
+    if !db_client
+        .redeem_event(&ticket.event_id, response::unix_now())
+        .await
+    {
+        info!(
+            "Verification not counted, event {} ended or is full",
+            ticket.event_id
+        );
+    }
+}
+
+/// Renders `data` as a QR code, also used by `/instructions`' card, certificates and `/checkin`
+pub fn qr_png(data: &str) -> Option<Vec<u8>> {
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    let image = code.render::<Luma<u8>>().min_dimensions(512, 512).build();
+    let mut png = Vec::new();
+    DynamicImage::ImageLuma8(image)
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .ok()?;
+    Some(png)
+}
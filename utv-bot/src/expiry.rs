@@ -0,0 +1,101 @@
+//! Verification expiry, so members who left UT don't stay verified forever.
+//!
+//! With `VERIFICATION_EXPIRY_DAYS` set, links are checked once a day and those older than that
+//! expire: the EID and claims are removed from the user's record, which keeps `expired_at`, the
+//! verified role is taken off them in every guild (the ✓ follows when the member update is
+//! handled) and they're asked by DM to `/verify` again. Links from before `verified_at` was
+//! recorded get the full period from the first check.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::http::Http;
+use serenity::model::id::UserId;
+use tracing::{error, info, warn};
+
+use crate::{audit, db, response, roles, settings, stats, webhooks};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const DAY: i64 = 24 * 60 * 60;
+
+/// Expires old verifications daily, doing nothing when `VERIFICATION_EXPIRY_DAYS` is unset
+pub async fn expire_loop(db_client: &'static db::DynamoDB, http: Arc<Http>) {
+    let days = match settings::verification_expiry_days() {
+        Ok(Some(days)) => days,
+        _ => return,
+    };
+    let bot_id = match http.get_current_user().await {
+        Ok(user) => user.id,
+        Err(why) => {
+            error!(
+                "Verification expiry disabled, cannot fetch current user: {}",
+                why
+            );
+            return;
+        }
+    };
+    loop {
+        let now = response::unix_now();
+        let cutoff = now - days as i64 * DAY;
+        let mut expired = 0;
+        for (user_id, verified_at) in db_client.verified_since().await {
+            match verified_at {
+                None => {
+                    db_client.set_verified_at(user_id, now).await;
+                }
+                Some(at) if at <= cutoff => {
+                    if expire(db_client, &http, bot_id, user_id, days).await {
+                        expired += 1;
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        if expired > 0 {
+            info!("Expired the verification of {} users", expired);
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+async fn expire(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    bot_id: UserId,
+    user_id: UserId,
+    days: u32,
+) -> bool {
+    if !db_client.expire_user(user_id, response::unix_now()).await {
+        error!("Failed to expire the verification of {}", user_id);
+        return false;
+    }
+    for guild_id in roles::unverify_everywhere(db_client, http, user_id).await {
+        audit::record(
+            db_client,
+            guild_id,
+            bot_id,
+            "verification.expired",
+            Some(user_id),
+            format!("Verified more than {} days ago", days),
+        )
+        .await;
+        stats::invalidate(guild_id);
+        webhooks::emit(db_client, guild_id, user_id, webhooks::Event::Expired).await;
+    }
+    let notice = format!(
+        "Your UT verification expired, since verifications last {} days. Run `/verify` in any \
+         server with the bot to verify again.",
+        days
+    );
+    let sent = match user_id.create_dm_channel(http).await {
+        Ok(dm) => dm.say(http, notice).await.map(|_| ()),
+        Err(why) => Err(why),
+    };
+    if let Err(why) = sent {
+        warn!(
+            "Cannot DM {} about their expired verification: {}",
+            user_id, why
+        );
+    }
+    true
+}
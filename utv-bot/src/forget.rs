@@ -0,0 +1,157 @@
+//! `/forgetme`, and cleanup when the bot is removed from a guild.
+//!
+//! After a confirmation, `/forgetme` deletes everything the user table holds about the account
+//! (its link to an EID, or what's left of an expired one), releases the EID's claim, takes the
+//! verified role off in every server and re-checks the member there, which strips the ✓ like
+//! for anyone unverified. Each server records it in the audit ledger without any detail.
+//!
+//! When the bot is kicked or banned from a guild, or the guild is deleted, every per-guild
+//! record is deleted like `/admin offboard` does. Guilds only unavailable during an outage are
+//! left alone.
+
+use std::sync::Arc;
+
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+use tracing::{error, info};
+
+use crate::{audit, cache, db, i18n, jobs, membership, response, roles, stats, webhooks};
+
+/// Custom id of the confirmation button
+pub const CONFIRM_ID: &str = "forgetme:confirm";
+
+/// Asks the user to confirm, since verifying again needs a new token
+pub async fn forgetme(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let locale = i18n::locale(db_client, command.guild_id).await;
+    if db_client.get_user_record(command.user.id).await.is_none() {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            i18n::text(locale, "forget.nothing-stored"),
+        )
+        .await;
+    }
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title(i18n::text(locale, "forget.confirm.title"))
+                                .description(i18n::text(locale, "forget.confirm"))
+                                .color(Color::from_rgb(255, 0, 0))
+                        })
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button
+                                        .custom_id(CONFIRM_ID)
+                                        .label(i18n::text(locale, "forget.button"))
+                                        .style(ButtonStyle::Danger)
+                                })
+                            })
+                        })
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+/// Handles the confirmation button
+pub async fn confirmed(
+    db_client: &db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+    jobs: &Arc<jobs::Queue>,
+) -> serenity::Result<()> {
+    let user_id = component.user.id;
+    let locale = i18n::locale(db_client, component.guild_id).await;
+    match db_client.forget_user(user_id).await {
+        db::UnlinkResult::Unlinked => {}
+        db::UnlinkResult::NotLinked => {
+            return response::respond_component_title(
+                &ctx,
+                &component,
+                true,
+                i18n::text(locale, "forget.nothing-stored"),
+            )
+            .await
+        }
+        db::UnlinkResult::Failed => {
+            return response::respond_component_title(
+                &ctx,
+                &component,
+                true,
+                i18n::text(locale, "forget.failed"),
+            )
+            .await
+        }
+    }
+    // taking roles off in every server takes a while
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+    let guilds = roles::unverify_everywhere(db_client, &ctx.http, user_id).await;
+    for guild_id in &guilds {
+        audit::record(db_client, *guild_id, user_id, "forgetme", Some(user_id), "").await;
+        stats::invalidate(*guild_id);
+        webhooks::emit(db_client, *guild_id, user_id, webhooks::Event::Unverified).await;
+        jobs.push(jobs::Job::Member {
+            guild_id: *guild_id,
+            user_id,
+        });
+    }
+    info!("Forgot {} at their request", user_id);
+
+    component
+        .edit_original_interaction_response(&ctx.http, |message| {
+            message
+                .create_embed(|embed| {
+                    embed
+                        .title(i18n::text(locale, "forget.done.title"))
+                        .description(i18n::format(
+                            locale,
+                            "forget.done",
+                            &[("count", &guilds.len().to_string())],
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+                .components(|components| components)
+        })
+        .await?;
+    Ok(())
+}
+
+/// Deletes a guild's records once the bot is no longer in it
+pub async fn guild_removed(db_client: &db::DynamoDB, guild_id: GuildId) {
+    if !db_client.delete_guild_data(guild_id).await {
+        error!(
+            "Some data of removed guild {} could not be deleted",
+            guild_id
+        );
+    }
+    cache::invalidate_roles(guild_id);
+    membership::forget(guild_id);
+    stats::forget_public(guild_id);
+    stats::invalidate(guild_id);
+    info!(
+        "Deleted the data of guild {} after it removed the bot",
+        guild_id
+    );
+}
@@ -0,0 +1,280 @@
+//! `utv-bot fsck [--repair]`: consistency check of the bot's tables.
+//!
+//! Looks for records that don't parse and references that lead nowhere, e.g. an EID linked to
+//! several accounts or an attestation for a member who never verified. Run it before and
+//! after schema migrations. `--repair` only fixes what the bot already ignores at runtime:
+//! it drops unreadable role mappings, attestations and scheduled tasks. Everything else,
+//! including the audit ledger, is reported for an operator to look at.
+
+use std::collections::{HashMap, HashSet};
+
+use aws_sdk_dynamodb::model::AttributeValue;
+
+use crate::config::GuildConfig;
+use crate::db::{self, attr_number, attr_string, Item, Table};
+use crate::scheduler;
+
+struct Problem {
+    table: Table,
+    item: Item,
+    description: String,
+    /// attributes to remove to repair it, the whole item when empty
+    repair: Option<Vec<&'static str>>,
+}
+
+impl Problem {
+    fn new(table: Table, item: &Item, description: impl Into<String>) -> Self {
+        Problem {
+            table,
+            item: item.clone(),
+            description: description.into(),
+            repair: None,
+        }
+    }
+
+    fn repair(mut self, attributes: &[&'static str]) -> Self {
+        self.repair = Some(attributes.to_vec());
+        self
+    }
+
+    fn key(&self) -> String {
+        self.table
+            .key_names()
+            .iter()
+            .map(|name| attr_string(&self.item, name).unwrap_or_else(|| "?".to_string()))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Runs the check, returning whether no unrepaired problems remain
+pub async fn run(args: &[String]) -> bool {
+    let repair = args.iter().any(|a| a == "--repair");
+    let db_client = db::DynamoDB::new("users").await;
+    match check(&db_client, repair).await {
+        Ok(clean) => clean,
+        Err(why) => {
+            // a table read in part would hide problems, so nothing is reported
+            eprintln!("{}", why);
+            false
+        }
+    }
+}
+
+async fn check(db_client: &db::DynamoDB, repair: bool) -> Result<bool, String> {
+    let users = db_client.raw_items(Table::Users).await?;
+    let mut problems = check_users(&users);
+    let linked = users
+        .iter()
+        .filter(|u| u.contains_key("encrypted_eid"))
+        .filter_map(|u| attr_number::<u64>(u, "discord_id"))
+        .collect::<HashSet<_>>();
+    problems.extend(check_guilds(&db_client.raw_items(Table::Guilds).await?));
+    problems.extend(check_audit(&db_client.raw_items(Table::Audit).await?));
+    problems.extend(check_attestations(
+        &db_client.raw_items(Table::Attestations).await?,
+        &linked,
+    ));
+    problems.extend(check_scheduled(
+        &db_client.raw_items(Table::Scheduled).await?,
+    ));
+
+    let mut remaining = 0;
+    for problem in &problems {
+        let repaired = match (&problem.repair, repair) {
+            (Some(attributes), true) => {
+                db_client
+                    .repair_item(problem.table, &problem.item, attributes)
+                    .await
+            }
+            _ => false,
+        };
+        if !repaired {
+            remaining += 1;
+        }
+        println!(
+            "{:?} {}: {}{}",
+            problem.table,
+            problem.key(),
+            problem.description,
+            match (&problem.repair, repaired) {
+                (_, true) => " (repaired)",
+                (Some(_), false) => " (repairable with --repair)",
+                (None, _) => "",
+            }
+        );
+    }
+    println!("{} problems found, {} remaining", problems.len(), remaining);
+    Ok(remaining == 0)
+}
+
+fn check_users(users: &[Item]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut by_eid: HashMap<String, Vec<String>> = HashMap::new();
+    for user in users {
+        if attr_number::<u64>(user, "discord_id").is_none() {
+            problems.push(Problem::new(
+                Table::Users,
+                user,
+                "discord_id is not a snowflake",
+            ));
+        }
+        match user.get("claims") {
+            Some(AttributeValue::S(claims))
+                if serde_json::from_str::<db::Claims>(claims).is_err() =>
+            {
+                problems.push(Problem::new(Table::Users, user, "claims don't parse"))
+            }
+            Some(AttributeValue::S(_)) | None => {}
+            Some(_) => problems.push(Problem::new(Table::Users, user, "claims is not a string")),
+        }
+        match attr_string(user, "encrypted_eid") {
+            Some(eid) if base64::decode(&eid).is_err() => problems.push(Problem::new(
+                Table::Users,
+                user,
+                "encrypted_eid is not base64",
+            )),
+            Some(eid) => {
+                if !user.contains_key("claims") {
+                    problems.push(Problem::new(Table::Users, user, "linked without claims"));
+                }
+                by_eid
+                    .entry(eid)
+                    .or_default()
+                    .push(attr_string(user, "discord_id").unwrap_or_default());
+            }
+            None => {}
+        }
+    }
+    for (_, accounts) in by_eid.into_iter().filter(|(_, a)| a.len() > 1) {
+        for user in users
+            .iter()
+            .filter(|u| attr_string(u, "discord_id").map_or(false, |id| accounts.contains(&id)))
+        {
+            problems.push(Problem::new(
+                Table::Users,
+                user,
+                format!(
+                    "EID is linked to {} accounts: {}",
+                    accounts.len(),
+                    accounts.join(", ")
+                ),
+            ));
+        }
+    }
+    problems
+}
+
+fn check_guilds(guilds: &[Item]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for guild in guilds {
+        if attr_number::<u64>(guild, "guild_id").is_none() {
+            problems.push(Problem::new(
+                Table::Guilds,
+                guild,
+                "guild_id is not a snowflake",
+            ));
+        }
+        if let Some(config) = attr_string(guild, "config") {
+            if let Err(why) = serde_json::from_str::<GuildConfig>(&config) {
+                problems.push(Problem::new(
+                    Table::Guilds,
+                    guild,
+                    format!(
+                        "config doesn't parse, the defaults are used instead: {}",
+                        why
+                    ),
+                ));
+            }
+        }
+        for key in ["affiliation_roles", "school_roles", "major_roles"] {
+            if let Some(mappings) = attr_string(guild, key) {
+                if serde_json::from_str::<HashMap<String, u64>>(&mappings).is_err() {
+                    problems.push(
+                        Problem::new(
+                            Table::Guilds,
+                            guild,
+                            format!("{} doesn't parse and is ignored", key),
+                        )
+                        .repair(&[key]),
+                    );
+                }
+            }
+        }
+    }
+    problems
+}
+
+fn check_audit(entries: &[Item]) -> Vec<Problem> {
+    entries
+        .iter()
+        .filter(|entry| {
+            attr_number::<u64>(entry, "guild_id").is_none()
+                || attr_number::<i64>(entry, "at").is_none()
+                || attr_number::<u64>(entry, "actor").is_none()
+                || attr_string(entry, "action").is_none()
+        })
+        .map(|entry| {
+            Problem::new(
+                Table::Audit,
+                entry,
+                "entry is missing guild_id, at, actor or action",
+            )
+        })
+        .collect()
+}
+
+fn check_attestations(attestations: &[Item], linked: &HashSet<u64>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for attestation in attestations {
+        let user_id = attr_string(attestation, "attestation_id").and_then(|id| {
+            let (user_id, role_id) = id.split_once(':')?;
+            role_id.parse::<u64>().ok()?;
+            user_id.parse::<u64>().ok()
+        });
+        match user_id {
+            None => problems.push(
+                Problem::new(
+                    Table::Attestations,
+                    attestation,
+                    "attestation_id doesn't parse",
+                )
+                .repair(&[]),
+            ),
+            Some(_) if attr_number::<i64>(attestation, "expires_at").is_none() => {
+                problems.push(Problem::new(
+                    Table::Attestations,
+                    attestation,
+                    "expires_at is missing, so the role is never removed",
+                ))
+            }
+            Some(user_id) if !linked.contains(&user_id) => problems.push(Problem::new(
+                Table::Attestations,
+                attestation,
+                "attested member has no linked EID",
+            )),
+            Some(_) => {}
+        }
+    }
+    problems
+}
+
+fn check_scheduled(tasks: &[Item]) -> Vec<Problem> {
+    tasks
+        .iter()
+        .filter(|task| {
+            attr_number::<i64>(task, "due_at").is_none()
+                || attr_string(task, "task")
+                    .and_then(|t| serde_json::from_str::<scheduler::Task>(&t).ok())
+                    .is_none()
+        })
+        .map(|task| {
+            Problem::new(
+                Table::Scheduled,
+                task,
+                "task is unreadable and would never run",
+            )
+            .repair(&[])
+        })
+        .collect()
+}
@@ -0,0 +1,126 @@
+//! Temporary guest passes for people who can't verify, such as prospective students and event
+//! speakers. The guest role is removed by the scheduler once the pass expires.
+
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+
+use crate::{audit, db, handlers, i18n, response, scheduler};
+
+/// Longest pass that can be granted, 30 days
+const MAX_HOURS: i64 = 30 * 24;
+
+pub async fn guest(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let role_id = match db_client.get_guild_config(guild_id).await.guest_role {
+        Some(role_id) => role_id,
+        None => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "No guest role is set. An administrator can set one with `/config guest-role`.",
+            )
+            .await
+        }
+    };
+    let options = &command.data.options;
+    let (user, hours) = match (
+        handlers::option_user(options, "user"),
+        handlers::option_int(options, "hours"),
+    ) {
+        (Some(user), Some(hours)) if (1..=MAX_HOURS).contains(&hours) => (user, hours),
+        (Some(_), Some(_)) => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                format!("Guest passes last between 1 and {} hours.", MAX_HOURS),
+            )
+            .await
+        }
+        _ => {
+            return response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    };
+
+    let expires_at = response::unix_now() + hours * 60 * 60;
+    // one pass per member, so granting again extends or shortens the current one
+    let scheduled = scheduler::schedule(
+        db_client,
+        format!("guest:{}:{}", guild_id, user.id),
+        expires_at,
+        &scheduler::Task::RevokeGuest {
+            guild_id,
+            user_id: user.id,
+            role_id,
+        },
+    )
+    .await;
+    if !scheduled {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Failed to save the guest pass, please try again",
+        )
+        .await;
+    }
+    if let Err(why) = ctx
+        .http
+        .add_member_role(guild_id.0, user.id.0, role_id.0)
+        .await
+    {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            format!("Failed to add the guest role: {}", why),
+        )
+        .await;
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "guest.grant",
+        Some(user.id),
+        format!("<@&{}> for {} hours", role_id, hours),
+    )
+    .await;
+
+    // posted for the guest to see, so in the guild's language
+    let locale = i18n::locale(db_client, Some(guild_id)).await;
+    response::respond_embed(&ctx, &command, false, |embed| {
+        embed
+            .title(i18n::text(locale, "guest.granted.title"))
+            .description(i18n::format(
+                locale,
+                "guest.granted",
+                &[
+                    ("user", &format!("<@{}>", user.id)),
+                    ("role", &format!("<@&{}>", role_id)),
+                    ("until", &response::datetime(expires_at)),
+                ],
+            ))
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
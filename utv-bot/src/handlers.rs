@@ -1,52 +1,171 @@
 use std::time::Duration;
-use serenity::model::prelude::application_command::ApplicationCommandInteractionDataOptionValue;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use serenity::model::prelude::application_command::{
+    ApplicationCommandInteractionDataOption, ApplicationCommandInteractionDataOptionValue,
+};
 use serenity::model::prelude::{
-    Guild, GuildId, InteractionApplicationCommandCallbackDataFlags, Message,
+    Guild, GuildId, InteractionApplicationCommandCallbackDataFlags, Member, Message,
+    PartialChannel, Role, User, UserId,
 };
 use serenity::{
-    builder::CreateEmbed,
+    builder::{CreateEmbed, CreateInteractionResponseData},
     client::Context,
     model::interactions::{
-        application_command::ApplicationCommandInteraction, InteractionResponseType,
+        application_command::ApplicationCommandInteraction, message_component::ButtonStyle,
+        InteractionResponseType,
     },
     utils::Color,
 };
+use tracing::{error, info};
 
-pub async fn verify(command: ApplicationCommandInteraction, ctx: Context) -> serenity::Result<()> {
-    let options = command
-        .data
-        .options
-        .get(0)
-        .expect("Expected EID")
-        .resolved
-        .as_ref()
-        .expect("Expected Value");
-    let mut res_ok = false;
-    if let ApplicationCommandInteractionDataOptionValue::String(eid) = options {
-        println!("Received EID: {}", eid);
-        let client = reqwest::Client::new();
-        let request_token =
-            std::env::var("REQUEST_TOKEN").expect("Expected REQUEST_TOKEN variable");
-        let mut eid = eid.clone();
-        eid.push('\n');
-        res_ok = client
-            .post(request_token)
-            .body(eid)
-            .send()
-            .await
-            .is_ok();
-        println!("Mail sent?: {}", res_ok);
+use crate::commands::{self, Audience, CommandHelp};
+use crate::config::GuildConfig;
+use crate::i18n::{self, Locale};
+use crate::{
+    analytics, audit, config, db, jobs, nickname_policy, nicknames, onboarding, redeem, response,
+    roles, settings, sso, stats, throttle, webhooks, IgnoreSet,
+};
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// The earliest time the member may verify, if the guild's account and membership age
+/// requirements are not met yet
+pub async fn verification_available_at(
+    db_client: &db::DynamoDB,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    member: Option<&Member>,
+) -> Option<i64> {
+    let config = db_client.get_guild_config(guild_id?).await;
+    age_gate(&config, user_id, member)
+}
+
+/// [`verification_available_at`] for a guild config at hand. Also withholds the roles of
+/// members verified elsewhere until they're old enough to verify in the guild.
+pub fn age_gate(config: &GuildConfig, user_id: UserId, member: Option<&Member>) -> Option<i64> {
+    let account_ready = user_id.created_at().timestamp() + config.min_account_age_days as i64 * DAY;
+    let member_ready = member.and_then(|m| m.joined_at).map_or(0, |joined| {
+        joined.timestamp() + config.min_membership_days as i64 * DAY
+    });
+    let ready = account_ready.max(member_ready);
+    if ready > response::unix_now() {
+        Some(ready)
+    } else {
+        None
+    }
+}
+
+/// Recognizes common mistakes in `/verify` input, returning guidance for the user.
+/// EIDs are 2 to 8 characters: a letter followed by letters and digits, like "abc123".
+pub fn eid_input_problem(locale: Locale, input: &str) -> Option<String> {
+    let input = input.trim();
+    let example = i18n::text(locale, "eid.example");
+    let with_example = |key| i18n::format(locale, key, &[("example", example)]);
+    if let Some((local, domain)) = input.split_once('@') {
+        let domain = domain.to_lowercase();
+        return Some(
+            if domain == "eid.utexas.edu" && eid_input_problem(locale, local).is_none() {
+                i18n::format(
+                    locale,
+                    "eid.email-of-eid",
+                    &[("eid", &local.to_lowercase())],
+                )
+            } else {
+                with_example("eid.email")
+            },
+        );
+    }
+    if !input.is_empty()
+        && input
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '-' || c == ' ')
+    {
+        return Some(with_example("eid.number"));
     }
+    // names may be written in any script, so look for spaces and letters outside ASCII
+    if input.contains(char::is_whitespace)
+        || input.chars().any(|c| c.is_alphabetic() && !c.is_ascii())
+    {
+        return Some(with_example("eid.name"));
+    }
+    let mut chars = input.chars();
+    let well_formed = chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric())
+        && (2..=8).contains(&input.len());
+    if !well_formed {
+        return Some(with_example("eid.malformed"));
+    }
+    None
+}
+
+pub async fn verify(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let locale = i18n::locale(db_client, command.guild_id).await;
+    if let Some(ready) = verification_available_at(
+        db_client,
+        command.guild_id,
+        command.user.id,
+        command.member.as_ref(),
+    )
+    .await
+    {
+        return response::respond_embed(&ctx, &command, true, |embed| {
+            embed
+                .title(i18n::text(locale, "verify.not-eligible.title"))
+                .description(i18n::format(
+                    locale,
+                    "verify.not-eligible",
+                    &[(
+                        "when",
+                        &response::timestamp(ready, response::TimestampStyle::Relative),
+                    )],
+                ))
+                .color(Color::from_rgb(255, 165, 0))
+        })
+        .await;
+    }
+    if let Ok(Some(_)) = settings::sso() {
+        return sso::offer(&ctx, &command, locale).await;
+    }
+    let eid = option_str(&command.data.options, "eid").unwrap_or_default();
+    if let Some(guidance) = eid_input_problem(locale, eid) {
+        return response::respond_embed(&ctx, &command, true, |embed| {
+            embed
+                .title(i18n::text(locale, "eid.invalid.title"))
+                .description(guidance)
+                .color(Color::from_rgb(255, 165, 0))
+        })
+        .await;
+    }
+    if let Some(retry_at) =
+        throttle::take(db_client, &ctx.http, command.guild_id, command.user.id).await
+    {
+        return response::respond_embed(&ctx, &command, true, |embed| {
+            embed
+                .title(i18n::text(locale, "throttle.title"))
+                .description(throttle::limited_message(locale, retry_at))
+                .color(Color::from_rgb(255, 165, 0))
+        })
+        .await;
+    }
+    let res_ok = request_email(db_client, command.guild_id, command.user.id, eid).await;
     command
         .create_interaction_response(&ctx.http, |interaction| {
             interaction.interaction_response_data(|message| {
                 message
                     .create_embed(|embed| {
-                        embed.title(if res_ok {
-                            "Token Verification Email Sent"
-                        } else {
-                            "Error: Please Check You Entered Your EID Correctly"
-                        })
+                        embed.title(i18n::text(
+                            locale,
+                            if res_ok {
+                                "verify.sent"
+                            } else {
+                                "verify.failed"
+                            },
+                        ))
                     })
                     .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
             })
@@ -54,23 +173,551 @@ pub async fn verify(command: ApplicationCommandInteraction, ctx: Context) -> ser
         .await
 }
 
+/// Asks the verification server to email a token to the EID's address on file. Returns false,
+/// for the caller's failure reply, when the request can't be made. EIDs are never logged.
+pub async fn request_email(
+    db_client: &db::DynamoDB,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    eid: &str,
+) -> bool {
+    let request_token = match settings::request_token() {
+        Ok(url) => url,
+        Err(why) => {
+            error!("Cannot request a verification email: {}", why);
+            return false;
+        }
+    };
+    let mut eid = eid.to_string();
+    eid.push('\n');
+    let res_ok = reqwest::Client::new()
+        .post(request_token)
+        .body(eid)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .is_ok();
+    info!("Verification email for {} requested: {}", user_id, res_ok);
+    if res_ok {
+        analytics::record(
+            db_client,
+            guild_id,
+            user_id,
+            analytics::Stage::EmailRequested,
+        )
+        .await;
+    }
+    res_ok
+}
+
+/// `/verify-beta`: the verification flow being trialed in pilot guilds. After sending the
+/// email it offers a button that opens the token form, instead of leaving members to find
+/// `/redeem` on their own.
+pub async fn verify_beta(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let enabled = match command.guild_id {
+        Some(guild_id) => config::beta_enabled(db_client, guild_id, "verify-beta").await,
+        None => false,
+    };
+    let locale = i18n::locale(db_client, command.guild_id).await;
+    if !enabled {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            i18n::text(locale, "verify.beta-unavailable"),
+        )
+        .await;
+    }
+    if let Some(ready) = verification_available_at(
+        db_client,
+        command.guild_id,
+        command.user.id,
+        command.member.as_ref(),
+    )
+    .await
+    {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            i18n::format(
+                locale,
+                "verify.available-at",
+                &[(
+                    "when",
+                    &response::timestamp(ready, response::TimestampStyle::Relative),
+                )],
+            ),
+        )
+        .await;
+    }
+    let eid = option_str(&command.data.options, "eid").unwrap_or_default();
+    if let Some(guidance) = eid_input_problem(locale, eid) {
+        return response::respond_embed(&ctx, &command, true, |embed| {
+            embed
+                .title(i18n::text(locale, "eid.invalid.title"))
+                .description(guidance)
+                .color(Color::from_rgb(255, 165, 0))
+        })
+        .await;
+    }
+    if let Some(retry_at) =
+        throttle::take(db_client, &ctx.http, command.guild_id, command.user.id).await
+    {
+        return response::respond_embed(&ctx, &command, true, |embed| {
+            embed
+                .title(i18n::text(locale, "throttle.title"))
+                .description(throttle::limited_message(locale, retry_at))
+                .color(Color::from_rgb(255, 165, 0))
+        })
+        .await;
+    }
+    if !request_email(db_client, command.guild_id, command.user.id, eid).await {
+        return response::respond_title(&ctx, &command, true, i18n::text(locale, "verify.failed"))
+            .await;
+    }
+    command
+        .create_interaction_response(&ctx.http, |interaction| {
+            interaction.interaction_response_data(|message| email_sent(message, locale))
+        })
+        .await
+}
+
+/// The reply once the email is on its way, with a button that opens the token form
+pub fn email_sent(
+    message: &mut CreateInteractionResponseData,
+    locale: Locale,
+) -> &mut CreateInteractionResponseData {
+    message
+        .create_embed(|embed| {
+            embed
+                .title(i18n::text(locale, "verify.email-sent.title"))
+                .description(i18n::text(locale, "verify.email-sent"))
+                .color(Color::from_rgb(191, 87, 0))
+        })
+        .components(|components| {
+            components.create_action_row(|row| {
+                row.create_button(|button| {
+                    button
+                        .custom_id(redeem::OPEN_BUTTON_ID)
+                        .label(i18n::text(locale, "verify.enter-token"))
+                        .style(ButtonStyle::Primary)
+                })
+            })
+        })
+        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+}
+
+/// Revokes a member's verification: unlinks their EID, takes the verified role and ✓ off them in
+/// this guild right away and queues the same in every other guild they share with the bot.
+pub async fn unverify(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+    ignore_set: IgnoreSet,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    if !is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let user = match option_user(&command.data.options, "user") {
+        Some(user) => user.clone(),
+        None => return response::respond_title(&ctx, &command, true, "Choose a member").await,
+    };
+    match db_client.unlink_user(user.id).await {
+        db::UnlinkResult::Unlinked => {}
+        db::UnlinkResult::NotLinked => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                format!("{} isn't verified", user.name),
+            )
+            .await
+        }
+        db::UnlinkResult::Failed => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "Failed to remove the verification, please try again",
+            )
+            .await
+        }
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "unverify",
+        Some(user.id),
+        "",
+    )
+    .await;
+    stats::invalidate(guild_id);
+    webhooks::emit(db_client, guild_id, user.id, webhooks::Event::Unverified).await;
+
+    let mut problems = Vec::new();
+    if let Ok(mut member) = guild_id.member(&ctx.http, user.id).await {
+        if let Ok(Some(role_id)) = roles::verified_role(db_client, &ctx.http, guild_id).await {
+            if member.roles.contains(&role_id) {
+                if let Err(why) = member.remove_role(&ctx.http, role_id).await {
+                    problems.push(format!("the verified role couldn't be removed: {}", why));
+                }
+            }
+        }
+        // the same cleanup unverified members get everywhere
+        let cleaned = nickname_policy::clean(&member.display_name());
+        if *member.display_name() != cleaned {
+            nicknames::wait_turn(guild_id, user.id, nicknames::Source::Member).await;
+            ignore_set.lock().await.insert(user.id);
+            if let Err(why) = member.edit(&ctx.http, |m| m.nickname(cleaned)).await {
+                problems.push(format!("their nickname couldn't be changed: {}", why));
+            }
+        }
+    }
+    let detail = format!("unverified by a moderator of {}", guild_id);
+    for other in roles::unverify_everywhere(db_client, &ctx.http, user.id).await {
+        if other == guild_id {
+            continue;
+        }
+        audit::record(
+            db_client,
+            other,
+            command.user.id,
+            "unverify",
+            Some(user.id),
+            detail.clone(),
+        )
+        .await;
+        stats::invalidate(other);
+        webhooks::emit(db_client, other, user.id, webhooks::Event::Unverified).await;
+        jobs.push(jobs::Job::Member {
+            guild_id: other,
+            user_id: user.id,
+        });
+    }
+    response::respond_title(
+        &ctx,
+        &command,
+        true,
+        if problems.is_empty() {
+            format!("{} is no longer verified", user.name)
+        } else {
+            format!(
+                "{} is no longer verified, but {}",
+                user.name,
+                problems.join(" and ")
+            )
+        },
+    )
+    .await
+}
+
+/// Whether the member invoking the command is a guild administrator
+pub fn is_admin(command: &ApplicationCommandInteraction) -> bool {
+    command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .map(|p| p.administrator())
+        .unwrap_or(false)
+}
+
+/// Whether the invoking member moderates the guild: administrators and members who can kick
+pub fn is_moderator(command: &ApplicationCommandInteraction) -> bool {
+    command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .map(|p| p.administrator() || p.kick_members())
+        .unwrap_or(false)
+}
+
+/// Finds the resolved value of a named option
+pub fn option<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a ApplicationCommandInteractionDataOptionValue> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.resolved.as_ref())
+}
+
+/// Whether an admin command was run with `format:json`, see `response::respond_json`
+pub fn wants_json(options: &[ApplicationCommandInteractionDataOption]) -> bool {
+    option_str(options, "format") == Some("json")
+}
+
+pub fn option_str<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a str> {
+    match option(options, name) {
+        Some(ApplicationCommandInteractionDataOptionValue::String(s)) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+pub fn option_int(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<i64> {
+    match option(options, name) {
+        Some(ApplicationCommandInteractionDataOptionValue::Integer(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+pub fn option_bool(
+    options: &[ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<bool> {
+    match option(options, name) {
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+pub fn option_user<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a User> {
+    match option(options, name) {
+        Some(ApplicationCommandInteractionDataOptionValue::User(user, _)) => Some(user),
+        _ => None,
+    }
+}
+
+pub fn option_role<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a Role> {
+    match option(options, name) {
+        Some(ApplicationCommandInteractionDataOptionValue::Role(role)) => Some(role),
+        _ => None,
+    }
+}
+
+pub fn option_channel<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a PartialChannel> {
+    match option(options, name) {
+        Some(ApplicationCommandInteractionDataOptionValue::Channel(channel)) => Some(channel),
+        _ => None,
+    }
+}
+
+/// A `YYYY-MM-DD` option as the unix timestamp of the start of that day in UTC
+pub fn option_date(options: &[ApplicationCommandInteractionDataOption], name: &str) -> Option<i64> {
+    let date = NaiveDate::parse_from_str(option_str(options, name)?, "%Y-%m-%d").ok()?;
+    Some(Utc.from_utc_date(&date).and_hms(0, 0, 0).timestamp())
+}
+
+/// The subcommand that was invoked, along with its options
+pub fn subcommand(
+    command: &ApplicationCommandInteraction,
+) -> Option<(&str, &[ApplicationCommandInteractionDataOption])> {
+    command
+        .data
+        .options
+        .get(0)
+        .map(|sub| (sub.name.as_str(), sub.options.as_slice()))
+}
+
+/// `/help`: the commands the member may run, or how to use one with `command:`. In a guild it
+/// also shows what verifying there involves and how many members are verified.
+pub async fn help_command(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let audience = if is_admin(&command) {
+        Audience::Administrators
+    } else if is_moderator(&command) {
+        Audience::Moderators
+    } else {
+        Audience::Everyone
+    };
+    if let Some(name) = option_str(&command.data.options, "command") {
+        let name = name.trim().trim_start_matches('/');
+        let entry = commands::command_help(name);
+        return response::respond_embed(&ctx, &command, true, |embed| match entry {
+            Some(entry) => command_detail(embed, entry, audience),
+            None => embed
+                .title(format!("There's No `/{}` Command", name))
+                .description("Run `/help` for the commands you can use.")
+                .color(Color::from_rgb(255, 165, 0)),
+        })
+        .await;
+    }
+
+    let (config, stats) = match command.guild_id {
+        Some(guild_id) => (
+            Some(db_client.get_guild_config(guild_id).await),
+            // cached, so spamming /help doesn't walk the member list every time
+            stats::guild_stats(db_client, &ctx.http, guild_id)
+                .await
+                .ok(),
+        ),
+        None => (None, None),
+    };
+    // the portal link is made for the member's account, so help is only shown to them
+    response::respond_embed(&ctx, &command, true, |embed| {
+        help(embed, &command, audience);
+        if let Some(config) = &config {
+            embed.field("In This Server", server_settings(config).join("\n"), false);
+        }
+        if let Some(stats) = stats {
+            embed.footer(|footer| {
+                footer.text(format!(
+                    "{} of {} members in this server are verified",
+                    stats.verified, stats.members
+                ))
+            });
+        }
+        embed
+    })
+    .await
+}
+
+/// Lists the commands the audience may run where the command was invoked, generated from
+/// `commands::REGISTRY`
 pub fn help<'a>(
     embed: &'a mut CreateEmbed,
-    _command: &ApplicationCommandInteraction,
+    command: &ApplicationCommandInteraction,
+    audience: Audience,
 ) -> &'a mut CreateEmbed {
     embed
-        .title("UTexas Verify Help Page")
-        .color(Color::from_rgb(0, 255, 0))
+        .title("UTexas Verify Help")
+        .description(format!(
+            "Verify with `/verify` and your EID, then `/redeem` the token you're emailed, or \
+             [verify on the web]({}) with your UT login; the link is made for your account, \
+             don't share it. `/help command:<name>` explains a command.",
+            onboarding::portal_link(command.guild_id, command.user.id)
+        ))
+        .color(Color::from_rgb(0, 255, 0));
+    for (title, needed) in [
+        ("Commands", Audience::Everyone),
+        ("Moderator Commands", Audience::Moderators),
+        ("Administrator Commands", Audience::Administrators),
+    ] {
+        if needed > audience {
+            continue;
+        }
+        let lines = commands::REGISTRY
+            .iter()
+            .filter(|entry| entry.audience == needed)
+            .filter(|entry| command.guild_id.is_some() || !entry.guild_only)
+            .map(|entry| format!("`/{}` {}", entry.name, entry.summary))
+            .collect::<Vec<_>>();
+        // kept under the field length limit
+        for (i, chunk) in lines.chunks(8).enumerate() {
+            embed.field(
+                if i == 0 { title } else { "\u{200b}" },
+                chunk.join("\n"),
+                false,
+            );
+        }
+    }
+    embed
+}
+
+fn command_detail<'a>(
+    embed: &'a mut CreateEmbed,
+    entry: &CommandHelp,
+    audience: Audience,
+) -> &'a mut CreateEmbed {
+    embed
+        .title(format!("`/{}`", entry.name))
+        .description(entry.summary)
+        .field("Usage", entry.detail, false)
         .field(
-            "`/verify`",
-            "Connect your UT EID to your discord account",
-            false,
+            "Who Can Run It",
+            match entry.audience {
+                Audience::Everyone => "Everyone",
+                Audience::Moderators => "Moderators: administrators and members who can kick",
+                Audience::Administrators => "Administrators",
+                Audience::Owner => "The bot's owner, in a DM with the bot",
+            },
+            true,
         )
         .field(
-            "`/rescan`",
-            "**ADMIN-ONLY**: checks all users in this guild for nickname compliance",
-            false,
+            "Where",
+            if entry.guild_only {
+                "Servers"
+            } else {
+                "Servers and DMs"
+            },
+            true,
         )
+        .color(if entry.audience > audience {
+            Color::from_rgb(255, 165, 0)
+        } else {
+            Color::from_rgb(0, 255, 0)
+        });
+    if entry.audience > audience {
+        embed.footer(|footer| footer.text("You can't run this command here."));
+    }
+    embed
+}
+
+/// What verifying in the guild involves, for members
+fn server_settings(config: &config::GuildConfig) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "Verifying gives the **{}** role",
+            config.verified_role_name()
+        ),
+        format!(
+            "Verified nicknames follow `{}`, with {} as the marker",
+            config.nickname_template(),
+            config.marker_symbol()
+        ),
+    ];
+    if config.min_account_age_days > 0 {
+        lines.push(format!(
+            "Discord accounts must be {} days old to verify",
+            config.min_account_age_days
+        ));
+    }
+    if config.min_membership_days > 0 {
+        lines.push(format!(
+            "Members can verify {} days after joining",
+            config.min_membership_days
+        ));
+    }
+    if let Ok(Some(days)) = settings::verification_expiry_days() {
+        lines.push(format!("Verifications expire after {} days", days));
+    }
+    if !config.voice_gated_channels.is_empty() {
+        lines.push(format!(
+            "Only verified members can join {}",
+            config
+                .voice_gated_channels
+                .iter()
+                .map(|channel| format!("<#{}>", channel))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if config.quarantine {
+        lines.push(match config.quarantine_channel {
+            Some(channel) => format!("Unverified members only see <#{}>", channel),
+            None => "Unverified members get the Unverified role".to_string(),
+        });
+    }
+    lines
 }
 
 pub fn unknown_command<'a>(
@@ -79,6 +726,8 @@ pub fn unknown_command<'a>(
 ) -> &'a mut CreateEmbed {
     embed
         .title("Incorrect Command Usage")
-        .description("Use one of 3 commands: `/verify`, `/help`, `/rescan`, and make sure your input values are valid.")
+        .description(
+            "Run `/help` for the commands you can use, and make sure your input values are valid.",
+        )
         .color(Color::from_rgb(255, 0, 0))
 }
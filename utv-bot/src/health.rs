@@ -0,0 +1,59 @@
+//! `/healthz` and `/readyz`, for container orchestrators' probes.
+//!
+//! `/healthz` answers as long as the process and its HTTP server are up. It doesn't reach
+//! Discord or DynamoDB, so a liveness probe doesn't restart the bot during their outages.
+//! `/readyz` only answers 200 once every shard of this instance is connected with a heartbeat
+//! latency under `READY_MAX_LATENCY_MS`, and stops when shutdown starts, so the instance is taken
+//! out of rotation while it drains. Its JSON body lists each shard's state.
+
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{json, Value};
+use serenity::gateway::ConnectionStage;
+
+use crate::{settings, sharding, shutdown};
+
+pub async fn healthz() -> (StatusCode, Json<Value>) {
+    (StatusCode::OK, Json(json!({ "status": "ok" })))
+}
+
+pub async fn readyz() -> (StatusCode, Json<Value>) {
+    let max_latency = settings::ready_max_latency_ms().unwrap_or_default() as u128;
+    let shards = sharding::states().await;
+    // a shard's latency is unknown until its first heartbeat, which is fine right after connecting
+    let slow = |shard: &sharding::ShardState| {
+        shard
+            .latency
+            .map_or(false, |latency| latency.as_millis() > max_latency)
+    };
+    let status = if shutdown::draining() {
+        "draining"
+    } else if shards.is_empty() {
+        "starting"
+    } else if shards
+        .iter()
+        .any(|shard| shard.stage != ConnectionStage::Connected)
+    {
+        "connecting"
+    } else if shards.iter().any(slow) {
+        "slow"
+    } else {
+        "ready"
+    };
+    let code = if status == "ready" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let shards = shards
+        .iter()
+        .map(|shard| {
+            json!({
+                "id": shard.id,
+                "stage": shard.stage.to_string(),
+                "latency_ms": shard.latency.map(|latency| latency.as_millis() as u64),
+            })
+        })
+        .collect::<Vec<_>>();
+    (code, Json(json!({ "status": status, "shards": shards })))
+}
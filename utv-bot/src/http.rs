@@ -0,0 +1,56 @@
+//! Embedded HTTP server for links handed out by the bot (e.g. event QR codes and certificates),
+//! the web portal's verifications, UT login, the API used by other bots, the admin dashboard, metrics and
+//! health checks
+
+use std::sync::Arc;
+
+use axum::{
+    routing::{get, post},
+    AddExtensionLayer, Router,
+};
+use serenity::http::Http;
+use tracing::{error, info};
+
+use crate::{api, certificate, dashboard, db, events, health, redeem, settings, sso, telemetry};
+
+/// Shared state handed to every route
+#[derive(Clone)]
+pub struct State {
+    pub db_client: &'static db::DynamoDB,
+    pub http: Arc<Http>,
+    /// `stable` or `canary`, for labelling metrics
+    pub deployment: &'static str,
+}
+
+pub async fn serve(state: State) {
+    let addr = settings::http_addr().unwrap_or_else(|why| panic!("{}", why));
+
+    let app = Router::new()
+        .route("/events/:ticket", get(events::redeem))
+        .route("/verify", post(redeem::callback))
+        .route("/sso/login", get(sso::start))
+        .route("/sso/callback", get(sso::callback))
+        .route("/v1/is-verified/:discord_id", get(api::is_verified))
+        .route("/v1/guilds/:guild_id/stats", get(api::guild_stats))
+        .route("/certificate-key", get(certificate::public_key))
+        .route("/certificate/:token", get(certificate::check))
+        .route("/metrics", get(telemetry::metrics))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/dashboard", get(dashboard::index))
+        .route("/dashboard/callback", get(dashboard::callback))
+        .route("/dashboard/:guild_id", get(dashboard::guild))
+        .route(
+            "/dashboard/:guild_id/verify-age",
+            post(dashboard::set_verify_age),
+        )
+        .layer(AddExtensionLayer::new(state));
+
+    info!("HTTP server listening on {}", addr);
+    if let Err(why) = axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+    {
+        error!("HTTP server error: {:?}", why);
+    }
+}
@@ -0,0 +1,518 @@
+//! Translations of the bot's replies to members.
+//!
+//! Replies are looked up by key in the guild's locale, set with `/config locale`. DMs and guilds
+//! without one get English, as do keys a locale doesn't translate yet, so a locale can be added
+//! a few messages at a time: add a variant to [`Locale`] and a table of messages like [`ES`].
+//! Messages take `{name}` placeholders, filled in with [`format`].
+//!
+//! Everything members see in a guild is translated: the verification flow, `/forgetme`,
+//! check-ins, guest passes, the on-join DMs and reminders, the default welcome after verifying
+//! and the UT Login pages. Moderator and admin replies are English, as are DMs not tied to a
+//! guild, like the DM verification flow and takeover or expiry notices.
+
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+
+use crate::{db, templates};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    Es,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::En
+    }
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Es];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Es => "es",
+        }
+    }
+
+    /// The locale's name in its own language
+    pub fn name(self) -> &'static str {
+        match self {
+            Locale::En => "English",
+            Locale::Es => "Español",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Locale> {
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.code() == code.trim().to_lowercase())
+    }
+
+    fn messages(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::En => EN,
+            Locale::Es => ES,
+        }
+    }
+}
+
+/// The locale replies in the guild are written in, English outside of guilds
+pub async fn locale(db_client: &db::DynamoDB, guild_id: Option<GuildId>) -> Locale {
+    match guild_id {
+        Some(guild_id) => db_client.get_guild_config(guild_id).await.locale,
+        None => Locale::En,
+    }
+}
+
+/// The message in the locale, or in English when it has no translation
+pub fn text(locale: Locale, key: &'static str) -> &'static str {
+    let find = |messages: &'static [(&str, &'static str)]| {
+        messages.iter().find(|(k, _)| *k == key).map(|(_, m)| *m)
+    };
+    find(locale.messages()).or_else(|| find(EN)).unwrap_or(key)
+}
+
+/// The message in the locale with its placeholders filled in
+pub fn format(locale: Locale, key: &'static str, values: &[(&str, &str)]) -> String {
+    templates::render(text(locale, key), values)
+}
+
+const EN: &[(&str, &str)] = &[
+    ("verify.not-eligible.title", "Not Yet Eligible to Verify"),
+    (
+        "verify.not-eligible",
+        "This server requires accounts and memberships of a minimum age before verifying. \
+         You can verify {when}.",
+    ),
+    ("verify.available-at", "You can verify {when}."),
+    ("verify.sent", "Token Verification Email Sent"),
+    (
+        "verify.failed",
+        "Error: Please Check You Entered Your EID Correctly",
+    ),
+    (
+        "verify.beta-unavailable",
+        "This command isn't available here, use `/verify` instead.",
+    ),
+    ("verify.email-sent.title", "Check Your Email"),
+    (
+        "verify.email-sent",
+        "We sent a token to the email address the UT Directory has for your EID. Press the \
+         button once you have it.",
+    ),
+    ("verify.enter-token", "Enter Token"),
+    ("verify.sso.title", "Log In With Your UT EID"),
+    (
+        "verify.sso",
+        "Press the button to log in with your UT EID. The link is yours alone and works for ten \
+         minutes; don't share it, whoever logs in with it verifies your account.",
+    ),
+    ("verify.sso.button", "Log In With UT"),
+    ("eid.invalid.title", "That Doesn't Look Like an EID"),
+    (
+        "eid.example",
+        "Your EID is the short login you use for UT Direct and Canvas, like `abc123`.",
+    ),
+    (
+        "eid.email-of-eid",
+        "That looks like an email address. Enter only your EID: `/verify eid:{eid}`",
+    ),
+    ("eid.email", "That looks like an email address. {example}"),
+    (
+        "eid.number",
+        "That looks like your UT ID number or a phone number, not your EID. {example}",
+    ),
+    ("eid.name", "That looks like a name, not an EID. {example}"),
+    (
+        "eid.malformed",
+        "EIDs are 2 to 8 letters and digits, starting with a letter. {example}",
+    ),
+    ("throttle.title", "Slow Down"),
+    (
+        "throttle.limited",
+        "Too many verification emails were requested. Try again {when}.",
+    ),
+    ("redeem.modal.title", "Redeem Verification Token"),
+    ("redeem.modal.label", "Token from your verification email"),
+    (
+        "redeem.file-too-large",
+        "That file is too large to be a verification token.",
+    ),
+    (
+        "redeem.file-unreadable",
+        "Couldn't read that file, attach the token as a plain text file.",
+    ),
+    ("redeem.linked.title", "Verified!"),
+    (
+        "redeem.linked",
+        "Your roles will be updated in every server shortly.",
+    ),
+    ("redeem.already-linked.title", "Already Verified"),
+    (
+        "redeem.already-linked",
+        "This Discord account is already linked to an EID.",
+    ),
+    ("redeem.invalid.title", "Invalid Token"),
+    (
+        "redeem.invalid",
+        "That token couldn't be validated. Copy the whole token from your email, or attach it \
+         as a text file with `/redeem file:`.",
+    ),
+    ("redeem.expired.title", "Expired Token"),
+    (
+        "redeem.expired",
+        "That token has expired. Ask the admin who gave it to you for a new one.",
+    ),
+    ("redeem.replayed.title", "Token Already Used"),
+    (
+        "redeem.replayed",
+        "That token was already used by another Discord account. Request a new one with \
+         `/verify`.",
+    ),
+    ("redeem.eid-in-use.title", "EID Already Linked"),
+    (
+        "redeem.eid-in-use",
+        "This EID is already linked to another Discord account. To move it to this one, ask an \
+         admin of a server you verified in to `/unverify` the other account.",
+    ),
+    ("redeem.banned.title", "Verification Refused"),
+    (
+        "redeem.banned",
+        "This EID belongs to an account banned from this server. The server's moderators have \
+         been told.",
+    ),
+    ("redeem.failed.title", "Something Went Wrong"),
+    ("redeem.failed", "Please try again in a moment."),
+    (
+        "roles.not-verified",
+        "Only verified members can pick roles, run `/verify` first.",
+    ),
+    ("roles.none", "This server has no roles to pick from yet."),
+    ("roles.title", "Pick Your Roles"),
+    (
+        "roles.description",
+        "Roles you unpick are removed, the rest of your roles stay.",
+    ),
+    ("roles.placeholder", "Your roles"),
+    ("roles.unchanged", "Your Roles Didn't Change"),
+    ("roles.updated", "Updated Your Roles"),
+    ("roles.added", "Added"),
+    ("roles.removed", "Removed"),
+    (
+        "roles.failed",
+        "{count} roles couldn't be changed, ask a moderator to check that they're below the \
+         bot's role.",
+    ),
+    (
+        "sso.confirm",
+        "Logging in verifies the Discord account {account} with your UT EID. Only continue if \
+         that's your account: whoever sent you this link would be verified as you.",
+    ),
+    ("sso.confirm.title", "Verify with UT Login"),
+    ("sso.confirm.link", "Log in with your UT EID"),
+    ("sso.login-failed", "UT login failed, run /verify again."),
+    ("sso.no-eid", "UT login didn't share your EID."),
+    ("sso.linked.title", "You're Verified"),
+    (
+        "sso.linked",
+        "Your roles and nickname are being updated in your servers. You can close this page.",
+    ),
+    ("sso.already-linked.title", "Already Verified"),
+    (
+        "sso.already-linked",
+        "This Discord account is already verified.",
+    ),
+    ("sso.eid-in-use.title", "EID Already Used"),
+    (
+        "sso.eid-in-use",
+        "Your EID verifies another Discord account. Ask a server admin for help if you lost \
+         access to it.",
+    ),
+    ("sso.banned.title", "Can't Verify"),
+    ("sso.banned", "You can't verify in this server."),
+    ("sso.failed.title", "Verification Failed"),
+    ("sso.failed", "Something went wrong, run /verify again."),
+    (
+        "forget.nothing-stored",
+        "The bot has nothing stored about your account",
+    ),
+    ("forget.confirm.title", "Delete Your Verification?"),
+    (
+        "forget.confirm",
+        "This deletes the link between your Discord account and your EID, takes the verified \
+         role and ✓ off in every server you share with the bot, and can't be undone. You can \
+         verify again later with a new token.",
+    ),
+    ("forget.button", "Delete"),
+    (
+        "forget.failed",
+        "Failed to delete your verification, please try again",
+    ),
+    ("forget.done.title", "Verification Deleted"),
+    (
+        "forget.done",
+        "The bot no longer stores your EID or anything about your verification. The verified \
+         role is off in the {count} servers you share with the bot, and your nickname is \
+         updated shortly.",
+    ),
+    ("checkin.title", "Check In: {event}"),
+    (
+        "checkin.description",
+        "Verified members can check in with the button below.",
+    ),
+    ("checkin.id", "Check-in id: {id}"),
+    ("checkin.button", "Check In"),
+    ("checkin.qr", "Scan to open the check-in in Discord."),
+    (
+        "checkin.not-verified",
+        "Only verified members can check in. Use `/verify` first!",
+    ),
+    ("checkin.recorded", "You're checked in!"),
+    (
+        "checkin.already",
+        "You've already checked in to this event.",
+    ),
+    ("checkin.failed", "Failed to check in, please try again"),
+    ("guest.granted.title", "Guest Pass Granted"),
+    ("guest.granted", "{user} has {role} until {until}."),
+    (
+        "onboarding.welcome",
+        "Welcome to {server}, {user}! Verify your UT EID to get access to the rest of the \
+         server: {link}",
+    ),
+    (
+        "onboarding.reminder",
+        "Reminder, {user}: you haven't verified your UT EID in {server} yet. Verify to get \
+         access to the rest of the server: {link}",
+    ),
+    (
+        "success.welcome",
+        "Welcome to {server}, {user}! You're now verified.",
+    ),
+    ("server.unknown", "the server"),
+];
+
+const ES: &[(&str, &str)] = &[
+    ("verify.not-eligible.title", "Aún No Puedes Verificarte"),
+    (
+        "verify.not-eligible",
+        "Este servidor exige una antigüedad mínima de la cuenta y de la membresía antes de \
+         verificarse. Podrás verificarte {when}.",
+    ),
+    ("verify.available-at", "Podrás verificarte {when}."),
+    ("verify.sent", "Correo de Verificación Enviado"),
+    (
+        "verify.failed",
+        "Error: Revisa que Escribiste tu EID Correctamente",
+    ),
+    (
+        "verify.beta-unavailable",
+        "Este comando no está disponible aquí, usa `/verify`.",
+    ),
+    ("verify.email-sent.title", "Revisa tu Correo"),
+    (
+        "verify.email-sent",
+        "Enviamos un token a la dirección de correo que el directorio de UT tiene para tu EID. \
+         Presiona el botón cuando lo tengas.",
+    ),
+    ("verify.enter-token", "Ingresar Token"),
+    ("verify.sso.title", "Inicia Sesión con tu UT EID"),
+    (
+        "verify.sso",
+        "Presiona el botón para iniciar sesión con tu UT EID. El enlace es solo tuyo y funciona \
+         durante diez minutos; no lo compartas, quien inicie sesión con él verifica tu cuenta.",
+    ),
+    ("verify.sso.button", "Iniciar Sesión con UT"),
+    ("eid.invalid.title", "Eso No Parece un EID"),
+    (
+        "eid.example",
+        "Tu EID es el usuario corto con el que entras a UT Direct y Canvas, como `abc123`.",
+    ),
+    (
+        "eid.email-of-eid",
+        "Eso parece una dirección de correo. Escribe solo tu EID: `/verify eid:{eid}`",
+    ),
+    ("eid.email", "Eso parece una dirección de correo. {example}"),
+    (
+        "eid.number",
+        "Eso parece tu número de UT ID o un número de teléfono, no tu EID. {example}",
+    ),
+    ("eid.name", "Eso parece un nombre, no un EID. {example}"),
+    (
+        "eid.malformed",
+        "Los EID tienen de 2 a 8 letras y dígitos y empiezan con una letra. {example}",
+    ),
+    ("throttle.title", "Más Despacio"),
+    (
+        "throttle.limited",
+        "Se pidieron demasiados correos de verificación. Inténtalo de nuevo {when}.",
+    ),
+    ("redeem.modal.title", "Canjear Token de Verificación"),
+    ("redeem.modal.label", "Token de tu correo de verificación"),
+    (
+        "redeem.file-too-large",
+        "Ese archivo es demasiado grande para ser un token de verificación.",
+    ),
+    (
+        "redeem.file-unreadable",
+        "No se pudo leer ese archivo, adjunta el token como un archivo de texto plano.",
+    ),
+    ("redeem.linked.title", "¡Verificado!"),
+    (
+        "redeem.linked",
+        "Tus roles se actualizarán en todos los servidores en breve.",
+    ),
+    ("redeem.already-linked.title", "Ya Estás Verificado"),
+    (
+        "redeem.already-linked",
+        "Esta cuenta de Discord ya está vinculada a un EID.",
+    ),
+    ("redeem.invalid.title", "Token Inválido"),
+    (
+        "redeem.invalid",
+        "No se pudo validar ese token. Copia el token completo de tu correo, o adjúntalo como \
+         archivo de texto con `/redeem file:`.",
+    ),
+    ("redeem.expired.title", "Token Vencido"),
+    (
+        "redeem.expired",
+        "Ese token ya venció. Pide uno nuevo al administrador que te lo dio.",
+    ),
+    ("redeem.replayed.title", "Token Ya Usado"),
+    (
+        "redeem.replayed",
+        "Otra cuenta de Discord ya usó ese token. Pide uno nuevo con `/verify`.",
+    ),
+    ("redeem.eid-in-use.title", "EID Ya Vinculado"),
+    (
+        "redeem.eid-in-use",
+        "Este EID ya está vinculado a otra cuenta de Discord. Para pasarlo a esta, pide a un \
+         administrador de un servidor donde te verificaste que use `/unverify` con la otra \
+         cuenta.",
+    ),
+    ("redeem.banned.title", "Verificación Rechazada"),
+    (
+        "redeem.banned",
+        "Este EID pertenece a una cuenta expulsada de este servidor. Ya se avisó a los \
+         moderadores del servidor.",
+    ),
+    ("redeem.failed.title", "Algo Salió Mal"),
+    ("redeem.failed", "Inténtalo de nuevo en un momento."),
+    (
+        "roles.not-verified",
+        "Solo los miembros verificados pueden elegir roles, usa `/verify` primero.",
+    ),
+    (
+        "roles.none",
+        "Este servidor aún no tiene roles para elegir.",
+    ),
+    ("roles.title", "Elige tus Roles"),
+    (
+        "roles.description",
+        "Los roles que desmarques se quitan, el resto de tus roles se quedan.",
+    ),
+    ("roles.placeholder", "Tus roles"),
+    ("roles.unchanged", "Tus Roles No Cambiaron"),
+    ("roles.updated", "Tus Roles se Actualizaron"),
+    ("roles.added", "Agregados"),
+    ("roles.removed", "Quitados"),
+    (
+        "roles.failed",
+        "No se pudieron cambiar {count} roles, pide a un moderador que revise que estén debajo \
+         del rol del bot.",
+    ),
+    (
+        "sso.confirm",
+        "Al iniciar sesión, la cuenta de Discord {account} se verifica con tu UT EID. Continúa \
+         solo si es tu cuenta: quien te haya enviado este enlace quedaría verificado como tú.",
+    ),
+    ("sso.confirm.title", "Verifícate con UT Login"),
+    ("sso.confirm.link", "Inicia sesión con tu UT EID"),
+    (
+        "sso.login-failed",
+        "No se pudo iniciar sesión con UT, usa /verify de nuevo.",
+    ),
+    ("sso.no-eid", "UT Login no compartió tu EID."),
+    ("sso.linked.title", "Estás Verificado"),
+    (
+        "sso.linked",
+        "Tus roles y apodo se están actualizando en tus servidores. Puedes cerrar esta página.",
+    ),
+    ("sso.already-linked.title", "Ya Verificado"),
+    (
+        "sso.already-linked",
+        "Esta cuenta de Discord ya está verificada.",
+    ),
+    ("sso.eid-in-use.title", "EID Ya Usado"),
+    (
+        "sso.eid-in-use",
+        "Tu EID verifica otra cuenta de Discord. Pide ayuda a un administrador del servidor si \
+         perdiste el acceso a ella.",
+    ),
+    ("sso.banned.title", "No Puedes Verificarte"),
+    ("sso.banned", "No puedes verificarte en este servidor."),
+    ("sso.failed.title", "La Verificación Falló"),
+    ("sso.failed", "Algo salió mal, usa /verify de nuevo."),
+    (
+        "forget.nothing-stored",
+        "El bot no tiene nada guardado sobre tu cuenta",
+    ),
+    ("forget.confirm.title", "¿Borrar tu Verificación?"),
+    (
+        "forget.confirm",
+        "Esto borra el vínculo entre tu cuenta de Discord y tu EID, quita el rol de verificado \
+         y la ✓ en todos los servidores que compartes con el bot, y no se puede deshacer. Puedes \
+         verificarte de nuevo más tarde con un token nuevo.",
+    ),
+    ("forget.button", "Borrar"),
+    (
+        "forget.failed",
+        "No se pudo borrar tu verificación, inténtalo de nuevo",
+    ),
+    ("forget.done.title", "Verificación Borrada"),
+    (
+        "forget.done",
+        "El bot ya no guarda tu EID ni nada sobre tu verificación. Se quitó el rol de \
+         verificado en los {count} servidores que compartes con el bot, y tu apodo se \
+         actualiza en breve.",
+    ),
+    ("checkin.title", "Registro: {event}"),
+    (
+        "checkin.description",
+        "Los miembros verificados pueden registrar su asistencia con el botón de abajo.",
+    ),
+    ("checkin.id", "Id del registro: {id}"),
+    ("checkin.button", "Registrarme"),
+    ("checkin.qr", "Escanea para abrir el registro en Discord."),
+    (
+        "checkin.not-verified",
+        "Solo los miembros verificados pueden registrarse. ¡Usa `/verify` primero!",
+    ),
+    ("checkin.recorded", "¡Quedaste registrado!"),
+    ("checkin.already", "Ya te registraste en este evento."),
+    (
+        "checkin.failed",
+        "No se pudo registrar tu asistencia, inténtalo de nuevo",
+    ),
+    ("guest.granted.title", "Pase de Invitado Otorgado"),
+    ("guest.granted", "{user} tiene {role} hasta {until}."),
+    (
+        "onboarding.welcome",
+        "¡Bienvenido a {server}, {user}! Verifica tu UT EID para acceder al resto del \
+         servidor: {link}",
+    ),
+    (
+        "onboarding.reminder",
+        "Recordatorio, {user}: aún no verificaste tu UT EID en {server}. Verifícate para \
+         acceder al resto del servidor: {link}",
+    ),
+    (
+        "success.welcome",
+        "¡Bienvenido a {server}, {user}! Ya estás verificado.",
+    ),
+    ("server.unknown", "el servidor"),
+];
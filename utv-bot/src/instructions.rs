@@ -0,0 +1,171 @@
+//! `/instructions`: a ready-to-post explanation of how to verify in this guild, for
+//! moderators to pin or share in announcement channels.
+
+use std::borrow::Cow;
+
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::channel::ChannelType;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+
+use crate::{config, db, events, handlers, response, roles, PORTAL_URL};
+
+const CARD_FILENAME: &str = "verify.png";
+
+pub async fn instructions(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_moderator(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be a moderator to run this command.",
+        )
+        .await;
+    }
+    let options = &command.data.options;
+    let channel = handlers::option_channel(options, "channel");
+    if let Some(channel) = channel {
+        if channel.kind != ChannelType::Text && channel.kind != ChannelType::News {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "Choose a text or announcement channel",
+            )
+            .await;
+        }
+    }
+    let guild = guild_id.to_partial_guild(&ctx.http).await?;
+    let config = db_client.get_guild_config(guild_id).await;
+    // branded with the verified role's color, falling back to burnt orange
+    let color = match roles::verified_role(db_client, &ctx.http, guild_id).await? {
+        Some(role_id) => guild
+            .roles
+            .get(&role_id)
+            .map(|role| role.colour)
+            .filter(|color| color.0 != 0),
+        None => None,
+    }
+    .unwrap_or_else(|| Color::from_rgb(191, 87, 0));
+    let portal = format!("{}/app?guild_id={}", PORTAL_URL.as_str(), guild_id);
+    let card = if handlers::option_bool(options, "card").unwrap_or(false) {
+        events::qr_png(&portal)
+    } else {
+        None
+    };
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(format!("How to verify in {}", guild.name))
+        .color(color)
+        .description(steps(&config))
+        .field(
+            "Prefer the web?",
+            format!("[Verify here]({})", portal),
+            false,
+        );
+    if let Some(icon) = guild.icon_url() {
+        embed.thumbnail(icon);
+    }
+    if let Some(requirements) = requirements(&config) {
+        embed.field("Requirements", requirements, false);
+    }
+    if card.is_some() {
+        embed.image(format!("attachment://{}", CARD_FILENAME));
+    }
+    embed.footer(|footer| footer.text("Other members never see your EID"));
+
+    match channel {
+        Some(channel) => {
+            channel
+                .id
+                .send_message(&ctx.http, |message| {
+                    if let Some(png) = card {
+                        message.add_file(AttachmentType::Bytes {
+                            data: Cow::from(png),
+                            filename: CARD_FILENAME.to_string(),
+                        });
+                    }
+                    message.set_embed(embed)
+                })
+                .await?;
+            response::respond_title(
+                &ctx,
+                &command,
+                true,
+                format!("Posted the instructions in <#{}>", channel.id),
+            )
+            .await
+        }
+        None => {
+            response::defer(&ctx, &command, false).await?;
+            command
+                .create_followup_message(&ctx.http, |message| {
+                    if let Some(png) = card {
+                        message.add_file(AttachmentType::Bytes {
+                            data: Cow::from(png),
+                            filename: CARD_FILENAME.to_string(),
+                        });
+                    }
+                    message.add_embed(embed)
+                })
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// The steps of the verification command this guild uses
+fn steps(config: &config::GuildConfig) -> String {
+    let verify = if config.beta_commands.iter().any(|c| c == "verify-beta") {
+        "`/verify-beta`"
+    } else {
+        "`/verify`"
+    };
+    format!(
+        "**1.** Run {} with your UT EID in any channel. A verification email is sent to your \
+         UT address.\n\
+         **2.** Run `/redeem` with the token from the email, or leave it empty to paste it into \
+         a form.\n\
+         **3.** That's it: you get the `{}` role{}.",
+        verify,
+        config.verified_role_name(),
+        if config.native_marker {
+            ", whose icon shows next to your name".to_string()
+        } else {
+            format!(
+                " and a {} after your nickname if you're a student",
+                config.marker_symbol()
+            )
+        }
+    )
+}
+
+fn requirements(config: &config::GuildConfig) -> Option<String> {
+    let mut requirements = Vec::new();
+    if config.min_account_age_days > 0 {
+        requirements.push(format!(
+            "Your Discord account must be at least {} days old.",
+            config.min_account_age_days
+        ));
+    }
+    if config.min_membership_days > 0 {
+        requirements.push(format!(
+            "You must have been in this server for at least {} days.",
+            config.min_membership_days
+        ));
+    }
+    if requirements.is_empty() {
+        None
+    } else {
+        Some(requirements.join("\n"))
+    }
+}
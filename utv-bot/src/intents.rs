@@ -0,0 +1,127 @@
+//! Gateway intents, derived from the features the bot runs with.
+//!
+//! Each gateway-driven feature declares the intents it needs. By default the bot requests
+//! exactly those, so disabling a feature with `DISABLED_FEATURES` also drops intents nothing
+//! else needs. An explicit `GATEWAY_INTENTS` list is checked against the enabled features
+//! instead, so a missing privileged intent fails at startup rather than silently.
+
+use std::collections::HashSet;
+
+use serenity::client::bridge::gateway::GatewayIntents;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Feature {
+    /// scanning every member when the bot joins or reconnects to a guild
+    GuildScans,
+    /// checking members as they join
+    MemberJoins,
+    /// continuous nickname enforcement when members change their nickname or roles
+    MemberUpdates,
+    /// verifying by DM, see `dm`
+    DirectMessages,
+    /// recording the EIDs of banned members, see `bans`
+    BanEvasion,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] = [
+        Feature::GuildScans,
+        Feature::MemberJoins,
+        Feature::MemberUpdates,
+        Feature::DirectMessages,
+        Feature::BanEvasion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::GuildScans => "guild-scans",
+            Feature::MemberJoins => "member-joins",
+            Feature::MemberUpdates => "member-updates",
+            Feature::DirectMessages => "direct-messages",
+            Feature::BanEvasion => "ban-evasion",
+        }
+    }
+
+    pub fn intents(self) -> GatewayIntents {
+        match self {
+            Feature::GuildScans => GatewayIntents::GUILDS,
+            Feature::MemberJoins | Feature::MemberUpdates => GatewayIntents::GUILD_MEMBERS,
+            Feature::DirectMessages => GatewayIntents::DIRECT_MESSAGES,
+            Feature::BanEvasion => GatewayIntents::GUILD_BANS,
+        }
+    }
+}
+
+/// The enabled features
+#[derive(Clone, Debug)]
+pub struct Features(HashSet<Feature>);
+
+impl Features {
+    /// Every feature except the ones named in `disabled`
+    pub fn parse(disabled: &str) -> Result<Features, String> {
+        let mut features = Feature::ALL.iter().copied().collect::<HashSet<_>>();
+        for name in disabled.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match Feature::ALL.iter().find(|f| f.name() == name) {
+                Some(feature) => features.remove(feature),
+                None => return Err(format!("DISABLED_FEATURES: unknown feature {}", name)),
+            };
+        }
+        Ok(Features(features))
+    }
+
+    pub fn enabled(&self, feature: Feature) -> bool {
+        self.0.contains(&feature)
+    }
+
+    /// The intents the enabled features need
+    pub fn required_intents(&self) -> GatewayIntents {
+        self.0
+            .iter()
+            .fold(GatewayIntents::empty(), |intents, f| intents | f.intents())
+    }
+}
+
+fn intent(name: &str) -> Option<GatewayIntents> {
+    Some(match name {
+        "GUILDS" => GatewayIntents::GUILDS,
+        "GUILD_MEMBERS" => GatewayIntents::GUILD_MEMBERS,
+        "GUILD_BANS" => GatewayIntents::GUILD_BANS,
+        "GUILD_PRESENCES" => GatewayIntents::GUILD_PRESENCES,
+        "GUILD_VOICE_STATES" => GatewayIntents::GUILD_VOICE_STATES,
+        "GUILD_MESSAGES" => GatewayIntents::GUILD_MESSAGES,
+        "DIRECT_MESSAGES" => GatewayIntents::DIRECT_MESSAGES,
+        _ => return None,
+    })
+}
+
+/// Resolves `GATEWAY_INTENTS`: `minimal` (the default) requests only what the enabled features
+/// need, a comma separated list of intent names is used as given if it covers them
+pub fn resolve(configured: &str, features: &Features) -> Result<GatewayIntents, String> {
+    let required = features.required_intents();
+    if configured.trim().is_empty() || configured.trim() == "minimal" {
+        return Ok(required);
+    }
+    let mut intents = GatewayIntents::empty();
+    for name in configured
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+    {
+        intents |=
+            intent(name).ok_or_else(|| format!("GATEWAY_INTENTS: unknown intent {}", name))?;
+    }
+    let missing = Feature::ALL
+        .iter()
+        .filter(|f| features.enabled(**f) && !intents.contains(f.intents()))
+        .map(|f| f.name())
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(intents)
+    } else {
+        Err(format!(
+            "GATEWAY_INTENTS is missing intents needed by {}; add them or list the features in \
+             DISABLED_FEATURES",
+            missing.join(", ")
+        ))
+    }
+}
@@ -0,0 +1,118 @@
+//! In-process queue for background work.
+//!
+//! Jobs run one at a time on a single worker, so bulk member updates queued from different
+//! places don't compete for the same rate limits.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::error;
+
+use crate::shutdown;
+
+#[derive(Debug)]
+pub enum Job {
+    /// Re-checks the members who joined a guild at or after `since`, whose join events may have
+    /// been missed while the gateway was disconnected
+    Reconcile { guild_id: GuildId, since: i64 },
+    /// Re-checks a single member, e.g. after their roles were changed by hand
+    Member { guild_id: GuildId, user_id: UserId },
+    /// Restores the member snapshots taken before a bulk job, see `snapshots`
+    Rollback {
+        guild_id: GuildId,
+        job_id: String,
+        actor: UserId,
+    },
+    /// Fetches a guild's dues roster and updates its dues role, see `membership`
+    DuesSync { guild_id: GuildId },
+}
+
+impl Job {
+    pub fn guild_id(&self) -> GuildId {
+        match self {
+            Job::Reconcile { guild_id, .. }
+            | Job::Member { guild_id, .. }
+            | Job::Rollback { guild_id, .. }
+            | Job::DuesSync { guild_id } => *guild_id,
+        }
+    }
+}
+
+pub struct Queue {
+    sender: UnboundedSender<Job>,
+    receiver: Mutex<Option<UnboundedReceiver<Job>>>,
+    /// descriptions of the jobs not yet picked up by the worker, for `/admin jobs` and the
+    /// shutdown report
+    pending: Arc<Mutex<VecDeque<(GuildId, String)>>>,
+}
+
+/// The worker's end of the queue
+pub struct Receiver {
+    receiver: UnboundedReceiver<Job>,
+    pending: Arc<Mutex<VecDeque<(GuildId, String)>>>,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Queue {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            pending: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn push(&self, job: Job) {
+        let description = (job.guild_id(), format!("{:?}", job));
+        if let Err(why) = self.sender.send(job) {
+            error!("Job queue closed, dropping {:?}", why.0);
+        } else {
+            self.pending.lock().unwrap().push_back(description);
+        }
+    }
+
+    /// Hands the receiving end to the worker; only the first call gets it
+    pub fn take_receiver(&self) -> Option<Receiver> {
+        let receiver = self.receiver.lock().unwrap().take()?;
+        Some(Receiver {
+            receiver,
+            pending: self.pending.clone(),
+        })
+    }
+
+    /// The jobs waiting for the worker, oldest first
+    pub fn pending(&self) -> Vec<String> {
+        self.pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, description)| description.clone())
+            .collect()
+    }
+
+    /// The jobs of one guild waiting for the worker, oldest first
+    pub fn pending_in(&self, guild_id: GuildId) -> Vec<String> {
+        self.pending
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(guild, _)| *guild == guild_id)
+            .map(|(_, description)| description.clone())
+            .collect()
+    }
+}
+
+impl Receiver {
+    /// The next job, or `None` once the bot starts shutting down; jobs not picked up stay
+    /// pending for the shutdown report
+    pub async fn recv(&mut self) -> Option<Job> {
+        let job = tokio::select! {
+            job = self.receiver.recv() => job?,
+            _ = shutdown::started() => return None,
+        };
+        self.pending.lock().unwrap().pop_front();
+        Some(job)
+    }
+}
@@ -0,0 +1,22 @@
+//! Logging through `tracing`, as text or as JSON lines for log aggregation.
+//!
+//! `LOG_LEVEL` takes a filter like `info` or `utv_bot=debug,serenity=warn`. Event handlers and
+//! interactions run in spans carrying the guild, member and command they're for, so every line
+//! logged while handling one can be traced back to it.
+
+use tracing_subscriber::EnvFilter;
+
+use crate::settings;
+
+/// Installs the global subscriber. Invalid settings fall back to the defaults here; they're
+/// reported with the rest by `Settings::from_env`.
+pub fn init() {
+    let filter =
+        settings::log_filter().unwrap_or_else(|_| EnvFilter::new(settings::DEFAULT_LOG_FILTER));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    if settings::log_json().unwrap_or(false) {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+}
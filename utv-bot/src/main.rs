@@ -1,35 +1,133 @@
+mod admin;
+mod alumni;
+mod analytics;
+mod api;
+mod api_keys;
+mod attest;
+mod audit;
+mod backup;
+mod bans;
+mod cache;
+mod certificate;
+mod channels;
+mod check_config;
+mod checkin;
+mod colors;
+mod commands;
+mod components;
+mod config;
+mod dashboard;
 mod db;
+mod db_admin;
+mod discord;
+mod dm;
+mod dry_run;
+mod elections;
+mod error;
+mod events;
+mod expiry;
+mod forget;
+mod fsck;
+mod guest;
 mod handlers;
+mod health;
+mod http;
+mod i18n;
+mod instructions;
+mod intents;
+mod jobs;
+mod logging;
+mod marker;
+mod members;
+mod membership;
+mod migrations;
+mod nickname_policy;
+mod nicknames;
+mod notes;
+mod offboard;
+mod onboarding;
+mod owner;
+mod panel;
+mod preview;
+mod quarantine;
+mod ratelimits;
+mod recheck;
+mod redeem;
+mod response;
+mod review;
+mod role_changes;
+mod roles;
+mod rush;
+mod scheduler;
+mod self_roles;
+mod selftest;
+mod settings;
+mod sharding;
+mod sheets;
+mod shutdown;
+mod snapshots;
+mod sso;
+mod stats;
+mod success;
+mod support;
+mod sweep;
+mod telemetry;
+mod templates;
+mod tenancy;
+mod throttle;
+mod transfer;
+mod unrenamable;
+mod webhooks;
+mod whois;
 
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::future::Future;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use aws_sdk_sqs::model::DeleteMessageBatchRequestEntry;
+use futures::stream::{self, StreamExt};
+use lazy_static::lazy_static;
 use serde::Deserialize;
-use serenity::http::GuildPagination;
-use serenity::model::guild::{Guild, Member, PartialGuild, Role};
+use serenity::model::channel::Message;
+use serenity::model::guild::{Guild, GuildUnavailable, Member, PartialGuild, Role};
 use serenity::model::id::{GuildId, RoleId, UserId};
 use serenity::model::prelude::application_command::ApplicationCommandInteraction;
+use serenity::model::user::User;
 use serenity::{
     async_trait,
-    client::bridge::gateway::GatewayIntents,
+    client::bridge::gateway::event::ShardStageUpdateEvent,
+    gateway::ConnectionStage,
     model::{
-        event::GuildMemberUpdateEvent,
+        event::{GuildMemberUpdateEvent, ResumedEvent},
         gateway::Ready,
-        interactions::{
-            application_command::{ApplicationCommand, ApplicationCommandOptionType},
-            Interaction, InteractionResponseType,
-        },
+        interactions::{Interaction, InteractionResponseType},
     },
     prelude::*,
 };
+use tracing::{error, info, info_span, instrument, warn, Instrument, Span};
 
 const REQUESTS_PER_SECOND: i32 = 10;
-const SQS_BECOME_VERIFIED_REQUEST_URL: &'static str = "https://sqs.us-east-1.amazonaws.com/402762806873/on-verification-update";
+/// Members a scan checks at once; nickname edits are still paced per guild by `nicknames`
+const SCAN_CONCURRENCY: usize = 4;
+/// Members a scan checks between progress reports
+const SCAN_BATCH: usize = 250;
+/// how far before a disconnect to look for missed joins, to cover events lost just before it
+const RECONCILE_SLACK_SECS: i64 = 60;
+/// window to reconcile when a resume arrives without a recorded disconnect
+const RECONCILE_FALLBACK_SECS: i64 = 15 * 60;
+
+lazy_static! {
+    /// Key used to sign links and tokens handed out by the bot
+    static ref SHARED_KEY: Vec<u8> = settings::shared_key().unwrap_or_else(|why| panic!("{}", why));
+    /// Base url under which the embedded HTTP server is reachable
+    static ref PUBLIC_URL: String = settings::public_url().unwrap_or_else(|why| panic!("{}", why));
+    static ref PORTAL_URL: String = settings::portal_url().unwrap_or_else(|why| panic!("{}", why));
+    static ref SQS_BECOME_VERIFIED_REQUEST_URL: String =
+        settings::verification_update_queue().unwrap_or_else(|why| panic!("{}", why));
+}
 
 type IgnoreSet = Arc<tokio::sync::Mutex<HashSet<UserId>>>;
 
@@ -38,9 +136,16 @@ struct Handler {
     // used to ignore an additional invocation of GuildMemberUpdateEvent
     ignore_set: IgnoreSet,
     background_task_running: AtomicBool,
+    /// unix time each shard's gateway connection dropped, absent while connected
+    disconnected_at: std::sync::Mutex<HashMap<u64, i64>>,
+    jobs: Arc<jobs::Queue>,
+    features: intents::Features,
+    /// a canary only handles its shards' events and leaves the background loops to stable
+    canary: bool,
 }
 
-/// Scans all users in the guild to check nickname compliance
+/// Scans all users in the guild to check nickname compliance, or with `dry-run` lists what it
+/// would change
 async fn rescan(
     user_db: &'static db::DynamoDB,
     command: ApplicationCommandInteraction,
@@ -48,137 +153,616 @@ async fn rescan(
     ctx: Context,
     ignore_set: IgnoreSet,
 ) -> serenity::Result<()> {
-    let output;
-    if !command
-        .member
-        .as_ref()
-        .unwrap()
-        .permissions
-        .unwrap()
-        .administrator()
-    {
-        output = "You must be an administrator to run this command.".to_string();
-    } else {
-        output = match scan(user_db, guild, ctx.clone(), ignore_set.clone()).await {
-            Ok(n) => format!(
-                "Command Sent Successfully (should complete in {} seconds)",
-                n
-            ),
-            Err(e) => format!("Command Failed: {}", e.to_string()),
-        };
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            false,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    if handlers::option_bool(&command.data.options, "dry-run").unwrap_or(false) {
+        return dry_run::run(user_db, command, guild, ctx).await;
     }
+    // the scan takes minutes in large guilds, its progress is edited into this response
+    response::defer(&ctx, &command, false).await?;
+    let guild_members = match members::fetch_all(&ctx.http, guild).await {
+        Ok(guild_members) => guild_members,
+        Err(why) => {
+            return scan_failed(&ctx, &command, format!("could not list members: {}", why)).await
+        }
+    };
+    let job_id = match snapshots::take(user_db, guild, "rescan", &guild_members).await {
+        Some(job_id) => job_id,
+        None => {
+            return scan_failed(
+                &ctx,
+                &command,
+                "could not snapshot members, so the scan was not started",
+            )
+            .await
+        }
+    };
+    let progress = ScanProgress {
+        total: guild_members.len(),
+        command,
+        job_id,
+    };
+    progress.report(&ctx, &ScanTally::default(), false).await;
+    scan(
+        user_db,
+        guild,
+        guild_members,
+        ctx,
+        ignore_set,
+        Some(progress),
+    );
+    Ok(())
+}
+
+async fn scan_failed(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    why: impl std::fmt::Display,
+) -> serenity::Result<()> {
     command
-        .create_interaction_response(&ctx.http, |interaction| {
-            interaction
-                .kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.create_embed(|embed| embed.title(output))
-                })
+        .edit_original_interaction_response(&ctx.http, |response| {
+            response.create_embed(|embed| embed.title(format!("Command Failed: {}", why)))
         })
-        .await
+        .await?;
+    Ok(())
+}
+
+/// What a scan has done so far
+#[derive(Default)]
+struct ScanTally {
+    checked: usize,
+    renamed: usize,
+    roles_added: usize,
+    failed: usize,
+}
+
+/// The `/rescan` response a scan edits its progress into
+struct ScanProgress {
+    command: ApplicationCommandInteraction,
+    job_id: String,
+    total: usize,
+}
+
+impl ScanProgress {
+    async fn report(&self, ctx: &Context, tally: &ScanTally, done: bool) {
+        let edited = self
+            .command
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response.create_embed(|embed| {
+                    embed
+                        .title(if done {
+                            "Scan Complete"
+                        } else {
+                            "Scanning Members"
+                        })
+                        .description(format!(
+                            "{}/{} members checked. Undo it with `/admin rollback job-id:{}`.",
+                            response::count(tally.checked),
+                            response::count(self.total),
+                            self.job_id
+                        ))
+                        .field("Nicknames fixed", response::count(tally.renamed), true)
+                        .field("Roles assigned", response::count(tally.roles_added), true)
+                        .field("Failed", response::count(tally.failed), true)
+                })
+            })
+            .await;
+        // interaction tokens expire after 15 minutes, the scan carries on regardless
+        if let Err(why) = edited {
+            warn!(
+                "Cannot report scan progress in {:?}: {}",
+                self.command.guild_id, why
+            );
+        }
+    }
 }
 
-async fn scan(
+/// Checks every member in the background, [`SCAN_CONCURRENCY`] at a time, reporting progress
+/// after each batch of [`SCAN_BATCH`] when started by `/rescan`
+fn scan(
     user_db: &'static db::DynamoDB,
     guild_id: GuildId,
+    guild_members: Vec<Member>,
     ctx: Context,
     ignore_set: IgnoreSet,
-) -> serenity::Result<String> {
-    let guild = ctx.http.get_guild(guild_id.into()).await?;
-    let mut guild_members = guild.members(&ctx.http, None, None).await?;
-    let numbers = match guild_members.len() {
-        1000 => "≥250".to_string(),
-        _ => format!("~{}", guild_members.len() / 10),
-    };
-    tokio::spawn(async move {
+    progress: Option<ScanProgress>,
+) {
+    let span = info_span!("scan", guild_id = %guild_id);
+    let task = async move {
+        let _job = ratelimits::job(guild_id, "scan");
+        let started = Instant::now();
         let role_mappings = user_db.get_role_config(guild_id).await;
-        // for pagination
-        while guild_members.len() > 0 {
-            let mut last_id = None;
-            for mut member in &mut guild_members {
-                handle_member_status(
-                    user_db,
-                    &ctx,
-                    &mut member,
-                    &role_mappings,
-                    ignore_set.clone(),
-                )
+        let mut tally = ScanTally::default();
+        for batch in guild_members.chunks(SCAN_BATCH) {
+            if shutdown::draining() {
+                info!(
+                    "Scan of {} stopped for shutdown after {} members",
+                    guild_id, tally.checked
+                );
+                return;
+            }
+            let (ctx, role_mappings) = (&ctx, &role_mappings);
+            let results = stream::iter(batch.iter().cloned())
+                .map(|mut member| {
+                    let ignore_set = ignore_set.clone();
+                    async move {
+                        let result = handle_member_status(
+                            user_db,
+                            ctx,
+                            &mut member,
+                            role_mappings,
+                            ignore_set,
+                            nicknames::Source::Scan,
+                        )
+                        .await;
+                        // sleep to stay far away from rate limit
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        (member.user.id, result)
+                    }
+                })
+                .buffer_unordered(SCAN_CONCURRENCY)
+                .collect::<Vec<_>>()
                 .await;
-                last_id = Some(member.user.id);
-                // sleep to stay far away from rate limit
-                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            for (user_id, result) in results {
+                tally.checked += 1;
+                match result {
+                    Ok(changes) => {
+                        tally.renamed += changes.renamed as usize;
+                        tally.roles_added += changes.roles_added;
+                    }
+                    Err(why) => {
+                        tally.failed += 1;
+                        error!("Scan of {} failed for {}: {}", guild_id, user_id, why);
+                    }
+                }
             }
-            guild_members = guild.members(&ctx.http, None, last_id).await.unwrap();
+            if let Some(progress) = &progress {
+                progress.report(ctx, &tally, false).await;
+            }
+        }
+        stats::record_scan(guild_id);
+        let trigger = if progress.is_some() { "rescan" } else { "automatic" };
+        telemetry::record_scan(trigger, started.elapsed());
+        unrenamable::report(user_db, &ctx.http, guild_id).await;
+        unrenamable::warn(user_db, &ctx.http, guild_id).await;
+        if let Some(progress) = &progress {
+            progress.report(&ctx, &tally, true).await;
+        }
+    };
+    tokio::spawn(task.instrument(span));
+}
+
+/// Runs `handle_member_status` on members who joined the guild at or after `since`
+async fn reconcile(
+    user_db: &'static db::DynamoDB,
+    guild_id: GuildId,
+    since: i64,
+    ctx: &Context,
+    ignore_set: IgnoreSet,
+) -> error::Result<()> {
+    let role_mappings = user_db.get_role_config(guild_id).await;
+    for mut member in members::fetch_all(&ctx.http, guild_id).await? {
+        if member.joined_at.map_or(true, |j| j.timestamp() < since) {
+            continue;
+        }
+        if let Err(why) = handle_member_status(
+            user_db,
+            ctx,
+            &mut member,
+            &role_mappings,
+            ignore_set.clone(),
+            nicknames::Source::Reconcile,
+        )
+        .await
+        {
+            error!(
+                "Reconciling {} failed for {}: {}",
+                guild_id, member.user.id, why
+            );
         }
-    });
-    Ok(numbers)
+        // sleep to stay far away from rate limit
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    unrenamable::warn(user_db, &ctx.http, guild_id).await;
+    Ok(())
+}
+
+/// What `handle_member_status` changed
+#[derive(Default)]
+struct MemberChanges {
+    renamed: bool,
+    roles_added: usize,
+}
+
+/// What `handle_member_status` looked up about the member, all [`apply_member_status`] needs to
+/// work out their roles and nickname
+struct MemberStatus {
+    config: config::GuildConfig,
+    /// the member's claims, if they're verified and can have roles in the guild
+    claims: Option<db::Claims>,
+    /// follows a verified member's name: the marker for students, the alumni suffix for former
+    /// students. Verified members without one keep their nickname.
+    marker: Option<String>,
+    /// the member's EID, if the nickname template shows it
+    eid: Option<String>,
+    owner: Option<UserId>,
+    now: i64,
+}
+
+/// The requests [`apply_member_status`] made and Discord's answers
+#[derive(Default)]
+struct Applied {
+    roles: Option<(Vec<RoleId>, serenity::Result<()>)>,
+    nickname: Option<(String, serenity::Result<()>)>,
+    /// the nickname the owner should have, which isn't requested since Discord never lets bots
+    /// rename them
+    owner_nickname: Option<String>,
 }
 
-/// Modifies the name and roles of the user to either sanitize it or assign it the ✓
+/// Modifies the name and roles of the user to either sanitize it or assign it the ✓. Failing to
+/// add roles doesn't keep the nickname from being set; the error is returned afterwards.
 async fn handle_member_status(
     db_client: &db::DynamoDB,
     ctx: &Context,
     mem: &mut Member,
     role_mappings: &HashMap<String, u64>,
     ignore_set: IgnoreSet,
-) -> bool {
+    source: nicknames::Source,
+) -> error::Result<MemberChanges> {
+    let _in_flight = shutdown::in_flight();
+    if !mem.user.bot {
+        members::record(db_client, mem.guild_id, mem.user.id).await;
+    }
+    let config = db_client.get_guild_config(mem.guild_id).await;
+    let claims = match db_client.get_user(mem.user.id.into()).await {
+        Some(_) if bans::evading(db_client, &ctx.http, mem).await => None,
+        // accounts verified elsewhere get no roles here until they pass the guild's age gate,
+        // the sweep after that adds them
+        Some(_) if handlers::age_gate(&config, mem.user.id, Some(mem)).is_some() => None,
+        // held verifications are treated as unverified until approved
+        Some(claims) if review::hold(db_client, &ctx.http, mem, &claims).await => None,
+        claims => claims,
+    };
+    quarantine::apply(db_client, &ctx.http, mem, claims.is_some()).await;
+    let marker = match &claims {
+        Some(claims) if claims.affiliation.contains(&"student".to_string()) => {
+            Some(if config.native_marker {
+                String::new()
+            } else {
+                config.marker_symbol().to_string()
+            })
+        }
+        Some(_) => alumni::former_student(db_client, ctx, mem, role_mappings).await,
+        None => None,
+    };
+    let eid = match marker {
+        Some(_) => nickname_policy::shown_eid(db_client, mem.user.id, &config).await,
+        None => None,
+    };
+    let status = MemberStatus {
+        config,
+        claims,
+        marker,
+        eid,
+        owner: unrenamable::owner(&ctx.http, mem.guild_id).await,
+        now: response::unix_now(),
+    };
     let original = mem.display_name().to_string();
-    let mut cleaned = mem
-        .display_name()
-        .replace(|c: char| !c.is_ascii(), "")
-        .trim()
-        .to_string();
-    if let Some(user_claims) = db_client.get_user(mem.user.id.into()).await {
-        let mut roles_to_add = Vec::new();
-        let mut user_tags = user_claims.affiliation.clone();
-        user_tags.extend(user_claims.major.clone());
-        user_tags.extend(user_claims.school.clone());
-        for tag in &user_tags {
-            if let Some(role_id) = role_mappings.get(tag) {
-                if !mem.roles.contains(&RoleId(*role_id)) {
-                    roles_to_add.push(RoleId(*role_id));
+    let applied =
+        apply_member_status(&*ctx.http, mem, &status, role_mappings, &ignore_set, source).await;
+    if status.claims.is_some() {
+        membership::apply(db_client, &ctx.http, mem).await;
+    }
+
+    let mut changes = MemberChanges::default();
+    let mut roles_failed = None;
+    if let Some((roles_to_add, result)) = applied.roles {
+        let roles = roles_to_add
+            .iter()
+            .map(|r| format!("<@&{}>", r))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match result {
+            Ok(_) => {
+                changes.roles_added = roles_to_add.len();
+                webhooks::emit(
+                    db_client,
+                    mem.guild_id,
+                    mem.user.id,
+                    webhooks::Event::RoleAssigned(roles_to_add.clone()),
+                )
+                .await;
+                telemetry::count(
+                    telemetry::Counter::RoleAssignments,
+                    "ok",
+                    roles_to_add.len() as u64,
+                );
+                audit::post(
+                    db_client,
+                    &ctx.http,
+                    mem.guild_id,
+                    audit::Post {
+                        action: "Roles added",
+                        member: mem.user.id,
+                        actor: None,
+                        reason: format!("{} ({})", roles, source.name()),
+                        failed: false,
+                    },
+                )
+                .await;
+            }
+            Err(why) => {
+                telemetry::count(
+                    telemetry::Counter::RoleAssignments,
+                    "failed",
+                    roles_to_add.len() as u64,
+                );
+                telemetry::count(telemetry::Counter::DiscordErrors, "add_roles", 1);
+                if unrenamable::is_forbidden(&why) {
+                    unrenamable::denied(mem.guild_id, mem.user.id);
+                    audit::post(
+                        db_client,
+                        &ctx.http,
+                        mem.guild_id,
+                        audit::Post {
+                            action: "Couldn't add roles",
+                            member: mem.user.id,
+                            actor: None,
+                            reason: format!(
+                                "Missing permissions, the bot's role must be above {}",
+                                roles
+                            ),
+                            failed: true,
+                        },
+                    )
+                    .await;
                 }
+                roles_failed = Some(error::Error::AddRoles(why));
             }
         }
-        if roles_to_add.len() > 0 && !mem.add_roles(&ctx.http, &roles_to_add).await.is_ok() {
-            eprintln!("Failed to Add Roles to {}", original);
-        }
-        if user_claims.affiliation.contains(&"student".to_string()) {
-            cleaned.push_str(" ✓");
-        } else {
-            return true;
+    }
+    // set for verified members, whose nickname the guild's policies decorate
+    let verified_config = status.marker.as_ref().map(|_| &status.config);
+    if let (Some(cleaned), Some(config)) = (&applied.owner_nickname, verified_config) {
+        unrenamable::handle(db_client, &ctx.http, mem, cleaned, config).await;
+    }
+    if let Some((cleaned, result)) = applied.nickname {
+        match result {
+            Ok(_) => {
+                changes.renamed = true;
+                telemetry::count(telemetry::Counter::NicknameEdits, "ok", 1);
+                audit::post(
+                    db_client,
+                    &ctx.http,
+                    mem.guild_id,
+                    audit::Post {
+                        action: "Nickname changed",
+                        member: mem.user.id,
+                        actor: None,
+                        reason: format!("`{}` to `{}` ({})", original, cleaned, source.name()),
+                        failed: false,
+                    },
+                )
+                .await;
+            }
+            Err(why) => {
+                telemetry::count(telemetry::Counter::NicknameEdits, "failed", 1);
+                telemetry::count(telemetry::Counter::DiscordErrors, "edit_nickname", 1);
+                if unrenamable::is_forbidden(&why) {
+                    unrenamable::denied(mem.guild_id, mem.user.id);
+                    audit::post(
+                        db_client,
+                        &ctx.http,
+                        mem.guild_id,
+                        audit::Post {
+                            action: "Couldn't change nickname",
+                            member: mem.user.id,
+                            actor: None,
+                            reason: format!(
+                                "Missing permissions to set `{}` ({})",
+                                cleaned,
+                                source.name()
+                            ),
+                            failed: true,
+                        },
+                    )
+                    .await;
+                }
+                match verified_config {
+                    Some(config) if unrenamable::is_forbidden(&why) => {
+                        unrenamable::handle(db_client, &ctx.http, mem, &cleaned, config).await;
+                    }
+                    _ => return Err(error::Error::Nickname(why)),
+                }
+            }
         }
     }
-    if original != cleaned {
-        {
-            ignore_set.lock().await.insert(mem.user.id);
+    roles_failed.map_or(Ok(changes), Err)
+}
+
+/// Adds the roles and sets the nickname the member's status calls for, leaving the reporting to
+/// `handle_member_status`
+async fn apply_member_status(
+    discord: &dyn discord::Discord,
+    mem: &mut Member,
+    status: &MemberStatus,
+    role_mappings: &HashMap<String, u64>,
+    ignore_set: &IgnoreSet,
+    source: nicknames::Source,
+) -> Applied {
+    let mut applied = Applied::default();
+    let original = mem.display_name().to_string();
+    let mut nickname = nickname_policy::clean(&original);
+    if let Some(claims) = &status.claims {
+        let roles_to_add = roles::mapped_roles(claims, role_mappings, &mem.roles);
+        if !roles_to_add.is_empty() {
+            let result = discord::add_roles(discord, mem, &roles_to_add).await;
+            applied.roles = Some((roles_to_add, result));
         }
-        mem.edit(&ctx.http, |m| m.nickname(cleaned)).await.is_ok()
-    } else {
-        false
+        nickname = match &status.marker {
+            Some(marker) => nickname_policy::compose(
+                &status.config,
+                &nickname,
+                claims,
+                status.eid.as_deref(),
+                marker,
+                status.now,
+            ),
+            None => original.clone(),
+        };
+    }
+    if original == nickname {
+        return applied;
+    }
+    if status.owner == Some(mem.user.id) {
+        applied.owner_nickname = Some(nickname);
+        return applied;
+    }
+    nicknames::wait_turn(mem.guild_id, mem.user.id, source).await;
+    {
+        ignore_set.lock().await.insert(mem.user.id);
+    }
+    let result = discord::set_nickname(discord, mem, &nickname).await;
+    if result.is_err() {
+        ignore_set.lock().await.remove(&mem.user.id);
     }
+    applied.nickname = Some((nickname, result));
+    applied
 }
 
 #[async_trait]
 impl EventHandler for Handler {
+    #[instrument(skip_all, fields(guild_id = %guild.id))]
     async fn guild_create(&self, ctx: Context, guild: Guild) {
-        scan(self.db_client, guild.id, ctx, self.ignore_set.clone())
-            .await
-            .unwrap();
+        if tenancy::enforce(self.db_client, &ctx.http, &guild).await {
+            return;
+        }
+        cache::set_roles(guild.id, guild.roles.clone());
+        // the scan picks up a changed marker, no separate reconciliation needed
+        marker::sync(self.db_client, &ctx.http, guild.id, guild.roles.values()).await;
+        if let Err(why) = roles::position_verified_role(self.db_client, &ctx.http, guild.id).await {
+            warn!("Cannot position the verified role of {}: {}", guild.id, why);
+        }
+        if !self.features.enabled(intents::Feature::GuildScans) {
+            return;
+        }
+        match members::fetch_all(&ctx.http, guild.id).await {
+            Ok(guild_members) => scan(
+                self.db_client,
+                guild.id,
+                guild_members,
+                ctx,
+                self.ignore_set.clone(),
+                None,
+            ),
+            Err(why) => warn!("Cannot scan {}: {}", guild.id, why),
+        }
+    }
+
+    async fn guild_delete(&self, _ctx: Context, incomplete: GuildUnavailable) {
+        // an outage rather than the bot being removed
+        if incomplete.unavailable {
+            return;
+        }
+        forget::guild_removed(self.db_client, incomplete.id).await;
+    }
+
+    async fn guild_role_create(&self, _ctx: Context, guild_id: GuildId, _role: Role) {
+        cache::invalidate_roles(guild_id);
+    }
+
+    #[instrument(skip_all, fields(guild_id = %guild_id))]
+    async fn guild_role_delete(&self, ctx: Context, guild_id: GuildId, role_id: RoleId) {
+        let deleted = cache::cached_role(guild_id, role_id);
+        cache::invalidate_roles(guild_id);
+        if roles::repair_deleted(self.db_client, &ctx.http, guild_id, role_id, deleted).await {
+            // gives verified members the recreated role
+            self.jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+        }
+    }
+
+    #[instrument(skip_all, fields(guild_id = %guild_id))]
+    async fn guild_role_update(&self, ctx: Context, guild_id: GuildId, role: Role) {
+        let before = cache::cached_role(guild_id, role.id);
+        cache::invalidate_roles(guild_id);
+        roles::follow_rename(self.db_client, &ctx.http, guild_id, before, &role).await;
+        let config = self.db_client.get_guild_config(guild_id).await;
+        if role.name != config.verified_role_name() {
+            return;
+        }
+        let guild_roles = match cache::roles(&ctx.http, guild_id).await {
+            Ok(guild_roles) => guild_roles,
+            Err(why) => {
+                warn!("Cannot fetch roles of {}: {}", guild_id, why);
+                return;
+            }
+        };
+        if marker::sync(self.db_client, &ctx.http, guild_id, guild_roles.values()).await {
+            // adds or takes off ✓ in every member's nickname
+            self.jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+        }
+        // an admin may have just moved it; moving it back fires this again, as a no-op
+        if let Err(why) = roles::position_verified_role(self.db_client, &ctx.http, guild_id).await {
+            warn!("Cannot position the verified role of {}: {}", guild_id, why);
+        }
     }
 
+    #[instrument(skip_all, fields(guild_id = %guild_id, user_id = %new_member.user.id))]
     async fn guild_member_addition(&self, ctx: Context, guild_id: GuildId, mut new_member: Member) {
+        stats::invalidate(guild_id);
+        if !self.features.enabled(intents::Feature::MemberJoins) {
+            return;
+        }
         let role_mappings = self.db_client.get_role_config(guild_id).await;
-        handle_member_status(
+        if let Err(why) = handle_member_status(
             self.db_client,
             &ctx,
             &mut new_member,
             &role_mappings,
             self.ignore_set.clone(),
+            nicknames::Source::Join,
         )
-        .await;
+        .await
+        {
+            warn!(
+                "Cannot update {} joining {}: {}",
+                new_member.user.id, guild_id, why
+            );
+        }
+        onboarding::run(self.db_client, &ctx.http, guild_id, &new_member).await;
+    }
+
+    async fn guild_ban_addition(&self, _ctx: Context, guild_id: GuildId, banned_user: User) {
+        if self.features.enabled(intents::Feature::BanEvasion) {
+            bans::banned(self.db_client, guild_id, banned_user.id).await;
+        }
+    }
+
+    async fn guild_ban_removal(&self, _ctx: Context, guild_id: GuildId, unbanned_user: User) {
+        if self.features.enabled(intents::Feature::BanEvasion) {
+            bans::unbanned(self.db_client, guild_id, unbanned_user.id).await;
+        }
+    }
+
+    async fn guild_member_removal(
+        &self,
+        _ctx: Context,
+        guild_id: GuildId,
+        user: User,
+        _member: Option<Member>,
+    ) {
+        stats::invalidate(guild_id);
+        members::left(self.db_client, guild_id, user.id).await;
     }
 
+    #[instrument(skip_all, fields(guild_id = %update.guild_id, user_id = %update.user.id))]
     async fn guild_member_update(&self, ctx: Context, update: GuildMemberUpdateEvent) {
         {
             let mut ignore_set = self.ignore_set.lock().await;
@@ -187,60 +771,221 @@ impl EventHandler for Handler {
                 return;
             }
         }
-        if let Ok(guild) = ctx.http.get_guild(update.guild_id.into()).await {
-            let role_mappings = self.db_client.get_role_config(guild.id).await;
-            if let Ok(mut member) = guild.member(&ctx.http, update.user.id).await {
-                handle_member_status(
-                    self.db_client,
-                    &ctx,
-                    &mut member,
-                    &role_mappings,
-                    self.ignore_set.clone(),
-                )
-                .await;
+        if !self.features.enabled(intents::Feature::MemberUpdates) {
+            return;
+        }
+        // the event carries the member's new state, but not as a `Member`
+        if let Ok(mut member) = update.guild_id.member(&ctx.http, update.user.id).await {
+            let role_mappings = self.db_client.get_role_config(update.guild_id).await;
+            if let Err(why) = handle_member_status(
+                self.db_client,
+                &ctx,
+                &mut member,
+                &role_mappings,
+                self.ignore_set.clone(),
+                nicknames::Source::Update,
+            )
+            .await
+            {
+                warn!(
+                    "Cannot update {} in {}: {}",
+                    member.user.id, update.guild_id, why
+                );
             }
         }
     }
 
-    async fn ready(&self, ctx: Context, ready: Ready) {
-        let commands = ApplicationCommand::set_global_application_commands(&ctx.http, |commands| {
-            commands
-                .create_application_command(|command| {
-                    command
-                        .name("verify")
-                        .description("Verify your Discord Account")
-                        .create_option(|option| {
-                            option
-                                .name("eid")
-                                .description("Your UT EID")
-                                .kind(ApplicationCommandOptionType::String)
-                                .required(true)
-                        })
-                })
-                .create_application_command(|command| {
-                    command
-                        .name("help")
-                        .description("Learn more about the bot and its commands")
-                })
-                .create_application_command(|command| {
-                    command.name("rescan").description(
-                        "Check all users in the guild for nickname compliance and role assignment",
-                    )
-                })
-        })
-        .await;
+    #[instrument(skip_all, fields(user_id = %message.author.id))]
+    async fn message(&self, ctx: Context, message: Message) {
+        if message.guild_id.is_some()
+            || message.author.bot
+            || !self.features.enabled(intents::Feature::DirectMessages)
+        {
+            return;
+        }
+        if let Err(why) = dm::handle(self.db_client, &ctx, &message).await {
+            warn!("Cannot reply to the DM from {}: {}", message.author.id, why);
+        }
+    }
+
+    async fn shard_stage_update(&self, _ctx: Context, event: ShardStageUpdateEvent) {
+        if event.old == ConnectionStage::Connected && event.new != ConnectionStage::Connected {
+            warn!("Shard {} disconnected", event.shard_id.0);
+            self.disconnected_at
+                .lock()
+                .unwrap()
+                .entry(event.shard_id.0)
+                .or_insert_with(response::unix_now);
+        }
+    }
+
+    async fn resume(&self, ctx: Context, _: ResumedEvent) {
+        let disconnected_at = self.disconnected_at.lock().unwrap().remove(&ctx.shard_id);
+        let since = match disconnected_at {
+            None => response::unix_now() - RECONCILE_FALLBACK_SECS,
+            Some(at) => at - RECONCILE_SLACK_SECS,
+        };
+        match sharding::guilds(&ctx.http).await {
+            Ok(guilds) => {
+                // the other shards' guilds didn't miss anything
+                let guilds = guilds
+                    .into_iter()
+                    .filter(|g| sharding::shard_of(*g) == ctx.shard_id)
+                    .collect::<Vec<_>>();
+                info!(
+                    "Shard {} resumed, reconciling members of {} guilds who joined since {}",
+                    ctx.shard_id,
+                    guilds.len(),
+                    since
+                );
+                for guild_id in guilds {
+                    self.jobs.push(jobs::Job::Reconcile { guild_id, since });
+                }
+            }
+            Err(why) => warn!("Cannot list guilds to reconcile after resume: {}", why),
+        }
+    }
 
-        println!(
-            "I now have the following global slash commands: {:#?}",
-            commands
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        sharding::set_total(ready.shard);
+        info!(
+            "Shard {} ready with {} guilds",
+            ctx.shard_id,
+            ready.guilds.len()
         );
+        // a fresh session replays guild_create for every guild, which rescans them
+        self.disconnected_at.lock().unwrap().remove(&ctx.shard_id);
+        let first_ready = !self
+            .background_task_running
+            .fetch_or(true, Ordering::Relaxed);
+        // global commands are the same for every shard
+        if first_ready {
+            commands::sync(&ctx.http).await;
+        }
+        // pilot guilds; disabling a beta command unregisters it right away
+        for guild in &ready.guilds {
+            let config = self.db_client.get_guild_config(guild.id()).await;
+            if !config.beta_commands.is_empty() || commands::registers_in(guild.id()) {
+                commands::sync_guild(&ctx.http, guild.id(), &config.beta_commands).await;
+            }
+        }
 
         let ctx = Arc::new(ctx);
 
-        if !self
-            .background_task_running
-            .fetch_or(true, Ordering::Relaxed)
-        {
+        if first_ready {
+            tokio::spawn(ratelimits::observe_loop(ctx.http.clone()));
+            shutdown::emit_previous(&ctx.http).await;
+            if let Some(mut receiver) = self.jobs.take_receiver() {
+                let ctx = ctx.clone();
+                let dbc = self.db_client;
+                let igset = self.ignore_set.clone();
+                tokio::spawn(async move {
+                    while let Some(job) = receiver.recv().await {
+                        let _running = match &job {
+                            jobs::Job::Reconcile { guild_id, .. } => {
+                                ratelimits::job(*guild_id, "reconcile")
+                            }
+                            jobs::Job::Member { guild_id, .. } => {
+                                ratelimits::job(*guild_id, "member")
+                            }
+                            jobs::Job::Rollback { guild_id, .. } => {
+                                ratelimits::job(*guild_id, "rollback")
+                            }
+                            jobs::Job::DuesSync { guild_id } => ratelimits::job(*guild_id, "dues"),
+                        };
+                        let result = match job {
+                            jobs::Job::Reconcile { guild_id, since } => {
+                                reconcile(dbc, guild_id, since, &ctx, igset.clone()).await
+                            }
+                            jobs::Job::Member { guild_id, user_id } => {
+                                match ctx.http.get_member(guild_id.0, user_id.0).await {
+                                    Ok(mut member) => {
+                                        let role_mappings = dbc.get_role_config(guild_id).await;
+                                        handle_member_status(
+                                            dbc,
+                                            &ctx,
+                                            &mut member,
+                                            &role_mappings,
+                                            igset.clone(),
+                                            nicknames::Source::Member,
+                                        )
+                                        .await
+                                        .map(|_| ())
+                                    }
+                                    Err(why) => Err(why.into()),
+                                }
+                            }
+                            jobs::Job::Rollback {
+                                guild_id,
+                                job_id,
+                                actor,
+                            } => snapshots::rollback(
+                                dbc,
+                                &ctx,
+                                guild_id,
+                                &job_id,
+                                actor,
+                                igset.clone(),
+                            )
+                            .await
+                            .map_err(error::Error::from),
+                            jobs::Job::DuesSync { guild_id } => {
+                                if let Err(why) = membership::sync(dbc, &ctx.http, guild_id).await {
+                                    error!("Dues sync of {} failed: {}", guild_id, why);
+                                }
+                                Ok(())
+                            }
+                        };
+                        if let Err(why) = result {
+                            error!("Job failed: {}", why);
+                        }
+                    }
+                });
+            }
+
+            rush::restore(self.db_client).await;
+
+            // these loops cover every guild and the verification queue, so only stable runs them
+            if self.canary {
+                return;
+            }
+            tokio::spawn(attest::expire_loop(self.db_client, ctx.http.clone()));
+            tokio::spawn(expiry::expire_loop(self.db_client, ctx.http.clone()));
+            tokio::spawn(scheduler::run_loop(
+                self.db_client,
+                ctx.http.clone(),
+                self.jobs.clone(),
+            ));
+            tokio::spawn(channels::enforce_loop(self.db_client, ctx.http.clone()));
+            tokio::spawn(roles::position_loop(self.db_client, ctx.http.clone()));
+            let sweep_hours = settings::sweep_interval_hours().ok().flatten();
+            if let Some(hours) =
+                sweep_hours.filter(|_| self.features.enabled(intents::Feature::GuildScans))
+            {
+                tokio::spawn(sweep::sweep_loop(
+                    self.db_client,
+                    ctx.clone(),
+                    self.ignore_set.clone(),
+                    Duration::from_secs(hours * 60 * 60),
+                ));
+            }
+            tokio::spawn(components::sweep_loop(self.db_client, ctx.http.clone()));
+            if let Ok(Some(target)) = settings::backup_target() {
+                tokio::spawn(backup::backup_loop(
+                    self.db_client,
+                    target,
+                    Duration::from_secs(settings::backup_interval_hours().unwrap_or(24) * 60 * 60),
+                    settings::backup_keep().unwrap_or(14),
+                ));
+            }
+            recheck::ensure_scheduled(self.db_client).await;
+            tokio::spawn(recheck::results_loop(self.db_client));
+            tokio::spawn(role_changes::watch_loop(
+                self.db_client,
+                ctx.http.clone(),
+                self.jobs.clone(),
+            ));
+
             let ctx1 = ctx.clone();
 
             let dbc = self.db_client;
@@ -251,34 +996,105 @@ impl EventHandler for Handler {
 
                 loop {
                     tokio::time::sleep(Duration::from_secs(1)).await;
+                    // messages left in the queue are handled after the restart
+                    if shutdown::draining() {
+                        break;
+                    }
 
-                    let out = client
+                    let out = match client
                         .receive_message()
-                        .queue_url(SQS_BECOME_VERIFIED_REQUEST_URL)
+                        .queue_url(SQS_BECOME_VERIFIED_REQUEST_URL.as_str())
                         .max_number_of_messages(REQUESTS_PER_SECOND)
                         .send()
                         .await
-                        .unwrap();
+                    {
+                        Ok(out) => out,
+                        Err(why) => {
+                            error!("{}", error::Error::Queue(why.to_string()));
+                            continue;
+                        }
+                    };
 
                     let messages = match out.messages {
                         Some(msgs) => msgs,
                         None => continue,
                     };
 
+                    // shutdown waits for the batch to be handled and acknowledged
+                    let _in_flight = shutdown::in_flight();
                     let mut entries = Vec::new();
+                    shutdown::set_unacknowledged(messages.len());
 
                     for msg in messages {
-                        let body = msg.body.expect("invalid message received");
-                        let req: BecomeVerifiedMessage =
-                            serde_json::from_str(&body).expect("invalid message received");
-                        let discord_id: u64 = req.discord_id.parse().unwrap_or(0);
-                        if let Ok(guilds) = ctx1.http.get_guilds(&GuildPagination::After(GuildId(0)), 100).await {
-                            for guild in guilds {
-                                if let Ok(mut member) = ctx1.http.get_member(guild.id.into(), discord_id).await {
-                                    let role_mappings = dbc.get_role_config(guild.id).await;
-                                    handle_member_status(dbc, &ctx1, &mut member, &role_mappings, igset.clone()).await;
-                                }
+                        // invalid messages are deleted too, or they'd be received again forever
+                        let discord_id = match parse_verified_message(msg.body.as_deref()) {
+                            Ok(discord_id) => {
+                                telemetry::count(telemetry::Counter::Verifications, "ok", 1);
+                                discord_id
+                            }
+                            Err(why) => {
+                                telemetry::count(telemetry::Counter::Verifications, "invalid", 1);
+                                error!("{}", why);
+                                0
                             }
+                        };
+                        let members = if discord_id == 0 {
+                            Vec::new()
+                        } else {
+                            members::mutual(dbc, &ctx1.http, UserId(discord_id)).await
+                        };
+                        for mut member in members {
+                            let guild_id = member.guild_id;
+                            let role_mappings = dbc.get_role_config(guild_id).await;
+                            if let Err(why) = handle_member_status(
+                                dbc,
+                                &ctx1,
+                                &mut member,
+                                &role_mappings,
+                                igset.clone(),
+                                nicknames::Source::Verification,
+                            )
+                            .instrument(info_span!(
+                                "verification",
+                                guild_id = %guild_id,
+                                user_id = discord_id
+                            ))
+                            .await
+                            {
+                                warn!("Cannot update {} in {}: {}", discord_id, guild_id, why);
+                            }
+                            audit::post(
+                                dbc,
+                                &ctx1.http,
+                                guild_id,
+                                audit::Post {
+                                    action: "Verification processed",
+                                    member: member.user.id,
+                                    actor: Some(member.user.id),
+                                    reason: "Verified their UT EID".to_string(),
+                                    failed: false,
+                                },
+                            )
+                            .await;
+                            stats::invalidate(guild_id);
+                            rush::record_verification(guild_id);
+                            stats::check_milestone(dbc, &ctx1.http, guild_id).await;
+                            analytics::record(
+                                dbc,
+                                Some(guild_id),
+                                member.user.id,
+                                analytics::Stage::Verified,
+                            )
+                            .await;
+                            sheets::append(dbc, guild_id, "verified", member.user.id).await;
+                            webhooks::emit(
+                                dbc,
+                                guild_id,
+                                member.user.id,
+                                webhooks::Event::Verified,
+                            )
+                            .await;
+                            success::run(dbc, &ctx1.http, guild_id, &member).await;
                         }
                         entries.push(
                             DeleteMessageBatchRequestEntry::builder()
@@ -288,94 +1104,334 @@ impl EventHandler for Handler {
                         );
                     }
 
-                    client
+                    let deleted = client
                         .delete_message_batch()
-                        .queue_url(SQS_BECOME_VERIFIED_REQUEST_URL)
+                        .queue_url(SQS_BECOME_VERIFIED_REQUEST_URL.as_str())
                         .set_entries(Some(entries))
                         .send()
-                        .await
-                        .unwrap();
+                        .await;
+                    if let Err(why) = deleted {
+                        error!("{}", error::Error::Queue(why.to_string()));
+                    }
+                    shutdown::set_unacknowledged(0);
                 }
             });
         }
     }
 
+    #[instrument(skip_all, fields(kind, name, guild_id, user_id))]
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::ApplicationCommand(command) = interaction {
-            if let Err(why) = match command.data.name.as_str() {
-                "verify" => handlers::verify(command, ctx).await,
-                "rescan" => match command.guild_id {
-                    Some(guild) => {
+        if shutdown::draining() {
+            shutdown::refuse(&ctx, &interaction).await;
+            return;
+        }
+        let _in_flight = shutdown::in_flight();
+        let started = Instant::now();
+        match interaction {
+            Interaction::ApplicationCommand(command) => {
+                let (name, guild_id) = (command.data.name.clone(), command.guild_id);
+                record_interaction("command", &name, guild_id, command.user.id);
+                let result = match (command.data.name.as_str(), command.guild_id) {
+                    ("verify", _) => handlers::verify(self.db_client, command, ctx).await,
+                    ("redeem", _) => redeem::redeem(self.db_client, command, ctx).await,
+                    ("help", _) => handlers::help_command(self.db_client, command, ctx).await,
+                    ("support", _) => support::support(command, ctx).await,
+                    ("status", _) => sharding::status(command, ctx).await,
+                    ("tenant", _) => tenancy::tenant(self.db_client, command, ctx).await,
+                    (name, _) if owner::COMMANDS.contains(&name) => {
+                        if owner::is_owner(&ctx.http, command.user.id).await {
+                            owner::run(self.db_client, command, ctx).await
+                        } else {
+                            owner::refuse(&ctx, &command).await
+                        }
+                    }
+                    ("forgetme", _) => forget::forgetme(self.db_client, command, ctx).await,
+                    ("certificate", _) => {
+                        certificate::certificate(self.db_client, command, ctx).await
+                    }
+                    ("verify-beta", _) => handlers::verify_beta(self.db_client, command, ctx).await,
+                    ("admin", Some(guild)) => {
+                        admin::admin(self.db_client, command, guild, ctx, &self.jobs).await
+                    }
+                    ("attest", Some(guild)) => {
+                        attest::attest(self.db_client, command, guild, ctx).await
+                    }
+                    ("attestations", Some(guild)) => {
+                        attest::attestations(self.db_client, command, guild, ctx).await
+                    }
+                    ("config", Some(guild)) => {
+                        config::config(self.db_client, command, guild, ctx, &self.jobs).await
+                    }
+                    ("eligible-voters", Some(guild)) => {
+                        elections::eligible_voters(self.db_client, command, guild, ctx).await
+                    }
+                    ("event-qr", Some(guild)) => {
+                        events::event_qr(self.db_client, command, guild, ctx).await
+                    }
+                    ("checkin", Some(guild)) => {
+                        checkin::checkin(self.db_client, command, guild, ctx).await
+                    }
+                    ("guest", Some(guild)) => {
+                        guest::guest(self.db_client, command, guild, ctx).await
+                    }
+                    ("roles", Some(guild)) => {
+                        self_roles::roles_command(self.db_client, command, guild, ctx).await
+                    }
+                    ("merge-roles", Some(guild)) => {
+                        roles::merge_roles(self.db_client, command, guild, ctx).await
+                    }
+                    ("instructions", Some(guild)) => {
+                        instructions::instructions(self.db_client, command, guild, ctx).await
+                    }
+                    ("note", Some(guild)) => notes::note(self.db_client, command, guild, ctx).await,
+                    ("rescan", Some(guild)) => {
                         rescan(self.db_client, command, guild, ctx, self.ignore_set.clone()).await
                     }
-                    None => {
+                    ("unverify", Some(guild)) => {
+                        handlers::unverify(
+                            self.db_client,
+                            command,
+                            guild,
+                            ctx,
+                            self.ignore_set.clone(),
+                            &self.jobs,
+                        )
+                        .await
+                    }
+                    ("whois", Some(guild)) => {
+                        whois::whois(self.db_client, command, guild, ctx).await
+                    }
+                    ("lookup", Some(guild)) => {
+                        whois::lookup(self.db_client, command, guild, ctx).await
+                    }
+                    ("stats", Some(guild)) => {
+                        stats::stats(self.db_client, command, guild, ctx).await
+                    }
+                    ("setup-panel", Some(guild)) => {
+                        panel::setup_panel(self.db_client, command, guild, ctx).await
+                    }
+                    (
+                        "admin" | "attest" | "attestations" | "config" | "eligible-voters"
+                        | "event-qr" | "checkin" | "guest" | "instructions" | "merge-roles"
+                        | "note" | "rescan" | "setup-panel" | "stats" | "unverify" | "whois"
+                        | "lookup",
+                        None,
+                    ) => {
+                        response::respond_title(
+                            &ctx,
+                            &command,
+                            false,
+                            "This command must be run inside of a guild, not a DM.",
+                        )
+                        .await
+                    }
+                    _ => {
                         command
                             .create_interaction_response(&ctx.http, |response| {
                                 response
                                     .kind(InteractionResponseType::ChannelMessageWithSource)
                                     .interaction_response_data(|message| {
                                         message.create_embed(|embed| {
-                                            embed.title(
-                                            "This command must be run inside of a guild, not a DM.",
-                                        )
+                                            match command.data.name.as_str() {
+                                                _ => handlers::unknown_command(embed, &command),
+                                            }
                                         })
                                     })
                             })
                             .await
                     }
-                },
-                _ => {
-                    command
-                        .create_interaction_response(&ctx.http, |response| {
-                            response
-                                .kind(InteractionResponseType::ChannelMessageWithSource)
-                                .interaction_response_data(|message| {
-                                    message.create_embed(|embed| match command.data.name.as_str() {
-                                        _ => handlers::unknown_command(embed, &command),
-                                    })
-                                })
-                        })
+                };
+                telemetry::record("command", &name, started.elapsed(), result.is_ok());
+                if let Err(why) = result {
+                    warn!("Cannot respond to slash command: {}", why);
+                    support::record_error(guild_id, &format!("/{}", name), why);
+                }
+            }
+            // keep components::handled in sync with these prefixes
+            Interaction::MessageComponent(component) => {
+                let (custom_id, guild_id) = (component.data.custom_id.clone(), component.guild_id);
+                record_interaction("component", &custom_id, guild_id, component.user.id);
+                let result = if custom_id.starts_with(checkin::COMPONENT_PREFIX) {
+                    checkin::redeem(self.db_client, component, ctx).await
+                } else if custom_id.starts_with(elections::COMPONENT_PREFIX) {
+                    elections::check(self.db_client, component, ctx).await
+                } else if custom_id.starts_with(preview::COMPONENT_PREFIX) {
+                    preview::handle(self.db_client, component, ctx, &self.jobs).await
+                } else if custom_id.starts_with(dry_run::COMPONENT_PREFIX) {
+                    dry_run::pressed(component, ctx).await
+                } else if custom_id.starts_with(roles::COMPONENT_PREFIX) {
+                    roles::merge_selected(self.db_client, component, ctx).await
+                } else if custom_id == self_roles::COMPONENT_ID {
+                    self_roles::selected(self.db_client, component, ctx).await
+                } else if custom_id == redeem::OPEN_BUTTON_ID {
+                    redeem::opened(self.db_client, component, ctx).await
+                } else if custom_id == offboard::CONFIRM_ID {
+                    offboard::confirmed(self.db_client, component, ctx, self.ignore_set.clone())
                         .await
+                } else if custom_id == forget::CONFIRM_ID {
+                    forget::confirmed(self.db_client, component, ctx, &self.jobs).await
+                } else if custom_id.starts_with(panel::COMPONENT_PREFIX) {
+                    panel::pressed(self.db_client, component, ctx).await
+                } else if custom_id.starts_with(review::COMPONENT_PREFIX) {
+                    review::pressed(self.db_client, component, ctx, &self.jobs).await
+                } else {
+                    Ok(())
+                };
+                telemetry::record("component", &custom_id, started.elapsed(), result.is_ok());
+                if let Err(why) = result {
+                    warn!("Cannot respond to component {}: {}", custom_id, why);
+                    support::record_error(guild_id, &format!("component {}", custom_id), why);
                 }
-            } {
-                println!("Cannot respond to slash command: {}", why);
             }
+            Interaction::ModalSubmit(modal) => {
+                let (custom_id, guild_id) = (modal.data.custom_id.clone(), modal.guild_id);
+                record_interaction("modal", &custom_id, guild_id, modal.user.id);
+                let result = if custom_id == redeem::MODAL_ID {
+                    redeem::submitted(self.db_client, modal, ctx).await
+                } else if custom_id == support::MODAL_ID {
+                    support::submitted(modal, ctx).await
+                } else if custom_id == panel::MODAL_ID {
+                    panel::submitted(self.db_client, modal, ctx).await
+                } else {
+                    Ok(())
+                };
+                telemetry::record("modal", &custom_id, started.elapsed(), result.is_ok());
+                if let Err(why) = result {
+                    warn!("Cannot respond to modal {}: {}", custom_id, why);
+                    support::record_error(guild_id, &format!("modal {}", custom_id), why);
+                }
+            }
+            _ => {}
         }
     }
 }
 
+/// Fills in the fields of `interaction_create`'s span
+fn record_interaction(kind: &str, name: &str, guild_id: Option<GuildId>, user_id: UserId) {
+    let span = Span::current();
+    span.record("kind", &kind);
+    span.record("name", &name);
+    if let Some(guild_id) = guild_id {
+        span.record("guild_id", &guild_id.0);
+    }
+    span.record("user_id", &user_id.0);
+}
+
+const USAGE: &str = "usage: utv-bot [--profile <name>] [serve]
+       utv-bot check-config [--offline]
+       utv-bot api-key ...
+       utv-bot db list|remove|stats|migrate|backup|restore
+       utv-bot fsck [--repair]";
+
 #[tokio::main]
 async fn main() {
-    // Configure the client with your Discord bot token in the environment.
-    let token = env::var("DISCORD_TOKEN").expect("Expected a discord bot token in the environment");
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    // before anything reads the environment
+    let config_file = settings::apply_config_file();
+    let profile = config_file
+        .clone()
+        .and_then(|_| settings::apply_profile(&mut args));
+    logging::init();
+    if let Ok(Some(path)) = &config_file {
+        info!("Using config file {}", path);
+    }
+    match profile {
+        Ok(Some(profile)) => info!("Using profile {}", profile),
+        Ok(None) => {}
+        Err(why) => {
+            error!("{}", why);
+            std::process::exit(1);
+        }
+    }
+    let succeeded = match args.first().map(String::as_str) {
+        Some("check-config") => {
+            Some(check_config::run(args.iter().any(|a| a == "--offline")).await)
+        }
+        Some("api-key") => Some(api_keys::run(&args[1..]).await),
+        Some("db") => Some(db_admin::run(&args[1..]).await),
+        Some("fsck") => Some(fsck::run(&args[1..]).await),
+        Some("serve") | None => None,
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            Some(false)
+        }
+    };
+    if let Some(succeeded) = succeeded {
+        std::process::exit(if succeeded { 0 } else { 1 });
+    }
 
-    // The Application Id is usually the Bot User Id.
-    let application_id: u64 = env::var("APPLICATION_ID")
-        .expect("Expected an application id in the environment")
-        .parse()
-        .expect("application id is not a valid id");
+    // Fail fast on a broken environment instead of when a setting is first used
+    let settings = match settings::Settings::from_env() {
+        Ok(settings) => settings,
+        Err(problems) => {
+            for problem in problems {
+                error!("{}", problem);
+            }
+            error!("Run `utv-bot check-config` for a full report");
+            std::process::exit(1);
+        }
+    };
 
     // DynamoDB Client
-    let db_client = Box::leak(Box::new(db::DynamoDB::new("users").await));
+    let db_client: &'static db::DynamoDB = Box::leak(Box::new(db::DynamoDB::new("users").await));
+    // the stable instance brings the tables up to date before anything reads them
+    if settings.deployment != "canary" {
+        if let Err(why) = migrations::run(db_client).await {
+            error!("Migrating the tables failed: {}", why);
+            std::process::exit(1);
+        }
+    }
     let ignore_set = Arc::new(Mutex::new(HashSet::new()));
+    let jobs = Arc::new(jobs::Queue::new());
     // Build our client.
-    let mut client = Client::builder(token)
-        .intents(GatewayIntents::GUILD_MEMBERS)
+    let mut client = Client::builder(&settings.discord_token)
+        .intents(settings.intents)
         .event_handler(Handler {
             db_client,
             ignore_set,
             background_task_running: AtomicBool::new(false),
+            disconnected_at: std::sync::Mutex::new(HashMap::new()),
+            jobs: jobs.clone(),
+            features: settings.features.clone(),
+            canary: settings.deployment == "canary",
         })
-        .application_id(application_id)
+        .raw_event_handler(shutdown::ShardActivity)
+        .application_id(settings.application_id)
         .await
         .expect("Error creating client");
 
-    // Finally, start a single shard, and start listening to events.
+    tokio::spawn(http::serve(http::State {
+        db_client,
+        http: client.cache_and_http.http.clone(),
+        deployment: settings.deployment,
+    }));
+
+    sharding::set_manager(client.shard_manager.clone());
+    tokio::spawn(shutdown::on_signal(
+        client.shard_manager.clone(),
+        jobs,
+        settings.deployment,
+    ));
+    tokio::spawn(owner::reload_on_hangup());
+
+    // Finally, start the shards, and start listening to events.
     //
     // Shards will automatically attempt to reconnect, and will perform
     // exponential backoff until it reconnects.
-    if let Err(why) = client.start().await {
-        println!("Client error: {:?}", why);
+    let started = match settings.shards {
+        Some(shards) => {
+            info!("Starting shards {} as {}", shards, settings.deployment);
+            client
+                .start_shard_range([shards.first, shards.last], shards.total)
+                .await
+        }
+        None => {
+            info!("Starting autosharded as {}", settings.deployment);
+            client.start_autosharded().await
+        }
+    };
+    if let Err(why) = started {
+        error!("Client error: {:?}", why);
     }
 }
 
@@ -383,3 +1439,150 @@ async fn main() {
 struct BecomeVerifiedMessage {
     discord_id: String
 }
+
+/// The Discord id of a `BecomeVerifiedMessage` received from the queue
+fn parse_verified_message(body: Option<&str>) -> error::Result<u64> {
+    let body = body.ok_or_else(|| error::Error::InvalidMessage("no body".to_string()))?;
+    let message: BecomeVerifiedMessage =
+        serde_json::from_str(body).map_err(|why| error::Error::InvalidMessage(why.to_string()))?;
+    message
+        .discord_id
+        .parse()
+        .map_err(|_| error::Error::InvalidMessage(format!("bad discord_id {}", message.discord_id)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::discord::mock::{member, MockDiscord};
+
+    const STUDENT: RoleId = RoleId(20);
+    const ECE: RoleId = RoleId(22);
+
+    fn claims(affiliation: &str) -> db::Claims {
+        db::Claims {
+            major: vec!["ece".to_string()],
+            school: vec![],
+            affiliation: vec![affiliation.to_string()],
+            name: None,
+        }
+    }
+
+    fn status(claims: Option<db::Claims>, marker: Option<&str>) -> MemberStatus {
+        MemberStatus {
+            config: Default::default(),
+            claims,
+            marker: marker.map(str::to_string),
+            eid: None,
+            owner: None,
+            now: 0,
+        }
+    }
+
+    async fn apply(
+        discord: &MockDiscord,
+        mem: &mut Member,
+        status: &MemberStatus,
+        ignore_set: &IgnoreSet,
+    ) -> Applied {
+        let role_mappings = HashMap::from([
+            ("student".to_string(), STUDENT.0),
+            ("ece".to_string(), ECE.0),
+        ]);
+        apply_member_status(
+            discord,
+            mem,
+            status,
+            &role_mappings,
+            ignore_set,
+            nicknames::Source::Scan,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn verified_student_gets_roles_and_marker() {
+        let discord = MockDiscord::default();
+        let ignore_set = IgnoreSet::default();
+        let mut mem = member(1, Some("Bevo"), &[]);
+        let status = status(Some(claims("student")), Some("✓"));
+        let applied = apply(&discord, &mut mem, &status, &ignore_set).await;
+        assert!(matches!(applied.roles, Some((_, Ok(_)))));
+        assert_eq!(
+            discord.requests(),
+            vec![
+                "add_roles 1 [RoleId(20), RoleId(22)]",
+                "set_nickname 1 Bevo ✓"
+            ]
+        );
+        assert_eq!(mem.roles, vec![STUDENT, ECE]);
+        assert_eq!(mem.nick.as_deref(), Some("Bevo ✓"));
+        // the rename isn't taken for the member changing their own name
+        assert!(ignore_set.lock().await.contains(&UserId(1)));
+    }
+
+    #[tokio::test]
+    async fn rescan_leaves_verified_member_alone() {
+        let discord = MockDiscord::default();
+        let ignore_set = IgnoreSet::default();
+        let mut mem = member(1, Some("Bevo"), &[]);
+        let status = status(Some(claims("student")), Some("✓"));
+        apply(&discord, &mut mem, &status, &ignore_set).await;
+        let rescan = MockDiscord::default();
+        let applied = apply(&rescan, &mut mem, &status, &ignore_set).await;
+        assert!(applied.roles.is_none() && applied.nickname.is_none());
+        assert!(rescan.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unverified_member_loses_fake_marker() {
+        let discord = MockDiscord::default();
+        let ignore_set = IgnoreSet::default();
+        let mut mem = member(2, Some("Bevo ✅"), &[]);
+        apply(&discord, &mut mem, &status(None, None), &ignore_set).await;
+        assert_eq!(discord.requests(), vec!["set_nickname 2 Bevo"]);
+        assert_eq!(mem.nick.as_deref(), Some("Bevo"));
+    }
+
+    #[tokio::test]
+    async fn verified_member_without_marker_keeps_nickname() {
+        let discord = MockDiscord::default();
+        let ignore_set = IgnoreSet::default();
+        let mut mem = member(3, Some("Bevo ✅"), &[]);
+        let status = status(Some(claims("faculty")), None);
+        apply(&discord, &mut mem, &status, &ignore_set).await;
+        assert_eq!(discord.requests(), vec!["add_roles 3 [RoleId(22)]"]);
+        assert_eq!(mem.nick.as_deref(), Some("Bevo ✅"));
+    }
+
+    #[tokio::test]
+    async fn owner_is_not_renamed() {
+        let discord = MockDiscord::default();
+        let ignore_set = IgnoreSet::default();
+        let mut mem = member(4, Some("Bevo ✅"), &[]);
+        let status = MemberStatus {
+            owner: Some(UserId(4)),
+            ..status(None, None)
+        };
+        let applied = apply(&discord, &mut mem, &status, &ignore_set).await;
+        assert_eq!(applied.owner_nickname.as_deref(), Some("Bevo"));
+        assert!(discord.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn refused_rename_is_reported() {
+        let discord = MockDiscord {
+            fail: true,
+            ..Default::default()
+        };
+        let ignore_set = IgnoreSet::default();
+        let mut mem = member(5, Some("Bevo"), &[]);
+        let status = status(Some(claims("student")), Some("✓"));
+        let applied = apply(&discord, &mut mem, &status, &ignore_set).await;
+        assert!(matches!(applied.roles, Some((_, Err(_)))));
+        assert!(matches!(applied.nickname, Some((_, Err(_)))));
+        assert!(mem.roles.is_empty());
+        assert_eq!(mem.nick.as_deref(), Some("Bevo"));
+        assert!(!ignore_set.lock().await.contains(&UserId(5)));
+    }
+}
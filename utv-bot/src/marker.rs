@@ -0,0 +1,59 @@
+//! How verified members are marked: a ✓ after their nickname, or the verified role's icon.
+//!
+//! Once a guild gives the `UTexas Verified` role an icon, Discord shows it next to members' names
+//! and the ✓ is redundant, so the bot stops adding it and a reconciliation takes the ones it
+//! added back off. Taking the icon away brings the ✓ back the same way. Nickname edits are
+//! the bot's most rate limited and most often overridden change, so guilds that can use role
+//! icons are better off without them.
+
+use serenity::http::Http;
+use serenity::model::guild::Role;
+use serenity::model::id::GuildId;
+use tracing::warn;
+
+use crate::{config, db};
+
+/// Whether the role shows a Discord-native marker next to members' names
+fn is_native(role: &Role) -> bool {
+    role.icon.is_some() || role.unicode_emoji.is_some()
+}
+
+/// Records whether the guild's verified role has an icon, returning whether that changed and
+/// nicknames need to be checked again
+pub async fn sync<'a>(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    guild_roles: impl IntoIterator<Item = &'a Role>,
+) -> bool {
+    let config = db_client.get_guild_config(guild_id).await;
+    let native = guild_roles
+        .into_iter()
+        .filter(|role| role.name == config.verified_role_name())
+        .min_by_key(|role| role.id)
+        .map_or(false, is_native);
+    if config.native_marker == native {
+        return false;
+    }
+    let bot = match http.get_current_user().await {
+        Ok(bot) => bot.id,
+        Err(why) => {
+            warn!("Cannot update the verified marker of {}: {}", guild_id, why);
+            return false;
+        }
+    };
+    let marker = config.marker_symbol().to_string();
+    config::update(db_client, guild_id, bot, |config| {
+        config.native_marker = native;
+        Some(if native {
+            format!("The verified role's icon replaces {} in nicknames", marker)
+        } else {
+            format!(
+                "The verified role has no icon, {} is added to nicknames again",
+                marker
+            )
+        })
+    })
+    .await
+    .is_ok()
+}
@@ -0,0 +1,90 @@
+//! Helpers for walking guild membership, and the registry of which guilds each user is in.
+//!
+//! Verifying, `/unverify` and `/forgetme` change a user in every guild they share with the bot.
+//! Rather than asking every guild for the member, the memberships table records the guilds each
+//! member was checked in, written the first time this process sees them there and deleted when
+//! they leave. Users the registry knows nothing about yet are looked up in every guild, and
+//! entries found to be stale are dropped.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serenity::http::{Http, HttpError};
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, UserId};
+use tracing::warn;
+
+use crate::{db, sharding};
+
+/// Discord returns at most this many members per request
+const PAGE_SIZE: u64 = 1000;
+
+lazy_static! {
+    /// memberships written by this process, which don't need writing again
+    static ref RECORDED: Mutex<HashSet<(GuildId, UserId)>> = Mutex::new(HashSet::new());
+}
+
+/// Fetches every member of a guild, following pagination
+pub async fn fetch_all(http: &Http, guild_id: GuildId) -> serenity::Result<Vec<Member>> {
+    let mut members = Vec::new();
+    loop {
+        let page = guild_id
+            .members(
+                http,
+                Some(PAGE_SIZE),
+                members.last().map(|m: &Member| m.user.id),
+            )
+            .await?;
+        let done = (page.len() as u64) < PAGE_SIZE;
+        members.extend(page);
+        if done {
+            return Ok(members);
+        }
+    }
+}
+
+/// Records that the user is in the guild
+pub async fn record(db_client: &db::DynamoDB, guild_id: GuildId, user_id: UserId) {
+    if !RECORDED.lock().unwrap().insert((guild_id, user_id)) {
+        return;
+    }
+    if !db_client.put_membership(guild_id, user_id).await {
+        RECORDED.lock().unwrap().remove(&(guild_id, user_id));
+    }
+}
+
+/// Forgets a member who left the guild
+pub async fn left(db_client: &db::DynamoDB, guild_id: GuildId, user_id: UserId) {
+    RECORDED.lock().unwrap().remove(&(guild_id, user_id));
+    db_client.delete_membership(guild_id, user_id).await;
+}
+
+/// The user as a member of every guild they share with the bot
+pub async fn mutual(db_client: &db::DynamoDB, http: &Http, user_id: UserId) -> Vec<Member> {
+    let mut guilds = db_client.member_guilds(user_id).await;
+    if guilds.is_empty() {
+        guilds = match sharding::guilds(http).await {
+            Ok(guilds) => guilds,
+            Err(why) => {
+                warn!("Cannot list guilds to find {} in: {}", user_id, why);
+                return Vec::new();
+            }
+        };
+    }
+    let mut members = Vec::new();
+    for guild_id in guilds {
+        match http.get_member(guild_id.0, user_id.0).await {
+            Ok(member) => {
+                record(db_client, guild_id, user_id).await;
+                members.push(member);
+            }
+            Err(serenity::Error::Http(why)) if matches!(&*why, HttpError::UnsuccessfulRequest(r) if r.status_code.as_u16() == 404) =>
+            {
+                left(db_client, guild_id, user_id).await;
+            }
+            Err(why) => warn!("Cannot fetch {} in {}: {}", user_id, guild_id, why),
+        }
+    }
+    members
+}
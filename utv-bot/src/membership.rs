@@ -0,0 +1,285 @@
+//! Org membership from outside Discord, e.g. who paid dues, granting a separate role to verified
+//! members on the roster. Set up per guild with `/config dues`.
+//!
+//! Rosters list EIDs, which are matched against verified members by their encrypted form, the
+//! same way `/admin bulk-lookup` does; plain EIDs are dropped as soon as they're encrypted. Each
+//! guild's roster is fetched again every [`SYNC_INTERVAL_SECS`], and the role is reconciled with
+//! it then; members who verify in between are checked against the last fetched roster.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::async_trait;
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tracing::{error, warn};
+
+use crate::{audit, db, members, scheduler, settings};
+
+pub const SYNC_INTERVAL_SECS: i64 = 6 * 60 * 60;
+const TASK_PREFIX: &str = "dues:";
+
+/// A source of the EIDs who are members of an org
+#[async_trait]
+pub trait MembershipProvider {
+    /// The EIDs on the roster, lower case
+    async fn members(&self) -> Result<HashSet<String>, String>;
+}
+
+/// A CSV file at a URL, e.g. a published spreadsheet, with the EIDs in a column headed `eid`
+/// or else the first column
+pub struct CsvProvider<'a> {
+    pub url: &'a str,
+}
+
+/// A JSON endpoint returning a list of EIDs, or of objects with an `eid` field
+pub struct ApiProvider<'a> {
+    pub url: &'a str,
+    pub token: Option<&'a str>,
+}
+
+#[async_trait]
+impl MembershipProvider for CsvProvider<'_> {
+    async fn members(&self) -> Result<HashSet<String>, String> {
+        let text = reqwest::get(self.url)
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|why| why.to_string())?
+            .text()
+            .await
+            .map_err(|why| why.to_string())?;
+        let mut rows = text.lines().map(|line| {
+            line.split(',')
+                .map(|cell| cell.trim().trim_matches('"').trim().to_lowercase())
+                .collect::<Vec<_>>()
+        });
+        let header = rows.next().unwrap_or_default();
+        let (column, header_is_eid) = match header.iter().position(|cell| cell == "eid") {
+            Some(column) => (column, true),
+            None => (0, false),
+        };
+        let mut eids = rows
+            .filter_map(|row| row.get(column).cloned())
+            .filter(|eid| !eid.is_empty())
+            .collect::<HashSet<_>>();
+        // without a header, the first row is already a member
+        if !header_is_eid {
+            eids.extend(header.get(0).cloned().filter(|eid| !eid.is_empty()));
+        }
+        Ok(eids)
+    }
+}
+
+#[async_trait]
+impl MembershipProvider for ApiProvider<'_> {
+    async fn members(&self) -> Result<HashSet<String>, String> {
+        let mut request = reqwest::Client::new().get(self.url);
+        if let Some(token) = self.token {
+            request = request.bearer_auth(token);
+        }
+        let body: Value = request
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|why| why.to_string())?
+            .json()
+            .await
+            .map_err(|why| why.to_string())?;
+        let entries = match &body {
+            Value::Array(entries) => entries,
+            _ => return Err("expected a JSON list of members".to_string()),
+        };
+        Ok(entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Value::String(eid) => Some(eid.as_str()),
+                entry => entry.get("eid").and_then(Value::as_str),
+            })
+            .map(|eid| eid.trim().to_lowercase())
+            .filter(|eid| !eid.is_empty())
+            .collect())
+    }
+}
+
+/// Where a guild's dues roster comes from and the role it grants
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DuesConfig {
+    pub role: RoleId,
+    pub source: Source,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind")]
+pub enum Source {
+    Csv { url: String },
+    Api { url: String, token: Option<String> },
+}
+
+impl Source {
+    fn provider(&self) -> Box<dyn MembershipProvider + Send + Sync + '_> {
+        match self {
+            Source::Csv { url } => Box::new(CsvProvider { url }),
+            Source::Api { url, token } => Box::new(ApiProvider {
+                url,
+                token: token.as_deref(),
+            }),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Source::Csv { url } => format!("CSV at <{}>", url),
+            Source::Api { url, .. } => format!("API at <{}>", url),
+        }
+    }
+}
+
+lazy_static! {
+    /// each guild's last fetched roster, as encrypted EIDs
+    static ref ROSTERS: Mutex<HashMap<GuildId, HashSet<String>>> = Mutex::new(HashMap::new());
+}
+
+/// Fetches a guild's roster and caches it in encrypted form
+async fn refresh(guild_id: GuildId, dues: &DuesConfig) -> Result<HashSet<String>, String> {
+    let key = match settings::encryption_key() {
+        Ok(Some(key)) => key,
+        _ => return Err("ENCRYPTION_KEY is not set".to_string()),
+    };
+    let roster = dues
+        .source
+        .provider()
+        .members()
+        .await?
+        .iter()
+        .map(|eid| base64::encode(utv_token::deterministic_aes::encrypt(eid.as_bytes(), &key)))
+        .collect::<HashSet<_>>();
+    ROSTERS.lock().unwrap().insert(guild_id, roster.clone());
+    Ok(roster)
+}
+
+/// The last fetched roster, fetching it when there's none yet
+async fn roster(guild_id: GuildId, dues: &DuesConfig) -> Result<HashSet<String>, String> {
+    if let Some(roster) = ROSTERS.lock().unwrap().get(&guild_id) {
+        return Ok(roster.clone());
+    }
+    refresh(guild_id, dues).await
+}
+
+/// Grants or takes the dues role of a verified member. Called with the member's other roles.
+pub async fn apply(db_client: &db::DynamoDB, http: &Http, member: &mut Member) {
+    let dues = match db_client.get_guild_config(member.guild_id).await.dues {
+        Some(dues) => dues,
+        None => return,
+    };
+    let roster = match roster(member.guild_id, &dues).await {
+        Ok(roster) => roster,
+        Err(why) => {
+            warn!("Cannot fetch dues roster of {}: {}", member.guild_id, why);
+            return;
+        }
+    };
+    let paid = match db_client.get_encrypted_eid(member.user.id.0).await {
+        Some(encrypted_eid) => roster.contains(&encrypted_eid),
+        None => false,
+    };
+    let result = match (paid, member.roles.contains(&dues.role)) {
+        (true, false) => member.add_role(http, dues.role).await,
+        (false, true) => member.remove_role(http, dues.role).await,
+        _ => return,
+    };
+    if let Err(why) = result {
+        error!("Failed to update dues role of {}: {}", member.user.id, why);
+    }
+}
+
+/// Fetches a guild's roster again and brings everyone's dues role in line with it
+pub async fn sync(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId) -> Result<(), String> {
+    let dues = match db_client.get_guild_config(guild_id).await.dues {
+        Some(dues) => dues,
+        None => return Ok(()),
+    };
+    let roster = refresh(guild_id, &dues)
+        .await?
+        .into_iter()
+        .collect::<Vec<_>>();
+    let paid = db_client
+        .users_by_encrypted_eid(&roster)
+        .await
+        .into_values()
+        .collect::<HashSet<UserId>>();
+    let (mut added, mut removed) = (0, 0);
+    for member in members::fetch_all(http, guild_id)
+        .await
+        .map_err(|why| why.to_string())?
+    {
+        let result = match (
+            paid.contains(&member.user.id),
+            member.roles.contains(&dues.role),
+        ) {
+            (true, false) => {
+                added += 1;
+                http.add_member_role(guild_id.0, member.user.id.0, dues.role.0)
+                    .await
+            }
+            (false, true) => {
+                removed += 1;
+                http.remove_member_role(guild_id.0, member.user.id.0, dues.role.0)
+                    .await
+            }
+            _ => continue,
+        };
+        if let Err(why) = result {
+            error!("Failed to update dues role of {}: {}", member.user.id, why);
+        }
+        // sleep to stay far away from rate limit
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    if added + removed > 0 {
+        if let Ok(bot) = http.get_current_user().await {
+            audit::record(
+                db_client,
+                guild_id,
+                bot.id,
+                "dues.sync",
+                None,
+                format!(
+                    "{} on roster, <@&{}> added to {} and removed from {}",
+                    roster.len(),
+                    dues.role,
+                    added,
+                    removed
+                ),
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+/// Schedules a guild's next roster sync at `due_at`, replacing any scheduled one
+pub async fn schedule(db_client: &db::DynamoDB, guild_id: GuildId, due_at: i64) {
+    let prefix = format!("{}{}:", TASK_PREFIX, guild_id);
+    for task in db_client.scheduled_with_prefix(&prefix).await {
+        db_client.delete_scheduled(&task.task_id).await;
+    }
+    // the scheduler deletes a task by id after running it, so each run gets a new id
+    if !scheduler::schedule(
+        db_client,
+        format!("{}{}", prefix, due_at),
+        due_at,
+        &scheduler::Task::DuesSync { guild_id },
+    )
+    .await
+    {
+        error!("Failed to schedule the dues sync of {}", guild_id);
+    }
+}
+
+/// Drops a guild's cached roster, e.g. after its source changed
+pub fn forget(guild_id: GuildId) {
+    ROSTERS.lock().unwrap().remove(&guild_id);
+}
@@ -0,0 +1,108 @@
+//! Ordered migrations of the stored records, tracked by a schema version in the meta table.
+//!
+//! Each migration brings records written by older versions up to its version and must be safe
+//! to run again, since an instance can stop halfway through one. A migration that can't read a
+//! whole table fails, so its version isn't recorded over records it never saw. The stable instance applies
+//! the pending ones at startup, before connecting to the gateway, and `utv-bot db migrate`
+//! applies them by hand. User records carry the version they were written in as
+//! `schema_version`, so a migration can tell which ones it still has to change.
+//!
+//! Attributes are only ever added, so an instance older than the tables, like a stable instance
+//! while a canary runs a newer version, keeps reading them and only logs a warning.
+
+use serenity::model::id::UserId;
+use tracing::{info, warn};
+
+use crate::db::{self, attr_number, attr_string, Table};
+
+/// The version this build writes records in, the last of [`MIGRATIONS`]
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Every migration by the version it brings the tables to, in order
+const MIGRATIONS: &[(u32, &str)] = &[
+    (1, "EID claims for accounts linked before the eids table"),
+    (2, "schema versions on user records"),
+];
+
+/// Applies the pending migrations, returning the version the tables are at
+pub async fn run(db_client: &db::DynamoDB) -> Result<u32, String> {
+    let current = db_client
+        .schema_version()
+        .await
+        .ok_or("Couldn't read the schema version")?;
+    if current > SCHEMA_VERSION {
+        warn!(
+            "The tables are at schema version {}, newer than this build's {}",
+            current, SCHEMA_VERSION
+        );
+        return Ok(current);
+    }
+    for (version, description) in MIGRATIONS.iter().filter(|(v, _)| *v > current) {
+        info!("Migrating to schema version {}: {}", version, description);
+        match version {
+            1 => eid_claims(db_client).await?,
+            2 => tag_users(db_client).await?,
+            _ => unreachable!("migration {} has no function", version),
+        }
+        if !db_client.set_schema_version(*version).await {
+            return Err(format!("Couldn't record schema version {}", version));
+        }
+    }
+    Ok(SCHEMA_VERSION)
+}
+
+/// Writes the EID claims of accounts linked before the eids table existed. EIDs linked to
+/// several accounts are left for an operator, as `utv-bot fsck` reports too.
+async fn eid_claims(db_client: &db::DynamoDB) -> Result<(), String> {
+    let (mut claimed, mut held, mut failed) = (0, 0, 0);
+    for user in db_client.raw_items(Table::Users).await? {
+        let (user_id, encrypted_eid) = match (
+            attr_number::<u64>(&user, "discord_id"),
+            attr_string(&user, "encrypted_eid"),
+        ) {
+            (Some(user_id), Some(encrypted_eid)) => (UserId(user_id), encrypted_eid),
+            _ => continue,
+        };
+        match db_client.claim_eid(&encrypted_eid, user_id).await {
+            db::EidClaim::New => claimed += 1,
+            db::EidClaim::Held => held += 1,
+            db::EidClaim::Taken => warn!(
+                "{} is linked to an EID another account claims, unlink one of them",
+                user_id
+            ),
+            db::EidClaim::Failed => failed += 1,
+        }
+    }
+    info!("EID claims: {} written, {} already in place", claimed, held);
+    if failed > 0 {
+        return Err(format!("Couldn't write {} claims, run it again", failed));
+    }
+    Ok(())
+}
+
+/// Tags the user records written before records had a schema version
+async fn tag_users(db_client: &db::DynamoDB) -> Result<(), String> {
+    let (mut tagged, mut failed) = (0, 0);
+    for user in db_client.raw_items(Table::Users).await? {
+        let user_id = match attr_number::<u64>(&user, "discord_id") {
+            Some(user_id) => UserId(user_id),
+            None => continue,
+        };
+        if attr_number::<u32>(&user, "schema_version").map_or(false, |v| v >= 2) {
+            continue;
+        }
+        if db_client.set_user_schema_version(user_id, 2).await {
+            tagged += 1;
+        } else {
+            failed += 1;
+        }
+    }
+    info!("Schema versions: {} user records tagged", tagged);
+    if failed > 0 {
+        return Err(format!(
+            "Couldn't tag {} user records, run it again",
+            failed
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! The nickname template verified members get, set per guild with `/config nickname`.
+//!
+//! Templates are filled with `{display_name}` (the member's own name without non-ASCII
+//! characters), `{real_name}` and `{first_name}` from the UT Directory, `{eid}`, `{marker}` (the
+//! ✓, or the alumni suffix for former students) and `{decoration}`. The default keeps the marker
+//! after the member's name. Whitespace left by empty placeholders is collapsed and names are
+//! shortened to fit Discord's limit.
+//!
+//! Members' own names are cleaned before they're used: compatibility forms are decomposed
+//! (fullwidth `Ｂｅｖｏ` is `Bevo`), accents and other combining marks are dropped, Cyrillic and
+//! Greek letters drawn like Latin ones are read as those, and anything left that isn't ASCII is
+//! removed. Every lookalike of the marker (`✔`, `☑`, `✅`, `√`, a ✓ with marks stacked on it) is
+//! removed with it, so unverified members can't fake being verified, while names keep their
+//! letters.
+//!
+//! Whatever the template adds around `{display_name}` is taken off the member's name before it's
+//! filled in again, so checking a member twice doesn't repeat it. Members verified before names
+//! were stored get their display name for `{real_name}` until the directory re-check fills it in,
+//! and `{eid}` stays empty unless the bot has `ENCRYPTION_KEY`.
+
+use serenity::model::id::UserId;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::GuildConfig;
+use crate::{db, settings, templates};
+
+pub const DEFAULT_TEMPLATE: &str = "{display_name} {marker} {decoration}";
+const PLACEHOLDERS: &[&str] = &[
+    "display_name",
+    "real_name",
+    "first_name",
+    "eid",
+    "marker",
+    "decoration",
+];
+/// placeholders that name the member, one of which a template must have
+const NAMES: &[&str] = &["display_name", "real_name", "first_name", "eid"];
+/// Cyrillic and Greek letters drawn like Latin ones, which would otherwise be removed from names
+const CONFUSABLES: &[(char, char)] = &[
+    ('А', 'A'),
+    ('В', 'B'),
+    ('Е', 'E'),
+    ('К', 'K'),
+    ('М', 'M'),
+    ('Н', 'H'),
+    ('О', 'O'),
+    ('Р', 'P'),
+    ('С', 'C'),
+    ('Т', 'T'),
+    ('Х', 'X'),
+    ('а', 'a'),
+    ('е', 'e'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('ѕ', 's'),
+    ('Α', 'A'),
+    ('Β', 'B'),
+    ('Ε', 'E'),
+    ('Ζ', 'Z'),
+    ('Η', 'H'),
+    ('Ι', 'I'),
+    ('Κ', 'K'),
+    ('Μ', 'M'),
+    ('Ν', 'N'),
+    ('Ο', 'O'),
+    ('Ρ', 'P'),
+    ('Τ', 'T'),
+    ('Υ', 'Y'),
+    ('Χ', 'X'),
+    ('ο', 'o'),
+];
+/// Discord's limit on nicknames
+const MAX_NICKNAME_CHARS: usize = 32;
+const MAX_TEMPLATE_CHARS: usize = 100;
+
+/// What a template is filled with
+pub struct Values<'a> {
+    pub display_name: &'a str,
+    pub real_name: Option<&'a str>,
+    pub eid: Option<&'a str>,
+    pub marker: &'a str,
+    pub decoration: Option<&'a str>,
+}
+
+/// The member's name reduced to ASCII, which takes markers, decorations and their lookalikes off
+pub fn clean(name: &str) -> String {
+    name.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| {
+            CONFUSABLES
+                .iter()
+                .find(|(from, _)| *from == c)
+                .map_or(c, |(_, to)| *to)
+        })
+        .filter(char::is_ascii)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Whether a template only has known placeholders and names the member
+pub fn is_valid(template: &str) -> bool {
+    let mut placeholders = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return false,
+        };
+        placeholders.push(&rest[start + 1..end]);
+        rest = &rest[end + 1..];
+    }
+    template.chars().count() <= MAX_TEMPLATE_CHARS
+        && placeholders.iter().all(|p| PLACEHOLDERS.contains(p))
+        && placeholders.iter().any(|p| NAMES.contains(p))
+}
+
+/// Fills the template in, shortening the names when the nickname would be too long
+pub fn render(template: &str, values: &Values) -> String {
+    let display_name = strip_template(template, values);
+    let real_name = values.real_name.unwrap_or(&display_name).to_string();
+    let nickname = fill(template, values, &display_name, &real_name);
+    let overflow = nickname.chars().count().saturating_sub(MAX_NICKNAME_CHARS);
+    if overflow == 0 {
+        return nickname;
+    }
+    let shorten = |name: &str| {
+        let keep = name.chars().count().saturating_sub(overflow).max(1);
+        name.chars()
+            .take(keep)
+            .collect::<String>()
+            .trim()
+            .to_string()
+    };
+    fill(
+        template,
+        values,
+        &shorten(&display_name),
+        &shorten(&real_name),
+    )
+    .chars()
+    .take(MAX_NICKNAME_CHARS)
+    .collect::<String>()
+    .trim()
+    .to_string()
+}
+
+fn fill(template: &str, values: &Values, display_name: &str, real_name: &str) -> String {
+    templates::render(
+        template,
+        &[
+            ("display_name", display_name),
+            ("real_name", real_name),
+            (
+                "first_name",
+                real_name.split_whitespace().next().unwrap_or(""),
+            ),
+            ("eid", values.eid.unwrap_or("")),
+            ("marker", values.marker),
+            ("decoration", values.decoration.unwrap_or("")),
+        ],
+    )
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// The display name without what the template puts before and after it, or around
+/// `{real_name}` when that's filled with the display name
+fn strip_template(template: &str, values: &Values) -> String {
+    let slot = match values.real_name {
+        Some(_) => "{display_name}",
+        None if template.contains("{display_name}") => "{display_name}",
+        None => "{real_name}",
+    };
+    let (before, after) = match template.split_once(slot) {
+        Some(parts) => parts,
+        None => return values.display_name.to_string(),
+    };
+    let real_name = values.real_name.unwrap_or("");
+    let prefix = clean(&fill(before, values, "", real_name));
+    let suffix = clean(&fill(after, values, "", real_name));
+    let name = values.display_name;
+    match name
+        .strip_prefix(prefix.as_str())
+        .and_then(|name| name.strip_suffix(suffix.as_str()))
+    {
+        Some(stripped) if !stripped.trim().is_empty() => stripped.trim().to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// The nickname of a verified member under the guild's template, with the EID looked up by
+/// [`shown_eid`]
+pub fn compose(
+    config: &GuildConfig,
+    display_name: &str,
+    claims: &db::Claims,
+    eid: Option<&str>,
+    marker: &str,
+    now: i64,
+) -> String {
+    render(
+        config.nickname_template(),
+        &Values {
+            display_name,
+            real_name: claims.name.as_deref(),
+            eid,
+            marker,
+            decoration: config.active_decoration(now),
+        },
+    )
+}
+
+/// The member's EID if the guild's template shows it, which is the only time it's decrypted
+pub async fn shown_eid(
+    db_client: &db::DynamoDB,
+    user_id: UserId,
+    config: &GuildConfig,
+) -> Option<String> {
+    if !config.nickname_template().contains("{eid}") {
+        return None;
+    }
+    eid(db_client, user_id).await
+}
+
+async fn eid(db_client: &db::DynamoDB, user_id: UserId) -> Option<String> {
+    let key = settings::encryption_key().ok().flatten()?;
+    let encrypted = base64::decode(db_client.get_encrypted_eid(user_id.0).await?).ok()?;
+    let eid = utv_token::deterministic_aes::decrypt(&encrypted, &key).ok()?;
+    String::from_utf8(eid).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_mark_lookalikes() {
+        for marker in [
+            "✓",
+            "✔",
+            "✔️",
+            "☑",
+            "☑️",
+            "✅",
+            "🗸",
+            "🗹",
+            "√",
+            "✓\u{fe0f}",
+            "ᐟ",
+        ] {
+            assert_eq!(clean(&format!("Bevo {}", marker)), "Bevo", "{}", marker);
+        }
+    }
+
+    #[test]
+    fn combining_marks() {
+        assert_eq!(clean("Bevo ✓\u{332}\u{305}"), "Bevo");
+        assert_eq!(clean("Bevo \u{20dd}\u{2713}\u{20e3}"), "Bevo");
+        assert_eq!(clean("Bevo v\u{301}\u{338}"), "Bevo v");
+    }
+
+    #[test]
+    fn names_keep_their_letters() {
+        assert_eq!(clean("José Núñez ✓"), "Jose Nunez");
+        assert_eq!(clean("Ｂｅｖｏ ✔"), "Bevo");
+        // Cyrillic е and о
+        assert_eq!(clean("B\u{435}v\u{43e} ☑"), "Bevo");
+        assert_eq!(clean("𝐁𝐞𝐯𝐨 ✅"), "Bevo");
+    }
+
+    #[test]
+    fn lookalike_marker_replaced_by_template() {
+        let display_name = clean("Bevo ✔");
+        let values = Values {
+            display_name: &display_name,
+            real_name: None,
+            eid: None,
+            marker: "✓",
+            decoration: None,
+        };
+        assert_eq!(render(DEFAULT_TEMPLATE, &values), "Bevo ✓");
+        assert_eq!(render("{marker} {display_name}", &values), "✓ Bevo");
+    }
+}
@@ -0,0 +1,157 @@
+//! Per-guild scheduling of nickname edits.
+//!
+//! Discord limits nickname edits per guild much more tightly than other member updates. Every
+//! nickname edit takes a token from its guild's bucket first, and edits a member is waiting on
+//! (they just verified, joined or changed their name) go before those of scans and other bulk
+//! jobs, so a large sweep can't hold up verification. Guilds in rush mode get a larger bucket.
+//! Waiting edits are listed by `/admin jobs`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serenity::model::id::{GuildId, UserId};
+
+use crate::{response, rush};
+
+/// edits a guild can make in a burst
+const CAPACITY: f64 = 5.0;
+/// sustained rate, one edit per this many seconds
+const REFILL_SECS: f64 = 1.0;
+/// the same for guilds in rush mode, still well within the global rate limit with
+/// `rush::MAX_RUSHING` guilds at a time
+const RUSH_CAPACITY: f64 = 10.0;
+const RUSH_REFILL_SECS: f64 = 0.5;
+
+/// What an edit is for, which decides its priority
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// the member just verified
+    Verification,
+    /// the member joined the guild
+    Join,
+    /// the member changed their name or roles
+    Update,
+    /// a queued re-check of a single member
+    Member,
+    Scan,
+    Sweep,
+    Reconcile,
+    Rollback,
+    Offboard,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Verification => "verification",
+            Source::Join => "join",
+            Source::Update => "member update",
+            Source::Member => "member re-check",
+            Source::Scan => "scan",
+            Source::Sweep => "sweep",
+            Source::Reconcile => "reconcile",
+            Source::Rollback => "rollback",
+            Source::Offboard => "offboard",
+        }
+    }
+
+    /// Whether a member is waiting on the edit, rather than a bulk job
+    pub fn interactive(self) -> bool {
+        matches!(
+            self,
+            Source::Verification | Source::Join | Source::Update | Source::Member
+        )
+    }
+}
+
+/// An edit waiting for its guild's bucket
+#[derive(Clone, Debug)]
+pub struct Queued {
+    pub user_id: UserId,
+    pub source: Source,
+    pub queued_at: i64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    queued: Vec<(u64, Queued)>,
+}
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<GuildId, Bucket>> = Mutex::new(HashMap::new());
+}
+
+/// Removes the edit from the queue if the caller stops waiting
+struct Waiting(GuildId, u64);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if let Some(bucket) = BUCKETS.lock().unwrap().get_mut(&self.0) {
+            bucket.queued.retain(|(id, _)| *id != self.1);
+        }
+    }
+}
+
+/// Waits until the guild may make another nickname edit, taking a token for it
+pub async fn wait_turn(guild_id: GuildId, user_id: UserId, source: Source) {
+    let id = rand::random::<u64>();
+    let _waiting = Waiting(guild_id, id);
+    {
+        let mut buckets = BUCKETS.lock().unwrap();
+        let bucket = buckets.entry(guild_id).or_insert_with(|| Bucket {
+            tokens: CAPACITY,
+            refilled: Instant::now(),
+            queued: Vec::new(),
+        });
+        bucket.queued.push((
+            id,
+            Queued {
+                user_id,
+                source,
+                queued_at: response::unix_now(),
+            },
+        ));
+    }
+    loop {
+        let (capacity, refill_secs) = if rush::active(guild_id) {
+            (RUSH_CAPACITY, RUSH_REFILL_SECS)
+        } else {
+            (CAPACITY, REFILL_SECS)
+        };
+        let wait = {
+            let mut buckets = BUCKETS.lock().unwrap();
+            let bucket = match buckets.get_mut(&guild_id) {
+                Some(bucket) => bucket,
+                None => return,
+            };
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed / refill_secs).min(capacity);
+            bucket.refilled = now;
+            // bulk edits yield to any interactive edit queued in the same guild
+            let yields =
+                !source.interactive() && bucket.queued.iter().any(|(_, q)| q.source.interactive());
+            if bucket.tokens >= 1.0 && !yields {
+                bucket.tokens -= 1.0;
+                return;
+            }
+            Duration::from_secs_f64(((1.0 - bucket.tokens) * refill_secs).max(0.1))
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// The guild's waiting edits, oldest first
+pub fn queued(guild_id: GuildId) -> Vec<Queued> {
+    let mut queued = BUCKETS
+        .lock()
+        .unwrap()
+        .get(&guild_id)
+        .map(|bucket| bucket.queued.iter().map(|(_, q)| q.clone()).collect())
+        .unwrap_or_else(Vec::new);
+    queued.sort_by_key(|q| q.queued_at);
+    queued
+}
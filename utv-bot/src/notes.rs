@@ -0,0 +1,142 @@
+//! `/note`: moderator notes about members.
+//!
+//! Notes are kept next to the member's verification status, so context from manual reviews
+//! and appeals doesn't get lost in DMs. Only moderators can add or read them.
+
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::utils::Color;
+
+use crate::{audit, db, handlers, response};
+
+/// Leaves room in the embed field for the author and date, fields are limited to 1024 characters
+const MAX_NOTE_LENGTH: usize = 1000;
+/// embeds hold at most 25 fields
+const MAX_LISTED: usize = 25;
+
+pub async fn note(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_moderator(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be a moderator to use notes.",
+        )
+        .await;
+    }
+    match handlers::subcommand(&command) {
+        Some(("add", options)) => add(db_client, &command, guild_id, options, &ctx).await,
+        Some(("list", options)) => list(db_client, &command, guild_id, options, &ctx).await,
+        _ => {
+            response::respond_embed(&ctx, &command, true, |embed| {
+                handlers::unknown_command(embed, &command)
+            })
+            .await
+        }
+    }
+}
+
+async fn add(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let (user, text) = match (
+        handlers::option_user(options, "user"),
+        handlers::option_str(options, "text").map(str::trim),
+    ) {
+        (Some(user), Some(text)) if !text.is_empty() => (user, text),
+        _ => return response::respond_title(ctx, command, true, "Enter a member and a note").await,
+    };
+    if text.chars().count() > MAX_NOTE_LENGTH {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            format!("Notes can be at most {} characters", MAX_NOTE_LENGTH),
+        )
+        .await;
+    }
+    let note = db::Note {
+        guild_id,
+        user_id: user.id,
+        author: command.user.id,
+        at: response::unix_now(),
+        text: text.to_string(),
+    };
+    if !db_client.add_note(&note).await {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Failed to save the note, please try again",
+        )
+        .await;
+    }
+    // the ledger records that a note exists, the text stays with the notes
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "note.add",
+        Some(user.id),
+        "",
+    )
+    .await;
+    response::respond_title(ctx, command, true, format!("Note added to {}", user.tag())).await
+}
+
+async fn list(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let user = match handlers::option_user(options, "user") {
+        Some(user) => user,
+        None => return response::respond_title(ctx, command, true, "Enter a member").await,
+    };
+    let notes = db_client.get_notes(guild_id, user.id).await;
+    let verified = db_client.is_verified(user.id.0).await;
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title(format!("Notes on {}", user.tag()))
+            .description(format!(
+                "{}. {} notes{}.",
+                if verified { "Verified" } else { "Not verified" },
+                notes.len(),
+                if notes.len() > MAX_LISTED {
+                    format!(", showing the latest {}", MAX_LISTED)
+                } else {
+                    String::new()
+                }
+            ))
+            .color(Color::from_rgb(191, 87, 0));
+        // mentions and timestamps only render in field values, not names
+        for (i, note) in notes.iter().rev().take(MAX_LISTED).enumerate() {
+            embed.field(
+                format!("#{}", notes.len() - i),
+                format!(
+                    "{}\n— <@{}>, {}",
+                    note.text,
+                    note.author,
+                    response::timestamp(note.at, response::TimestampStyle::ShortDate)
+                ),
+                false,
+            );
+        }
+        embed
+    })
+    .await
+}
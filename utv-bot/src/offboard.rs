@@ -0,0 +1,251 @@
+//! `/admin offboard`: removes the bot from a guild cleanly.
+//!
+//! After a confirmation, members' ✓ and alumni decorations are stripped, the verified and
+//! Unverified roles are deleted, the guild's data is handed to the admin as a JSON export, every per-guild record
+//! is deleted and the bot leaves. Links between Discord accounts and EIDs are global and are
+//! kept, since members may still be verified through other guilds.
+
+use std::borrow::Cow;
+use std::time::Duration;
+
+use serde_json::json;
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+use tracing::{error, info};
+
+use crate::{db, members, nicknames, quarantine, response, roles, IgnoreSet};
+
+/// Custom id of the confirmation button
+pub const CONFIRM_ID: &str = "offboard:confirm";
+
+/// Asks the admin to confirm, since offboarding can't be undone
+pub async fn prompt(
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title("Remove the Bot From This Server?")
+                                .description(
+                                    "This removes the verified marker from nicknames, deletes the verified and Unverified roles, \
+                                     sends you an export of this server's configuration, \
+                                     attestations and audit ledger, deletes them from the bot and \
+                                     leaves the server. It can't be undone.",
+                                )
+                                .color(Color::from_rgb(255, 0, 0))
+                        })
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button
+                                        .custom_id(CONFIRM_ID)
+                                        .label("Offboard")
+                                        .style(ButtonStyle::Danger)
+                                })
+                            })
+                        })
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+/// Handles the confirmation button
+pub async fn confirmed(
+    db_client: &'static db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+    ignore_set: IgnoreSet,
+) -> serenity::Result<()> {
+    let guild_id = match component.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let is_admin = component
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .map_or(false, |p| p.administrator());
+    if !is_admin {
+        return response::respond_component_title(
+            &ctx,
+            &component,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+    let export = export(db_client, guild_id).await;
+    let role_name = db_client
+        .get_guild_config(guild_id)
+        .await
+        .verified_role_name()
+        .to_string();
+    let stripped = strip_decorations(db_client, &ctx, guild_id, ignore_set).await?;
+    for role in ctx.http.get_guild_roles(guild_id.0).await? {
+        if role.name == role_name {
+            roles::expect_change(role.id);
+            if let Err(why) = guild_id.delete_role(&ctx.http, role.id).await {
+                error!("Failed to delete verified role {}: {}", role.id, why);
+            }
+        }
+    }
+    // left behind, it would keep unverified members out of the guild's channels
+    if let Err(why) = quarantine::disable(db_client, &ctx.http, guild_id).await {
+        error!(
+            "Failed to delete the Unverified role of {}: {}",
+            guild_id, why
+        );
+    }
+    // the data is only deleted once the admin has the export
+    component
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(export.into_bytes()),
+                    filename: format!("offboard-{}.json", guild_id),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title("Offboarded")
+                        .description(format!(
+                            "Removed decorations from {} members. The attached export is all \
+                             the bot stored about this server; it is now deleted and the bot \
+                             is leaving.",
+                            stripped
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+        .await?;
+    if !db_client.delete_guild_data(guild_id).await {
+        error!(
+            "Some data of offboarded guild {} could not be deleted",
+            guild_id
+        );
+    }
+    info!(
+        "Offboarded guild {} at the request of {}",
+        guild_id, component.user.id
+    );
+    guild_id.leave(&ctx.http).await
+}
+
+/// The guild's configuration, role mappings, attestations, notes and audit ledger as JSON
+async fn export(db_client: &db::DynamoDB, guild_id: GuildId) -> String {
+    let attestations = db_client
+        .guild_attestations(guild_id)
+        .await
+        .iter()
+        .map(|a| {
+            json!({
+                "user_id": a.user_id.0.to_string(),
+                "role_id": a.role_id.0.to_string(),
+                "term": a.term,
+                "expires_at": a.expires_at,
+                "attested_by": a.attested_by.0.to_string(),
+                "attested_at": a.attested_at,
+            })
+        })
+        .collect::<Vec<_>>();
+    let notes = db_client
+        .guild_notes(guild_id)
+        .await
+        .iter()
+        .map(|n| {
+            json!({
+                "user_id": n.user_id.0.to_string(),
+                "author": n.author.0.to_string(),
+                "at": n.at,
+                "text": n.text,
+            })
+        })
+        .collect::<Vec<_>>();
+    let audit = db_client
+        .get_audit(guild_id, 0)
+        .await
+        .iter()
+        .map(|e| {
+            json!({
+                "at": e.at,
+                "actor": e.actor.0.to_string(),
+                "action": e.action,
+                "target": e.target.map(|t| t.0.to_string()),
+                "detail": e.detail,
+            })
+        })
+        .collect::<Vec<_>>();
+    let export = json!({
+        "guild_id": guild_id.0.to_string(),
+        "exported_at": response::unix_now(),
+        "config": db_client.get_guild_config(guild_id).await,
+        "role_mappings": db_client.get_role_config(guild_id).await,
+        "attestations": attestations,
+        "notes": notes,
+        "audit": audit,
+    });
+    serde_json::to_string_pretty(&export).unwrap_or_default()
+}
+
+/// Removes the marker or alumni suffix the bot added to nicknames, returning how many were changed
+async fn strip_decorations(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    guild_id: GuildId,
+    ignore_set: IgnoreSet,
+) -> serenity::Result<usize> {
+    let config = db_client.get_guild_config(guild_id).await;
+    let mut suffixes = vec![config.marker_symbol().to_string()];
+    suffixes.extend(config.alumni_suffix);
+    let mut stripped = 0;
+    for member in members::fetch_all(&ctx.http, guild_id).await? {
+        let nick = match &member.nick {
+            Some(nick) => nick,
+            None => continue,
+        };
+        let cleaned = match suffixes
+            .iter()
+            .find_map(|suffix| nick.strip_suffix(suffix.as_str()))
+        {
+            Some(cleaned) => cleaned.trim_end().to_string(),
+            None => continue,
+        };
+        nicknames::wait_turn(guild_id, member.user.id, nicknames::Source::Offboard).await;
+        // keep the member update from adding the decoration back
+        {
+            ignore_set.lock().await.insert(member.user.id);
+        }
+        if let Err(why) = member.edit(&ctx.http, |m| m.nickname(cleaned)).await {
+            ignore_set.lock().await.remove(&member.user.id);
+            error!(
+                "Failed to strip the nickname of {}: {}",
+                member.user.id, why
+            );
+            continue;
+        }
+        stripped += 1;
+        // sleep to stay far away from rate limit
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Ok(stripped)
+}
@@ -0,0 +1,229 @@
+//! What unverified members get when they join, configured with `/config on-join`.
+//!
+//! The bot DMs them how to verify, with a link to the portal that names the guild and their
+//! account. Members who turned off DMs from server members are mentioned in the guild's fallback
+//! channel instead, when it has one.
+//!
+//! Guilds can also have members who haven't verified yet reminded, a given number of days after
+//! they were first seen joining, at most [`MAX_REMINDERS`] times. Each reminder is a scheduled
+//! task that schedules the next one, so they survive restarts; the chain ends once the member
+//! verifies or leaves, or the guild turns reminders off. Members who joined before reminders
+//! were turned on aren't reminded.
+
+use serde::{Deserialize, Serialize};
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::model::user::User;
+use tracing::warn;
+
+use crate::i18n::{self, Locale};
+use crate::scheduler::{self, Task};
+use crate::{db, response, templates, PORTAL_URL};
+
+pub const MAX_REMINDERS: usize = 3;
+/// the longest a reminder can be put off, in days
+pub const MAX_REMINDER_DAYS: u32 = 30;
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct OnboardingActions {
+    /// DM unverified members who join
+    pub dm: bool,
+    /// channel they're mentioned in when the DM can't be delivered
+    pub fallback_channel: Option<ChannelId>,
+    /// message with `{user}`, `{server}` and `{link}` placeholders, a default one when unset
+    pub template: Option<String>,
+    /// days after joining that members who haven't verified are reminded, ascending
+    pub reminder_days: Vec<u32>,
+}
+
+impl OnboardingActions {
+    /// The actions as a sentence, for `/config`
+    pub fn describe(&self) -> String {
+        let fallback = match self.fallback_channel {
+            Some(channel) => format!(", or a mention in <#{}> when DMs are closed", channel),
+            None => String::new(),
+        };
+        let days = self
+            .reminder_days
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        match (self.dm, days.is_empty()) {
+            (false, true) => "Nothing".to_string(),
+            (true, true) => format!("A DM with how to verify{}", fallback),
+            (false, false) => format!("DM reminders after {} days{}", days, fallback),
+            (true, false) => format!(
+                "A DM with how to verify and reminders after {} days{}",
+                days, fallback
+            ),
+        }
+    }
+}
+
+/// Reads reminder days like `1, 7`, where `off` means none
+pub fn parse_reminder_days(text: &str) -> Option<Vec<u32>> {
+    if text.trim().eq_ignore_ascii_case("off") {
+        return Some(Vec::new());
+    }
+    let mut days = text
+        .split(',')
+        .map(|day| day.trim().parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    days.sort_unstable();
+    days.dedup();
+    let valid =
+        days.len() <= MAX_REMINDERS && days.iter().all(|day| (1..=MAX_REMINDER_DAYS).contains(day));
+    Some(days).filter(|_| valid)
+}
+
+/// The portal, opened for the member's account and the guild they came from, if any
+pub fn portal_link(guild_id: Option<GuildId>, user_id: UserId) -> String {
+    match guild_id {
+        Some(guild_id) => format!(
+            "{}/app?guild_id={}&user_id={}",
+            PORTAL_URL.as_str(),
+            guild_id,
+            user_id
+        ),
+        None => format!("{}/app?user_id={}", PORTAL_URL.as_str(), user_id),
+    }
+}
+
+/// Tells a member who just joined how to verify and schedules their first reminder, if they
+/// haven't verified and the guild asked for it
+pub async fn run(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId, member: &Member) {
+    if member.user.bot {
+        return;
+    }
+    let config = db_client.get_guild_config(guild_id).await;
+    let actions = config.onboarding;
+    if (!actions.dm && actions.reminder_days.is_empty())
+        || db_client.is_verified(member.user.id.0).await
+    {
+        return;
+    }
+    if let Some(days) = actions.reminder_days.first() {
+        let first_seen = response::unix_now();
+        let due_at = first_seen + *days as i64 * DAY;
+        schedule_reminder(db_client, guild_id, member.user.id, first_seen, 0, due_at).await;
+    }
+    if actions.dm {
+        let template = actions
+            .template
+            .as_deref()
+            .unwrap_or_else(|| i18n::text(config.locale, "onboarding.welcome"));
+        tell(
+            http,
+            guild_id,
+            &member.user,
+            &actions,
+            template,
+            config.locale,
+        )
+        .await;
+    }
+}
+
+/// Reminds a member to verify, unless they did or left, and schedules the next reminder
+pub async fn remind(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    user_id: UserId,
+    first_seen: i64,
+    sent: usize,
+) {
+    let config = db_client.get_guild_config(guild_id).await;
+    let actions = config.onboarding;
+    // fewer reminders may have been configured since this was scheduled
+    if sent >= actions.reminder_days.len() || db_client.is_verified(user_id.0).await {
+        return;
+    }
+    let member = match guild_id.member(http, user_id).await {
+        Ok(member) => member,
+        Err(_) => return,
+    };
+    let template = i18n::text(config.locale, "onboarding.reminder");
+    tell(
+        http,
+        guild_id,
+        &member.user,
+        &actions,
+        template,
+        config.locale,
+    )
+    .await;
+    if let Some(days) = actions.reminder_days.get(sent + 1) {
+        // reminders configured sooner since this one was scheduled still wait a day
+        let due_at = (first_seen + *days as i64 * DAY).max(response::unix_now() + DAY);
+        schedule_reminder(db_client, guild_id, user_id, first_seen, sent + 1, due_at).await;
+    }
+}
+
+async fn schedule_reminder(
+    db_client: &db::DynamoDB,
+    guild_id: GuildId,
+    user_id: UserId,
+    first_seen: i64,
+    sent: usize,
+    due_at: i64,
+) {
+    let task = Task::VerifyReminder {
+        guild_id,
+        user_id,
+        first_seen,
+        sent,
+    };
+    // one per member, so rejoining starts over
+    let task_id = format!("reminder:{}:{}", guild_id, user_id);
+    if !scheduler::schedule(db_client, task_id, due_at, &task).await {
+        warn!("Cannot schedule a verification reminder for {}", user_id);
+    }
+}
+
+/// DMs the member the message, or mentions them in the fallback channel when that fails
+async fn tell(
+    http: &Http,
+    guild_id: GuildId,
+    user: &User,
+    actions: &OnboardingActions,
+    template: &str,
+    locale: Locale,
+) {
+    let server = match guild_id.to_partial_guild(http).await {
+        Ok(guild) => guild.name,
+        Err(_) => i18n::text(locale, "server.unknown").to_string(),
+    };
+    let link = portal_link(Some(guild_id), user.id);
+    let render = |user: &str| {
+        templates::render(
+            template,
+            &[("user", user), ("server", &server), ("link", &link)],
+        )
+    };
+    let sent = match user.create_dm_channel(http).await {
+        Ok(dm) => dm.say(http, render(&user.name)).await.map(|_| ()),
+        Err(why) => Err(why),
+    };
+    let why = match sent {
+        Ok(()) => return,
+        Err(why) => why,
+    };
+    let channel = match actions.fallback_channel {
+        Some(channel) => channel,
+        None => {
+            warn!("Cannot DM {} how to verify: {}", user.id, why);
+            return;
+        }
+    };
+    if let Err(why) = channel.say(http, render(&format!("<@{}>", user.id))).await {
+        warn!(
+            "Cannot tell {} how to verify in {}: {}",
+            user.id, channel, why
+        );
+    }
+}
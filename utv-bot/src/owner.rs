@@ -0,0 +1,265 @@
+//! Commands for the bot's owner, run in a DM with the bot: `/guilds`, `/announce` and
+//! `/shutdown`.
+//!
+//! The owner is `OWNER_ID`, or the owner of the bot's Discord application when it's unset, and
+//! is checked before any of these commands runs. `/announce` posts in each server's audit
+//! channel, set with `/config audit-channel`, since what the bot's operator has to say is for
+//! the server's moderators; servers without one are skipped. `/shutdown` stops the bot like
+//! SIGTERM does, for its supervisor to start it again, and `/shutdown reload:True` instead
+//! reads the config file and the `--profile` again and drops the cached roles and stats, like
+//! SIGHUP does. New settings that don't validate are rejected and the previous ones kept.
+//! Settings read once on startup, like the token and the shards, keep their value until a
+//! restart, and the reply or the log names those the reload found changed.
+
+use futures::stream::{self, StreamExt};
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::id::UserId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+use tracing::{info, warn};
+
+use crate::{cache, db, handlers, response, settings, sharding, shutdown, stats, SCAN_CONCURRENCY};
+
+pub const COMMANDS: [&str; 3] = ["guilds", "announce", "shutdown"];
+/// servers listed by `/guilds`, largest first
+const GUILDS_LISTED: usize = 50;
+
+/// The bot's owner
+pub async fn owner(http: &Http) -> serenity::Result<UserId> {
+    if let Ok(Some(owner)) = settings::owner_id() {
+        return Ok(owner);
+    }
+    Ok(http.get_current_application_info().await?.owner.id)
+}
+
+pub async fn is_owner(http: &Http, user_id: UserId) -> bool {
+    match owner(http).await {
+        Ok(owner) => owner == user_id,
+        Err(why) => {
+            warn!("Cannot look up the bot's owner: {}", why);
+            false
+        }
+    }
+}
+
+/// The reply to anyone else running an owner-only command
+pub async fn refuse(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> serenity::Result<()> {
+    response::respond_title(
+        ctx,
+        command,
+        true,
+        "Only the bot's owner can run this command.",
+    )
+    .await
+}
+
+/// Runs one of [`COMMANDS`], once the caller was checked to be the owner
+pub async fn run(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if command.guild_id.is_some() {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            format!("Run `/{}` in a DM with the bot.", command.data.name),
+        )
+        .await;
+    }
+    match command.data.name.as_str() {
+        "guilds" => guilds(db_client, &command, &ctx).await,
+        "announce" => announce(db_client, &command, &ctx).await,
+        "shutdown" if handlers::option_bool(&command.data.options, "reload") == Some(true) => {
+            reload(&command, &ctx).await
+        }
+        _ => {
+            info!("Shutdown requested by {}", command.user.id);
+            response::respond_title(&ctx, &command, true, "Shutting down").await?;
+            shutdown::request();
+            Ok(())
+        }
+    }
+}
+
+async fn guilds(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    response::defer(ctx, command, true).await?;
+    let infos = sharding::guild_infos(&ctx.http).await?;
+    let http = &ctx.http;
+    let mut counted = stream::iter(infos.iter())
+        .map(|info| async move { (info, stats::guild_stats(db_client, http, info.id).await) })
+        .buffer_unordered(SCAN_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    counted.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.as_ref().map_or(0, |s| s.members)));
+
+    let (members, verified) = counted
+        .iter()
+        .filter_map(|(_, stats)| stats.as_ref().ok())
+        .fold((0, 0), |(m, v), s| (m + s.members, v + s.verified));
+    let lines = counted
+        .iter()
+        .take(GUILDS_LISTED)
+        .map(|(info, stats)| match stats {
+            Ok(stats) => format!(
+                "**{}** (`{}`): {} members, {} verified",
+                info.name,
+                info.id,
+                response::count(stats.members),
+                response::count(stats.verified)
+            ),
+            Err(why) => format!("**{}** (`{}`): couldn't count: {}", info.name, info.id, why),
+        })
+        .collect::<Vec<_>>();
+    command
+        .edit_original_interaction_response(&ctx.http, |response| {
+            response.create_embed(|embed| {
+                embed
+                    .title(format!("In {} Servers", response::count(infos.len())))
+                    .description(format!(
+                        "{} members, {} of them verified",
+                        response::count(members),
+                        response::count(verified)
+                    ))
+                    .field(
+                        "Largest Servers",
+                        response::field_lines(&lines, "None"),
+                        false,
+                    )
+                    .color(Color::from_rgb(191, 87, 0))
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn announce(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let options = &command.data.options;
+    let (title, message) = match (
+        handlers::option_str(options, "title"),
+        handlers::option_str(options, "message"),
+    ) {
+        (Some(title), Some(message)) => (title, message),
+        _ => return Ok(()),
+    };
+    response::defer(ctx, command, true).await?;
+    let guilds = sharding::guilds(&ctx.http).await?;
+    let http = &ctx.http;
+    let results = stream::iter(guilds.iter())
+        .map(|guild_id| async move {
+            let channel = db_client.get_guild_config(*guild_id).await.audit_channel?;
+            let sent = channel
+                .send_message(http, |m| {
+                    m.embed(|embed| {
+                        embed
+                            .title(title)
+                            .description(message)
+                            .footer(|footer| footer.text("From the bot's operator"))
+                            .color(Color::from_rgb(191, 87, 0))
+                    })
+                })
+                .await;
+            if let Err(why) = &sent {
+                warn!("Cannot post the announcement in {}: {}", guild_id, why);
+            }
+            Some(sent.is_ok())
+        })
+        .buffer_unordered(SCAN_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let sent = results.iter().filter(|r| **r == Some(true)).count();
+    let failed = results.iter().filter(|r| **r == Some(false)).count();
+    let skipped = results.iter().filter(|r| r.is_none()).count();
+    command
+        .edit_original_interaction_response(&ctx.http, |response| {
+            response.create_embed(|embed| {
+                embed
+                    .title(format!("Announced in {} Servers", response::count(sent)))
+                    .description(format!(
+                        "{} couldn't be posted in, {} have no audit channel.",
+                        response::count(failed),
+                        response::count(skipped)
+                    ))
+                    .color(Color::from_rgb(191, 87, 0))
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Reloads the settings and drops what was cached with the old ones
+fn reload_settings() -> Result<settings::Reloaded, String> {
+    let reloaded = settings::reload()?;
+    cache::clear();
+    stats::clear();
+    Ok(reloaded)
+}
+
+/// Reloads the settings whenever the process receives SIGHUP
+pub async fn reload_on_hangup() {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(why) => {
+            warn!("Cannot listen for SIGHUP: {}", why);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        let reloaded = match reload_settings() {
+            Ok(reloaded) => reloaded,
+            Err(why) => {
+                warn!("Cannot reload the settings: {}", why);
+                continue;
+            }
+        };
+        info!("Reloaded on SIGHUP");
+        if !reloaded.pending.is_empty() {
+            warn!(
+                "Restart to apply the changes to {}",
+                reloaded.pending.join(", ")
+            );
+        }
+    }
+}
+
+async fn reload(command: &ApplicationCommandInteraction, ctx: &Context) -> serenity::Result<()> {
+    let reloaded = match reload_settings() {
+        Ok(reloaded) => reloaded,
+        Err(why) => {
+            return response::respond_embed(ctx, command, true, |embed| {
+                embed.title("Couldn't Reload the Settings").description(why)
+            })
+            .await
+        }
+    };
+    info!("Reloaded for {}", command.user.id);
+    let title = match reloaded.profile {
+        Some(profile) => format!("Reloaded Profile {}", profile),
+        None => "Reloaded the Settings".to_string(),
+    };
+    let description = match reloaded.pending.as_slice() {
+        [] => "The settings are valid.".to_string(),
+        pending => format!(
+            "The settings are valid. These only change on a restart: {}",
+            pending.join(", ")
+        ),
+    };
+    response::respond_embed(ctx, command, true, |embed| {
+        embed.title(title).description(description)
+    })
+    .await
+}
@@ -0,0 +1,238 @@
+//! `/setup-panel`: a message with buttons to verify, for servers whose members don't find the
+//! slash commands on their own.
+//!
+//! "Verify" opens a form asking for the EID and sends the email like `/verify`, "Enter Token"
+//! opens the token form like `/redeem`, and "Verify on the Web" replies with a link to the portal
+//! opened for the server and the member's account. The panel's buttons never expire.
+
+use serenity::builder::CreateInteractionResponseData;
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::{
+    ActionRowComponent, ButtonStyle, InputTextStyle, MessageComponentInteraction,
+};
+use serenity::model::interactions::modal::ModalSubmitInteraction;
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+
+use crate::{components, db, handlers, i18n, onboarding, redeem, response, throttle};
+
+/// Custom id prefix of the panel's buttons, followed by `eid` or `portal`
+pub const COMPONENT_PREFIX: &str = "verify-panel:";
+/// Custom id of the EID form
+pub const MODAL_ID: &str = "verify-panel";
+
+pub async fn setup_panel(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let role_name = db_client
+        .get_guild_config(guild_id)
+        .await
+        .verified_role_name()
+        .to_string();
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title("Verify Your UT EID")
+                                .description(format!(
+                                    "Get the **{}** role by verifying you're a UT student, \
+                                     faculty or staff member. Press **Verify** and enter your \
+                                     EID to get a token by email, then **Enter Token** once it \
+                                     arrives. You can also verify on the web with your UT login.",
+                                    role_name
+                                ))
+                                .color(Color::from_rgb(191, 87, 0))
+                        })
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button
+                                        .custom_id(format!("{}eid", COMPONENT_PREFIX))
+                                        .label("Verify")
+                                        .style(ButtonStyle::Primary)
+                                })
+                                .create_button(|button| {
+                                    button
+                                        .custom_id(redeem::OPEN_BUTTON_ID)
+                                        .label("Enter Token")
+                                        .style(ButtonStyle::Secondary)
+                                })
+                                .create_button(|button| {
+                                    button
+                                        .custom_id(format!("{}portal", COMPONENT_PREFIX))
+                                        .label("Verify on the Web")
+                                        .style(ButtonStyle::Secondary)
+                                })
+                            })
+                        })
+                })
+        })
+        .await?;
+    components::track_response(db_client, &command, guild_id, &ctx, None).await;
+    Ok(())
+}
+
+/// Handles a press of one of the panel's buttons
+pub async fn pressed(
+    db_client: &db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let guild_id = match component.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    if db_client.is_verified(component.user.id.0).await {
+        return response::respond_component_title(
+            &ctx,
+            &component,
+            true,
+            "You're already verified",
+        )
+        .await;
+    }
+    match &component.data.custom_id[COMPONENT_PREFIX.len()..] {
+        "portal" => {
+            let link = onboarding::portal_link(Some(guild_id), component.user.id);
+            component
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message
+                                .create_embed(|embed| {
+                                    embed
+                                        .title("Verify on the Web")
+                                        .description(format!(
+                                            "[Open the portal]({}) and log in with your UT EID. \
+                                             The link is made for your account, don't share it.",
+                                            link
+                                        ))
+                                        .color(Color::from_rgb(191, 87, 0))
+                                })
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await
+        }
+        _ => {
+            if let Some(ready) = handlers::verification_available_at(
+                db_client,
+                Some(guild_id),
+                component.user.id,
+                component.member.as_ref(),
+            )
+            .await
+            {
+                return response::respond_component_title(
+                    &ctx,
+                    &component,
+                    true,
+                    format!(
+                        "You can verify {}.",
+                        response::timestamp(ready, response::TimestampStyle::Relative)
+                    ),
+                )
+                .await;
+            }
+            component
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::Modal)
+                        .interaction_response_data(eid_modal)
+                })
+                .await
+        }
+    }
+}
+
+fn eid_modal(modal: &mut CreateInteractionResponseData) -> &mut CreateInteractionResponseData {
+    modal
+        .custom_id(MODAL_ID)
+        .title("Verify Your UT EID")
+        .components(|components| {
+            components.create_action_row(|row| {
+                row.create_input_text(|input| {
+                    input
+                        .custom_id("eid")
+                        .label("Your EID, like abc123")
+                        .style(InputTextStyle::Short)
+                        .max_length(64)
+                        .required(true)
+                })
+            })
+        })
+}
+
+/// Handles a submitted EID form
+pub async fn submitted(
+    db_client: &db::DynamoDB,
+    modal: ModalSubmitInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let eid = modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(text) if text.custom_id == "eid" => {
+                Some(text.value.trim().to_string())
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+    let locale = i18n::locale(db_client, modal.guild_id).await;
+    let problem = match handlers::eid_input_problem(locale, &eid) {
+        Some(guidance) => Some((i18n::text(locale, "eid.invalid.title"), guidance)),
+        None => match throttle::take(db_client, &ctx.http, modal.guild_id, modal.user.id).await {
+            Some(retry_at) => Some((
+                i18n::text(locale, "throttle.title"),
+                throttle::limited_message(locale, retry_at),
+            )),
+            None if !handlers::request_email(db_client, modal.guild_id, modal.user.id, &eid)
+                .await =>
+            {
+                Some((i18n::text(locale, "verify.failed"), String::new()))
+            }
+            None => None,
+        },
+    };
+    modal
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| match problem {
+                    Some((title, guidance)) => message
+                        .create_embed(|embed| {
+                            embed
+                                .title(title)
+                                .description(guidance)
+                                .color(Color::from_rgb(255, 165, 0))
+                        })
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL),
+                    None => handlers::email_sent(message, locale),
+                })
+        })
+        .await
+}
@@ -0,0 +1,327 @@
+//! Previews of `/config` changes to the nickname policy.
+//!
+//! Changes that rename members (`/config alumni`, `/config decoration`, `/config marker` and
+//! `/config nickname`) aren't saved right away: the admin gets the change's summary with buttons
+//! to preview how many members it would modify, apply it or cancel. Previews work from the
+//! member list and roles alone rather than looking up every member's verification, so they're
+//! an estimate. Pending changes are kept in memory for [`PENDING_SECS`].
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+
+use crate::config::{self, GuildConfig, Setter};
+use crate::{db, jobs, members, response};
+
+pub const COMPONENT_PREFIX: &str = "config-preview:";
+const PENDING_SECS: i64 = 15 * 60;
+
+struct Pending {
+    guild_id: GuildId,
+    actor: UserId,
+    name: String,
+    setter: Setter,
+    options: Vec<ApplicationCommandInteractionDataOption>,
+    summary: String,
+    created_at: i64,
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<String, Pending>> = Mutex::new(HashMap::new());
+}
+
+/// How many members a change would modify
+struct Impact {
+    nicknames: usize,
+    /// members holding the old alumni role, who wouldn't count as alumni any more
+    no_longer_alumni: usize,
+}
+
+/// Checks a change and asks the admin to preview or apply it
+pub async fn offer(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: &Context,
+    name: &str,
+    setter: Setter,
+    options: &[ApplicationCommandInteractionDataOption],
+) -> serenity::Result<()> {
+    let mut candidate = db_client.get_guild_config(guild_id).await;
+    let summary = match setter(&mut candidate, options) {
+        Some(summary) => summary,
+        None => {
+            return response::respond_title(ctx, command, true, "Missing or invalid setting").await
+        }
+    };
+    let id = format!("{:016x}", rand::random::<u64>());
+    {
+        let now = response::unix_now();
+        let mut pending = PENDING.lock().unwrap();
+        pending.retain(|_, p| p.created_at + PENDING_SECS > now);
+        pending.insert(
+            id.clone(),
+            Pending {
+                guild_id,
+                actor: command.user.id,
+                name: name.to_string(),
+                setter,
+                options: options.to_vec(),
+                summary: summary.clone(),
+                created_at: now,
+            },
+        );
+    }
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| review_embed(embed, &summary, None))
+                        .components(|components| buttons(components, &id))
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+fn review_embed<'a>(
+    embed: &'a mut CreateEmbed,
+    summary: &str,
+    impact: Option<&Impact>,
+) -> &'a mut CreateEmbed {
+    embed
+        .title("Review This Change")
+        .description(summary)
+        .color(Color::from_rgb(191, 87, 0));
+    match impact {
+        Some(impact) => {
+            let mut text = format!(
+                "About {} members would have their nickname changed.",
+                impact.nicknames
+            );
+            if impact.no_longer_alumni > 0 {
+                text.push_str(&format!(
+                    " {} members hold the current alumni role and wouldn't count as alumni any more.",
+                    impact.no_longer_alumni
+                ));
+            }
+            embed.field("Impact", text, false)
+        }
+        None => embed.footer(|footer| {
+            footer.text("Nothing is changed until you apply it. Changes are kept for 15 minutes.")
+        }),
+    }
+}
+
+fn buttons<'a>(components: &'a mut CreateComponents, id: &str) -> &'a mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(format!("{}preview:{}", COMPONENT_PREFIX, id))
+                .label("Preview impact")
+                .style(ButtonStyle::Secondary)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!("{}apply:{}", COMPONENT_PREFIX, id))
+                .label("Apply")
+                .style(ButtonStyle::Primary)
+        })
+        .create_button(|button| {
+            button
+                .custom_id(format!("{}cancel:{}", COMPONENT_PREFIX, id))
+                .label("Cancel")
+                .style(ButtonStyle::Secondary)
+        })
+    })
+}
+
+/// Handles the preview, apply and cancel buttons
+pub async fn handle(
+    db_client: &db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    let (action, id) = match component.data.custom_id[COMPONENT_PREFIX.len()..].split_once(':') {
+        Some(parts) => parts,
+        None => return Ok(()),
+    };
+    let known = {
+        let pending = PENDING.lock().unwrap();
+        pending.get(id).map(|p| {
+            (
+                p.guild_id,
+                p.actor,
+                p.summary.clone(),
+                p.created_at + PENDING_SECS > response::unix_now(),
+            )
+        })
+    };
+    let (guild_id, summary) = match known {
+        Some((guild_id, actor, summary, true)) if actor == component.user.id => (guild_id, summary),
+        Some((_, _, _, true)) => {
+            return response::respond_component_title(
+                &ctx,
+                &component,
+                true,
+                "Only the admin who made this change can apply it.",
+            )
+            .await
+        }
+        _ => {
+            return update_message(&ctx, &component, |embed| {
+                embed
+                    .title("This change expired")
+                    .description("Run the command again to make it.")
+            })
+            .await
+        }
+    };
+    match action {
+        "preview" => {
+            component
+                .create_interaction_response(&ctx.http, |response| {
+                    response.kind(InteractionResponseType::DeferredUpdateMessage)
+                })
+                .await?;
+            let impact = impact(db_client, &ctx, guild_id, id).await?;
+            component
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    response
+                        .create_embed(|embed| review_embed(embed, &summary, Some(&impact)))
+                        .components(|components| buttons(components, id))
+                })
+                .await?;
+            Ok(())
+        }
+        "apply" => {
+            let pending = match PENDING.lock().unwrap().remove(id) {
+                Some(pending) => pending,
+                None => return Ok(()),
+            };
+            let title = match config::update(db_client, guild_id, component.user.id, |config| {
+                (pending.setter)(config, &pending.options)
+            })
+            .await
+            {
+                Ok(summary) => {
+                    config::apply_side_effects(
+                        db_client,
+                        &ctx.http,
+                        guild_id,
+                        &pending.name,
+                        &pending.options,
+                        jobs,
+                        summary,
+                    )
+                    .await
+                }
+                Err(why) => why.to_string(),
+            };
+            update_message(&ctx, &component, |embed| embed.title(title)).await
+        }
+        _ => {
+            PENDING.lock().unwrap().remove(id);
+            update_message(&ctx, &component, |embed| {
+                embed.title("Change cancelled, nothing was changed")
+            })
+            .await
+        }
+    }
+}
+
+/// Replaces the review message, removing its buttons
+async fn update_message(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    f: impl FnOnce(&mut CreateEmbed) -> &mut CreateEmbed,
+) -> serenity::Result<()> {
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message.create_embed(f).components(|components| components)
+                })
+        })
+        .await
+}
+
+/// Estimates the members whose nickname the pending change would modify
+async fn impact(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    guild_id: GuildId,
+    id: &str,
+) -> serenity::Result<Impact> {
+    let current = db_client.get_guild_config(guild_id).await;
+    let mut candidate = current.clone();
+    if let Some(pending) = PENDING.lock().unwrap().get(id) {
+        (pending.setter)(&mut candidate, &pending.options);
+    }
+    let student_role = db_client
+        .get_role_config(guild_id)
+        .await
+        .get("student")
+        .map(|r| RoleId(*r));
+    let members = members::fetch_all(&ctx.http, guild_id).await?;
+    Ok(estimate(&current, &candidate, student_role, &members))
+}
+
+/// Members with the Student role get the marker and the decoration, members with the alumni role and
+/// without the Student role get the alumni suffix and the decoration
+fn estimate(
+    current: &GuildConfig,
+    candidate: &GuildConfig,
+    student_role: Option<RoleId>,
+    members: &[Member],
+) -> Impact {
+    let now = response::unix_now();
+    let decoration_changed = current.active_decoration(now) != candidate.active_decoration(now);
+    let alumni_role_changed = current.alumni_role != candidate.alumni_role;
+    let suffix_changed = current.alumni_suffix != candidate.alumni_suffix;
+    // the role's icon stands in for the marker
+    let marker_changed =
+        !current.native_marker && current.marker_symbol() != candidate.marker_symbol();
+    let template_changed = current.nickname_template() != candidate.nickname_template();
+    let mut impact = Impact {
+        nicknames: 0,
+        no_longer_alumni: 0,
+    };
+    for member in members.iter().filter(|m| !m.user.bot) {
+        let student = student_role.map_or(false, |r| member.roles.contains(&r));
+        let alumnus = !student
+            && current
+                .alumni_role
+                .map_or(false, |r| member.roles.contains(&r));
+        if alumnus && alumni_role_changed {
+            impact.no_longer_alumni += 1;
+        }
+        if (student && (decoration_changed || marker_changed || template_changed))
+            || (alumnus
+                && (decoration_changed
+                    || suffix_changed
+                    || alumni_role_changed
+                    || template_changed))
+        {
+            impact.nicknames += 1;
+        }
+    }
+    impact
+}
@@ -0,0 +1,139 @@
+//! The Unverified role, enabled with `/config quarantine`.
+//!
+//! The bot creates the role, gives it to members who aren't verified when they join or are
+//! checked, and takes it off once they verify. With a welcome channel set, the role is denied
+//! View Channel on every category and uncategorized channel and allowed it in the welcome
+//! channel, so unverified members only see that one; channels whose permissions aren't synced
+//! with their category are left to the guild's admins. The overwrites are kept in place by
+//! `channels::enforce`. Disabling quarantine deletes the role, which removes its overwrites.
+
+use serenity::http::Http;
+use serenity::model::channel::{ChannelType, PermissionOverwrite, PermissionOverwriteType};
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::Permissions;
+use tracing::warn;
+
+use crate::{cache, db, roles, telemetry};
+
+pub const ROLE_NAME: &str = "Unverified";
+
+/// Creates the role unless the guild still has the one created earlier, and stores it
+pub async fn enable(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<RoleId> {
+    let _creating = roles::creation_lock(guild_id).await;
+    let mut config = db_client.get_guild_config(guild_id).await;
+    if let Some(role_id) = config.quarantine_role {
+        if cache::roles(http, guild_id).await?.contains_key(&role_id) {
+            return Ok(role_id);
+        }
+    }
+    let role = guild_id
+        .create_role(http, |role| {
+            role.name(ROLE_NAME)
+                .permissions(Permissions::empty())
+                .mentionable(false)
+        })
+        .await?;
+    cache::invalidate_roles(guild_id);
+    config.quarantine_role = Some(role.id);
+    if !db_client.set_guild_config(guild_id, &config).await {
+        // an untracked role would never be cleaned up
+        let _ = guild_id.delete_role(http, role.id).await;
+        return Err(serenity::Error::Other("couldn't save the role"));
+    }
+    Ok(role.id)
+}
+
+/// Deletes the role, if the bot created one
+pub async fn disable(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<()> {
+    let mut config = db_client.get_guild_config(guild_id).await;
+    let role_id = match config.quarantine_role.take() {
+        Some(role_id) => role_id,
+        None => return Ok(()),
+    };
+    roles::expect_change(role_id);
+    if let Err(why) = guild_id.delete_role(http, role_id).await {
+        // already deleted by hand is fine
+        if cache::roles(http, guild_id).await?.contains_key(&role_id) {
+            return Err(why);
+        }
+    }
+    cache::invalidate_roles(guild_id);
+    if !db_client.set_guild_config(guild_id, &config).await {
+        return Err(serenity::Error::Other("couldn't save the configuration"));
+    }
+    Ok(())
+}
+
+/// Gives a member the role while they're unverified and takes it off once they are
+pub async fn apply(db_client: &db::DynamoDB, http: &Http, member: &mut Member, verified: bool) {
+    if member.user.bot {
+        return;
+    }
+    let config = db_client.get_guild_config(member.guild_id).await;
+    let role_id = match config.quarantine_role.filter(|_| config.quarantine) {
+        Some(role_id) => role_id,
+        None => return,
+    };
+    let result = match (verified, member.roles.contains(&role_id)) {
+        (true, true) => member.remove_role(http, role_id).await,
+        (false, false) => member.add_role(http, role_id).await,
+        _ => return,
+    };
+    if let Err(why) = result {
+        telemetry::count(telemetry::Counter::DiscordErrors, "quarantine", 1);
+        warn!(
+            "Cannot {} {} in {}: {}",
+            if verified { "release" } else { "quarantine" },
+            member.user.id,
+            member.guild_id,
+            why
+        );
+    }
+}
+
+/// Limits the role to the welcome channel, only editing overwrites that drifted
+pub async fn gate_channels(
+    http: &Http,
+    guild_id: GuildId,
+    role_id: RoleId,
+    welcome_channel: ChannelId,
+) -> serenity::Result<()> {
+    let kind = PermissionOverwriteType::Role(role_id);
+    for (channel_id, channel) in guild_id.channels(http).await? {
+        let visible = channel_id == welcome_channel;
+        if !visible && channel.category_id.is_some() && channel.kind != ChannelType::Category {
+            continue;
+        }
+        let current = channel
+            .permission_overwrites
+            .iter()
+            .find(|o| o.kind == kind);
+        let (mut allow, mut deny) = current
+            .map_or((Permissions::empty(), Permissions::empty()), |o| {
+                (o.allow, o.deny)
+            });
+        allow.remove(Permissions::VIEW_CHANNEL);
+        deny.remove(Permissions::VIEW_CHANNEL);
+        if visible {
+            allow.insert(Permissions::VIEW_CHANNEL);
+        } else {
+            deny.insert(Permissions::VIEW_CHANNEL);
+        }
+        if current.map_or(false, |o| o.allow == allow && o.deny == deny) {
+            continue;
+        }
+        channel
+            .create_permission(http, &PermissionOverwrite { allow, deny, kind })
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Rate limit telemetry for `/admin ratelimits`.
+//!
+//! Serenity waits out exhausted buckets before sending, so big scans rarely produce actual 429s;
+//! they instead hold up every other request on the same route, including verification
+//! responses. An observer samples the HTTP ratelimiter and records when a bucket runs dry,
+//! along with the bulk jobs that were running at the time.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serenity::http::Http;
+use serenity::model::id::GuildId;
+
+use crate::response;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// exhaustion events kept for the report
+const HISTORY: usize = 500;
+
+/// A bucket that ran out of requests
+#[derive(Clone, Debug)]
+pub struct Exhaustion {
+    pub at: i64,
+    pub route: String,
+    /// bulk jobs running when it happened
+    pub jobs: Vec<(GuildId, &'static str)>,
+}
+
+/// The state of a bucket at the last sample
+#[derive(Clone, Debug)]
+pub struct Bucket {
+    pub route: String,
+    pub limit: i64,
+    pub remaining: i64,
+}
+
+lazy_static! {
+    static ref RUNNING: Mutex<Vec<(GuildId, &'static str)>> = Mutex::new(Vec::new());
+    static ref EXHAUSTED: Mutex<VecDeque<Exhaustion>> = Mutex::new(VecDeque::new());
+    static ref BUCKETS: Mutex<Vec<Bucket>> = Mutex::new(Vec::new());
+}
+
+/// Marks a bulk job as running in a guild until the guard is dropped
+pub struct JobGuard(GuildId, &'static str);
+
+pub fn job(guild_id: GuildId, kind: &'static str) -> JobGuard {
+    RUNNING.lock().unwrap().push((guild_id, kind));
+    JobGuard(guild_id, kind)
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap();
+        if let Some(i) = running.iter().position(|j| *j == (self.0, self.1)) {
+            running.remove(i);
+        }
+    }
+}
+
+pub async fn observe_loop(http: Arc<Http>) {
+    // buckets already reported as exhausted, until they refill
+    let mut dry: HashMap<String, bool> = HashMap::new();
+    loop {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        let routes = http.ratelimiter.routes();
+        let mut buckets = Vec::new();
+        for (route, ratelimit) in routes.read().await.iter() {
+            let ratelimit = ratelimit.lock().await;
+            let route = format!("{:?}", route);
+            let exhausted = ratelimit.limit() > 0 && ratelimit.remaining() == 0;
+            let was_exhausted = dry.insert(route.clone(), exhausted).unwrap_or(false);
+            if exhausted && !was_exhausted {
+                let mut history = EXHAUSTED.lock().unwrap();
+                history.push_back(Exhaustion {
+                    at: response::unix_now(),
+                    route: route.clone(),
+                    jobs: RUNNING.lock().unwrap().clone(),
+                });
+                if history.len() > HISTORY {
+                    history.pop_front();
+                }
+            }
+            buckets.push(Bucket {
+                route,
+                limit: ratelimit.limit(),
+                remaining: ratelimit.remaining(),
+            });
+        }
+        *BUCKETS.lock().unwrap() = buckets;
+    }
+}
+
+/// Buckets as of the last sample, most utilized first
+pub fn buckets() -> Vec<Bucket> {
+    let mut buckets = BUCKETS.lock().unwrap().clone();
+    buckets.sort_by_key(|b| std::cmp::Reverse((b.limit - b.remaining) * 100 / b.limit.max(1)));
+    buckets
+}
+
+/// Exhaustion events at or after `since`, oldest first
+pub fn exhausted_since(since: i64) -> Vec<Exhaustion> {
+    EXHAUSTED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|e| e.at >= since)
+        .cloned()
+        .collect()
+}
+
+/// Bulk jobs running right now
+pub fn running() -> Vec<(GuildId, &'static str)> {
+    RUNNING.lock().unwrap().clone()
+}
@@ -0,0 +1,210 @@
+//! Monthly re-check of stored EIDs against the UT Directory.
+//!
+//! Claims are only read from the directory when a member verifies, so a student who left UT
+//! would keep their roles indefinitely. When `EID_RECHECK_PERCENT` is set, a sample of linked
+//! users (or all of them at 100) is sent to the verification server every
+//! [`RECHECK_INTERVAL_SECS`]. It looks each EID up again and replies with the current claims;
+//! changed claims are stored and the user is re-checked in every guild like after verifying,
+//! which moves former students to the alumni role and fills in the names of members verified
+//! before names were stored.
+
+use std::time::Duration;
+
+use aws_sdk_sqs::model::DeleteMessageBatchRequestEntry;
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::json;
+use serenity::model::id::UserId;
+use tracing::{error, info, warn};
+
+use crate::{db, redeem, response, scheduler, settings};
+
+const RECHECK_INTERVAL_SECS: i64 = 30 * 24 * 60 * 60;
+const TASK_PREFIX: &str = "eid-recheck:";
+
+lazy_static! {
+    /// the verification server's queue, shared with `/verify`
+    static ref SQS_VERIFICATION_REQUEST_URL: String =
+        settings::verification_request_queue().unwrap_or_else(|why| panic!("{}", why));
+    static ref SQS_RECHECK_RESULT_URL: String =
+        settings::recheck_result_queue().unwrap_or_else(|why| panic!("{}", why));
+}
+
+#[derive(Deserialize)]
+struct RecheckResult {
+    discord_id: String,
+    /// the EID is no longer in the directory, or lacks the attributes verification needs
+    departed: bool,
+    #[serde(default)]
+    claims: Option<db::Claims>,
+}
+
+/// Schedules the first re-check when enabled and none is pending
+pub async fn ensure_scheduled(db_client: &db::DynamoDB) {
+    if !matches!(settings::eid_recheck_percent(), Ok(Some(_))) {
+        return;
+    }
+    if db_client
+        .scheduled_with_prefix(TASK_PREFIX)
+        .await
+        .is_empty()
+    {
+        schedule_next(db_client).await;
+    }
+}
+
+async fn schedule_next(db_client: &db::DynamoDB) {
+    let due_at = response::unix_now() + RECHECK_INTERVAL_SECS;
+    // the scheduler deletes a task by id after running it, so each run gets a new id
+    if !scheduler::schedule(
+        db_client,
+        format!("{}{}", TASK_PREFIX, due_at),
+        due_at,
+        &scheduler::Task::EidRecheck,
+    )
+    .await
+    {
+        error!("Failed to schedule the next EID re-check");
+    }
+}
+
+/// Sends the sampled users to the verification server and schedules the next run. Does
+/// nothing once `EID_RECHECK_PERCENT` is unset, which stops the re-checks.
+pub async fn request(db_client: &db::DynamoDB) {
+    let percent = match settings::eid_recheck_percent() {
+        Ok(Some(percent)) => percent,
+        _ => return,
+    };
+    let users = db_client.linked_users().await;
+    let config = aws_config::load_from_env().await;
+    let client = aws_sdk_sqs::Client::new(&config);
+    let mut sent = 0;
+    for (discord_id, encrypted_eid) in users.iter() {
+        if rand::thread_rng().gen_range(0..100) >= percent {
+            continue;
+        }
+        let result = client
+            .send_message()
+            .queue_url(SQS_VERIFICATION_REQUEST_URL.as_str())
+            .message_body(
+                json!({
+                    "discord_id": discord_id.0.to_string(),
+                    "encrypted_eid": encrypted_eid,
+                })
+                .to_string(),
+            )
+            .send()
+            .await;
+        match result {
+            Ok(_) => sent += 1,
+            Err(why) => error!("Failed to request re-check of {}: {}", discord_id, why),
+        }
+    }
+    info!(
+        "Requested directory re-checks of {} of {} users",
+        sent,
+        users.len()
+    );
+    schedule_next(db_client).await;
+}
+
+/// Applies the verification server's re-check results
+pub async fn results_loop(db_client: &'static db::DynamoDB) {
+    let config = aws_config::load_from_env().await;
+    let client = aws_sdk_sqs::Client::new(&config);
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let messages = match client
+            .receive_message()
+            .queue_url(SQS_RECHECK_RESULT_URL.as_str())
+            .max_number_of_messages(10)
+            .send()
+            .await
+        {
+            Ok(out) => out.messages.unwrap_or_default(),
+            Err(why) => {
+                warn!("Cannot receive re-check results: {}", why);
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            }
+        };
+        if messages.is_empty() {
+            continue;
+        }
+        let mut entries = Vec::new();
+        for msg in messages {
+            match msg
+                .body
+                .as_deref()
+                .map(serde_json::from_str::<RecheckResult>)
+            {
+                Some(Ok(result)) => apply(db_client, result).await,
+                _ => error!("Dropping unreadable re-check result {:?}", msg.body),
+            }
+            entries.push(
+                DeleteMessageBatchRequestEntry::builder()
+                    .set_id(Some(entries.len().to_string()))
+                    .set_receipt_handle(msg.receipt_handle)
+                    .build(),
+            );
+        }
+        if let Err(why) = client
+            .delete_message_batch()
+            .queue_url(SQS_RECHECK_RESULT_URL.as_str())
+            .set_entries(Some(entries))
+            .send()
+            .await
+        {
+            warn!("Cannot delete re-check results: {}", why);
+        }
+    }
+}
+
+async fn apply(db_client: &db::DynamoDB, result: RecheckResult) {
+    let discord_id = match result.discord_id.parse() {
+        Ok(id) => UserId(id),
+        Err(_) => return,
+    };
+    let stored = match db_client.get_user(discord_id.0).await {
+        Some(claims) => claims,
+        None => return,
+    };
+    let current = match (result.departed, result.claims) {
+        (true, _) => db::Claims {
+            major: Vec::new(),
+            school: Vec::new(),
+            affiliation: Vec::new(),
+            name: None,
+        },
+        (false, Some(claims)) => claims,
+        (false, None) => return,
+    };
+    if current.major == stored.major
+        && current.school == stored.school
+        && current.affiliation == stored.affiliation
+        && current.name == stored.name
+    {
+        return;
+    }
+    let lost_affiliation = stored
+        .affiliation
+        .iter()
+        .any(|a| !current.affiliation.contains(a));
+    let departed_at = Some(response::unix_now()).filter(|_| lost_affiliation);
+    if !db_client
+        .update_claims(discord_id, &current, departed_at)
+        .await
+    {
+        error!("Failed to store re-checked claims of {}", discord_id);
+        return;
+    }
+    if lost_affiliation {
+        info!(
+            "Directory re-check: {} no longer has affiliation {:?}",
+            discord_id, stored.affiliation
+        );
+    }
+    // re-applies roles and nicknames in every guild, as after verifying
+    redeem::announce(discord_id).await;
+}
@@ -0,0 +1,564 @@
+//! `/redeem`: links a Discord account with the verification token from the email sent by
+//! `/verify`.
+//!
+//! Tokens are long base64 strings that mobile keyboards like to autocorrect, so besides the
+//! slash option they can be pasted into a modal, attached as a text file or sent by DM, and
+//! whitespace, quotes and the surrounding link are stripped before validation.
+//!
+//! The web portal hands tokens over with `POST /verify` instead, signed together with the
+//! Discord account it logged in, see [`callback`]. Members who log in with their UT EID through
+//! `sso` skip the token and are linked by [`link_claims`] directly.
+//!
+//! An EID only verifies one account: redeeming a token on a second account while the EID is
+//! still linked to the first is refused, so a forwarded email can't verify alts. With
+//! `EID_TAKEOVER=takeover` the EID moves to the new account instead, for people who lost access
+//! to theirs: the old account is unlinked and unverified everywhere, moderators are told in the
+//! audit channel and the old account by DM.
+//!
+//! Tokens are refused `TOKEN_MAX_AGE_HOURS` after they were issued, and each is bound to the
+//! first account that redeems it, so a captured token can't verify another account even while
+//! the EID is unlinked. Tokens from before issue times and nonces were added are bound by their
+//! hash and refused once their own expiry passes; ones without an expiry are refused outright.
+
+use aws_sdk_sqs::Client as SqsClient;
+use axum::extract::Extension;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serenity::builder::{CreateEmbed, CreateInteractionResponseData};
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::channel::Message;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOptionValue,
+};
+use serenity::model::interactions::message_component::{
+    ActionRowComponent, InputTextStyle, MessageComponentInteraction,
+};
+use serenity::model::interactions::modal::ModalSubmitInteraction;
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::model::user::User;
+use serenity::utils::Color;
+use tracing::{error, info, warn};
+
+use crate::i18n::{self, Locale};
+use crate::{
+    analytics, audit, bans, db, events, handlers, http, response, roles, settings, stats, success,
+    webhooks, SHARED_KEY, SQS_BECOME_VERIFIED_REQUEST_URL,
+};
+
+/// Custom id of the token modal
+pub const MODAL_ID: &str = "redeem";
+/// Custom id of the button that opens the token modal
+pub const OPEN_BUTTON_ID: &str = "redeem:open";
+/// Token files are a few hundred bytes, anything much larger isn't one
+const MAX_ATTACHMENT_BYTES: u64 = 16 * 1024;
+/// What the portal signs its verifications for, so no other signed value passes as one
+pub const PORTAL_PURPOSE: &str = "portal-verification";
+/// How long a signed portal verification can be handed over after it was signed
+const CALLBACK_VALIDITY_SECS: i64 = 5 * 60;
+/// How far ahead of the bot's clock the verification server's may run
+const CLOCK_SKEW_SECS: i64 = 5 * 60;
+
+/// A token the portal's user redeemed, signed by the portal with `SHARED_KEY`
+#[derive(Deserialize)]
+struct PortalVerification {
+    /// a string, since JavaScript numbers can't hold every snowflake
+    discord_id: String,
+    token: String,
+    signed_at: i64,
+    /// the `events` ticket the member arrived with, counted once they're linked
+    #[serde(default)]
+    event: Option<String>,
+}
+
+/// Reduces pasted input to the bare token: drops the link it was sent in, quotes and any
+/// whitespace or line breaks inserted along the way
+fn normalize(input: &str) -> String {
+    let token = input.rsplit('#').next().unwrap_or(input);
+    token
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '"' | '\'' | '`'))
+        .collect()
+}
+
+pub async fn redeem(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let options = &command.data.options;
+    let locale = i18n::locale(db_client, command.guild_id).await;
+    let input = if let Some(token) = handlers::option_str(options, "token") {
+        token.to_string()
+    } else if let Some(ApplicationCommandInteractionDataOptionValue::Attachment(file)) =
+        handlers::option(options, "file")
+    {
+        if file.size > MAX_ATTACHMENT_BYTES {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                i18n::text(locale, "redeem.file-too-large"),
+            )
+            .await;
+        }
+        match file.download().await.map(String::from_utf8) {
+            Ok(Ok(text)) => text,
+            _ => {
+                return response::respond_title(
+                    &ctx,
+                    &command,
+                    true,
+                    i18n::text(locale, "redeem.file-unreadable"),
+                )
+                .await
+            }
+        }
+    } else {
+        analytics::record(
+            db_client,
+            command.guild_id,
+            command.user.id,
+            analytics::Stage::ModalOpened,
+        )
+        .await;
+        return show_modal(&command, &ctx, locale).await;
+    };
+
+    analytics::record(
+        db_client,
+        command.guild_id,
+        command.user.id,
+        analytics::Stage::TokenSubmitted,
+    )
+    .await;
+    let result = link(
+        db_client,
+        &ctx.http,
+        command.guild_id,
+        command.user.id,
+        &input,
+    )
+    .await;
+    let welcome = guild_message(db_client, &ctx, command.guild_id, &result, &command.user).await;
+    response::respond_embed(&ctx, &command, true, |embed| {
+        result_embed(embed, locale, result, welcome)
+    })
+    .await
+}
+
+async fn show_modal(
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+    locale: Locale,
+) -> serenity::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::Modal)
+                .interaction_response_data(|modal| token_modal(modal, locale))
+        })
+        .await
+}
+
+/// Opens the token form from the button `/verify-beta` shows after sending the email
+pub async fn opened(
+    db_client: &'static db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    analytics::record(
+        db_client,
+        component.guild_id,
+        component.user.id,
+        analytics::Stage::ModalOpened,
+    )
+    .await;
+    let locale = i18n::locale(db_client, component.guild_id).await;
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::Modal)
+                .interaction_response_data(|modal| token_modal(modal, locale))
+        })
+        .await
+}
+
+fn token_modal(
+    modal: &mut CreateInteractionResponseData,
+    locale: Locale,
+) -> &mut CreateInteractionResponseData {
+    modal
+        .custom_id(MODAL_ID)
+        .title(i18n::text(locale, "redeem.modal.title"))
+        .components(|components| {
+            components.create_action_row(|row| {
+                row.create_input_text(|input| {
+                    input
+                        .custom_id("token")
+                        .label(i18n::text(locale, "redeem.modal.label"))
+                        .style(InputTextStyle::Paragraph)
+                        .required(true)
+                })
+            })
+        })
+}
+
+/// Handles a submitted token modal
+pub async fn submitted(
+    db_client: &'static db::DynamoDB,
+    modal: ModalSubmitInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let input = modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(text) if text.custom_id == "token" => {
+                Some(text.value.clone())
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+    analytics::record(
+        db_client,
+        modal.guild_id,
+        modal.user.id,
+        analytics::Stage::TokenSubmitted,
+    )
+    .await;
+    let locale = i18n::locale(db_client, modal.guild_id).await;
+    let result = link(db_client, &ctx.http, modal.guild_id, modal.user.id, &input).await;
+    let welcome = guild_message(db_client, &ctx, modal.guild_id, &result, &modal.user).await;
+    modal
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| result_embed(embed, locale, result, welcome))
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+/// Redeems a token sent by DM, see `dm`, replying with the outcome. Returns whether the
+/// conversation is over, either verified or already verified.
+pub async fn redeem_message(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    message: &Message,
+) -> serenity::Result<bool> {
+    let result = link(
+        db_client,
+        &ctx.http,
+        None,
+        message.author.id,
+        &message.content,
+    )
+    .await;
+    let done = matches!(result, Outcome::Linked | Outcome::AlreadyLinked);
+    message
+        .channel_id
+        .send_message(&ctx.http, |reply| {
+            reply.embed(|embed| result_embed(embed, Locale::En, result, None))
+        })
+        .await?;
+    Ok(done)
+}
+
+/// `POST /verify`, with a [`PortalVerification`] signed like tokens as the body. Links the
+/// account right away and queues the role and nickname update in every guild.
+pub async fn callback(
+    body: String,
+    Extension(state): Extension<http::State>,
+) -> (StatusCode, Json<Value>) {
+    let verification: PortalVerification =
+        match utv_token::verify(PORTAL_PURPOSE, body.trim(), &SHARED_KEY) {
+            Ok(verification) => verification,
+            Err(_) => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "status": "bad-signature" })),
+                )
+            }
+        };
+    let now = response::unix_now();
+    if (now - verification.signed_at).abs() > CALLBACK_VALIDITY_SECS {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "status": "stale" })));
+    }
+    let discord_id = match verification.discord_id.parse() {
+        Ok(discord_id) => UserId(discord_id),
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "status": "bad-request" })),
+            )
+        }
+    };
+    let outcome = link(
+        state.db_client,
+        &state.http,
+        None,
+        discord_id,
+        &verification.token,
+    )
+    .await;
+    if let (Outcome::Linked, Some(ticket)) = (&outcome, &verification.event) {
+        events::attribute(state.db_client, ticket).await;
+    }
+    let (code, status) = match outcome {
+        Outcome::Linked => (StatusCode::OK, "linked"),
+        Outcome::AlreadyLinked => (StatusCode::CONFLICT, "already-linked"),
+        Outcome::EidInUse => (StatusCode::CONFLICT, "eid-in-use"),
+        Outcome::InvalidToken => (StatusCode::BAD_REQUEST, "invalid-token"),
+        Outcome::ExpiredToken => (StatusCode::BAD_REQUEST, "expired-token"),
+        Outcome::ReplayedToken => (StatusCode::CONFLICT, "replayed-token"),
+        Outcome::Banned => (StatusCode::FORBIDDEN, "banned"),
+        Outcome::Failed => (StatusCode::INTERNAL_SERVER_ERROR, "failed"),
+    };
+    (code, Json(json!({ "status": status })))
+}
+
+pub enum Outcome {
+    Linked,
+    AlreadyLinked,
+    InvalidToken,
+    ExpiredToken,
+    /// another account already redeemed the token
+    ReplayedToken,
+    EidInUse,
+    /// the EID's account was banned from the guild it was redeemed in
+    Banned,
+    Failed,
+}
+
+/// Links the account to the token's EID. Redeemed in a guild, an EID banned there is refused,
+/// see `bans`.
+async fn link(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: Option<GuildId>,
+    discord_id: UserId,
+    input: &str,
+) -> Outcome {
+    let token = normalize(input);
+    let claims = match utv_token::decode_token(&token, &SHARED_KEY) {
+        Ok(claims) => claims,
+        Err(_) => return Outcome::InvalidToken,
+    };
+    let now = response::unix_now();
+    if claims
+        .issued_at
+        .map_or(false, |at| at > now + CLOCK_SKEW_SECS)
+    {
+        return Outcome::InvalidToken;
+    }
+    let max_age = settings::token_max_age_hours().unwrap_or(24) * 60 * 60;
+    let refused_at = match (claims.issued_at, claims.expires_at) {
+        (Some(issued_at), Some(expires_at)) => (issued_at + max_age).min(expires_at),
+        (Some(issued_at), None) => issued_at + max_age,
+        (None, Some(expires_at)) => expires_at,
+        (None, None) => return Outcome::ExpiredToken,
+    };
+    if refused_at <= now {
+        return Outcome::ExpiredToken;
+    }
+    let nonce = match &claims.nonce {
+        Some(nonce) => base64::encode(nonce),
+        None => db::sha256_hex(token.as_bytes()),
+    };
+    match db_client.use_token(&nonce, discord_id, refused_at).await {
+        db::TokenUse::Unused => {}
+        db::TokenUse::UsedByOther => {
+            warn!(
+                "{} redeemed a token already redeemed by another account",
+                discord_id
+            );
+            return Outcome::ReplayedToken;
+        }
+        db::TokenUse::Failed => return Outcome::Failed,
+    }
+    link_claims(
+        db_client,
+        http,
+        guild_id,
+        discord_id,
+        &base64::encode(&claims.encrypted_eid),
+        db::Claims {
+            major: claims.major,
+            school: claims.school,
+            affiliation: claims.affiliation,
+            name: claims.name,
+        },
+    )
+    .await
+}
+
+/// Links the account to an EID that was proven some way, by a token or by logging in with
+/// `sso`, and queues the update of its roles and nicknames
+pub async fn link_claims(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: Option<GuildId>,
+    discord_id: UserId,
+    encrypted_eid: &str,
+    claims: db::Claims,
+) -> Outcome {
+    if let Some(guild_id) = guild_id {
+        if bans::evades(db_client, http, guild_id, discord_id, encrypted_eid).await {
+            return Outcome::Banned;
+        }
+    }
+    let linked = db_client
+        .users_by_encrypted_eid(&[encrypted_eid.to_string()])
+        .await;
+    if let Some(&previous) = linked.values().find(|user| **user != discord_id) {
+        if !settings::eid_takeover().unwrap_or(false) {
+            return Outcome::EidInUse;
+        }
+        if !take_over(db_client, http, previous, discord_id).await {
+            return Outcome::Failed;
+        }
+    }
+    let result = db_client
+        .link_user(discord_id, encrypted_eid, &claims)
+        .await;
+    match result {
+        db::LinkResult::Linked => {
+            announce(discord_id).await;
+            Outcome::Linked
+        }
+        db::LinkResult::AlreadyLinked => Outcome::AlreadyLinked,
+        db::LinkResult::EidInUse => Outcome::EidInUse,
+        db::LinkResult::Failed => Outcome::Failed,
+    }
+}
+
+/// Unlinks the account an EID is linked to so another can verify with it, unverifying it in
+/// every guild and telling moderators and the account. `false` when it couldn't be unlinked.
+async fn take_over(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    previous: UserId,
+    discord_id: UserId,
+) -> bool {
+    if matches!(
+        db_client.unlink_user(previous).await,
+        db::UnlinkResult::Failed
+    ) {
+        return false;
+    }
+    info!("EID of {} taken over by {}", previous, discord_id);
+    let reason = format!("<@{}> verified with the same EID", discord_id);
+    for guild_id in roles::unverify_everywhere(db_client, http, previous).await {
+        audit::record(
+            db_client,
+            guild_id,
+            discord_id,
+            "verification.takeover",
+            Some(previous),
+            format!("EID moved to {}", discord_id),
+        )
+        .await;
+        audit::post(
+            db_client,
+            http,
+            guild_id,
+            audit::Post {
+                action: "Verification taken over",
+                member: previous,
+                actor: Some(discord_id),
+                reason: reason.clone(),
+                failed: false,
+            },
+        )
+        .await;
+        stats::invalidate(guild_id);
+        webhooks::emit(db_client, guild_id, previous, webhooks::Event::Unverified).await;
+    }
+    let notice = "Your UT EID was used to verify another Discord account, so this account is no \
+                  longer verified. If that wasn't you, contact the admins of your servers.";
+    let sent = match previous.create_dm_channel(http).await {
+        Ok(dm) => dm.say(http, notice).await.map(|_| ()),
+        Err(why) => Err(why),
+    };
+    if let Err(why) = sent {
+        warn!("Cannot DM {} about the takeover: {}", previous, why);
+    }
+    true
+}
+
+/// Queues the same update the portal sends, so roles and nicknames are applied in every guild
+pub async fn announce(discord_id: UserId) {
+    let config = aws_config::load_from_env().await;
+    let sent = SqsClient::new(&config)
+        .send_message()
+        .queue_url(SQS_BECOME_VERIFIED_REQUEST_URL.as_str())
+        .message_body(json!({ "discord_id": discord_id.0.to_string() }).to_string())
+        .send()
+        .await;
+    if let Err(why) = sent {
+        error!("Failed to announce verification of {}: {}", discord_id, why);
+    }
+}
+
+/// The message the guild adds to the reply after a successful verification, see `success`
+async fn guild_message(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    outcome: &Outcome,
+    user: &User,
+) -> Option<String> {
+    match (outcome, guild_id) {
+        (Outcome::Linked, Some(guild_id)) => {
+            success::ephemeral_message(db_client, &ctx.http, guild_id, &user.name).await
+        }
+        _ => None,
+    }
+}
+
+fn result_embed(
+    embed: &mut CreateEmbed,
+    locale: Locale,
+    outcome: Outcome,
+    guild_message: Option<String>,
+) -> &mut CreateEmbed {
+    let red = Color::from_rgb(255, 0, 0);
+    let (title, description, color) = match outcome {
+        Outcome::Linked => (
+            "redeem.linked.title",
+            "redeem.linked",
+            Color::from_rgb(0, 255, 0),
+        ),
+        Outcome::AlreadyLinked => (
+            "redeem.already-linked.title",
+            "redeem.already-linked",
+            Color::from_rgb(191, 87, 0),
+        ),
+        Outcome::InvalidToken => ("redeem.invalid.title", "redeem.invalid", red),
+        Outcome::ExpiredToken => ("redeem.expired.title", "redeem.expired", red),
+        Outcome::ReplayedToken => ("redeem.replayed.title", "redeem.replayed", red),
+        Outcome::EidInUse => ("redeem.eid-in-use.title", "redeem.eid-in-use", red),
+        Outcome::Banned => ("redeem.banned.title", "redeem.banned", red),
+        Outcome::Failed => ("redeem.failed.title", "redeem.failed", red),
+    };
+    embed
+        .title(i18n::text(locale, title))
+        .description(i18n::text(locale, description))
+        .color(color);
+    // the guild's own message, in whatever language it wrote it
+    if let Some(message) = guild_message {
+        embed.field("\u{200b}", message, false);
+    }
+    embed
+}
@@ -0,0 +1,207 @@
+//! Helpers shared by every command for building interaction responses.
+//!
+//! Timestamps are rendered with Discord's `<t:unix:style>` markup so each client shows them in
+//! the reader's own locale and timezone, instead of us guessing a format server-side.
+
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::MessageComponentInteraction;
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+
+/// The display styles supported by Discord's timestamp markup
+#[derive(Clone, Copy, Debug)]
+pub enum TimestampStyle {
+    /// `16:20`
+    ShortTime,
+    /// `16:20:30`
+    LongTime,
+    /// `20/04/2021`
+    ShortDate,
+    /// `20 April 2021`
+    LongDate,
+    /// `20 April 2021 16:20`
+    ShortDateTime,
+    /// `Tuesday, 20 April 2021 16:20`
+    LongDateTime,
+    /// `2 months ago`, `in 5 minutes`
+    Relative,
+}
+
+impl TimestampStyle {
+    fn flag(self) -> char {
+        match self {
+            TimestampStyle::ShortTime => 't',
+            TimestampStyle::LongTime => 'T',
+            TimestampStyle::ShortDate => 'd',
+            TimestampStyle::LongDate => 'D',
+            TimestampStyle::ShortDateTime => 'f',
+            TimestampStyle::LongDateTime => 'F',
+            TimestampStyle::Relative => 'R',
+        }
+    }
+}
+
+/// Seconds since the unix epoch
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Formats a count with thousands separators, e.g. `8,000`
+pub fn count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Formats a unix timestamp (in seconds) using Discord's timestamp markup
+pub fn timestamp(unix: i64, style: TimestampStyle) -> String {
+    format!("<t:{}:{}>", unix, style.flag())
+}
+
+/// Absolute date and time followed by the relative form, e.g. `20 April 2021 16:20 (2 months ago)`
+pub fn datetime(unix: i64) -> String {
+    format!(
+        "{} ({})",
+        timestamp(unix, TimestampStyle::ShortDateTime),
+        timestamp(unix, TimestampStyle::Relative)
+    )
+}
+
+/// Embed field values are limited to this many characters
+pub const FIELD_LIMIT: usize = 1024;
+
+/// Joins lines into an embed field value, dropping trailing lines that do not fit
+pub fn field_lines(lines: &[String], placeholder: &str) -> String {
+    if lines.is_empty() {
+        return placeholder.to_string();
+    }
+    let mut value = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let more = format!("\n…and {} more", lines.len() - i);
+        let reserved = if i + 1 < lines.len() { more.len() } else { 0 };
+        if value.len() + line.len() + 1 + reserved > FIELD_LIMIT {
+            value.push_str(more.trim_start());
+            break;
+        }
+        value.push_str(line);
+        value.push('\n');
+    }
+    value.trim_end().to_string()
+}
+
+/// Responds to a command with a single embed, optionally only visible to the invoking user
+pub async fn respond_embed<F>(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    ephemeral: bool,
+    f: F,
+) -> serenity::Result<()>
+where
+    F: FnOnce(&mut CreateEmbed) -> &mut CreateEmbed,
+{
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.create_embed(f);
+                    if ephemeral {
+                        message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    }
+                    message
+                })
+        })
+        .await
+}
+
+/// Responds to a command with `value` as a private JSON attachment instead of an embed, for
+/// admin commands run with `format:json` by org scripts
+pub async fn respond_json(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    name: &str,
+    value: &impl Serialize,
+) -> serenity::Result<()> {
+    let data = serde_json::to_vec_pretty(value).map_err(serenity::Error::Json)?;
+    // interaction responses can't carry files, followups can
+    defer(ctx, command, true).await?;
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message.add_file(AttachmentType::Bytes {
+                data: Cow::from(data),
+                filename: format!("{}.json", name),
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Responds to a command with an embed containing only a title
+pub async fn respond_title(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    ephemeral: bool,
+    title: impl ToString,
+) -> serenity::Result<()> {
+    respond_embed(ctx, command, ephemeral, |embed| embed.title(title)).await
+}
+
+/// Responds to a button or select menu press with an embed containing only a title
+pub async fn respond_component_title(
+    ctx: &Context,
+    component: &MessageComponentInteraction,
+    ephemeral: bool,
+    title: impl ToString,
+) -> serenity::Result<()> {
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message.create_embed(|embed| embed.title(title));
+                    if ephemeral {
+                        message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    }
+                    message
+                })
+        })
+        .await
+}
+
+/// Acknowledges a command that needs more than the 3 seconds Discord allows for a response.
+/// The real response must then be sent as a followup message.
+pub async fn defer(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    ephemeral: bool,
+) -> serenity::Result<()> {
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    if ephemeral {
+                        message.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                    }
+                    message
+                })
+        })
+        .await
+}
@@ -0,0 +1,326 @@
+//! Moderator review of suspicious verifications, enabled with `/config review`.
+//!
+//! A verification matching one of the guild's rules is held: the member is treated as unverified
+//! there, without roles or ✓, and the verification is posted in the review channel with Approve
+//! and Deny buttons for moderators. Approving lets the member's roles and nickname through like
+//! any verification, denying keeps them out, and a later verification of the same EID is held
+//! again. Rules are a Discord account younger than a number of days, a directory name sharing
+//! no word with the member's name, and an EID denied before in the guild, which is always
+//! checked. Verifications that match no rule are let through and never checked again, and
+//! members who verified and joined before review was enabled are left alone.
+
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, UserId};
+use serenity::model::interactions::message_component::{ButtonStyle, MessageComponentInteraction};
+use serenity::model::interactions::InteractionResponseType;
+use serenity::utils::Color;
+use tracing::warn;
+
+use crate::{audit, cache, db, jobs, nickname_policy, response, stats};
+
+/// Custom id prefix of the review buttons, followed by `approve:` or `deny:` and the member's id
+pub const COMPONENT_PREFIX: &str = "review:";
+
+const DAY: i64 = 24 * 60 * 60;
+const PENDING: &str = "pending";
+const APPROVED: &str = "approved";
+const DENIED: &str = "denied";
+
+/// When verifications are held, part of the guild's config
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReviewConfig {
+    /// channel held verifications are posted in
+    pub channel: ChannelId,
+    /// accounts younger than this are held, none when 0
+    pub min_account_age_days: u32,
+    /// held when the directory name shares no word with the member's name
+    pub name_mismatch: bool,
+    /// verifications and joins before this are left alone
+    pub since: i64,
+}
+
+impl ReviewConfig {
+    pub fn describe(&self) -> String {
+        let mut rules = Vec::new();
+        if self.min_account_age_days > 0 {
+            rules.push(format!(
+                "from accounts younger than {} days",
+                self.min_account_age_days
+            ));
+        }
+        if self.name_mismatch {
+            rules.push("whose directory name doesn't match the member's name".to_string());
+        }
+        rules.push("of EIDs denied before".to_string());
+        format!(
+            "Verifications {} are held for review in <#{}>",
+            rules.join(", or "),
+            self.channel
+        )
+    }
+}
+
+/// Whether a verified member is held in their guild and treated as unverified. A verification
+/// matching the guild's rules is held and posted for review the first time it's seen.
+pub async fn hold(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    member: &Member,
+    claims: &db::Claims,
+) -> bool {
+    let guild_id = member.guild_id;
+    let config = match db_client.get_guild_config(guild_id).await.review {
+        Some(config) => config,
+        None => return false,
+    };
+    let record = match db_client.get_user_record(member.user.id).await {
+        Some(record) => record,
+        None => return false,
+    };
+    let eid_hash = match &record.encrypted_eid {
+        Some(encrypted_eid) => db::eid_hash(encrypted_eid),
+        None => return false,
+    };
+    match db_client.get_review(guild_id, member.user.id).await {
+        Some(review) if review.eid_hash == eid_hash => return review.decision != APPROVED,
+        // a verification of another EID is reviewed again
+        Some(_) => {}
+        None => {
+            let joined_at = member.joined_at.map_or(0, |j| j.timestamp());
+            if record.verified_at.unwrap_or(0) < config.since && joined_at < config.since {
+                return false;
+            }
+        }
+    }
+
+    let reasons = reasons(db_client, member, claims, &config, &eid_hash).await;
+    let review = db::Review {
+        guild_id,
+        user_id: member.user.id,
+        eid_hash,
+        decision: if reasons.is_empty() {
+            APPROVED
+        } else {
+            PENDING
+        }
+        .to_string(),
+        reasons,
+        requested_at: response::unix_now(),
+        decided_by: None,
+    };
+    // only the check that stores the review posts it
+    if !db_client.put_review(&review).await || review.reasons.is_empty() {
+        return !review.reasons.is_empty();
+    }
+    post(http, member, claims, &config, &review.reasons).await;
+    if let Ok(bot_id) = cache::bot_id(http).await {
+        audit::record(
+            db_client,
+            guild_id,
+            bot_id,
+            "review.hold",
+            Some(member.user.id),
+            review.reasons.join(", "),
+        )
+        .await;
+    }
+    true
+}
+
+/// The rules a verification matches
+async fn reasons(
+    db_client: &db::DynamoDB,
+    member: &Member,
+    claims: &db::Claims,
+    config: &ReviewConfig,
+    eid_hash: &str,
+) -> Vec<String> {
+    let mut reasons = Vec::new();
+    let account_age = response::unix_now() - member.user.id.created_at().timestamp();
+    if config.min_account_age_days > 0 && account_age < config.min_account_age_days as i64 * DAY {
+        reasons.push(format!("Discord account is {} days old", account_age / DAY));
+    }
+    if config.name_mismatch {
+        if let Some(name) = &claims.name {
+            if !names_match(name, &member.display_name(), &member.user.name) {
+                reasons.push("directory name doesn't match".to_string());
+            }
+        }
+    }
+    if db_client
+        .guild_reviews(member.guild_id)
+        .await
+        .iter()
+        .any(|r| r.eid_hash == eid_hash && r.decision == DENIED)
+    {
+        reasons.push("EID denied before".to_string());
+    }
+    reasons
+}
+
+/// Whether the member's nickname or username has a word of their directory name
+fn names_match(directory_name: &str, display_name: &str, username: &str) -> bool {
+    let words = |name: &str| {
+        nickname_policy::clean(name)
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|w| w.len() > 1)
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let own = [words(display_name), words(username)].concat();
+    words(directory_name)
+        .iter()
+        .any(|word| own.iter().any(|w| w.contains(word.as_str())))
+}
+
+async fn post(
+    http: &Http,
+    member: &Member,
+    claims: &db::Claims,
+    config: &ReviewConfig,
+    reasons: &[String],
+) {
+    let user_id = member.user.id;
+    let posted = config
+        .channel
+        .send_message(http, |message| {
+            message
+                .create_embed(|embed| {
+                    embed
+                        .title("Verification Held for Review")
+                        .description(format!(
+                            "<@{}> verified and matched the server's review rules. They're \
+                             treated as unverified until a moderator approves.",
+                            user_id
+                        ))
+                        .field("Reasons", reasons.join("\n"), false)
+                        .field(
+                            "Directory Name",
+                            claims.name.as_deref().unwrap_or("Unknown"),
+                            true,
+                        )
+                        .field("Member Name", member.display_name(), true)
+                        .field(
+                            "Affiliation",
+                            if claims.affiliation.is_empty() {
+                                "None".to_string()
+                            } else {
+                                claims.affiliation.join(", ")
+                            },
+                            true,
+                        )
+                        .field(
+                            "Account Created",
+                            response::timestamp(
+                                user_id.created_at().timestamp(),
+                                response::TimestampStyle::Relative,
+                            ),
+                            true,
+                        )
+                        .color(Color::from_rgb(255, 165, 0))
+                })
+                .components(|components| {
+                    components.create_action_row(|row| {
+                        row.create_button(|button| {
+                            button
+                                .custom_id(format!("{}approve:{}", COMPONENT_PREFIX, user_id))
+                                .label("Approve")
+                                .style(ButtonStyle::Success)
+                        })
+                        .create_button(|button| {
+                            button
+                                .custom_id(format!("{}deny:{}", COMPONENT_PREFIX, user_id))
+                                .label("Deny")
+                                .style(ButtonStyle::Danger)
+                        })
+                    })
+                })
+        })
+        .await;
+    if let Err(why) = posted {
+        warn!(
+            "Cannot post the review of {} in {}: {}",
+            user_id, config.channel, why
+        );
+    }
+}
+
+/// Handles the Approve and Deny buttons
+pub async fn pressed(
+    db_client: &db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    let guild_id = match component.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let is_moderator = component
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .map_or(false, |p| p.administrator() || p.kick_members());
+    if !is_moderator {
+        return response::respond_component_title(
+            &ctx,
+            &component,
+            true,
+            "You must be a moderator to review verifications.",
+        )
+        .await;
+    }
+    let (action, user_id) = match component.data.custom_id[COMPONENT_PREFIX.len()..]
+        .split_once(':')
+        .and_then(|(action, id)| Some((action, UserId(id.parse().ok()?))))
+    {
+        Some(parts) => parts,
+        None => return Ok(()),
+    };
+    let (decision, outcome) = match action {
+        "approve" => (APPROVED, "Approved"),
+        _ => (DENIED, "Denied"),
+    };
+    if !db_client
+        .decide_review(guild_id, user_id, decision, component.user.id)
+        .await
+    {
+        return response::respond_component_title(
+            &ctx,
+            &component,
+            true,
+            "This verification was already reviewed.",
+        )
+        .await;
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        component.user.id,
+        &format!("review.{}", action),
+        Some(user_id),
+        "",
+    )
+    .await;
+    if decision == APPROVED {
+        stats::invalidate(guild_id);
+        jobs.push(jobs::Job::Member { guild_id, user_id });
+    }
+
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    // the review's embed is kept, only the buttons are replaced by the outcome
+                    message
+                        .content(format!("{} by <@{}>", outcome, component.user.id))
+                        .components(|components| components)
+                })
+        })
+        .await
+}
@@ -0,0 +1,149 @@
+//! Attribution of role changes made outside the bot.
+//!
+//! Guild audit logs are polled for role updates touching bot-managed roles (the verified role
+//! and mapped roles) made by anyone but the bot. Each change is written to the audit ledger
+//! with the acting moderator, and the member is queued for reconciliation.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use serenity::http::Http;
+use serenity::model::id::{GuildId, UserId};
+use tracing::{error, warn};
+
+use crate::{audit, db, jobs, response, sharding};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// audit log action type of member role updates
+const MEMBER_ROLE_UPDATE: u8 = 25;
+const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+/// Polls every guild's audit log, starting from when the bot started
+pub async fn watch_loop(db_client: &'static db::DynamoDB, http: Arc<Http>, jobs: Arc<jobs::Queue>) {
+    let bot_id = match http.get_current_user().await {
+        Ok(user) => user.id,
+        Err(why) => {
+            error!(
+                "Role change attribution disabled, cannot fetch current user: {}",
+                why
+            );
+            return;
+        }
+    };
+    // entry ids are snowflakes, so this skips entries from before startup
+    let start = ((response::unix_now() * 1000 - DISCORD_EPOCH_MS) as u64) << 22;
+    let mut cursors: HashMap<GuildId, u64> = HashMap::new();
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let guilds = match sharding::guilds(&http).await {
+            Ok(guilds) => guilds,
+            Err(why) => {
+                warn!("Cannot list guilds to check role changes: {}", why);
+                continue;
+            }
+        };
+        for guild_id in guilds {
+            let cursor = *cursors.entry(guild_id).or_insert(start);
+            match poll(db_client, &http, &jobs, guild_id, cursor, bot_id).await {
+                Ok(newest) => {
+                    cursors.insert(guild_id, newest);
+                }
+                // usually a missing View Audit Log permission
+                Err(why) => warn!("Cannot read audit log of {}: {}", guild_id, why),
+            }
+        }
+    }
+}
+
+/// Handles the guild's role updates newer than `cursor`, returning the newest entry id seen.
+/// Only the latest 100 entries are read, so bursts larger than that between polls are missed.
+async fn poll(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    jobs: &jobs::Queue,
+    guild_id: GuildId,
+    cursor: u64,
+    bot_id: UserId,
+) -> serenity::Result<u64> {
+    let logs = guild_id
+        .audit_logs(http, Some(MEMBER_ROLE_UPDATE), None, None, Some(100))
+        .await?;
+    let mut entries = logs
+        .entries
+        .into_values()
+        .filter(|e| e.id.0 > cursor)
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|e| e.id);
+    let newest = match entries.last() {
+        Some(entry) => entry.id.0,
+        None => return Ok(cursor),
+    };
+
+    let mapped: HashSet<u64> = db_client
+        .get_role_config(guild_id)
+        .await
+        .into_values()
+        .collect();
+    let config = db_client.get_guild_config(guild_id).await;
+    for entry in entries.iter().filter(|e| e.user_id != bot_id) {
+        let target = match entry.target_id {
+            Some(target) => UserId(target),
+            None => continue,
+        };
+        let mut touched = false;
+        for change in entry.changes.iter().flatten() {
+            let action = match change.name.as_str() {
+                "$add" => "role.external_add",
+                "$remove" => "role.external_remove",
+                _ => continue,
+            };
+            // role changes list the affected roles as {"id": "...", "name": "..."}
+            let changed_roles = change.new.as_ref().and_then(Value::as_array);
+            for role in changed_roles.into_iter().flatten() {
+                let id: u64 = match role["id"].as_str().and_then(|id| id.parse().ok()) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                if role["name"].as_str() != Some(config.verified_role_name())
+                    && !mapped.contains(&id)
+                {
+                    continue;
+                }
+                let unverified =
+                    action == "role.external_add" && !db_client.is_verified(target.0).await;
+                audit::record(
+                    db_client,
+                    guild_id,
+                    entry.user_id,
+                    action,
+                    Some(target),
+                    format!(
+                        "<@&{}>{}{}",
+                        id,
+                        if unverified {
+                            " (member is not verified)"
+                        } else {
+                            ""
+                        },
+                        entry
+                            .reason
+                            .as_ref()
+                            .map(|r| format!(", reason: {}", r))
+                            .unwrap_or_default()
+                    ),
+                )
+                .await;
+                touched = true;
+            }
+        }
+        if touched {
+            jobs.push(jobs::Job::Member {
+                guild_id,
+                user_id: target,
+            });
+        }
+    }
+    Ok(newest)
+}
@@ -0,0 +1,831 @@
+//! The verified role's position, and reconciliation of duplicate roles.
+//!
+//! The verified role is kept just below the bot's highest role: roles low in the list get
+//! buried in the member list when hoisted, and a role an admin moves above the bot can no longer
+//! be assigned. It's checked when the bot joins, when the role changes and every
+//! [`POSITION_INTERVAL`].
+//!
+//! Older servers often end up with several "UTexas Verified" roles (created by previous bot
+//! instances or copied by hand), or duplicates of mapped affiliation/major roles. `/merge-roles`
+//! finds them and lets an admin pick the canonical role; members are moved onto it and the
+//! duplicates are deleted or left alone.
+//!
+//! Roles changed by hand in Discord are repaired from their role events: a deleted verified
+//! role is recreated as it was, and a renamed one is followed by saving its new name. Changes
+//! the bot makes itself are marked with [`expect_change`] first and left alone.
+//!
+//! The bot only creates a role while holding the guild's [`creation_lock`] and after checking
+//! again that it's still missing, so events arriving together can't create it twice. Copies
+//! made before that, identical to the verified or Unverified role down to their colour and
+//! permissions, are merged into it with the periodic position check: their members are moved
+//! onto the role and the copies deleted. Duplicates that differ are left to `/merge-roles`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::guild::Role;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::MessageComponentInteraction;
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+use tracing::{error, warn};
+
+use crate::{
+    audit, cache, config, db, handlers, members, quarantine, ratelimits, response, sharding,
+    snapshots, telemetry,
+};
+
+/// The verified role's name unless the guild renamed it with `/config role`
+pub const VERIFIED_ROLE_NAME: &str = "UTexas Verified";
+
+const POSITION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Custom id prefix of the merge select menus, followed by `delete` or `keep`
+pub const COMPONENT_PREFIX: &str = "merge-roles:";
+
+lazy_static! {
+    static ref EXPECTED: Mutex<HashSet<RoleId>> = Mutex::new(HashSet::new());
+    static ref CREATING: Mutex<HashMap<GuildId, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Held while checking for and creating one of the guild's roles
+pub async fn creation_lock(guild_id: GuildId) -> tokio::sync::OwnedMutexGuard<()> {
+    let lock = CREATING
+        .lock()
+        .unwrap()
+        .entry(guild_id)
+        .or_default()
+        .clone();
+    lock.lock_owned().await
+}
+
+/// The guild's verified role; with duplicates, the oldest one
+pub async fn verified_role(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<Option<RoleId>> {
+    let config = db_client.get_guild_config(guild_id).await;
+    Ok(find_verified(
+        &cache::roles(http, guild_id).await?,
+        config.verified_role_name(),
+    ))
+}
+
+/// The oldest of the roles with the verified role's name
+pub fn find_verified(roles: &HashMap<RoleId, Role>, name: &str) -> Option<RoleId> {
+    roles
+        .values()
+        .filter(|r| r.name == name)
+        .map(|r| r.id)
+        .min()
+}
+
+/// The roles mapped to the member's affiliations, major and school that they don't have yet
+pub fn mapped_roles(
+    claims: &db::Claims,
+    role_mappings: &HashMap<String, u64>,
+    has: &[RoleId],
+) -> Vec<RoleId> {
+    let mut roles = Vec::new();
+    let tags = claims
+        .affiliation
+        .iter()
+        .chain(&claims.major)
+        .chain(&claims.school);
+    for role in tags
+        .filter_map(|tag| role_mappings.get(tag))
+        .map(|r| RoleId(*r))
+    {
+        if !has.contains(&role) && !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+    roles
+}
+
+/// Takes the verified role off a user in every guild, returning the guilds they're a member of
+pub async fn unverify_everywhere(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    user_id: UserId,
+) -> Vec<GuildId> {
+    let mut member_of = Vec::new();
+    for member in members::mutual(db_client, http, user_id).await {
+        let guild_id = member.guild_id;
+        if let Ok(Some(role_id)) = verified_role(db_client, http, guild_id).await {
+            if member.roles.contains(&role_id) {
+                if let Err(why) = http
+                    .remove_member_role(guild_id.0, user_id.0, role_id.0)
+                    .await
+                {
+                    telemetry::count(telemetry::Counter::DiscordErrors, "remove_role", 1);
+                    error!(
+                        "Failed to remove the verified role of {} in {}: {}",
+                        user_id, guild_id, why
+                    );
+                }
+            }
+        }
+        member_of.push(guild_id);
+    }
+    member_of
+}
+
+/// Moves the verified role just below the bot's highest role, returning whether it was moved
+pub async fn position_verified_role(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<bool> {
+    let role_id = match verified_role(db_client, http, guild_id).await? {
+        Some(role_id) => role_id,
+        None => return Ok(false),
+    };
+    let guild_roles = cache::roles(http, guild_id).await?;
+    let bot_id = cache::bot_id(http).await?;
+    let top = guild_id
+        .member(http, bot_id)
+        .await?
+        .roles
+        .iter()
+        .filter_map(|r| guild_roles.get(r))
+        .map(|r| r.position)
+        .max();
+    let (top, current) = match (top, guild_roles.get(&role_id)) {
+        (Some(top), Some(role)) => (top, role.position),
+        _ => return Ok(false),
+    };
+    if current >= top {
+        // the bot can't move roles above its own either
+        error!(
+            "The verified role of {} is above the bot's highest role and can't be assigned",
+            guild_id
+        );
+        return Ok(false);
+    }
+    if current == top - 1 {
+        return Ok(false);
+    }
+    guild_id
+        .edit_role_position(http, role_id, (top - 1) as u64)
+        .await?;
+    audit::record(
+        db_client,
+        guild_id,
+        bot_id,
+        "role.position",
+        None,
+        format!("<@&{}> moved just below the bot's highest role", role_id),
+    )
+    .await;
+    Ok(true)
+}
+
+/// Puts back verified roles that admins moved
+pub async fn position_loop(db_client: &'static db::DynamoDB, http: Arc<Http>) {
+    loop {
+        tokio::time::sleep(POSITION_INTERVAL).await;
+        let guilds = match sharding::guilds(&http).await {
+            Ok(guilds) => guilds,
+            Err(why) => {
+                warn!("Cannot list guilds to position verified roles: {}", why);
+                continue;
+            }
+        };
+        for guild_id in guilds {
+            if let Err(why) = reconcile_duplicates(db_client, &http, guild_id).await {
+                warn!("Cannot merge the duplicate roles of {}: {}", guild_id, why);
+            }
+            if let Err(why) = position_verified_role(db_client, &http, guild_id).await {
+                warn!("Cannot position the verified role of {}: {}", guild_id, why);
+            }
+        }
+    }
+}
+
+/// Roles named like the canonical one and identical to it, as the bot would have created them
+pub fn identical_duplicates(roles: &HashMap<RoleId, Role>, canonical: RoleId) -> Vec<RoleId> {
+    let canonical = match roles.get(&canonical) {
+        Some(canonical) => canonical,
+        None => return Vec::new(),
+    };
+    let mut duplicates = roles
+        .values()
+        .filter(|r| {
+            r.id != canonical.id
+                && !r.managed
+                && r.name == canonical.name
+                && r.colour == canonical.colour
+                && r.hoist == canonical.hoist
+                && r.mentionable == canonical.mentionable
+                && r.permissions == canonical.permissions
+        })
+        .map(|r| r.id)
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    duplicates
+}
+
+/// Merges identical copies of the verified and Unverified roles into them, returning how many
+pub async fn reconcile_duplicates(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<usize> {
+    let config = db_client.get_guild_config(guild_id).await;
+    let roles = cache::roles(http, guild_id).await?;
+    let canonicals = find_verified(&roles, config.verified_role_name())
+        .into_iter()
+        .chain(config.quarantine_role.filter(|_| config.quarantine));
+    let merges = canonicals
+        .map(|canonical| (canonical, identical_duplicates(&roles, canonical)))
+        .filter(|(_, duplicates)| !duplicates.is_empty())
+        .collect::<Vec<_>>();
+    if merges.is_empty() {
+        return Ok(0);
+    }
+    let members = members::fetch_all(http, guild_id).await?;
+    let bot_id = cache::bot_id(http).await?;
+    let mut merged = 0;
+    for (canonical, duplicates) in merges {
+        let mut moved = 0;
+        for member in &members {
+            if !member.roles.iter().any(|r| duplicates.contains(r)) {
+                continue;
+            }
+            let mut roles = member
+                .roles
+                .iter()
+                .filter(|r| !duplicates.contains(r))
+                .copied()
+                .collect::<Vec<_>>();
+            if !roles.contains(&canonical) {
+                roles.push(canonical);
+            }
+            if let Err(why) = guild_id
+                .edit_member(http, member.user.id, |m| m.roles(&roles))
+                .await
+            {
+                warn!(
+                    "Cannot move {} onto <@&{}> in {}: {}",
+                    member.user.id, canonical, guild_id, why
+                );
+                continue;
+            }
+            moved += 1;
+            // sleep to stay far away from rate limit
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        for duplicate in &duplicates {
+            db_client
+                .replace_mapped_role(guild_id, *duplicate, Some(canonical))
+                .await;
+            expect_change(*duplicate);
+            if let Err(why) = guild_id.delete_role(http, *duplicate).await {
+                warn!("Cannot delete duplicate role {}: {}", duplicate, why);
+                continue;
+            }
+            merged += 1;
+        }
+        audit::record(
+            db_client,
+            guild_id,
+            bot_id,
+            "roles.reconcile",
+            None,
+            format!(
+                "merged {} identical duplicates into <@&{}>, {} members moved",
+                duplicates.len(),
+                canonical,
+                moved
+            ),
+        )
+        .await;
+    }
+    cache::invalidate_roles(guild_id);
+    Ok(merged)
+}
+
+/// Marks a role the bot is about to delete or rename, so the event that follows isn't repaired
+pub fn expect_change(role_id: RoleId) {
+    EXPECTED.lock().unwrap().insert(role_id);
+}
+
+fn expected(role_id: RoleId) -> bool {
+    EXPECTED.lock().unwrap().remove(&role_id)
+}
+
+/// Repairs what relied on a role someone deleted, returning whether members need checking again.
+/// The verified role is recreated as it was and its mappings follow it, other mapped roles lose
+/// their mappings, and the Unverified role comes back while quarantine is on.
+pub async fn repair_deleted(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    role_id: RoleId,
+    deleted: Option<Role>,
+) -> bool {
+    if expected(role_id) {
+        return false;
+    }
+    let config = db_client.get_guild_config(guild_id).await;
+    if config.quarantine && config.quarantine_role == Some(role_id) {
+        return match quarantine::enable(db_client, http, guild_id).await {
+            Ok(recreated) => {
+                if let Some(channel) = config.quarantine_channel {
+                    if let Err(why) =
+                        quarantine::gate_channels(http, guild_id, recreated, channel).await
+                    {
+                        warn!("Cannot gate the channels of {}: {}", guild_id, why);
+                    }
+                }
+                record_repair(
+                    db_client,
+                    http,
+                    guild_id,
+                    format!("deleted Unverified role recreated as <@&{}>", recreated),
+                )
+                .await;
+                true
+            }
+            Err(why) => {
+                warn!(
+                    "Cannot recreate the Unverified role of {}: {}",
+                    guild_id, why
+                );
+                false
+            }
+        };
+    }
+    let mapped = db_client
+        .get_role_config(guild_id)
+        .await
+        .values()
+        .any(|r| *r == role_id.0);
+    // without the role as last fetched there's no telling it was the verified role
+    let deleted = match deleted.filter(|r| r.name == config.verified_role_name()) {
+        Some(deleted) => deleted,
+        None => {
+            if mapped && db_client.replace_mapped_role(guild_id, role_id, None).await {
+                record_repair(
+                    db_client,
+                    http,
+                    guild_id,
+                    format!("mappings to deleted role {} removed", role_id),
+                )
+                .await;
+            }
+            return false;
+        }
+    };
+    // a duplicate left behind is the verified role from now on
+    let creating = creation_lock(guild_id).await;
+    let replacement = match verified_role(db_client, http, guild_id).await {
+        Ok(Some(existing)) => Ok(existing),
+        Ok(None) => guild_id
+            .create_role(http, |role| {
+                role.name(&deleted.name)
+                    .colour(deleted.colour.0 as u64)
+                    .hoist(deleted.hoist)
+                    .mentionable(deleted.mentionable)
+                    .permissions(deleted.permissions)
+            })
+            .await
+            .map(|role| role.id),
+        Err(why) => Err(why),
+    };
+    let replacement = match replacement {
+        Ok(replacement) => replacement,
+        Err(why) => {
+            warn!("Cannot recreate the verified role of {}: {}", guild_id, why);
+            if mapped {
+                db_client.replace_mapped_role(guild_id, role_id, None).await;
+            }
+            return false;
+        }
+    };
+    cache::invalidate_roles(guild_id);
+    drop(creating);
+    if mapped {
+        db_client
+            .replace_mapped_role(guild_id, role_id, Some(replacement))
+            .await;
+    }
+    if let Err(why) = position_verified_role(db_client, http, guild_id).await {
+        warn!("Cannot position the verified role of {}: {}", guild_id, why);
+    }
+    record_repair(
+        db_client,
+        http,
+        guild_id,
+        format!("deleted verified role replaced by <@&{}>", replacement),
+    )
+    .await;
+    true
+}
+
+/// Keeps finding the verified role after someone renamed it by hand in Discord
+pub async fn follow_rename(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    before: Option<Role>,
+    after: &Role,
+) {
+    if expected(after.id) {
+        return;
+    }
+    let before = match before {
+        Some(before) if before.name != after.name => before,
+        _ => return,
+    };
+    let config = db_client.get_guild_config(guild_id).await;
+    if before.name != config.verified_role_name() {
+        return;
+    }
+    // with a duplicate still carrying the name, that one is the verified role now
+    let duplicated = cache::roles(http, guild_id)
+        .await
+        .map(|roles| roles.values().any(|r| r.name == before.name))
+        .unwrap_or(true);
+    if duplicated {
+        return;
+    }
+    let actor = match cache::bot_id(http).await {
+        Ok(bot_id) => bot_id,
+        Err(why) => {
+            warn!(
+                "Cannot follow the verified role renamed in {}: {}",
+                guild_id, why
+            );
+            return;
+        }
+    };
+    let followed = config::update(db_client, guild_id, actor, |config| {
+        config.verified_role_name =
+            Some(after.name.clone()).filter(|name| name != VERIFIED_ROLE_NAME);
+        Some(format!(
+            "<@&{}> renamed to {} in Discord, followed",
+            after.id, after.name
+        ))
+    })
+    .await;
+    if let Err(why) = followed {
+        error!(
+            "Failed to follow the verified role renamed in {}: {}",
+            guild_id, why
+        );
+    }
+}
+
+/// Records a repair in the audit ledger, with the bot as the actor
+async fn record_repair(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId, detail: String) {
+    if let Ok(bot_id) = cache::bot_id(http).await {
+        audit::record(db_client, guild_id, bot_id, "role.repair", None, detail).await;
+    }
+}
+
+/// Groups of roles sharing a name, where the name is the verified role's or one of the roles is
+/// used by the guild's role mappings
+async fn duplicate_groups(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    guild_id: GuildId,
+) -> serenity::Result<Vec<Vec<Role>>> {
+    let mapped: HashSet<u64> = db_client
+        .get_role_config(guild_id)
+        .await
+        .into_values()
+        .collect();
+    let config = db_client.get_guild_config(guild_id).await;
+    let mut by_name: HashMap<String, Vec<Role>> = HashMap::new();
+    for role in guild_id.roles(&ctx.http).await?.into_values() {
+        by_name.entry(role.name.clone()).or_default().push(role);
+    }
+    let mut groups = by_name
+        .into_iter()
+        .filter(|(name, roles)| {
+            roles.len() > 1
+                && (name == config.verified_role_name()
+                    || roles.iter().any(|r| mapped.contains(&r.id.0)))
+        })
+        .map(|(_, mut roles)| {
+            roles.sort_by_key(|r| r.id);
+            roles
+        })
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|roles| roles[0].id);
+    Ok(groups)
+}
+
+pub async fn merge_roles(
+    db_client: &'static db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let groups = duplicate_groups(db_client, &ctx, guild_id).await?;
+    let group = match groups.first() {
+        Some(group) => group,
+        None => {
+            return response::respond_title(&ctx, &command, true, "No duplicate roles found").await
+        }
+    };
+
+    // one group at a time; the command can be run again for the rest
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title(format!("{} roles named \"{}\"", group.len(), group[0].name))
+                                .description(format!(
+                                    "Pick the role to keep. Members with any of the others are \
+                                     moved onto it.{}",
+                                    if groups.len() > 1 {
+                                        format!(
+                                            "\n{} more groups of duplicates remain, run \
+                                             `/merge-roles` again afterwards.",
+                                            groups.len() - 1
+                                        )
+                                    } else {
+                                        String::new()
+                                    }
+                                ))
+                                .color(Color::from_rgb(191, 87, 0))
+                        })
+                        .components(|components| {
+                            for (mode, placeholder) in [
+                                ("delete", "Keep this role and delete the others"),
+                                ("keep", "Keep this role and ignore the others"),
+                            ] {
+                                components.create_action_row(|row| {
+                                    row.create_select_menu(|menu| {
+                                        menu.custom_id(format!("{}{}", COMPONENT_PREFIX, mode))
+                                            .placeholder(placeholder)
+                                            .options(|options| {
+                                                for role in group.iter().take(25) {
+                                                    options.create_option(|option| {
+                                                        option
+                                                            .label(format!(
+                                                                "{} ({})",
+                                                                role.name, role.id
+                                                            ))
+                                                            .value(role.id.to_string())
+                                                            .description(format!(
+                                                                "position {}, {}",
+                                                                role.position,
+                                                                if role.managed {
+                                                                    "managed"
+                                                                } else {
+                                                                    "unmanaged"
+                                                                }
+                                                            ))
+                                                    });
+                                                }
+                                                options
+                                            })
+                                    })
+                                });
+                            }
+                            components
+                        })
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+/// Handles the choice of canonical role from a merge select menu
+pub async fn merge_selected(
+    db_client: &'static db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let guild_id = match component.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let is_admin = component
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .map_or(false, |p| p.administrator());
+    if !is_admin {
+        return response::respond_component_title(
+            &ctx,
+            &component,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let canonical = component
+        .data
+        .values
+        .get(0)
+        .and_then(|v| v.parse().ok())
+        .map(RoleId);
+    let delete = component.data.custom_id.ends_with("delete");
+
+    let group = duplicate_groups(db_client, &ctx, guild_id)
+        .await?
+        .into_iter()
+        .find(|g| g.iter().any(|r| Some(r.id) == canonical));
+    let (canonical, duplicates) = match (canonical, group) {
+        (Some(canonical), Some(group)) => (
+            canonical,
+            group
+                .into_iter()
+                .map(|r| r.id)
+                .filter(|r| *r != canonical)
+                .collect::<Vec<_>>(),
+        ),
+        _ => {
+            return response::respond_component_title(
+                &ctx,
+                &component,
+                true,
+                "Those roles are no longer duplicated",
+            )
+            .await
+        }
+    };
+
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredUpdateMessage)
+        })
+        .await?;
+
+    let _job = ratelimits::job(guild_id, "merge-roles");
+    let affected = members::fetch_all(&ctx.http, guild_id)
+        .await?
+        .into_iter()
+        .filter(|m| m.roles.iter().any(|r| duplicates.contains(r)))
+        .collect::<Vec<_>>();
+    let job_id = match snapshots::take(db_client, guild_id, "merge-roles", &affected).await {
+        Some(job_id) => job_id,
+        None => {
+            component
+                .edit_original_interaction_response(&ctx.http, |message| {
+                    message
+                        .content("Could not snapshot members, so no roles were merged")
+                        .components(|components| components)
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut moved = 0;
+    for mut member in affected {
+        if !member.roles.contains(&canonical)
+            && member.add_role(&ctx.http, canonical).await.is_err()
+        {
+            error!("Failed to add merged role to {}", member.user.id);
+            continue;
+        }
+        if let Err(why) = member.remove_roles(&ctx.http, &duplicates).await {
+            error!(
+                "Failed to remove duplicate roles from {}: {}",
+                member.user.id, why
+            );
+        }
+        moved += 1;
+        // sleep to stay far away from rate limit
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    for duplicate in &duplicates {
+        db_client
+            .replace_mapped_role(guild_id, *duplicate, Some(canonical))
+            .await;
+        if delete {
+            expect_change(*duplicate);
+            if let Err(why) = guild_id.delete_role(&ctx.http, *duplicate).await {
+                error!("Failed to delete duplicate role {}: {}", duplicate, why);
+            }
+        }
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        component.user.id,
+        "roles.merge",
+        None,
+        format!(
+            "merged {} duplicates into <@&{}> and {} them, {} members moved ({})",
+            duplicates.len(),
+            canonical,
+            if delete { "deleted" } else { "kept" },
+            moved,
+            job_id
+        ),
+    )
+    .await;
+
+    component
+        .edit_original_interaction_response(&ctx.http, |message| {
+            message
+                .create_embed(|embed| {
+                    embed
+                        .title("Roles Merged")
+                        .description(format!(
+                            "Moved {} members onto <@&{}> and {} {} duplicate roles. \
+                             Undo the moves with `/admin rollback job-id:{}`.",
+                            moved,
+                            canonical,
+                            if delete { "deleted" } else { "kept" },
+                            duplicates.len(),
+                            job_id
+                        ))
+                        .color(Color::from_rgb(0, 255, 0))
+                })
+                .components(|components| components)
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::discord::mock::role;
+
+    #[test]
+    fn oldest_verified_role_is_found() {
+        let roles = [
+            role(7, VERIFIED_ROLE_NAME),
+            role(5, VERIFIED_ROLE_NAME),
+            role(3, "Student"),
+        ]
+        .into_iter()
+        .map(|r| (r.id, r))
+        .collect::<HashMap<_, _>>();
+        assert_eq!(find_verified(&roles, VERIFIED_ROLE_NAME), Some(RoleId(5)));
+        assert_eq!(find_verified(&roles, "Longhorn"), None);
+    }
+
+    #[test]
+    fn only_identical_copies_are_duplicates() {
+        let mut hoisted = role(9, VERIFIED_ROLE_NAME);
+        hoisted.hoist = true;
+        let roles = [
+            role(5, VERIFIED_ROLE_NAME),
+            role(8, VERIFIED_ROLE_NAME),
+            role(6, VERIFIED_ROLE_NAME),
+            hoisted,
+            role(7, "Student"),
+        ]
+        .into_iter()
+        .map(|r| (r.id, r))
+        .collect::<HashMap<_, _>>();
+        assert_eq!(
+            identical_duplicates(&roles, RoleId(5)),
+            vec![RoleId(6), RoleId(8)]
+        );
+        assert!(identical_duplicates(&roles, RoleId(7)).is_empty());
+        assert!(identical_duplicates(&roles, RoleId(1)).is_empty());
+    }
+
+    #[test]
+    fn claims_map_to_missing_roles() {
+        let claims = db::Claims {
+            major: vec!["ece".to_string()],
+            school: vec!["engineering".to_string()],
+            affiliation: vec!["student".to_string()],
+            name: None,
+        };
+        let mappings = HashMap::from([
+            ("student".to_string(), 20),
+            ("ece".to_string(), 22),
+            ("engineering".to_string(), 22),
+        ]);
+        assert_eq!(
+            mapped_roles(&claims, &mappings, &[RoleId(20)]),
+            vec![RoleId(22)]
+        );
+        assert!(mapped_roles(&claims, &HashMap::new(), &[]).is_empty());
+    }
+}
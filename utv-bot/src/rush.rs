@@ -0,0 +1,199 @@
+//! Rush mode: a time-boxed boost for a guild expecting a wave of verifications, e.g. during
+//! orientation week. Started with `/admin rush-mode`.
+//!
+//! While it lasts, the guild's nickname edits get a larger bucket (see `nicknames`), its caches
+//! are warmed up front, and a progress message in the chosen channel is kept up to date. Only
+//! [`MAX_RUSHING`] guilds can rush at once, so together they stay within Discord's global rate
+//! limit. Rush mode ends on its own through a scheduled task; the progress message doesn't
+//! survive a restart, the boost does.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId};
+use tracing::warn;
+
+use crate::response::{self, TimestampStyle};
+use crate::{db, scheduler, stats};
+
+pub const MAX_HOURS: i64 = 72;
+/// guilds that may rush at the same time
+const MAX_RUSHING: usize = 3;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(60);
+const TASK_PREFIX: &str = "rush:";
+
+struct Rush {
+    ends_at: i64,
+    /// members verified since rush mode started
+    verified: u64,
+}
+
+lazy_static! {
+    static ref RUSHING: Mutex<HashMap<GuildId, Rush>> = Mutex::new(HashMap::new());
+}
+
+/// Whether the guild is rushing right now
+pub fn active(guild_id: GuildId) -> bool {
+    RUSHING
+        .lock()
+        .unwrap()
+        .get(&guild_id)
+        .map_or(false, |rush| rush.ends_at > response::unix_now())
+}
+
+/// Counts a verification towards the guild's progress, if it's rushing
+pub fn record_verification(guild_id: GuildId) {
+    if let Some(rush) = RUSHING.lock().unwrap().get_mut(&guild_id) {
+        if rush.ends_at > response::unix_now() {
+            rush.verified += 1;
+        }
+    }
+}
+
+/// Starts or extends rush mode until `ends_at`, posting progress in `channel`
+pub async fn start(
+    db_client: &'static db::DynamoDB,
+    http: Arc<Http>,
+    guild_id: GuildId,
+    ends_at: i64,
+    channel: ChannelId,
+) -> Result<(), String> {
+    let extended = {
+        let mut rushing = RUSHING.lock().unwrap();
+        let now = response::unix_now();
+        rushing.retain(|_, rush| rush.ends_at > now);
+        match rushing.get_mut(&guild_id) {
+            Some(rush) => {
+                rush.ends_at = ends_at;
+                true
+            }
+            None if rushing.len() >= MAX_RUSHING => {
+                return Err(
+                    "Too many servers are in rush mode right now, please try again later"
+                        .to_string(),
+                )
+            }
+            None => {
+                rushing.insert(
+                    guild_id,
+                    Rush {
+                        ends_at,
+                        verified: 0,
+                    },
+                );
+                false
+            }
+        }
+    };
+    let prefix = format!("{}{}:", TASK_PREFIX, guild_id);
+    for task in db_client.scheduled_with_prefix(&prefix).await {
+        db_client.delete_scheduled(&task.task_id).await;
+    }
+    scheduler::schedule(
+        db_client,
+        format!("{}{}", prefix, ends_at),
+        ends_at,
+        &scheduler::Task::EndRush { guild_id },
+    )
+    .await;
+    // warm the member count, which the first wave of `/help` and progress updates need
+    if let Err(why) = stats::guild_stats(db_client, &http, guild_id).await {
+        warn!("Cannot warm up stats of {}: {}", guild_id, why);
+    }
+    // the progress message posted when it started keeps going
+    if !extended {
+        tokio::spawn(progress_loop(db_client, http, guild_id, channel));
+    }
+    Ok(())
+}
+
+/// Ends rush mode early or when its time is up. The guild's count stays around for the final
+/// progress update.
+pub fn end(guild_id: GuildId) {
+    if let Some(rush) = RUSHING.lock().unwrap().get_mut(&guild_id) {
+        rush.ends_at = rush.ends_at.min(response::unix_now());
+    }
+}
+
+/// Picks rush mode back up after a restart from the scheduled tasks ending it
+pub async fn restore(db_client: &db::DynamoDB) {
+    let now = response::unix_now();
+    let tasks = db_client.scheduled_with_prefix(TASK_PREFIX).await;
+    let mut rushing = RUSHING.lock().unwrap();
+    for task in tasks {
+        let guild_id = task.task_id[TASK_PREFIX.len()..]
+            .split(':')
+            .next()
+            .and_then(|id| id.parse().ok())
+            .map(GuildId);
+        if let (Some(guild_id), true) = (guild_id, task.due_at > now) {
+            rushing.insert(
+                guild_id,
+                Rush {
+                    ends_at: task.due_at,
+                    verified: 0,
+                },
+            );
+        }
+    }
+}
+
+/// Keeps a progress message in `channel` up to date until rush mode ends
+async fn progress_loop(
+    db_client: &db::DynamoDB,
+    http: Arc<Http>,
+    guild_id: GuildId,
+    channel: ChannelId,
+) {
+    let mut message = match channel
+        .say(&http, progress(db_client, &http, guild_id).await)
+        .await
+    {
+        Ok(message) => message,
+        Err(why) => {
+            warn!("Cannot post rush mode progress in {}: {}", channel, why);
+            return;
+        }
+    };
+    loop {
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
+        let running = active(guild_id);
+        let content = progress(db_client, &http, guild_id).await;
+        if let Err(why) = message.edit(&http, |m| m.content(content)).await {
+            warn!("Cannot update rush mode progress in {}: {}", channel, why);
+            return;
+        }
+        if !running {
+            return;
+        }
+    }
+}
+
+async fn progress(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId) -> String {
+    let (ends_at, verified) = match RUSHING.lock().unwrap().get(&guild_id) {
+        Some(rush) => (Some(rush.ends_at), rush.verified),
+        None => (None, 0),
+    };
+    let total = match stats::guild_stats(db_client, http, guild_id).await {
+        Ok(stats) => format!(
+            " {} of {} members are verified.",
+            stats.verified, stats.members
+        ),
+        Err(_) => String::new(),
+    };
+    match ends_at {
+        Some(ends_at) if ends_at > response::unix_now() => format!(
+            "🏃 **Rush mode** until {}: {} members verified so far.{}",
+            response::timestamp(ends_at, TimestampStyle::ShortDateTime),
+            verified,
+            total
+        ),
+        _ => format!(
+            "Rush mode has ended, {} members verified during it.{}",
+            verified, total
+        ),
+    }
+}
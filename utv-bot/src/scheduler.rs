@@ -0,0 +1,146 @@
+//! One-off tasks that run at a later time, persisted so they survive restarts.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::http::Http;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use tracing::error;
+
+use crate::{audit, config, db, jobs, membership, onboarding, recheck, response, rush};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind")]
+pub enum Task {
+    /// Removes an expired guest pass
+    RevokeGuest {
+        guild_id: GuildId,
+        user_id: UserId,
+        role_id: RoleId,
+    },
+    /// Ends a guild's nickname decoration and re-checks its members to take it off
+    EndDecoration { guild_id: GuildId },
+    /// Sends a sample of linked users to be re-checked against the directory, see `recheck`
+    EidRecheck,
+    /// Queues a guild's dues roster sync and schedules the next one, see `membership`
+    DuesSync { guild_id: GuildId },
+    /// Ends a guild's rush mode, see `rush`
+    EndRush { guild_id: GuildId },
+    /// Reminds a member who hasn't verified yet, see `onboarding`
+    VerifyReminder {
+        guild_id: GuildId,
+        user_id: UserId,
+        /// unix time the member was seen joining, which reminders are counted from
+        first_seen: i64,
+        /// reminders sent before this one
+        sent: usize,
+    },
+}
+
+/// Schedules `task` to run at `due_at`, replacing the task previously scheduled under `task_id`
+pub async fn schedule(db_client: &db::DynamoDB, task_id: String, due_at: i64, task: &Task) -> bool {
+    let task = match serde_json::to_string(task) {
+        Ok(task) => task,
+        Err(_) => return false,
+    };
+    db_client
+        .put_scheduled(&db::ScheduledTask {
+            task_id,
+            due_at,
+            task,
+        })
+        .await
+}
+
+/// Runs due tasks, removing each one after it ran
+pub async fn run_loop(db_client: &'static db::DynamoDB, http: Arc<Http>, jobs: Arc<jobs::Queue>) {
+    let bot_id = match http.get_current_user().await {
+        Ok(user) => user.id,
+        Err(why) => {
+            error!("Scheduler disabled, cannot fetch current user: {}", why);
+            return;
+        }
+    };
+    loop {
+        for scheduled in db_client.due_scheduled(response::unix_now()).await {
+            match serde_json::from_str(&scheduled.task) {
+                Ok(task) => run(db_client, &http, &jobs, bot_id, task).await,
+                Err(why) => error!("Dropping unreadable task {:?}: {}", scheduled, why),
+            }
+            db_client.delete_scheduled(&scheduled.task_id).await;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn run(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    jobs: &jobs::Queue,
+    bot_id: UserId,
+    task: Task,
+) {
+    match task {
+        Task::RevokeGuest {
+            guild_id,
+            user_id,
+            role_id,
+        } => {
+            if let Err(why) = http
+                .remove_member_role(guild_id.0, user_id.0, role_id.0)
+                .await
+            {
+                error!("Failed to remove guest role from {}: {}", user_id, why);
+            }
+            audit::record(
+                db_client,
+                guild_id,
+                bot_id,
+                "guest.expire",
+                Some(user_id),
+                format!("<@&{}>", role_id),
+            )
+            .await;
+        }
+        Task::EndDecoration { guild_id } => {
+            let ended = config::update(db_client, guild_id, bot_id, |config| {
+                // a decoration set again since this was scheduled has its own task
+                match &config.decoration {
+                    Some(d) if d.ends_at <= response::unix_now() => {
+                        config.decoration = None;
+                        Some("decoration ended".to_string())
+                    }
+                    _ => None,
+                }
+            })
+            .await
+            .is_ok();
+            if ended {
+                jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+            }
+        }
+        Task::EidRecheck => recheck::request(db_client).await,
+        Task::EndRush { guild_id } => rush::end(guild_id),
+        Task::VerifyReminder {
+            guild_id,
+            user_id,
+            first_seen,
+            sent,
+        } => onboarding::remind(db_client, http, guild_id, user_id, first_seen, sent).await,
+        Task::DuesSync { guild_id } => {
+            // the chain ends once the guild turns dues off
+            if db_client.get_guild_config(guild_id).await.dues.is_some() {
+                jobs.push(jobs::Job::DuesSync { guild_id });
+                membership::schedule(
+                    db_client,
+                    guild_id,
+                    response::unix_now() + membership::SYNC_INTERVAL_SECS,
+                )
+                .await;
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! `/roles`, with which verified members pick roles like their courses or major.
+//!
+//! Admins choose the roles with `/config selfrole add` and `/config selfrole remove`. `/roles`
+//! shows them in a select menu with the member's current ones picked, and submitting it adds the
+//! newly picked roles and removes the others, leaving every role that isn't self-assignable
+//! alone. Only verified members may use it, which is checked again when the menu is submitted.
+
+use serenity::client::Context;
+use serenity::model::id::{GuildId, RoleId};
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::MessageComponentInteraction;
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+use tracing::warn;
+
+use crate::{cache, db, i18n, response};
+
+pub const COMPONENT_ID: &str = "self-roles";
+
+pub async fn roles_command(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let config = db_client.get_guild_config(guild_id).await;
+    let locale = config.locale;
+    if !db_client.is_verified(command.user.id.0).await {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            i18n::text(locale, "roles.not-verified"),
+        )
+        .await;
+    }
+    let guild_roles = cache::roles(&ctx.http, guild_id).await?;
+    // roles deleted since they were added are skipped
+    let roles = config
+        .self_roles
+        .iter()
+        .filter_map(|role| guild_roles.get(role))
+        .collect::<Vec<_>>();
+    if roles.is_empty() {
+        return response::respond_title(&ctx, &command, true, i18n::text(locale, "roles.none"))
+            .await;
+    }
+    let current = command
+        .member
+        .as_ref()
+        .map(|member| member.roles.clone())
+        .unwrap_or_default();
+
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title(i18n::text(locale, "roles.title"))
+                                .description(i18n::text(locale, "roles.description"))
+                                .color(Color::from_rgb(191, 87, 0))
+                        })
+                        .components(|components| {
+                            components.create_action_row(|row| {
+                                row.create_select_menu(|menu| {
+                                    menu.custom_id(COMPONENT_ID)
+                                        .placeholder(i18n::text(locale, "roles.placeholder"))
+                                        .min_values(0)
+                                        .max_values(roles.len() as u64)
+                                        .options(|options| {
+                                            for role in &roles {
+                                                options.create_option(|option| {
+                                                    option
+                                                        .label(&role.name)
+                                                        .value(role.id.to_string())
+                                                        .default_selection(
+                                                            current.contains(&role.id),
+                                                        )
+                                                });
+                                            }
+                                            options
+                                        })
+                                })
+                            })
+                        })
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
+
+/// Handles a submitted `/roles` menu
+pub async fn selected(
+    db_client: &db::DynamoDB,
+    component: MessageComponentInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let (guild_id, member) = match (component.guild_id, component.member.as_ref()) {
+        (Some(guild_id), Some(member)) => (guild_id, member),
+        _ => return Ok(()),
+    };
+    let config = db_client.get_guild_config(guild_id).await;
+    let locale = config.locale;
+    // the member may have unverified since the menu was shown
+    if !db_client.is_verified(member.user.id.0).await {
+        return response::respond_component_title(
+            &ctx,
+            &component,
+            true,
+            i18n::text(locale, "roles.not-verified"),
+        )
+        .await;
+    }
+    let picked = component
+        .data
+        .values
+        .iter()
+        .filter_map(|value| value.parse().ok().map(RoleId))
+        .filter(|role| config.self_roles.contains(role))
+        .collect::<Vec<_>>();
+
+    let (mut added, mut removed, mut failed) = (Vec::new(), Vec::new(), 0);
+    for role in &config.self_roles {
+        let has = member.roles.contains(role);
+        let result = match (picked.contains(role), has) {
+            (true, false) => ctx
+                .http
+                .add_member_role(guild_id.0, member.user.id.0, role.0)
+                .await
+                .map(|_| added.push(format!("<@&{}>", role))),
+            (false, true) => ctx
+                .http
+                .remove_member_role(guild_id.0, member.user.id.0, role.0)
+                .await
+                .map(|_| removed.push(format!("<@&{}>", role))),
+            _ => Ok(()),
+        };
+        if let Err(why) = result {
+            warn!(
+                "Cannot change self-assigned role {} of {}: {}",
+                role, member.user.id, why
+            );
+            failed += 1;
+        }
+    }
+
+    component
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| {
+                            embed
+                                .title(i18n::text(
+                                    locale,
+                                    if added.is_empty() && removed.is_empty() {
+                                        "roles.unchanged"
+                                    } else {
+                                        "roles.updated"
+                                    },
+                                ))
+                                .color(Color::from_rgb(191, 87, 0));
+                            if !added.is_empty() {
+                                embed.field(
+                                    i18n::text(locale, "roles.added"),
+                                    added.join(", "),
+                                    false,
+                                );
+                            }
+                            if !removed.is_empty() {
+                                embed.field(
+                                    i18n::text(locale, "roles.removed"),
+                                    removed.join(", "),
+                                    false,
+                                );
+                            }
+                            if failed > 0 {
+                                embed.description(i18n::format(
+                                    locale,
+                                    "roles.failed",
+                                    &[("count", &failed.to_string())],
+                                ));
+                            }
+                            embed
+                        })
+                        .components(|components| components)
+                })
+        })
+        .await
+}
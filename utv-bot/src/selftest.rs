@@ -0,0 +1,232 @@
+//! `/admin selftest`: an end-to-end check of a deploy, for the bot's owner.
+//!
+//! Each subsystem is exercised for real against the test guild set with `SELFTEST_GUILD_ID`: a
+//! role is created and deleted there, the sacrificial `SELFTEST_USER_ID` member is renamed and
+//! named back, a synthetic account is linked, read and unlinked in the user table, and a token is
+//! signed and validated. Every check runs even when an earlier one fails, so one reply shows all
+//! that's broken.
+
+use std::time::Instant;
+
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::InteractionApplicationCommandCallbackDataFlags;
+use serenity::utils::Color;
+use tracing::info;
+use utv_token::VerifiedClaims;
+
+use crate::{db, owner, response, settings, SHARED_KEY};
+
+/// Name of the temporary role and prefix of the test nickname, so leftovers are recognizable
+const NAME: &str = "utv-selftest";
+const AFFILIATION: &str = "selftest";
+
+enum Outcome {
+    Passed(String),
+    Failed(String),
+    Skipped(&'static str),
+}
+
+pub async fn selftest(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let owner = owner::owner(&ctx.http).await?;
+    if command.user.id != owner {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Only the bot's owner can run this command.",
+        )
+        .await;
+    }
+    let guild_id = match settings::selftest_guild() {
+        Ok(Some(guild_id)) => guild_id,
+        _ => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Set `SELFTEST_GUILD_ID` to a test server to run the self-test.",
+            )
+            .await
+        }
+    };
+    response::defer(ctx, command, true).await?;
+
+    let started = Instant::now();
+    let checks = [
+        ("Roles", roles(&ctx.http, guild_id).await),
+        ("Nicknames", nicknames(&ctx.http, guild_id).await),
+        ("User table", user_table(db_client).await),
+        ("Tokens", tokens()),
+    ];
+    let failed = checks
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+        .count();
+    info!(
+        "Self-test by {} in {}: {} of {} checks failed",
+        command.user.id,
+        guild_id,
+        failed,
+        checks.len()
+    );
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .create_embed(|embed| {
+                    if failed == 0 {
+                        embed
+                            .title("Self-test passed")
+                            .color(Color::from_rgb(0, 255, 0));
+                    } else {
+                        embed
+                            .title(format!(
+                                "Self-test failed: {} of {} checks",
+                                failed,
+                                checks.len()
+                            ))
+                            .color(Color::from_rgb(255, 0, 0));
+                    }
+                    for (name, outcome) in &checks {
+                        let text = match outcome {
+                            Outcome::Passed(detail) => format!("✅ {}", detail),
+                            Outcome::Failed(why) => format!("❌ {}", why),
+                            Outcome::Skipped(why) => format!("⏭️ {}", why),
+                        };
+                        embed.field(name, text, false);
+                    }
+                    embed.footer(|footer| {
+                        footer.text(format!(
+                            "Ran in {:.1}s against guild {}",
+                            started.elapsed().as_secs_f32(),
+                            guild_id
+                        ))
+                    })
+                })
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+        .await?;
+    Ok(())
+}
+
+/// Creates a temporary role and deletes it again
+async fn roles(http: &Http, guild_id: GuildId) -> Outcome {
+    let role = match guild_id.create_role(http, |role| role.name(NAME)).await {
+        Ok(role) => role,
+        Err(why) => return Outcome::Failed(format!("Couldn't create a role: {}", why)),
+    };
+    match guild_id.delete_role(http, role.id).await {
+        Ok(()) => Outcome::Passed("Created and deleted a role".to_string()),
+        Err(why) => Outcome::Failed(format!(
+            "Created role {} but couldn't delete it: {}",
+            role.id, why
+        )),
+    }
+}
+
+/// Renames the test member, checks the nickname stuck and restores the old one
+async fn nicknames(http: &Http, guild_id: GuildId) -> Outcome {
+    let user_id = match settings::selftest_user() {
+        Ok(Some(user_id)) => user_id,
+        _ => return Outcome::Skipped("`SELFTEST_USER_ID` is not set"),
+    };
+    let member = match guild_id.member(http, user_id).await {
+        Ok(member) => member,
+        Err(why) => return Outcome::Failed(format!("Couldn't fetch <@{}>: {}", user_id, why)),
+    };
+    let original = member.nick.clone();
+    let nickname = format!("{}-{:04x}", NAME, rand::random::<u16>());
+    if let Err(why) = member.edit(http, |m| m.nickname(&nickname)).await {
+        return Outcome::Failed(format!("Couldn't rename <@{}>: {}", user_id, why));
+    }
+    let renamed = guild_id
+        .member(http, user_id)
+        .await
+        .map_or(false, |m| m.nick.as_deref() == Some(nickname.as_str()));
+    // an empty nickname removes it
+    if let Err(why) = member
+        .edit(http, |m| m.nickname(original.unwrap_or_default()))
+        .await
+    {
+        return Outcome::Failed(format!(
+            "Renamed <@{}> but couldn't restore their nickname: {}",
+            user_id, why
+        ));
+    }
+    if renamed {
+        Outcome::Passed(format!("Renamed <@{}> and named them back", user_id))
+    } else {
+        Outcome::Failed(format!(
+            "Renaming <@{}> succeeded but the nickname didn't change",
+            user_id
+        ))
+    }
+}
+
+/// Links, reads and unlinks an account that can't exist: Discord ids are far below 2^63
+async fn user_table(db_client: &db::DynamoDB) -> Outcome {
+    let user_id = UserId(rand::random::<u64>() | 1 << 63);
+    let claims = db::Claims {
+        major: vec![],
+        school: vec![],
+        affiliation: vec![AFFILIATION.to_string()],
+        name: None,
+    };
+    let encrypted_eid = format!("{}:{}", AFFILIATION, user_id);
+    if !matches!(
+        db_client.link_user(user_id, &encrypted_eid, &claims).await,
+        db::LinkResult::Linked
+    ) {
+        return Outcome::Failed("Couldn't write a user record".to_string());
+    }
+    let read = db_client.get_user(user_id.0).await;
+    let unlinked = matches!(
+        db_client.unlink_user(user_id).await,
+        db::UnlinkResult::Unlinked
+    );
+    match (read, unlinked) {
+        (Some(read), true) if read.affiliation == claims.affiliation => {
+            Outcome::Passed("Wrote, read and deleted a user record".to_string())
+        }
+        (_, false) => Outcome::Failed(format!(
+            "Couldn't delete the test record of {}, unlink it by hand",
+            user_id
+        )),
+        _ => Outcome::Failed("The user record read back didn't match".to_string()),
+    }
+}
+
+/// Signs a short-lived token with `SHARED_KEY`, validates it and checks a tampered copy is refused
+fn tokens() -> Outcome {
+    let token = utv_token::encode_token(
+        &VerifiedClaims {
+            encrypted_eid: AFFILIATION.as_bytes().to_vec(),
+            major: vec![],
+            school: vec![],
+            affiliation: vec![AFFILIATION.to_string()],
+            expires_at: Some(response::unix_now() + 60),
+            issued_at: Some(response::unix_now()),
+            nonce: Some(rand::random::<[u8; 16]>().to_vec()),
+            name: None,
+        },
+        &SHARED_KEY,
+    );
+    match utv_token::decode_token(&token, &SHARED_KEY) {
+        Ok(claims) if claims.affiliation == [AFFILIATION] => {}
+        _ => return Outcome::Failed("A freshly signed token didn't validate".to_string()),
+    }
+    let mut tampered = token.into_bytes();
+    let middle = tampered.len() / 2;
+    tampered[middle] = if tampered[middle] == b'A' { b'B' } else { b'A' };
+    match utv_token::decode_token(&String::from_utf8_lossy(&tampered), &SHARED_KEY) {
+        Ok(_) => Outcome::Failed("A tampered token validated".to_string()),
+        Err(_) => Outcome::Passed("Signed and validated a token".to_string()),
+    }
+}
@@ -0,0 +1,973 @@
+//! Typed view of the environment variables the bot is configured with.
+//!
+//! Each variable has its own parser so it can be read lazily where it's used, while
+//! `Settings::from_env` validates all of them at once on startup and for `check-config`.
+//!
+//! The variables can also be written in a TOML config file, see [`apply_config_file`]; the
+//! process environment wins over it, and a `--profile` over both. The parsers read a copy of the
+//! variables taken once those are applied, which [`reload`] replaces with new values that
+//! validate; the process environment itself is never changed after startup.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::sync::{Mutex, RwLock};
+
+use lazy_static::lazy_static;
+use reqwest::Url;
+use ring::signature::Ed25519KeyPair;
+use serenity::client::bridge::gateway::GatewayIntents;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use tracing_subscriber::EnvFilter;
+
+use crate::backup;
+use crate::intents::{self, Features};
+
+pub struct Settings {
+    pub discord_token: String,
+    pub application_id: u64,
+    pub request_token: String,
+    pub shared_key: Vec<u8>,
+    pub public_url: String,
+    pub portal_url: String,
+    pub http_addr: SocketAddr,
+    pub features: Features,
+    pub intents: GatewayIntents,
+    pub deployment: &'static str,
+    pub shards: Option<Shards>,
+}
+
+/// The shards one instance runs, out of the total the bot is split into
+#[derive(Clone, Copy, Debug)]
+pub struct Shards {
+    pub first: u64,
+    pub last: u64,
+    pub total: u64,
+}
+
+impl fmt::Display for Shards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}/{}", self.first, self.last, self.total)
+    }
+}
+
+impl Settings {
+    /// Reads every setting, collecting all problems rather than stopping at the first
+    pub fn from_env() -> Result<Settings, Vec<String>> {
+        let mut problems = Vec::new();
+        let settings = (
+            collect(discord_token(), &mut problems),
+            collect(application_id(), &mut problems),
+            collect(request_token(), &mut problems),
+            collect(shared_key(), &mut problems),
+            collect(public_url(), &mut problems),
+            collect(portal_url(), &mut problems),
+            collect(http_addr(), &mut problems),
+            collect(features(), &mut problems),
+            collect(deployment(), &mut problems),
+            collect(shards(), &mut problems),
+        );
+        collect(support_channel(), &mut problems);
+        collect(owner_log_channel(), &mut problems);
+        collect(eid_recheck_percent(), &mut problems);
+        collect(encryption_key(), &mut problems);
+        collect(sso(), &mut problems);
+        collect(owner_id(), &mut problems);
+        collect(trusted_admins(), &mut problems);
+        collect(tenancy_allowlist(), &mut problems);
+        collect(allowed_guilds(), &mut problems);
+        collect(certificate_key(), &mut problems);
+        collect(command_guild(), &mut problems);
+        collect(selftest_guild(), &mut problems);
+        collect(verification_expiry_days(), &mut problems);
+        collect(eid_takeover(), &mut problems);
+        collect(token_max_age_hours(), &mut problems);
+        collect(sweep_interval_hours(), &mut problems);
+        collect(ready_max_latency_ms(), &mut problems);
+        collect(backup_target(), &mut problems);
+        collect(backup_interval_hours(), &mut problems);
+        collect(backup_keep(), &mut problems);
+        collect(log_filter(), &mut problems);
+        collect(log_json(), &mut problems);
+        collect(selftest_user(), &mut problems);
+        collect(verification_update_queue(), &mut problems);
+        collect(verification_request_queue(), &mut problems);
+        collect(recheck_result_queue(), &mut problems);
+        if let (Some("canary"), Some(None)) = (settings.8, settings.9) {
+            problems.push("DEPLOYMENT=canary requires SHARDS".to_string());
+        }
+        let intents = settings
+            .7
+            .as_ref()
+            .and_then(|features| collect(gateway_intents(features), &mut problems));
+        match (settings, intents) {
+            (
+                (
+                    Some(discord_token),
+                    Some(application_id),
+                    Some(request_token),
+                    Some(shared_key),
+                    Some(public_url),
+                    Some(portal_url),
+                    Some(http_addr),
+                    Some(features),
+                    Some(deployment),
+                    Some(shards),
+                ),
+                Some(intents),
+            ) if problems.is_empty() => Ok(Settings {
+                discord_token,
+                application_id,
+                request_token,
+                shared_key,
+                public_url,
+                portal_url,
+                http_addr,
+                features,
+                intents,
+                deployment,
+                shards,
+            }),
+            _ => Err(problems),
+        }
+    }
+}
+
+fn collect<T>(result: Result<T, String>, problems: &mut Vec<String>) -> Option<T> {
+    result.map_err(|why| problems.push(why)).ok()
+}
+
+fn required(name: &str) -> Result<String, String> {
+    match var(name) {
+        Some(value) if !value.trim().is_empty() => Ok(value),
+        _ => Err(format!("{} is not set", name)),
+    }
+}
+
+fn http_url(name: &str, value: String) -> Result<String, String> {
+    match Url::parse(&value) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+            // links are built by appending paths
+            Ok(value.trim_end_matches('/').to_string())
+        }
+        _ => Err(format!("{} is not an http(s) URL: {}", name, value)),
+    }
+}
+
+lazy_static! {
+    /// the variables the settings are read from: the process environment once the config file
+    /// and profile are applied, then whatever a reload accepted
+    static ref VARIABLES: RwLock<HashMap<String, String>> = RwLock::new(environment());
+    /// the profile applied on startup, which `/shutdown reload:True` applies again
+    static ref PROFILE: Mutex<Option<String>> = Mutex::new(None);
+    /// variables the config file set because the process environment didn't, which reloading it
+    /// may change
+    static ref FROM_FILE: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+thread_local! {
+    /// variables a reload is validating, read instead of `VARIABLES` on the thread checking them
+    static CANDIDATE: RefCell<Option<HashMap<String, String>>> = const { RefCell::new(None) };
+}
+
+fn environment() -> HashMap<String, String> {
+    env::vars_os()
+        .filter_map(|(variable, value)| {
+            Some((variable.into_string().ok()?, value.into_string().ok()?))
+        })
+        .collect()
+}
+
+fn var(name: &str) -> Option<String> {
+    CANDIDATE.with(|candidate| match &*candidate.borrow() {
+        Some(variables) => variables.get(name).cloned(),
+        None => VARIABLES.read().unwrap().get(name).cloned(),
+    })
+}
+
+/// Variables only read on startup, which a reload leaves at their current value: what the
+/// connection to Discord, the HTTP server, the logs and the background loops were started with,
+/// and what the lazily initialized keys, links and queues were read from
+const RESTART: [&str; 23] = [
+    "DISCORD_TOKEN",
+    "APPLICATION_ID",
+    "SHARED_KEY",
+    "PUBLIC_URL",
+    "PORTAL_URL",
+    "CERTIFICATE_SIGNING_KEY",
+    "VERIFICATION_UPDATE_QUEUE_URL",
+    "VERIFICATION_REQUEST_QUEUE_URL",
+    "RECHECK_RESULT_QUEUE_URL",
+    "HTTP_ADDR",
+    "DISABLED_FEATURES",
+    "GATEWAY_INTENTS",
+    "DEPLOYMENT",
+    "SHARDS",
+    "COMMAND_GUILD_ID",
+    "SSO_ISSUER",
+    "SWEEP_INTERVAL_HOURS",
+    "BACKUP_TARGET",
+    "BACKUP_INTERVAL_HOURS",
+    "BACKUP_KEEP",
+    "LOG_LEVEL",
+    "LOG_FORMAT",
+    "TABLE_PREFIX",
+];
+
+/// Sets the variables of the config file the process environment doesn't, returning the file's
+/// path when there is one.
+///
+/// The file is `CONFIG_FILE` (default `config.toml`, which may be missing), a TOML table mapping
+/// variable names to their values: strings, numbers, booleans, or arrays for the comma-separated
+/// ones like `ALLOWED_GUILD_IDS`. They're also set in the process environment for the AWS SDK,
+/// which is why this runs first thing on startup.
+pub fn apply_config_file() -> Result<Option<String>, String> {
+    let (path, variables) = match read_config_file()? {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+    let mut from_file = FROM_FILE.lock().unwrap();
+    for (variable, value) in variables {
+        if env::var_os(&variable).is_none() {
+            env::set_var(&variable, value);
+            from_file.insert(variable);
+        }
+    }
+    *VARIABLES.write().unwrap() = environment();
+    Ok(Some(path))
+}
+
+/// What a reload did: the profile it applied again, and the [`RESTART`] variables it left alone
+/// though their value changed
+pub struct Reloaded {
+    pub profile: Option<String>,
+    pub pending: Vec<&'static str>,
+}
+
+/// Reads the config file and the startup profile again, for `/shutdown reload:True` and SIGHUP.
+///
+/// Variables the file set are changed or removed to match it, and those set in the process
+/// environment keep their value; [`RESTART`] variables all keep theirs, and the changed ones are
+/// returned so the operator knows to restart. Both files are read and the new values checked with
+/// [`Settings::from_env`] before any setting changes, so a typo never goes live: the previous
+/// settings are kept and the problems returned instead.
+pub fn reload() -> Result<Reloaded, String> {
+    let variables = read_config_file()?
+        .map(|(_, variables)| variables)
+        .unwrap_or_default();
+    let name = PROFILE.lock().unwrap().clone();
+    let profile = match &name {
+        Some(name) => read_profile(name)?,
+        None => HashMap::new(),
+    };
+    let mut from_file = FROM_FILE.lock().unwrap();
+    let current = VARIABLES.read().unwrap().clone();
+    let (candidate, reloaded_from_file) = merge(&current, &from_file, variables, profile);
+    check(&candidate).map_err(|problems| {
+        format!(
+            "Kept the previous settings, the new ones have problems:\n{}",
+            problems.join("\n")
+        )
+    })?;
+    let (applied, pending) = pin(&current, candidate);
+    check(&applied).map_err(|problems| {
+        format!(
+            "Kept the previous settings, the new ones only work after a restart:\n{}",
+            problems.join("\n")
+        )
+    })?;
+    *VARIABLES.write().unwrap() = applied;
+    *from_file = reloaded_from_file;
+    Ok(Reloaded {
+        profile: name,
+        pending,
+    })
+}
+
+/// The variables after reloading the config file and profile, and which of them the file sets
+fn merge(
+    current: &HashMap<String, String>,
+    from_file: &HashSet<String>,
+    file: HashMap<String, String>,
+    profile: HashMap<String, String>,
+) -> (HashMap<String, String>, HashSet<String>) {
+    let mut variables = current.clone();
+    let mut reloaded_from_file = HashSet::new();
+    for variable in from_file {
+        if !file.contains_key(variable) {
+            variables.remove(variable);
+        }
+    }
+    for (variable, value) in file {
+        if from_file.contains(&variable) || !current.contains_key(&variable) {
+            variables.insert(variable.clone(), value);
+            reloaded_from_file.insert(variable);
+        }
+    }
+    // a file variable the reload doesn't apply yet is still the file's to change next time
+    reloaded_from_file.extend(
+        from_file
+            .iter()
+            .filter(|variable| RESTART.contains(&variable.as_str()))
+            .cloned(),
+    );
+    // the profile takes precedence over the file, as on startup
+    variables.extend(profile);
+    (variables, reloaded_from_file)
+}
+
+/// Puts the [`RESTART`] variables back to their current value, returning those that changed
+fn pin(
+    current: &HashMap<String, String>,
+    mut variables: HashMap<String, String>,
+) -> (HashMap<String, String>, Vec<&'static str>) {
+    let mut pending = Vec::new();
+    for variable in RESTART {
+        if variables.get(variable) == current.get(variable) {
+            continue;
+        }
+        pending.push(variable);
+        match current.get(variable) {
+            Some(value) => variables.insert(variable.to_string(), value.clone()),
+            None => variables.remove(variable),
+        };
+    }
+    (variables, pending)
+}
+
+/// Validates the variables as if they were the settings, without any other thread seeing them
+fn check(variables: &HashMap<String, String>) -> Result<(), Vec<String>> {
+    CANDIDATE.with(|candidate| *candidate.borrow_mut() = Some(variables.clone()));
+    let result = Settings::from_env().map(|_| ());
+    CANDIDATE.with(|candidate| *candidate.borrow_mut() = None);
+    result
+}
+
+fn read_config_file() -> Result<Option<(String, HashMap<String, String>)>, String> {
+    let (path, required) = match var("CONFIG_FILE") {
+        Some(path) if !path.trim().is_empty() => (path, true),
+        _ => ("config.toml".to_string(), false),
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(why) if required || why.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Cannot read {}: {}", path, why))
+        }
+        Err(_) => return Ok(None),
+    };
+    let values: HashMap<String, toml::Value> = toml::from_str(&text)
+        .map_err(|why| format!("{} is not a valid config file: {}", path, why))?;
+    let mut variables = HashMap::new();
+    for (variable, value) in values {
+        let value = match value {
+            toml::Value::Array(items) => items
+                .into_iter()
+                .map(|item| config_value(&path, &variable, item))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => config_value(&path, &variable, value)?,
+        };
+        variables.insert(variable, value);
+    }
+    Ok(Some((path, variables)))
+}
+
+fn config_value(path: &str, variable: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        _ => Err(format!(
+            "{} in {} must be a string, number, boolean or array of them",
+            variable, path
+        )),
+    }
+}
+
+/// Variables no two profiles may share, so staging can't act on production's bot or tables
+const SEPARATED: [&str; 2] = ["DISCORD_TOKEN", "TABLE_PREFIX"];
+
+/// Applies the `--profile <name>` given on the command line and removes it from `args`,
+/// returning the profile's name.
+///
+/// Profiles are read from `PROFILES_FILE` (default `profiles.json`), a JSON object mapping each
+/// profile's name to the environment variables it sets. They take precedence over the process
+/// environment, which still provides everything the profile leaves out, and are set in it too.
+pub fn apply_profile(args: &mut Vec<String>) -> Result<Option<String>, String> {
+    let position = match args.iter().position(|arg| arg == "--profile") {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let name = args
+        .get(position + 1)
+        .cloned()
+        .ok_or_else(|| "--profile needs a profile name".to_string())?;
+    args.drain(position..=position + 1);
+    for (variable, value) in read_profile(&name)? {
+        env::set_var(variable, value);
+    }
+    *VARIABLES.write().unwrap() = environment();
+    *PROFILE.lock().unwrap() = Some(name.clone());
+    Ok(Some(name))
+}
+
+/// The variables the profile sets, read again by [`reload`] so changes to `PROFILES_FILE` take
+/// effect without a restart. Variables the profile no longer sets keep their value.
+fn read_profile(name: &str) -> Result<HashMap<String, String>, String> {
+    let path = var("PROFILES_FILE").unwrap_or_else(|| "profiles.json".to_string());
+    let text = fs::read_to_string(&path).map_err(|why| format!("Cannot read {}: {}", path, why))?;
+    let profiles: HashMap<String, HashMap<String, String>> = serde_json::from_str(&text)
+        .map_err(|why| format!("{} is not a valid profiles file: {}", path, why))?;
+    let profile = profiles
+        .get(name)
+        .ok_or_else(|| format!("{} has no profile named {}", path, name))?;
+    for (other, variables) in profiles.iter().filter(|(other, _)| *other != name) {
+        for variable in SEPARATED {
+            let value = |vars: &HashMap<String, String>| {
+                vars.get(variable)
+                    .map(|v| v.trim().to_string())
+                    .unwrap_or_default()
+            };
+            if value(profile) == value(variables) {
+                return Err(format!(
+                    "Profiles {} and {} in {} must set different {}",
+                    name, other, path, variable
+                ));
+            }
+        }
+    }
+    Ok(profile.clone())
+}
+
+/// Bot token used to connect to Discord
+pub fn discord_token() -> Result<String, String> {
+    required("DISCORD_TOKEN")
+}
+
+/// The application id, usually the bot user id
+pub fn application_id() -> Result<u64, String> {
+    required("APPLICATION_ID")?
+        .parse()
+        .map_err(|_| "APPLICATION_ID is not a valid id".to_string())
+}
+
+/// Endpoint EIDs are posted to by `/verify`
+pub fn request_token() -> Result<String, String> {
+    required("REQUEST_TOKEN").and_then(|url| http_url("REQUEST_TOKEN", url))
+}
+
+/// Key used to sign links and tokens handed out by the bot
+pub fn shared_key() -> Result<Vec<u8>, String> {
+    match base64::decode_config(required("SHARED_KEY")?, base64::URL_SAFE_NO_PAD) {
+        Ok(key) if !key.is_empty() => Ok(key),
+        Ok(_) => Err("SHARED_KEY is empty".to_string()),
+        Err(why) => Err(format!(
+            "SHARED_KEY is not unpadded URL-safe base64: {}",
+            why
+        )),
+    }
+}
+
+/// Base url under which the embedded HTTP server is reachable
+pub fn public_url() -> Result<String, String> {
+    http_url("PUBLIC_URL", required("PUBLIC_URL")?)
+}
+
+pub fn portal_url() -> Result<String, String> {
+    http_url(
+        "PORTAL_URL",
+        var("PORTAL_URL").unwrap_or_else(|| "https://verifiedbot.com".to_string()),
+    )
+}
+
+/// Address the embedded HTTP server binds to
+pub fn http_addr() -> Result<SocketAddr, String> {
+    let addr = var("HTTP_ADDR").unwrap_or_else(|| "0.0.0.0:8080".to_string());
+    addr.parse()
+        .map_err(|_| format!("HTTP_ADDR is not a valid socket address: {}", addr))
+}
+
+/// Gateway-driven features, all enabled unless listed in `DISABLED_FEATURES`
+pub fn features() -> Result<Features, String> {
+    Features::parse(&var("DISABLED_FEATURES").unwrap_or_default())
+}
+
+pub fn gateway_intents(features: &Features) -> Result<GatewayIntents, String> {
+    intents::resolve(&var("GATEWAY_INTENTS").unwrap_or_default(), features)
+}
+
+/// OAuth client secret for the web dashboard, which is disabled when unset
+pub fn client_secret() -> Option<String> {
+    required("DISCORD_CLIENT_SECRET").ok()
+}
+
+/// Google service account key file used by the Sheets sync, which is disabled when unset
+pub fn google_service_account_file() -> Option<String> {
+    required("GOOGLE_SERVICE_ACCOUNT_FILE").ok()
+}
+
+/// Channel in the maintainers' guild that `/support` reports are sent to, disabled when unset
+pub fn support_channel() -> Result<Option<ChannelId>, String> {
+    match required("SUPPORT_CHANNEL_ID") {
+        Ok(id) => id
+            .parse()
+            .map(|id| Some(ChannelId(id)))
+            .map_err(|_| "SUPPORT_CHANNEL_ID is not a valid id".to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Which deployment this instance is, `stable` (the default) or `canary`. Metrics are
+/// labelled with it, and only the stable instance runs the background loops.
+pub fn deployment() -> Result<&'static str, String> {
+    match var("DEPLOYMENT").unwrap_or_default().trim() {
+        "" | "stable" => Ok("stable"),
+        "canary" => Ok("canary"),
+        other => Err(format!(
+            "DEPLOYMENT must be stable or canary, not {}",
+            other
+        )),
+    }
+}
+
+/// The shards this instance runs, as `first-last/total` or `index/total`; all of them when unset
+pub fn shards() -> Result<Option<Shards>, String> {
+    let value = var("SHARDS").unwrap_or_default();
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    let invalid = || format!("SHARDS is not of the form first-last/total: {}", value);
+    let (range, total) = value.trim().split_once('/').ok_or_else(invalid)?;
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let shards = Shards {
+        first: first.parse().map_err(|_| invalid())?,
+        last: last.parse().map_err(|_| invalid())?,
+        total: total.parse().map_err(|_| invalid())?,
+    };
+    if shards.first > shards.last || shards.last >= shards.total {
+        return Err(invalid());
+    }
+    Ok(Some(shards))
+}
+
+/// Channel the bot's owner gets operational messages in, like the report of the last shutdown
+pub fn owner_log_channel() -> Result<Option<ChannelId>, String> {
+    match required("OWNER_LOG_CHANNEL_ID") {
+        Ok(id) => id
+            .parse()
+            .map(|id| Some(ChannelId(id)))
+            .map_err(|_| "OWNER_LOG_CHANNEL_ID is not a valid id".to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The bot's own info lines and warnings from its dependencies
+pub const DEFAULT_LOG_FILTER: &str = "warn,utv_bot=info";
+
+/// Which lines are logged, as a `tracing` filter like `info` or `utv_bot=debug,serenity=warn`
+pub fn log_filter() -> Result<EnvFilter, String> {
+    let value = var("LOG_LEVEL").unwrap_or_default();
+    if value.trim().is_empty() {
+        return Ok(EnvFilter::new(DEFAULT_LOG_FILTER));
+    }
+    EnvFilter::try_new(value.trim())
+        .map_err(|why| format!("LOG_LEVEL is not a valid filter: {}", why))
+}
+
+/// Whether logs are written as JSON lines (`LOG_FORMAT=json`) instead of text (`text`, the default)
+pub fn log_json() -> Result<bool, String> {
+    match var("LOG_FORMAT").unwrap_or_default().trim() {
+        "" | "text" => Ok(false),
+        "json" => Ok(true),
+        other => Err(format!("LOG_FORMAT must be text or json, not {}", other)),
+    }
+}
+
+/// Where the state report is written on shutdown
+pub fn state_report_file() -> String {
+    var("STATE_REPORT_FILE").unwrap_or_else(|| "state-report.json".to_string())
+}
+
+/// Share of linked users re-checked against the directory each month, disabled when unset
+pub fn eid_recheck_percent() -> Result<Option<u32>, String> {
+    match required("EID_RECHECK_PERCENT") {
+        Ok(percent) => match percent.trim().parse() {
+            Ok(percent) if (1..=100).contains(&percent) => Ok(Some(percent)),
+            _ => Err(format!(
+                "EID_RECHECK_PERCENT must be between 1 and 100, not {}",
+                percent
+            )),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// Days after which a verification expires and the member has to verify again, see `expiry`;
+/// verifications don't expire when unset
+pub fn verification_expiry_days() -> Result<Option<u32>, String> {
+    match required("VERIFICATION_EXPIRY_DAYS") {
+        Ok(days) => match days.trim().parse() {
+            Ok(days) if days > 0 => Ok(Some(days)),
+            _ => Err(format!(
+                "VERIFICATION_EXPIRY_DAYS must be a positive number of days, not {}",
+                days
+            )),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// As long as the verification server's emailed tokens are valid
+const DEFAULT_TOKEN_MAX_AGE_HOURS: i64 = 24;
+
+/// Hours after it's issued that a verification token is refused, see `redeem`; tokens also carry
+/// their own expiry, which the verification server sets to a day
+pub fn token_max_age_hours() -> Result<i64, String> {
+    match required("TOKEN_MAX_AGE_HOURS") {
+        Ok(hours) => match hours.trim().parse() {
+            Ok(hours) if hours > 0 => Ok(hours),
+            _ => Err(format!(
+                "TOKEN_MAX_AGE_HOURS must be a positive number of hours, not {}",
+                hours
+            )),
+        },
+        Err(_) => Ok(DEFAULT_TOKEN_MAX_AGE_HOURS),
+    }
+}
+
+/// Nightly, to catch what events and scans at startup missed
+const DEFAULT_SWEEP_INTERVAL_HOURS: u64 = 24;
+
+/// Hours between sweeps of every guild's members, see `sweep`; `0` turns them off
+pub fn sweep_interval_hours() -> Result<Option<u64>, String> {
+    match required("SWEEP_INTERVAL_HOURS") {
+        Ok(hours) => match hours.trim().parse() {
+            Ok(0) => Ok(None),
+            Ok(hours) => Ok(Some(hours)),
+            Err(_) => Err(format!(
+                "SWEEP_INTERVAL_HOURS must be a number of hours, not {}",
+                hours
+            )),
+        },
+        Err(_) => Ok(Some(DEFAULT_SWEEP_INTERVAL_HOURS)),
+    }
+}
+
+/// Where backups are written, a directory or an `s3://bucket/prefix`; none are taken when unset
+pub fn backup_target() -> Result<Option<backup::Target>, String> {
+    match required("BACKUP_TARGET") {
+        Ok(target) => backup::Target::parse(target.trim())
+            .map(Some)
+            .map_err(|why| format!("BACKUP_TARGET is invalid: {}", why)),
+        Err(_) => Ok(None),
+    }
+}
+
+const DEFAULT_BACKUP_INTERVAL_HOURS: u64 = 24;
+
+/// Hours between backups to `BACKUP_TARGET`, see `backup`
+pub fn backup_interval_hours() -> Result<u64, String> {
+    match required("BACKUP_INTERVAL_HOURS") {
+        Ok(hours) => match hours.trim().parse() {
+            Ok(hours) if hours > 0 => Ok(hours),
+            _ => Err(format!(
+                "BACKUP_INTERVAL_HOURS must be a positive number of hours, not {}",
+                hours
+            )),
+        },
+        Err(_) => Ok(DEFAULT_BACKUP_INTERVAL_HOURS),
+    }
+}
+
+/// Two weeks of daily backups
+const DEFAULT_BACKUP_KEEP: usize = 14;
+
+/// How many backups are kept in a `BACKUP_TARGET` directory
+pub fn backup_keep() -> Result<usize, String> {
+    match required("BACKUP_KEEP") {
+        Ok(keep) => match keep.trim().parse() {
+            Ok(keep) if keep > 0 => Ok(keep),
+            _ => Err(format!(
+                "BACKUP_KEEP must be a positive number of backups, not {}",
+                keep
+            )),
+        },
+        Err(_) => Ok(DEFAULT_BACKUP_KEEP),
+    }
+}
+
+/// Slower than Discord's usual heartbeat round trip by far, so only a struggling shard exceeds it
+const DEFAULT_READY_MAX_LATENCY_MS: u64 = 2000;
+
+/// Heartbeat latency above which a shard makes `/readyz` fail, see `health`
+pub fn ready_max_latency_ms() -> Result<u64, String> {
+    match required("READY_MAX_LATENCY_MS") {
+        Ok(ms) => match ms.trim().parse() {
+            Ok(ms) if ms > 0 => Ok(ms),
+            _ => Err(format!(
+                "READY_MAX_LATENCY_MS must be a positive number of milliseconds, not {}",
+                ms
+            )),
+        },
+        Err(_) => Ok(DEFAULT_READY_MAX_LATENCY_MS),
+    }
+}
+
+/// Whether verifying with an EID linked to another account moves it over (`EID_TAKEOVER=takeover`)
+/// instead of being refused (`reject`, the default), see `redeem`
+pub fn eid_takeover() -> Result<bool, String> {
+    match var("EID_TAKEOVER").unwrap_or_default().trim() {
+        "" | "reject" => Ok(false),
+        "takeover" => Ok(true),
+        other => Err(format!(
+            "EID_TAKEOVER must be reject or takeover, not {}",
+            other
+        )),
+    }
+}
+
+/// The verification server's EID encryption key, which features matching or issuing EIDs need
+/// (`/admin bulk-lookup`, `/admin issue-token`, `/config dues`); they're disabled when unset
+pub fn encryption_key() -> Result<Option<Vec<u8>>, String> {
+    let key = match required("ENCRYPTION_KEY") {
+        Ok(key) => key,
+        Err(_) => return Ok(None),
+    };
+    match base64::decode_config(key, base64::URL_SAFE_NO_PAD) {
+        Ok(key) if key.len() == 32 => Ok(Some(key)),
+        Ok(_) => Err("ENCRYPTION_KEY must be 32 bytes".to_string()),
+        Err(why) => Err(format!(
+            "ENCRYPTION_KEY is not unpadded URL-safe base64: {}",
+            why
+        )),
+    }
+}
+
+/// The university's OpenID Connect provider, which `/verify` logs members in with instead of
+/// emailing tokens, see `sso`
+pub struct Sso {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// the userinfo claim holding the EID
+    pub eid_claim: String,
+    /// the userinfo claim holding the affiliations, a string or an array of them
+    pub affiliation_claim: String,
+}
+
+/// UT login for `/verify`, from `SSO_ISSUER`, `SSO_CLIENT_ID` and `SSO_CLIENT_SECRET`; members
+/// are emailed tokens when unset. The EID is encrypted like the verification server does, so
+/// it needs `ENCRYPTION_KEY`.
+pub fn sso() -> Result<Option<Sso>, String> {
+    let issuer = match required("SSO_ISSUER") {
+        Ok(issuer) => http_url("SSO_ISSUER", issuer.trim().to_string())?,
+        Err(_) => return Ok(None),
+    };
+    if encryption_key()?.is_none() {
+        return Err("SSO_ISSUER requires ENCRYPTION_KEY".to_string());
+    }
+    let claim = |name, default: &str| {
+        var(name)
+            .filter(|claim| !claim.trim().is_empty())
+            .map_or_else(|| default.to_string(), |claim| claim.trim().to_string())
+    };
+    Ok(Some(Sso {
+        issuer,
+        client_id: required("SSO_CLIENT_ID")?.trim().to_string(),
+        client_secret: required("SSO_CLIENT_SECRET")?.trim().to_string(),
+        eid_claim: claim("SSO_EID_CLAIM", "preferred_username"),
+        affiliation_claim: claim("SSO_AFFILIATION_CLAIM", "eduPersonAffiliation"),
+    }))
+}
+
+/// Ed25519 key `/certificate` signs with, as unpadded URL-safe base64 of its PKCS#8 document;
+/// certificates are disabled when unset
+pub fn certificate_key() -> Result<Option<Ed25519KeyPair>, String> {
+    let key = match required("CERTIFICATE_SIGNING_KEY") {
+        Ok(key) => key,
+        Err(_) => return Ok(None),
+    };
+    let pkcs8 = base64::decode_config(key.trim(), base64::URL_SAFE_NO_PAD).map_err(|why| {
+        format!(
+            "CERTIFICATE_SIGNING_KEY is not unpadded URL-safe base64: {}",
+            why
+        )
+    })?;
+    Ed25519KeyPair::from_pkcs8(&pkcs8)
+        .map(Some)
+        .map_err(|_| "CERTIFICATE_SIGNING_KEY is not an Ed25519 PKCS#8 key".to_string())
+}
+
+/// Whether only approved guilds are served (`TENANCY=allowlist`) instead of any that invites the
+/// bot (`open`, the default), see `tenancy`
+pub fn tenancy_allowlist() -> Result<bool, String> {
+    match var("TENANCY").unwrap_or_default().trim() {
+        "" | "open" => Ok(false),
+        "allowlist" => Ok(true),
+        other => Err(format!("TENANCY must be open or allowlist, not {}", other)),
+    }
+}
+
+/// Guilds approved in the environment, from the comma-separated `ALLOWED_GUILD_IDS`, besides
+/// those approved with `/tenant add`
+pub fn allowed_guilds() -> Result<Vec<GuildId>, String> {
+    let ids = match required("ALLOWED_GUILD_IDS") {
+        Ok(ids) => ids,
+        Err(_) => return Ok(Vec::new()),
+    };
+    ids.split(',')
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map(GuildId)
+                .map_err(|_| format!("ALLOWED_GUILD_IDS has an invalid id: {}", id))
+        })
+        .collect()
+}
+
+/// The bot's owner, who may run `/guilds`, `/announce`, `/shutdown` and the other owner-only
+/// commands; the owner of the bot's Discord application when unset
+pub fn owner_id() -> Result<Option<UserId>, String> {
+    match required("OWNER_ID") {
+        Ok(id) => id
+            .trim()
+            .parse()
+            .map(|id| Some(UserId(id)))
+            .map_err(|_| "OWNER_ID is not a valid id".to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Users besides the bot's owner who may `/admin issue-token`, from the comma-separated
+/// `TRUSTED_ADMIN_IDS`
+pub fn trusted_admins() -> Result<Vec<UserId>, String> {
+    let ids = match required("TRUSTED_ADMIN_IDS") {
+        Ok(ids) => ids,
+        Err(_) => return Ok(Vec::new()),
+    };
+    ids.split(',')
+        .map(|id| id.trim())
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map(UserId)
+                .map_err(|_| format!("TRUSTED_ADMIN_IDS has an invalid id: {}", id))
+        })
+        .collect()
+}
+
+/// Prepended to every DynamoDB table name, so deployments can share an AWS account
+pub fn table_prefix() -> String {
+    var("TABLE_PREFIX").unwrap_or_default().trim().to_string()
+}
+
+/// Guild the commands are registered in instead of globally, for staging bots that are only
+/// used in a test guild; Discord applies guild commands right away
+pub fn command_guild() -> Result<Option<GuildId>, String> {
+    match required("COMMAND_GUILD_ID") {
+        Ok(id) => id
+            .parse()
+            .map(|id| Some(GuildId(id)))
+            .map_err(|_| "COMMAND_GUILD_ID is not a valid id".to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Test guild `/admin selftest` creates a role and renames a member in
+pub fn selftest_guild() -> Result<Option<GuildId>, String> {
+    match required("SELFTEST_GUILD_ID") {
+        Ok(id) => id
+            .parse()
+            .map(|id| Some(GuildId(id)))
+            .map_err(|_| "SELFTEST_GUILD_ID is not a valid id".to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Sacrificial member of the test guild that `/admin selftest` renames and names back
+pub fn selftest_user() -> Result<Option<UserId>, String> {
+    match required("SELFTEST_USER_ID") {
+        Ok(id) => id
+            .parse()
+            .map(|id| Some(UserId(id)))
+            .map_err(|_| "SELFTEST_USER_ID is not a valid id".to_string()),
+        Err(_) => Ok(None),
+    }
+}
+
+fn queue_url(name: &str, default: &str) -> Result<String, String> {
+    http_url(name, var(name).unwrap_or_else(|| default.to_string()))
+}
+
+/// Queue the portal and `/redeem` announce verifications on
+pub fn verification_update_queue() -> Result<String, String> {
+    queue_url(
+        "VERIFICATION_UPDATE_QUEUE_URL",
+        "https://sqs.us-east-1.amazonaws.com/402762806873/on-verification-update",
+    )
+}
+
+/// The verification server's queue, shared with `/verify`
+pub fn verification_request_queue() -> Result<String, String> {
+    queue_url(
+        "VERIFICATION_REQUEST_QUEUE_URL",
+        "https://sqs.us-east-1.amazonaws.com/402762806873/eid_verification_requests",
+    )
+}
+
+/// Queue the verification server answers directory re-checks on
+pub fn recheck_result_queue() -> Result<String, String> {
+    queue_url(
+        "RECHECK_RESULT_QUEUE_URL",
+        "https://sqs.us-east-1.amazonaws.com/402762806873/eid_recheck_results",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(variable, value)| (variable.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reloads_leave_restart_variables_alone() {
+        let current = variables(&[
+            ("PUBLIC_URL", "https://a.example"),
+            ("SUPPORT_CHANNEL_ID", "1"),
+            ("OWNER_ID", "5"),
+        ]);
+        let from_file: HashSet<String> = ["PUBLIC_URL", "SUPPORT_CHANNEL_ID"]
+            .iter()
+            .map(|variable| variable.to_string())
+            .collect();
+        let file = variables(&[
+            ("PUBLIC_URL", "https://b.example"),
+            ("OWNER_ID", "6"),
+            ("TENANCY", "allowlist"),
+        ]);
+        let (candidate, from_file) =
+            merge(&current, &from_file, file, variables(&[("OWNER_ID", "7")]));
+        assert_eq!(
+            candidate,
+            variables(&[
+                ("PUBLIC_URL", "https://b.example"),
+                ("OWNER_ID", "7"),
+                ("TENANCY", "allowlist"),
+            ])
+        );
+        assert!(from_file.contains("TENANCY") && !from_file.contains("OWNER_ID"));
+        let (applied, pending) = pin(&current, candidate);
+        assert_eq!(applied["PUBLIC_URL"], "https://a.example");
+        assert_eq!(pending, ["PUBLIC_URL"]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Shards: which one a guild's events arrive on, and `/status`, which shows how each is doing.
+//!
+//! Without `SHARDS` the bot is autosharded, running as many shards as Discord recommends for
+//! its guild count. The periodic passes over every guild (channel policies, role positions and
+//! the audit log watch) take the guilds round-robin by shard rather than in id order, so no
+//! shard's guilds all wait until the end of a pass.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::client::Context;
+use serenity::gateway::ConnectionStage;
+use serenity::http::{GuildPagination, Http};
+use serenity::model::guild::GuildInfo;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+
+use crate::{response, settings};
+
+/// guilds listed per request, Discord's maximum
+const PAGE: u64 = 200;
+
+lazy_static! {
+    static ref MANAGER: Mutex<Option<Arc<serenity::prelude::Mutex<ShardManager>>>> =
+        Mutex::new(None);
+}
+
+/// shards the bot is split into, known once a shard is ready
+static TOTAL: AtomicU64 = AtomicU64::new(1);
+
+/// Keeps the shard manager for `/status`
+pub fn set_manager(manager: Arc<serenity::prelude::Mutex<ShardManager>>) {
+    *MANAGER.lock().unwrap() = Some(manager);
+}
+
+/// A shard this instance runs, as the shard manager sees it
+pub struct ShardState {
+    pub id: u64,
+    pub stage: ConnectionStage,
+    /// heartbeat round trip, unknown until the first heartbeat is acknowledged
+    pub latency: Option<Duration>,
+}
+
+/// The shards this instance runs, by id; none before the shard manager started
+pub async fn states() -> Vec<ShardState> {
+    let manager = match MANAGER.lock().unwrap().clone() {
+        Some(manager) => manager,
+        None => return Vec::new(),
+    };
+    let manager = manager.lock().await;
+    let runners = manager.runners.lock().await;
+    let mut states = runners
+        .iter()
+        .map(|(id, runner)| ShardState {
+            id: id.0,
+            stage: runner.stage,
+            latency: runner.latency,
+        })
+        .collect::<Vec<_>>();
+    states.sort_by_key(|state| state.id);
+    states
+}
+
+/// Records the shard count sent with a shard's ready event, as `[shard id, total]`
+pub fn set_total(shard: Option<[u64; 2]>) {
+    if let Some([_, total]) = shard {
+        TOTAL.store(total.max(1), Ordering::Relaxed);
+    }
+}
+
+/// The shard the guild's events are delivered to
+pub fn shard_of(guild_id: GuildId) -> u64 {
+    (guild_id.0 >> 22) % TOTAL.load(Ordering::Relaxed)
+}
+
+/// Every guild the bot is in, in id order, with its name
+pub async fn guild_infos(http: &Http) -> serenity::Result<Vec<GuildInfo>> {
+    let mut guilds = Vec::new();
+    let mut after = GuildId(0);
+    loop {
+        let page = http
+            .get_guilds(&GuildPagination::After(after), PAGE)
+            .await?;
+        let last = match page.last() {
+            Some(last) => last.id,
+            None => break,
+        };
+        let full = page.len() as u64 == PAGE;
+        guilds.extend(page);
+        if !full {
+            break;
+        }
+        after = last;
+    }
+    Ok(guilds)
+}
+
+/// Every guild the bot is in, round-robin by shard
+pub async fn guilds(http: &Http) -> serenity::Result<Vec<GuildId>> {
+    let mut by_shard: BTreeMap<u64, Vec<GuildId>> = BTreeMap::new();
+    for guild in guild_infos(http).await? {
+        by_shard
+            .entry(shard_of(guild.id))
+            .or_default()
+            .push(guild.id);
+    }
+    let longest = by_shard.values().map(Vec::len).max().unwrap_or(0);
+    let mut guilds = Vec::new();
+    for i in 0..longest {
+        guilds.extend(by_shard.values().filter_map(|shard| shard.get(i)));
+    }
+    Ok(guilds)
+}
+
+pub async fn status(command: ApplicationCommandInteraction, ctx: Context) -> serenity::Result<()> {
+    let shards = states()
+        .await
+        .iter()
+        .map(|shard| {
+            format!(
+                "shard {}: {}, {}",
+                shard.id,
+                shard.stage,
+                shard
+                    .latency
+                    .map_or("latency unknown".to_string(), |latency| format!(
+                        "{} ms",
+                        latency.as_millis()
+                    ))
+            )
+        })
+        .collect::<Vec<_>>();
+    let deployment = settings::deployment().unwrap_or_default();
+    response::respond_embed(&ctx, &command, true, |embed| {
+        embed
+            .title("Status")
+            .description(format!(
+                "Version {} ({}), running {} of {} shards.",
+                env!("CARGO_PKG_VERSION"),
+                deployment,
+                shards.len(),
+                TOTAL.load(Ordering::Relaxed)
+            ))
+            .field("Shards", response::field_lines(&shards, "None"), false);
+        if let Some(guild_id) = command.guild_id {
+            embed.field("This Server", format!("shard {}", shard_of(guild_id)), true);
+        }
+        embed.color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
@@ -0,0 +1,174 @@
+//! Google Sheets sync: appends verification events to a spreadsheet chosen per guild with
+//! `/config sheet`, so officers don't have to copy exports by hand.
+//!
+//! The bot authenticates as the service account in `GOOGLE_SERVICE_ACCOUNT_FILE`; each
+//! spreadsheet has to be shared with that account's email address.
+
+use chrono::{TimeZone, Utc};
+use lazy_static::lazy_static;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::Deserialize;
+use serde_json::json;
+use serenity::model::id::{GuildId, UserId};
+use tokio::sync::Mutex;
+use tracing::{error, warn};
+
+use crate::{db, response, settings};
+
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+/// rows are appended after the last row of the first sheet's table
+const RANGE: &str = "A1";
+
+lazy_static! {
+    /// access token and the unix time it expires at
+    static ref ACCESS_TOKEN: Mutex<Option<(String, i64)>> = Mutex::new(None);
+}
+
+/// The fields of a service account key file we use
+#[derive(Deserialize)]
+pub struct ServiceAccount {
+    pub client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: i64,
+}
+
+pub fn service_account() -> Option<ServiceAccount> {
+    let path = settings::google_service_account_file()?;
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|why| warn!("Cannot read {}: {}", path, why))
+        .ok()?;
+    serde_json::from_str(&contents)
+        .map_err(|why| error!("Invalid service account file {}: {}", path, why))
+        .ok()
+}
+
+/// Accepts either a spreadsheet id or a link to the spreadsheet
+pub fn spreadsheet_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    let id = match input.split_once("/spreadsheets/d/") {
+        Some((_, rest)) => rest.split('/').next().unwrap_or_default(),
+        None => input,
+    };
+    if !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Some(id.to_string())
+    } else {
+        None
+    }
+}
+
+/// Signs a JWT assertion and exchanges it for an access token
+async fn fetch_token(account: &ServiceAccount) -> Result<AccessToken, String> {
+    let now = response::unix_now();
+    let encode = |value: serde_json::Value| {
+        base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD)
+    };
+    let message = format!(
+        "{}.{}",
+        encode(json!({ "alg": "RS256", "typ": "JWT" })),
+        encode(json!({
+            "iss": account.client_email,
+            "scope": SCOPE,
+            "aud": account.token_uri,
+            "iat": now,
+            "exp": now + 60 * 60,
+        }))
+    );
+    let der = base64::decode(
+        account
+            .private_key
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect::<String>(),
+    )
+    .map_err(|why| format!("invalid private key: {}", why))?;
+    let key =
+        RsaKeyPair::from_pkcs8(&der).map_err(|why| format!("invalid private key: {}", why))?;
+    let mut signature = vec![0; key.public_modulus_len()];
+    key.sign(
+        &RSA_PKCS1_SHA256,
+        &SystemRandom::new(),
+        message.as_bytes(),
+        &mut signature,
+    )
+    .map_err(|_| "cannot sign the token request".to_string())?;
+    let assertion = format!(
+        "{}.{}",
+        message,
+        base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+    );
+
+    reqwest::Client::new()
+        .post(&account.token_uri)
+        .form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", assertion.as_str()),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|why| why.to_string())?
+        .json()
+        .await
+        .map_err(|why| why.to_string())
+}
+
+async fn access_token(account: &ServiceAccount) -> Result<String, String> {
+    let mut cached = ACCESS_TOKEN.lock().await;
+    match &*cached {
+        // refresh a minute early so a token doesn't expire mid-request
+        Some((token, expires_at)) if *expires_at > response::unix_now() + 60 => Ok(token.clone()),
+        _ => {
+            let token = fetch_token(account).await?;
+            *cached = Some((
+                token.access_token.clone(),
+                response::unix_now() + token.expires_in,
+            ));
+            Ok(token.access_token)
+        }
+    }
+}
+
+/// Appends a row for a verification event to the guild's spreadsheet, if it has one.
+/// Failures are logged rather than returned, the sheet is only a convenience copy.
+pub async fn append(db_client: &db::DynamoDB, guild_id: GuildId, event: &str, user_id: UserId) {
+    let spreadsheet = match db_client.get_guild_config(guild_id).await.sheet_id {
+        Some(spreadsheet) => spreadsheet,
+        None => return,
+    };
+    let account = match service_account() {
+        Some(account) => account,
+        None => return,
+    };
+    let result = async {
+        let token = access_token(&account).await?;
+        let now = Utc.timestamp(response::unix_now(), 0).to_rfc3339();
+        reqwest::Client::new()
+            .post(format!(
+                "https://sheets.googleapis.com/v4/spreadsheets/{}/values/{}:append",
+                spreadsheet, RANGE
+            ))
+            .query(&[("valueInputOption", "RAW")])
+            .bearer_auth(token)
+            .json(&json!({ "values": [[now, event, user_id.0.to_string()]] }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|why| why.to_string())?;
+        Ok::<(), String>(())
+    }
+    .await;
+    if let Err(why) = result {
+        error!("Failed to append to the sheet of {}: {}", guild_id, why);
+    }
+}
@@ -0,0 +1,337 @@
+//! State report written on shutdown and posted to the owner's log channel on the next start.
+//!
+//! Deploys stop the bot with SIGTERM, and its owner can with `/shutdown`. New work stops first: interactions are answered with a
+//! request to try again, and scans, the job worker and the verification queue stop picking up
+//! more. Member updates and interactions already running get [`DRAIN_TIMEOUT`] to finish, so
+//! nicknames and roles aren't left half applied; every write goes straight to DynamoDB, there's
+//! nothing local to flush. The report then records what the restart interrupts: jobs still
+//! queued for the worker, work the drain gave up on, verification updates taken off the queue
+//! but not yet acknowledged, the size of the queue's dead-letter queue and when each shard last
+//! received an event.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use aws_sdk_sqs::model::QueueAttributeName;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::client::{Context, RawEventHandler};
+use serenity::http::{AttachmentType, Http};
+use serenity::model::event::Event;
+use serenity::model::interactions::{
+    Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::{jobs, response, settings, SQS_BECOME_VERIFIED_REQUEST_URL};
+
+#[derive(Serialize, Deserialize)]
+struct StateReport {
+    deployment: String,
+    version: String,
+    started_at: i64,
+    stopped_at: i64,
+    pending_jobs: Vec<String>,
+    /// member updates and interactions still running when the drain timed out
+    #[serde(default)]
+    interrupted_work: usize,
+    /// verification updates received from SQS whose handling was cut short
+    unacknowledged_updates: usize,
+    /// messages in the verification queue's dead-letter queue, if it has one
+    dead_letter_queue: Option<u64>,
+    /// unix time of the last gateway event per shard id
+    last_events: BTreeMap<u64, i64>,
+}
+
+/// How long work already running may take to finish once shutdown starts
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref STARTED_AT: i64 = response::unix_now();
+    static ref LAST_EVENTS: Mutex<BTreeMap<u64, i64>> = Mutex::new(BTreeMap::new());
+    static ref DRAIN: Notify = Notify::new();
+    static ref REQUESTED: Notify = Notify::new();
+}
+
+static UNACKNOWLEDGED: AtomicUsize = AtomicUsize::new(0);
+static DRAINING: AtomicBool = AtomicBool::new(false);
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Work shutdown waits for, until dropped
+pub struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Marks work shutdown should let finish, like a member update
+pub fn in_flight() -> InFlight {
+    IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+    InFlight
+}
+
+/// Whether shutdown has started and no new work should be picked up
+pub fn draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Resolves once shutdown starts
+pub async fn started() {
+    // created before the check so a shutdown starting in between still wakes it
+    let notified = DRAIN.notified();
+    if draining() {
+        return;
+    }
+    notified.await;
+}
+
+/// Starts shutdown as if the process received SIGTERM
+pub fn request() {
+    // stored until `on_signal` waits for it
+    REQUESTED.notify_one();
+}
+
+/// Answers an interaction arriving during shutdown with a request to try again
+pub async fn refuse(ctx: &Context, interaction: &Interaction) {
+    let result = match interaction {
+        Interaction::ApplicationCommand(command) => {
+            command
+                .create_interaction_response(&ctx.http, restarting)
+                .await
+        }
+        Interaction::MessageComponent(component) => {
+            component
+                .create_interaction_response(&ctx.http, restarting)
+                .await
+        }
+        Interaction::ModalSubmit(modal) => {
+            modal
+                .create_interaction_response(&ctx.http, restarting)
+                .await
+        }
+        _ => Ok(()),
+    };
+    if let Err(why) = result {
+        warn!("Cannot refuse an interaction during shutdown: {}", why);
+    }
+}
+
+fn restarting(response: &mut CreateInteractionResponse) -> &mut CreateInteractionResponse {
+    response
+        .kind(InteractionResponseType::ChannelMessageWithSource)
+        .interaction_response_data(|message| {
+            message
+                .content("The bot is restarting, try again in a minute.")
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+        })
+}
+
+/// Records the time of every gateway event by shard
+pub struct ShardActivity;
+
+#[async_trait]
+impl RawEventHandler for ShardActivity {
+    async fn raw_event(&self, ctx: Context, _: Event) {
+        LAST_EVENTS
+            .lock()
+            .unwrap()
+            .insert(ctx.shard_id, response::unix_now());
+    }
+}
+
+/// Sets how many verification updates are being handled but not yet deleted from the queue
+pub fn set_unacknowledged(count: usize) {
+    UNACKNOWLEDGED.store(count, Ordering::Relaxed);
+}
+
+/// Waits for SIGTERM, ctrl-c or [`request`], then stops new work, lets running work finish, writes the report
+/// and stops the shards
+pub async fn on_signal(
+    shard_manager: Arc<serenity::prelude::Mutex<ShardManager>>,
+    jobs: Arc<jobs::Queue>,
+    deployment: &'static str,
+) {
+    lazy_static::initialize(&STARTED_AT);
+    let mut terminate =
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(why) => {
+                warn!("Cannot listen for SIGTERM: {}", why);
+                return;
+            }
+        };
+    tokio::select! {
+        _ = terminate.recv() => {},
+        _ = tokio::signal::ctrl_c() => {},
+        _ = REQUESTED.notified() => {},
+    }
+    info!("Shutting down");
+    DRAINING.store(true, Ordering::Relaxed);
+    DRAIN.notify_waiters();
+    let draining_since = Instant::now();
+    while IN_FLIGHT.load(Ordering::Relaxed) > 0 && draining_since.elapsed() < DRAIN_TIMEOUT {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let interrupted_work = IN_FLIGHT.load(Ordering::Relaxed);
+    if interrupted_work > 0 {
+        warn!(
+            "Gave up waiting for {} member updates and interactions",
+            interrupted_work
+        );
+    }
+
+    let report = StateReport {
+        deployment: deployment.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        started_at: *STARTED_AT,
+        stopped_at: response::unix_now(),
+        pending_jobs: jobs.pending(),
+        interrupted_work,
+        unacknowledged_updates: UNACKNOWLEDGED.load(Ordering::Relaxed),
+        dead_letter_queue: dead_letter_queue_size().await,
+        last_events: LAST_EVENTS.lock().unwrap().clone(),
+    };
+    let path = settings::state_report_file();
+    match serde_json::to_vec_pretty(&report).map(|json| std::fs::write(&path, json)) {
+        Ok(Ok(())) => info!("Wrote state report to {}", path),
+        Ok(Err(why)) => warn!("Cannot write state report to {}: {}", path, why),
+        Err(why) => warn!("Cannot serialize state report: {}", why),
+    }
+    shard_manager.lock().await.shutdown_all().await;
+}
+
+/// Number of messages in the dead-letter queue of the verification queue's redrive policy
+async fn dead_letter_queue_size() -> Option<u64> {
+    let config = aws_config::load_from_env().await;
+    let client = aws_sdk_sqs::Client::new(&config);
+    let attributes = |url: String, name: QueueAttributeName| {
+        let client = client.clone();
+        async move {
+            client
+                .get_queue_attributes()
+                .queue_url(url)
+                .attribute_names(name.clone())
+                .send()
+                .await
+                .ok()?
+                .attributes?
+                .remove(&name)
+        }
+    };
+    let policy: serde_json::Value = serde_json::from_str(
+        &attributes(
+            SQS_BECOME_VERIFIED_REQUEST_URL.to_string(),
+            QueueAttributeName::RedrivePolicy,
+        )
+        .await?,
+    )
+    .ok()?;
+    // the policy names the queue by ARN, whose last segment is the queue name
+    let name = policy["deadLetterTargetArn"].as_str()?.rsplit(':').next()?;
+    let url = client
+        .get_queue_url()
+        .queue_name(name)
+        .send()
+        .await
+        .ok()?
+        .queue_url?;
+    attributes(url, QueueAttributeName::ApproximateNumberOfMessages)
+        .await?
+        .parse()
+        .ok()
+}
+
+/// Posts the report left by the previous shutdown, if any, then removes it
+pub async fn emit_previous(http: &Http) {
+    let path = settings::state_report_file();
+    let json = match std::fs::read(&path) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+    let report: StateReport = match serde_json::from_slice(&json) {
+        Ok(report) => report,
+        Err(why) => {
+            warn!("Ignoring unreadable state report {}: {}", path, why);
+            let _ = std::fs::remove_file(&path);
+            return;
+        }
+    };
+    let channel = match settings::owner_log_channel().ok().flatten() {
+        Some(channel) => channel,
+        None => {
+            info!("Previous shutdown: {}", String::from_utf8_lossy(&json));
+            let _ = std::fs::remove_file(&path);
+            return;
+        }
+    };
+    let sent = channel
+        .send_message(http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: json.clone().into(),
+                    filename: "state-report.json".to_string(),
+                })
+                .embed(|embed| {
+                    embed
+                        .title(format!("Restarted after shutdown ({})", report.deployment))
+                        .description(format!(
+                            "Version {} ran from {} until {}.",
+                            report.version,
+                            response::datetime(report.started_at),
+                            response::datetime(report.stopped_at)
+                        ))
+                        .field("Pending Jobs", report.pending_jobs.len(), true)
+                        .field("Interrupted Work", report.interrupted_work, true)
+                        .field(
+                            "Unacknowledged Updates",
+                            report.unacknowledged_updates,
+                            true,
+                        )
+                        .field(
+                            "Dead-Letter Queue",
+                            report
+                                .dead_letter_queue
+                                .map_or("unknown".to_string(), |n| n.to_string()),
+                            true,
+                        )
+                        .field(
+                            "Last Events",
+                            response::field_lines(
+                                &report
+                                    .last_events
+                                    .iter()
+                                    .map(|(shard, at)| {
+                                        format!(
+                                            "shard {}: {}",
+                                            shard,
+                                            response::timestamp(
+                                                *at,
+                                                response::TimestampStyle::LongTime
+                                            )
+                                        )
+                                    })
+                                    .collect::<Vec<_>>(),
+                                "None",
+                            ),
+                            false,
+                        )
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await;
+    match sent {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&path);
+        }
+        // kept for the next start
+        Err(why) => warn!("Cannot post state report: {}", why),
+    }
+}
@@ -0,0 +1,119 @@
+//! Role and nickname snapshots taken before bulk jobs, so `/admin rollback` can revert them.
+//!
+//! Snapshots are kept for a week; DynamoDB deletes them through the table's TTL after that.
+
+use std::time::Duration;
+
+use rand::Rng;
+use serenity::client::Context;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, UserId};
+use tracing::error;
+
+use crate::{audit, db, nicknames, response, IgnoreSet};
+
+const RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Snapshots the given members before a job of `kind` (e.g. `rescan`) changes them, returning
+/// the job id to roll back with, or `None` if the snapshot could not be stored
+pub async fn take(
+    db_client: &db::DynamoDB,
+    guild_id: GuildId,
+    kind: &str,
+    members: &[Member],
+) -> Option<String> {
+    let job_id = format!("{}-{:08x}", kind, rand::thread_rng().gen::<u32>());
+    let expires_at = response::unix_now() + RETENTION_SECS;
+    let snapshots = members
+        .iter()
+        .map(|member| db::MemberSnapshot {
+            job_id: job_id.clone(),
+            guild_id,
+            user_id: member.user.id,
+            roles: member.roles.clone(),
+            nick: member.nick.clone(),
+            expires_at,
+        })
+        .collect::<Vec<_>>();
+    if db_client.put_snapshots(&snapshots).await {
+        Some(job_id)
+    } else {
+        None
+    }
+}
+
+/// Whether a job has snapshots in this guild
+pub async fn exists(db_client: &db::DynamoDB, guild_id: GuildId, job_id: &str) -> bool {
+    db_client
+        .get_snapshots(job_id)
+        .await
+        .iter()
+        .any(|s| s.guild_id == guild_id)
+}
+
+/// Restores the roles and nickname of every member in a job's snapshot who is still in the
+/// guild
+pub async fn rollback(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    guild_id: GuildId,
+    job_id: &str,
+    actor: UserId,
+    ignore_set: IgnoreSet,
+) -> serenity::Result<()> {
+    // roles deleted since the snapshot can't be given back
+    let existing = guild_id.roles(&ctx.http).await?;
+    let mut restored = 0;
+    for snapshot in db_client.get_snapshots(job_id).await {
+        if snapshot.guild_id != guild_id {
+            continue;
+        }
+        let member = match ctx.http.get_member(guild_id.0, snapshot.user_id.0).await {
+            Ok(member) => member,
+            // members who left since have nothing to restore
+            Err(_) => continue,
+        };
+        let mut roles = snapshot
+            .roles
+            .iter()
+            .filter(|r| existing.contains_key(r))
+            .copied()
+            .collect::<Vec<_>>();
+        roles.sort();
+        let mut current = member.roles.clone();
+        current.sort();
+        if roles == current && member.nick == snapshot.nick {
+            continue;
+        }
+        if member.nick != snapshot.nick {
+            nicknames::wait_turn(guild_id, member.user.id, nicknames::Source::Rollback).await;
+        }
+        // a single edit, so the one member update it causes is swallowed by the ignore set
+        // instead of the handler redoing the job's changes
+        {
+            ignore_set.lock().await.insert(member.user.id);
+        }
+        let nick = snapshot.nick.clone().unwrap_or_default();
+        if let Err(why) = member
+            .edit(&ctx.http, |m| m.roles(&roles).nickname(nick))
+            .await
+        {
+            ignore_set.lock().await.remove(&member.user.id);
+            error!("Failed to roll back {}: {}", member.user.id, why);
+            continue;
+        }
+        restored += 1;
+        // sleep to stay far away from rate limit
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    audit::record(
+        db_client,
+        guild_id,
+        actor,
+        "admin.rollback",
+        None,
+        format!("rolled back {}, {} members restored", job_id, restored),
+    )
+    .await;
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! Verifying by logging in with a UT EID, through the university's OpenID Connect provider set
+//! with `SSO_ISSUER`, instead of typing an EID and redeeming an emailed token.
+//!
+//! `/verify` replies with a link to `/sso/login`, carrying the member's Discord account and the
+//! server signed with `SHARED_KEY` and valid for [`LOGIN_SECS`]. That page names the Discord
+//! account about to be verified before sending the member on to the provider, since whoever
+//! logs in through a forwarded link verifies the account that asked for it. The provider sends
+//! the member back to `/sso/callback`, where the code is exchanged for the userinfo claims: the
+//! EID from `SSO_EID_CLAIM` and the affiliations from `SSO_AFFILIATION_CLAIM`. The EID is
+//! encrypted like the verification server does and linked the same way redeemed tokens are,
+//! so taken EIDs, bans and takeovers behave alike, and the roles and nickname follow in every
+//! server. Without `SSO_ISSUER`, `/verify` emails tokens as before.
+
+use axum::extract::{Extension, Query};
+use axum::http::header::SET_COOKIE;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use rand::Rng;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use serenity::client::Context;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::ButtonStyle;
+use serenity::model::interactions::InteractionApplicationCommandCallbackDataFlags;
+use serenity::utils::Color;
+use tracing::{info, warn};
+
+use crate::dashboard::{self, escape, page};
+use crate::i18n::{self, Locale};
+use crate::redeem::{self, Outcome};
+use crate::settings::{self, Sso};
+use crate::{db, http, response, templates, PUBLIC_URL, SHARED_KEY};
+
+/// How long a `/verify` link can be used for
+const LOGIN_SECS: i64 = 10 * 60;
+const STATE_COOKIE: &str = "utv_sso_state";
+/// What login links are signed for, so no other signed value passes as one
+const LOGIN_PURPOSE: &str = "sso-login";
+
+type Error = (StatusCode, &'static str);
+
+/// Who asked to be verified, signed into the login link and passed through the provider as the
+/// OAuth state
+#[derive(Serialize, Deserialize)]
+struct Login {
+    discord_id: u64,
+    guild_id: Option<u64>,
+    expires_at: i64,
+    /// makes each link's state unique
+    nonce: u64,
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    state: String,
+}
+
+#[derive(Deserialize)]
+pub struct Callback {
+    code: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct OAuthToken {
+    access_token: String,
+}
+
+fn redirect_uri() -> String {
+    format!("{}/sso/callback", PUBLIC_URL.as_str())
+}
+
+fn login_failed<E>(locale: Locale) -> impl FnOnce(E) -> Error {
+    move |_| {
+        (
+            StatusCode::BAD_GATEWAY,
+            i18n::text(locale, "sso.login-failed"),
+        )
+    }
+}
+
+fn configured() -> Result<Sso, Error> {
+    match settings::sso() {
+        Ok(Some(sso)) => Ok(sso),
+        _ => Err((StatusCode::NOT_FOUND, "UT login is disabled.")),
+    }
+}
+
+/// The signed login the state carries, if it's still valid
+fn login(state: &str) -> Result<Login, Error> {
+    match utv_token::verify::<Login>(LOGIN_PURPOSE, state, &SHARED_KEY) {
+        Ok(login) if login.expires_at > response::unix_now() => Ok(login),
+        _ => Err((
+            StatusCode::BAD_REQUEST,
+            "This link expired, run /verify again.",
+        )),
+    }
+}
+
+async fn discover(client: &reqwest::Client, sso: &Sso, locale: Locale) -> Result<Discovery, Error> {
+    client
+        .get(format!("{}/.well-known/openid-configuration", sso.issuer))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(login_failed(locale))?
+        .json()
+        .await
+        .map_err(login_failed(locale))
+}
+
+/// The reply to `/verify` when UT login is configured: a link to log in with
+pub async fn offer(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+    locale: Locale,
+) -> serenity::Result<()> {
+    let login = Login {
+        discord_id: command.user.id.0,
+        guild_id: command.guild_id.map(|g| g.0),
+        expires_at: response::unix_now() + LOGIN_SECS,
+        nonce: rand::thread_rng().gen(),
+    };
+    let url = format!(
+        "{}/sso/login?state={}",
+        PUBLIC_URL.as_str(),
+        utv_token::sign(LOGIN_PURPOSE, &login, &SHARED_KEY)
+    );
+    command
+        .create_interaction_response(&ctx.http, |interaction| {
+            interaction.interaction_response_data(|message| {
+                message
+                    .create_embed(|embed| {
+                        embed
+                            .title(i18n::text(locale, "verify.sso.title"))
+                            .description(i18n::text(locale, "verify.sso"))
+                            .color(Color::from_rgb(191, 87, 0))
+                    })
+                    .components(|components| {
+                        components.create_action_row(|row| {
+                            row.create_button(|button| {
+                                button
+                                    .style(ButtonStyle::Link)
+                                    .url(url)
+                                    .label(i18n::text(locale, "verify.sso.button"))
+                            })
+                        })
+                    })
+                    .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+        })
+        .await
+}
+
+/// `GET /sso/login`: names the Discord account being verified and links to the provider
+pub async fn start(
+    Query(query): Query<LoginQuery>,
+    Extension(state): Extension<http::State>,
+) -> Result<Response, Error> {
+    let sso = configured()?;
+    let login = login(&query.state)?;
+    let locale = i18n::locale(state.db_client, login.guild_id.map(GuildId)).await;
+    let discovery = discover(&reqwest::Client::new(), &sso, locale).await?;
+    let url = Url::parse_with_params(
+        &discovery.authorization_endpoint,
+        &[
+            ("client_id", sso.client_id.as_str()),
+            ("redirect_uri", redirect_uri().as_str()),
+            ("response_type", "code"),
+            ("scope", "openid profile"),
+            ("state", query.state.as_str()),
+        ],
+    )
+    .map_err(login_failed(locale))?;
+    let account = match state.http.get_user(login.discord_id).await {
+        Ok(user) => user.tag(),
+        Err(_) => login.discord_id.to_string(),
+    };
+    let body = format!(
+        "<p>{}</p><p><a href=\"{}\">{}</a></p>",
+        templates::render(
+            &escape(i18n::text(locale, "sso.confirm")),
+            &[("account", &format!("<b>{}</b>", escape(&account)))],
+        ),
+        escape(url.as_str()),
+        escape(i18n::text(locale, "sso.confirm.link"))
+    );
+    let mut headers = HeaderMap::new();
+    set_cookie(&mut headers, &query.state, LOGIN_SECS);
+    let title = i18n::text(locale, "sso.confirm.title");
+    Ok((headers, page(title, &body)).into_response())
+}
+
+/// `GET /sso/callback`: links the account to the EID the provider vouched for
+pub async fn callback(
+    Query(callback): Query<Callback>,
+    headers: HeaderMap,
+    Extension(state): Extension<http::State>,
+) -> Result<Response, Error> {
+    let sso = configured()?;
+    // the browser that saw whose account it verifies has to be the one finishing
+    if dashboard::cookie(&headers, STATE_COOKIE) != Some(callback.state.as_str()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "This login wasn't started here, run /verify again.",
+        ));
+    }
+    let login = login(&callback.state)?;
+    let locale = i18n::locale(state.db_client, login.guild_id.map(GuildId)).await;
+    let client = reqwest::Client::new();
+    let discovery = discover(&client, &sso, locale).await?;
+    let token: OAuthToken = client
+        .post(&discovery.token_endpoint)
+        .form(&[
+            ("client_id", sso.client_id.as_str()),
+            ("client_secret", sso.client_secret.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code", callback.code.as_str()),
+            ("redirect_uri", redirect_uri().as_str()),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(login_failed(locale))?
+        .json()
+        .await
+        .map_err(login_failed(locale))?;
+    let userinfo: Map<String, Value> = client
+        .get(&discovery.userinfo_endpoint)
+        .bearer_auth(&token.access_token)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(login_failed(locale))?
+        .json()
+        .await
+        .map_err(login_failed(locale))?;
+
+    let eid = match userinfo.get(&sso.eid_claim).and_then(Value::as_str) {
+        Some(eid) if !eid.trim().is_empty() => eid.trim().to_lowercase(),
+        _ => {
+            warn!("UT login returned no {} claim", sso.eid_claim);
+            return Err((StatusCode::BAD_GATEWAY, i18n::text(locale, "sso.no-eid")));
+        }
+    };
+    let key = settings::encryption_key()
+        .ok()
+        .flatten()
+        .ok_or((StatusCode::NOT_FOUND, "UT login is disabled."))?;
+    let claims = db::Claims {
+        major: Vec::new(),
+        school: Vec::new(),
+        affiliation: affiliations(userinfo.get(&sso.affiliation_claim)),
+        name: userinfo
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    };
+    let discord_id = UserId(login.discord_id);
+    let outcome = redeem::link_claims(
+        state.db_client,
+        &state.http,
+        login.guild_id.map(GuildId),
+        discord_id,
+        &base64::encode(utv_token::deterministic_aes::encrypt(eid.as_bytes(), &key)),
+        claims,
+    )
+    .await;
+    if let Outcome::Linked = outcome {
+        info!("{} verified with UT login", discord_id);
+    }
+    let (title, message) = match outcome {
+        Outcome::Linked => ("sso.linked.title", "sso.linked"),
+        Outcome::AlreadyLinked => ("sso.already-linked.title", "sso.already-linked"),
+        Outcome::EidInUse => ("sso.eid-in-use.title", "sso.eid-in-use"),
+        Outcome::Banned => ("sso.banned.title", "sso.banned"),
+        _ => ("sso.failed.title", "sso.failed"),
+    };
+    let body = format!("<p>{}</p>", escape(i18n::text(locale, message)));
+    let mut headers = HeaderMap::new();
+    set_cookie(&mut headers, "", 0);
+    Ok((headers, page(i18n::text(locale, title), &body)).into_response())
+}
+
+/// The affiliation claim, given as one string or an array, as lowercase affiliations
+fn affiliations(claim: Option<&Value>) -> Vec<String> {
+    let values = match claim {
+        Some(Value::String(value)) => vec![value.as_str()],
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    values
+        .into_iter()
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn set_cookie(headers: &mut HeaderMap, value: &str, max_age: i64) {
+    let cookie = format!(
+        "{}={}; Path=/sso; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        STATE_COOKIE, value, max_age
+    );
+    if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+        headers.append(SET_COOKIE, cookie);
+    }
+}
@@ -0,0 +1,336 @@
+//! Per-guild verification counts, cached briefly.
+//!
+//! They also drive the milestone announcements configured with `/config milestones`.
+//!
+//! Counting means walking the guild's member list and looking every member up in the user
+//! table, which is too slow to repeat for each `/help` in a large server. Counts are kept for
+//! [`TTL_SECS`] and dropped early when a member joins or verifies.
+//!
+//! Guilds can publish their count on the public stats endpoint. Anyone can call it, so those
+//! counts are cached separately for [`PUBLIC_TTL_SECS`] regardless of joins, and only one is
+//! recounted at a time.
+//!
+//! `/stats` goes further for the guild's admins: recent verifications and the most common
+//! affiliations, from the records of every member, which is why it isn't cached. It also shows
+//! what the guild's last sweep fixed.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+use tracing::warn;
+
+use crate::{db, handlers, members, response, templates};
+
+const DEFAULT_MILESTONE_TEMPLATE: &str = "🎉 {server} just reached {count} verified members!";
+
+pub const TTL_SECS: i64 = 5 * 60;
+pub const PUBLIC_TTL_SECS: i64 = 15 * 60;
+const DAY: i64 = 24 * 60 * 60;
+/// affiliations listed by `/stats`
+const TOP_AFFILIATIONS: usize = 5;
+
+#[derive(Clone, Copy, Debug)]
+pub struct GuildStats {
+    /// members who aren't bots
+    pub members: usize,
+    pub verified: usize,
+}
+
+/// What a sweep of the guild's members did, see `sweep`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sweep {
+    pub finished_at: i64,
+    pub checked: usize,
+    pub renamed: usize,
+    pub roles_added: usize,
+    pub failed: usize,
+}
+
+lazy_static! {
+    /// stats and the unix time they were computed at
+    static ref CACHE: Mutex<HashMap<GuildId, (i64, GuildStats)>> = Mutex::new(HashMap::new());
+    static ref PUBLIC_CACHE: Mutex<HashMap<GuildId, (i64, GuildStats)>> =
+        Mutex::new(HashMap::new());
+    static ref PUBLIC_RECOUNT: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    /// when each guild's last full scan finished
+    static ref LAST_SCANS: Mutex<HashMap<GuildId, i64>> = Mutex::new(HashMap::new());
+    static ref LAST_SWEEPS: Mutex<HashMap<GuildId, Sweep>> = Mutex::new(HashMap::new());
+}
+
+pub async fn guild_stats(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<GuildStats> {
+    let now = response::unix_now();
+    if let Some((at, stats)) = CACHE.lock().unwrap().get(&guild_id) {
+        if now - at < TTL_SECS {
+            return Ok(*stats);
+        }
+    }
+    let member_ids = members::fetch_all(http, guild_id)
+        .await?
+        .into_iter()
+        .filter(|m| !m.user.bot)
+        .map(|m| m.user.id)
+        .collect::<Vec<_>>();
+    let stats = GuildStats {
+        members: member_ids.len(),
+        verified: db_client.verified_among(&member_ids).await.len(),
+    };
+    CACHE.lock().unwrap().insert(guild_id, (now, stats));
+    Ok(stats)
+}
+
+/// Drops a guild's cached stats after its membership or verifications changed
+pub fn invalidate(guild_id: GuildId) {
+    CACHE.lock().unwrap().remove(&guild_id);
+}
+
+/// Drops every guild's cached stats, for `/shutdown reload:True`
+pub fn clear() {
+    CACHE.lock().unwrap().clear();
+    PUBLIC_CACHE.lock().unwrap().clear();
+}
+
+fn fresh_public(guild_id: GuildId, now: i64) -> Option<(i64, GuildStats)> {
+    PUBLIC_CACHE
+        .lock()
+        .unwrap()
+        .get(&guild_id)
+        .filter(|(at, _)| now - at < PUBLIC_TTL_SECS)
+        .copied()
+}
+
+/// Stats for the public endpoint, with the unix time they were counted at
+pub async fn public_stats(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+) -> serenity::Result<(i64, GuildStats)> {
+    let now = response::unix_now();
+    if let Some(cached) = fresh_public(guild_id, now) {
+        return Ok(cached);
+    }
+    let _recount = PUBLIC_RECOUNT.lock().await;
+    // another request may have counted while this one waited
+    if let Some(cached) = fresh_public(guild_id, now) {
+        return Ok(cached);
+    }
+    let stats = guild_stats(db_client, http, guild_id).await?;
+    PUBLIC_CACHE.lock().unwrap().insert(guild_id, (now, stats));
+    Ok((now, stats))
+}
+
+/// Stops serving a guild's cached public stats, after it stopped publishing them
+pub fn forget_public(guild_id: GuildId) {
+    PUBLIC_CACHE.lock().unwrap().remove(&guild_id);
+}
+
+pub fn record_scan(guild_id: GuildId) {
+    LAST_SCANS
+        .lock()
+        .unwrap()
+        .insert(guild_id, response::unix_now());
+}
+
+/// When the guild's last full scan since the bot started finished
+pub fn last_scan(guild_id: GuildId) -> Option<i64> {
+    LAST_SCANS.lock().unwrap().get(&guild_id).copied()
+}
+
+/// Records a finished sweep, which is also a full scan
+pub fn record_sweep(guild_id: GuildId, sweep: Sweep) {
+    record_scan(guild_id);
+    LAST_SWEEPS.lock().unwrap().insert(guild_id, sweep);
+}
+
+/// The guild's last sweep since the bot started
+pub fn last_sweep(guild_id: GuildId) -> Option<Sweep> {
+    LAST_SWEEPS.lock().unwrap().get(&guild_id).copied()
+}
+
+/// The largest milestone at or below `verified`: 100, 500, then every thousand
+fn milestone(verified: usize) -> u64 {
+    match verified as u64 {
+        n if n >= 1000 => n / 1000 * 1000,
+        n if n >= 500 => 500,
+        n if n >= 100 => 100,
+        _ => 0,
+    }
+}
+
+/// Announces the verified-member milestone the guild just reached, if it announces them.
+/// Called after a member verifies.
+pub async fn check_milestone(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId) {
+    let mut config = db_client.get_guild_config(guild_id).await;
+    let channel = match config.milestone_channel {
+        Some(channel) => channel,
+        None => return,
+    };
+    let reached = match guild_stats(db_client, http, guild_id).await {
+        Ok(stats) => milestone(stats.verified),
+        Err(why) => {
+            warn!("Cannot count verified members of {}: {}", guild_id, why);
+            return;
+        }
+    };
+    let announce = match config.last_milestone {
+        Some(last) => reached > last,
+        None => false,
+    };
+    if config.last_milestone == Some(reached) {
+        return;
+    }
+    config.last_milestone = Some(reached);
+    if !db_client.set_guild_config(guild_id, &config).await || !announce {
+        return;
+    }
+    let server = match guild_id.to_partial_guild(http).await {
+        Ok(guild) => guild.name,
+        Err(_) => "This server".to_string(),
+    };
+    let message = templates::render(
+        config
+            .milestone_template
+            .as_deref()
+            .unwrap_or(DEFAULT_MILESTONE_TEMPLATE),
+        &[("count", &reached.to_string()), ("server", &server)],
+    );
+    if let Err(why) = channel.say(http, message).await {
+        warn!("Cannot announce milestone in {}: {}", channel, why);
+    }
+}
+
+pub async fn stats(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    response::defer(&ctx, &command, true).await?;
+    let member_ids = members::fetch_all(&ctx.http, guild_id)
+        .await?
+        .into_iter()
+        .filter(|m| !m.user.bot)
+        .map(|m| m.user.id)
+        .collect::<Vec<_>>();
+    let verified = db_client
+        .records_among(&member_ids)
+        .await
+        .into_values()
+        .filter(|record| record.encrypted_eid.is_some())
+        .collect::<Vec<_>>();
+
+    let now = response::unix_now();
+    let since = |days: i64| {
+        verified
+            .iter()
+            .filter(|r| r.verified_at.map_or(false, |at| at > now - days * DAY))
+            .count()
+    };
+    let share = |n: usize| {
+        if member_ids.is_empty() {
+            0.0
+        } else {
+            n as f64 * 100.0 / member_ids.len() as f64
+        }
+    };
+    let mut affiliations: HashMap<&str, usize> = HashMap::new();
+    for record in &verified {
+        for affiliation in record.claims.iter().flat_map(|c| &c.affiliation) {
+            *affiliations.entry(affiliation.as_str()).or_default() += 1;
+        }
+    }
+    let mut affiliations = affiliations.into_iter().collect::<Vec<_>>();
+    affiliations.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let top = affiliations
+        .iter()
+        .take(TOP_AFFILIATIONS)
+        .map(|(affiliation, n)| format!("**{}**: {}", affiliation, n))
+        .collect::<Vec<_>>();
+    let undated = verified.iter().filter(|r| r.verified_at.is_none()).count();
+
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message.create_embed(|embed| {
+                embed
+                    .title("Verification Stats")
+                    .field("Members", member_ids.len(), true)
+                    .field(
+                        "Verified",
+                        format!("{} ({:.1}%)", verified.len(), share(verified.len())),
+                        true,
+                    )
+                    .field("\u{200b}", "\u{200b}", true);
+                for days in [7, 30] {
+                    let n = since(days);
+                    embed.field(
+                        format!("Last {} Days", days),
+                        format!(
+                            "{} verified, {:.1}% of members, {:.1} a day",
+                            n,
+                            share(n),
+                            n as f64 / days as f64
+                        ),
+                        true,
+                    );
+                }
+                embed.field(
+                    "Top Affiliations",
+                    if top.is_empty() {
+                        "None".to_string()
+                    } else {
+                        top.join("\n")
+                    },
+                    false,
+                );
+                embed.field(
+                    "Last Sweep",
+                    match last_sweep(guild_id) {
+                        Some(sweep) => format!(
+                            "{}: {} members checked, {} nicknames fixed, {} roles assigned, \
+                             {} failed",
+                            response::timestamp(
+                                sweep.finished_at,
+                                response::TimestampStyle::Relative
+                            ),
+                            sweep.checked,
+                            sweep.renamed,
+                            sweep.roles_added,
+                            sweep.failed
+                        ),
+                        None => "None since the bot started".to_string(),
+                    },
+                    false,
+                );
+                if undated > 0 {
+                    embed.footer(|footer| {
+                        footer.text(format!(
+                            "{} members verified before verification dates were recorded and \
+                             aren't in the recent counts.",
+                            undated
+                        ))
+                    });
+                }
+                embed.color(Color::from_rgb(191, 87, 0))
+            })
+        })
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! What members see after verifying, configured with `/config on-verify`.
+//!
+//! Besides the confirmation `/redeem` always shows, a guild can add its own message to that
+//! reply, welcome the member publicly in a channel and DM them, e.g. with links to the org's
+//! resources. All three use the same template.
+
+use serde::{Deserialize, Serialize};
+use serenity::http::Http;
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId};
+use tracing::warn;
+
+use crate::i18n::{self, Locale};
+use crate::{db, templates};
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct SuccessActions {
+    /// add the message to the `/redeem` reply only the member sees
+    pub ephemeral: bool,
+    /// channel the member is welcomed in publicly
+    pub welcome_channel: Option<ChannelId>,
+    /// DM the message to the member
+    pub dm: bool,
+    /// message with `{user}` and `{server}` placeholders, a default one when unset
+    pub template: Option<String>,
+}
+
+impl SuccessActions {
+    fn render(&self, locale: Locale, user: &str, server: &str) -> String {
+        templates::render(
+            self.template
+                .as_deref()
+                .unwrap_or_else(|| i18n::text(locale, "success.welcome")),
+            &[("user", user), ("server", server)],
+        )
+    }
+
+    /// The actions as a sentence, for `/config`
+    pub fn describe(&self) -> String {
+        let mut actions = Vec::new();
+        if self.ephemeral {
+            actions.push("a message in their `/redeem` reply".to_string());
+        }
+        if let Some(channel) = self.welcome_channel {
+            actions.push(format!("a welcome in <#{}>", channel));
+        }
+        if self.dm {
+            actions.push("a DM".to_string());
+        }
+        if actions.is_empty() {
+            "the default confirmation".to_string()
+        } else {
+            actions.join(", ")
+        }
+    }
+}
+
+async fn server_name(http: &Http, guild_id: GuildId, locale: Locale) -> String {
+    match guild_id.to_partial_guild(http).await {
+        Ok(guild) => guild.name,
+        Err(_) => i18n::text(locale, "server.unknown").to_string(),
+    }
+}
+
+/// The guild's message for the `/redeem` reply, if it adds one
+pub async fn ephemeral_message(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    user: &str,
+) -> Option<String> {
+    let config = db_client.get_guild_config(guild_id).await;
+    let actions = config.success_actions;
+    if !actions.ephemeral {
+        return None;
+    }
+    let server = server_name(http, guild_id, config.locale).await;
+    Some(actions.render(config.locale, user, &server))
+}
+
+/// Welcomes a member who just verified, in public and by DM as the guild configured
+pub async fn run(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId, member: &Member) {
+    let config = db_client.get_guild_config(guild_id).await;
+    let actions = config.success_actions;
+    if actions.welcome_channel.is_none() && !actions.dm {
+        return;
+    }
+    let server = server_name(http, guild_id, config.locale).await;
+    if let Some(channel) = actions.welcome_channel {
+        let message = actions.render(config.locale, &format!("<@{}>", member.user.id), &server);
+        if let Err(why) = channel.say(http, message).await {
+            warn!("Cannot welcome {} in {}: {}", member.user.id, channel, why);
+        }
+    }
+    if actions.dm {
+        let message = actions.render(config.locale, &member.user.name, &server);
+        let sent = match member.user.create_dm_channel(http).await {
+            Ok(dm) => dm.say(http, message).await.map(|_| ()),
+            Err(why) => Err(why),
+        };
+        // members can turn off DMs from server members
+        if let Err(why) = sent {
+            warn!("Cannot DM {} after verifying: {}", member.user.id, why);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! `/support`: bug reports forwarded to the maintainers' support channel.
+//!
+//! Reports used to arrive as DMs without the context needed to look into them. The form asks
+//! for a summary and details, and the report adds the guild, shard and the bot's recent errors
+//! in that guild. No member ids or names other than the reporter's are included.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serenity::builder::CreateInteractionResponseData;
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::model::interactions::message_component::{ActionRowComponent, InputTextStyle};
+use serenity::model::interactions::modal::ModalSubmitInteraction;
+use serenity::model::interactions::{
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+use serenity::utils::Color;
+use tracing::warn;
+
+use crate::{response, settings};
+
+/// Custom id of the report modal
+pub const MODAL_ID: &str = "support";
+/// errors kept across all guilds
+const HISTORY: usize = 200;
+/// errors attached to a single report
+const REPORTED_ERRORS: usize = 5;
+
+struct RecordedError {
+    at: i64,
+    guild_id: Option<GuildId>,
+    message: String,
+}
+
+lazy_static! {
+    static ref RECENT_ERRORS: Mutex<VecDeque<RecordedError>> = Mutex::new(VecDeque::new());
+}
+
+/// Remembers an error for the diagnostics of later reports from the same guild
+pub fn record_error(guild_id: Option<GuildId>, context: &str, why: impl Display) {
+    let mut errors = RECENT_ERRORS.lock().unwrap();
+    errors.push_back(RecordedError {
+        at: response::unix_now(),
+        guild_id,
+        message: format!("{}: {}", context, why),
+    });
+    if errors.len() > HISTORY {
+        errors.pop_front();
+    }
+}
+
+fn recent_errors(guild_id: Option<GuildId>) -> Vec<String> {
+    let errors = RECENT_ERRORS.lock().unwrap();
+    let mut recent = errors
+        .iter()
+        .rev()
+        .filter(|e| e.guild_id == guild_id)
+        .take(REPORTED_ERRORS)
+        .map(|e| {
+            format!(
+                "{} {}",
+                response::timestamp(e.at, response::TimestampStyle::ShortDateTime),
+                e.message
+            )
+        })
+        .collect::<Vec<_>>();
+    recent.reverse();
+    recent
+}
+
+pub async fn support(command: ApplicationCommandInteraction, ctx: Context) -> serenity::Result<()> {
+    if settings::support_channel().ok().flatten().is_none() {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Support reports are not enabled on this instance of the bot.",
+        )
+        .await;
+    }
+    command
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::Modal)
+                .interaction_response_data(report_modal)
+        })
+        .await
+}
+
+fn report_modal(modal: &mut CreateInteractionResponseData) -> &mut CreateInteractionResponseData {
+    modal
+        .custom_id(MODAL_ID)
+        .title("Report a Problem")
+        .components(|components| {
+            components
+                .create_action_row(|row| {
+                    row.create_input_text(|input| {
+                        input
+                            .custom_id("summary")
+                            .label("What went wrong?")
+                            .style(InputTextStyle::Short)
+                            .max_length(100)
+                            .required(true)
+                    })
+                })
+                .create_action_row(|row| {
+                    row.create_input_text(|input| {
+                        input
+                            .custom_id("details")
+                            .label("What did you do, and what did you expect?")
+                            .style(InputTextStyle::Paragraph)
+                            .max_length(1000)
+                            .required(true)
+                    })
+                })
+                .create_action_row(|row| {
+                    row.create_input_text(|input| {
+                        input
+                            .custom_id("command")
+                            .label("Command involved, if any")
+                            .style(InputTextStyle::Short)
+                            .max_length(100)
+                            .required(false)
+                    })
+                })
+        })
+}
+
+/// Handles a submitted report, forwarding it to the support channel
+pub async fn submitted(modal: ModalSubmitInteraction, ctx: Context) -> serenity::Result<()> {
+    let field = |id: &str| {
+        modal
+            .data
+            .components
+            .iter()
+            .flat_map(|row| row.components.iter())
+            .find_map(|component| match component {
+                ActionRowComponent::InputText(text) if text.custom_id == id => {
+                    Some(text.value.trim().to_string())
+                }
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+    let (summary, details, command) = (field("summary"), field("details"), field("command"));
+    let report_id = format!("{:08x}", rand::random::<u32>());
+    let errors = recent_errors(modal.guild_id);
+
+    let forwarded = match settings::support_channel().ok().flatten() {
+        Some(channel) => channel
+            .send_message(&ctx.http, |message| {
+                message.embed(|embed| {
+                    embed
+                        .title(format!("Report {}: {}", report_id, summary))
+                        .description(&details)
+                        .field(
+                            "Reporter",
+                            format!("{} ({})", modal.user.tag(), modal.user.id),
+                            true,
+                        )
+                        .field(
+                            "Guild",
+                            modal
+                                .guild_id
+                                .map_or("DM".to_string(), |guild| guild.to_string()),
+                            true,
+                        )
+                        .field("Shard", ctx.shard_id, true)
+                        .field("Version", env!("CARGO_PKG_VERSION"), true)
+                        .field(
+                            "Command",
+                            if command.is_empty() { "-" } else { &command },
+                            true,
+                        )
+                        .field(
+                            "Recent Errors",
+                            response::field_lines(&errors, "None"),
+                            false,
+                        )
+                        .timestamp(chrono::Utc::now().to_rfc3339())
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+            })
+            .await
+            .map_err(|why| warn!("Cannot forward support report {}: {}", report_id, why))
+            .is_ok(),
+        None => false,
+    };
+    let title = if forwarded {
+        format!(
+            "Thanks! Your report was sent to the maintainers. Reference: {}",
+            report_id
+        )
+    } else {
+        "Failed to send your report, please try again later".to_string()
+    };
+    modal
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|message| {
+                    message
+                        .create_embed(|embed| embed.title(title))
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await
+}
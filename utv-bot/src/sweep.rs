@@ -0,0 +1,151 @@
+//! Sweeps: every guild's members checked on a schedule, like the scan when the bot joins.
+//!
+//! Events can be missed while disconnected and admins change roles or nicknames in ways no
+//! event covers, so every `SWEEP_INTERVAL_HOURS` (nightly by default) each member goes through
+//! the same checks as on join. A sweep is background work, so it's paced by a token bucket to
+//! [`MEMBERS_PER_SECOND`] and backs off for [`BACKOFF`] whenever a rate limit bucket ran dry,
+//! leaving room for verifications. Results are shown by `/stats`.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use tracing::{error, info, warn};
+
+use crate::{
+    db, handle_member_status, members, nicknames, ratelimits, response, sharding, shutdown, stats,
+    telemetry, IgnoreSet,
+};
+
+/// members checked per second on average
+const MEMBERS_PER_SECOND: f64 = 5.0;
+/// members checked back to back after a quiet spell
+const BURST: f64 = 10.0;
+/// pause after a rate limit bucket ran dry
+const BACKOFF: Duration = Duration::from_secs(5);
+
+/// Hands out [`MEMBERS_PER_SECOND`] tokens a second, holding at most [`BURST`]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new() -> TokenBucket {
+        TokenBucket {
+            tokens: BURST,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Waits for a token and takes it
+    async fn take(&mut self) {
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * MEMBERS_PER_SECOND).min(BURST);
+            self.refilled_at = now;
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs_f64(
+                (1.0 - self.tokens) / MEMBERS_PER_SECOND,
+            ))
+            .await;
+        }
+    }
+}
+
+/// Sweeps every guild once per interval
+pub async fn sweep_loop(
+    db_client: &'static db::DynamoDB,
+    ctx: Arc<Context>,
+    ignore_set: IgnoreSet,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let guilds = match sharding::guilds(&ctx.http).await {
+            Ok(guilds) => guilds,
+            Err(why) => {
+                warn!("Cannot list guilds to sweep: {}", why);
+                continue;
+            }
+        };
+        let started = Instant::now();
+        let mut bucket = TokenBucket::new();
+        for guild_id in &guilds {
+            if shutdown::draining() {
+                return;
+            }
+            sweep(db_client, &ctx, ignore_set.clone(), *guild_id, &mut bucket).await;
+        }
+        info!(
+            "Swept {} guilds in {} minutes",
+            guilds.len(),
+            started.elapsed().as_secs() / 60
+        );
+    }
+}
+
+async fn sweep(
+    db_client: &db::DynamoDB,
+    ctx: &Context,
+    ignore_set: IgnoreSet,
+    guild_id: GuildId,
+    bucket: &mut TokenBucket,
+) {
+    let _job = ratelimits::job(guild_id, "sweep");
+    let started = Instant::now();
+    let guild_members = match members::fetch_all(&ctx.http, guild_id).await {
+        Ok(guild_members) => guild_members,
+        Err(why) => {
+            warn!("Cannot list members of {} to sweep: {}", guild_id, why);
+            return;
+        }
+    };
+    let role_mappings = db_client.get_role_config(guild_id).await;
+    let mut result = stats::Sweep::default();
+    for mut member in guild_members {
+        if shutdown::draining() {
+            info!(
+                "Sweep of {} stopped for shutdown after {} members",
+                guild_id, result.checked
+            );
+            return;
+        }
+        bucket.take().await;
+        if !ratelimits::exhausted_since(response::unix_now() - BACKOFF.as_secs() as i64).is_empty()
+        {
+            tokio::time::sleep(BACKOFF).await;
+        }
+        result.checked += 1;
+        match handle_member_status(
+            db_client,
+            ctx,
+            &mut member,
+            &role_mappings,
+            ignore_set.clone(),
+            nicknames::Source::Sweep,
+        )
+        .await
+        {
+            Ok(changes) => {
+                result.renamed += changes.renamed as usize;
+                result.roles_added += changes.roles_added;
+            }
+            Err(why) => {
+                result.failed += 1;
+                error!(
+                    "Sweep of {} failed for {}: {}",
+                    guild_id, member.user.id, why
+                );
+            }
+        }
+    }
+    result.finished_at = response::unix_now();
+    stats::record_sweep(guild_id, result);
+    telemetry::record_scan("sweep", started.elapsed());
+}
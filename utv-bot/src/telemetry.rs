@@ -0,0 +1,206 @@
+//! Metrics served at `/metrics` in Prometheus' text format: interaction errors and latency,
+//! verifications processed, role and nickname changes, failed Discord requests, scan durations
+//! and DynamoDB latency. Scraping needs an API key scoped to the `metrics` endpoint, since the
+//! listener is public.
+//!
+//! Every series is labelled with the deployment (`stable` or `canary`), so a canary running a
+//! few shards can be compared side by side with the stable instance serving the rest.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::extract::Extension;
+use lazy_static::lazy_static;
+
+use crate::api::Authorized;
+use crate::{http, settings};
+
+/// upper bounds of the latency histogram buckets, in seconds
+const BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+/// scans of large guilds take the better part of an hour
+const SCAN_BUCKETS: [f64; 7] = [10.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0];
+const DB_BUCKETS: [f64; 8] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// A histogram of durations, with the bucket bounds it was created with
+struct Series {
+    count: u64,
+    errors: u64,
+    seconds: f64,
+    bounds: &'static [f64],
+    buckets: Vec<u64>,
+}
+
+impl Series {
+    fn new(bounds: &'static [f64]) -> Series {
+        Series {
+            count: 0,
+            errors: 0,
+            seconds: 0.0,
+            bounds,
+            buckets: vec![0; bounds.len()],
+        }
+    }
+
+    fn observe(&mut self, elapsed: Duration, ok: bool) {
+        let seconds = elapsed.as_secs_f64();
+        self.count += 1;
+        self.seconds += seconds;
+        if !ok {
+            self.errors += 1;
+        }
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter_mut()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        for (bound, count) in self.bounds.iter().zip(self.buckets.iter()) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.seconds);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Counters, each a Prometheus series per label value
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    /// verification updates taken off the queue, by `result`
+    Verifications,
+    /// roles added to members, by `result`
+    RoleAssignments,
+    /// nickname edits, by `result`
+    NicknameEdits,
+    /// failed Discord API requests outside interactions, by `op`
+    DiscordErrors,
+}
+
+impl Counter {
+    fn name(self) -> &'static str {
+        match self {
+            Counter::Verifications => "utv_verifications_processed_total",
+            Counter::RoleAssignments => "utv_role_assignments_total",
+            Counter::NicknameEdits => "utv_nickname_edits_total",
+            Counter::DiscordErrors => "utv_discord_errors_total",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Counter::DiscordErrors => "op",
+            _ => "result",
+        }
+    }
+}
+
+lazy_static! {
+    /// keyed by interaction kind and name
+    static ref SERIES: Mutex<BTreeMap<(&'static str, String), Series>> = Mutex::new(BTreeMap::new());
+    /// keyed by what started the scan
+    static ref SCANS: Mutex<BTreeMap<&'static str, Series>> = Mutex::new(BTreeMap::new());
+    /// keyed by `DynamoDB` operation
+    static ref DB: Mutex<BTreeMap<&'static str, Series>> = Mutex::new(BTreeMap::new());
+    static ref COUNTERS: Mutex<BTreeMap<(Counter, &'static str), u64>> = Mutex::new(BTreeMap::new());
+}
+
+/// Records a handled interaction. Component and modal ids are cut at the first `:` so ids
+/// carrying state don't each become a series.
+pub fn record(kind: &'static str, name: &str, elapsed: Duration, ok: bool) {
+    let name = name.split(':').next().unwrap_or(name).to_string();
+    SERIES
+        .lock()
+        .unwrap()
+        .entry((kind, name))
+        .or_insert_with(|| Series::new(&BUCKETS))
+        .observe(elapsed, ok);
+}
+
+/// Adds `n` to a counter's series for `label`
+pub fn count(counter: Counter, label: &'static str, n: u64) {
+    *COUNTERS
+        .lock()
+        .unwrap()
+        .entry((counter, label))
+        .or_default() += n;
+}
+
+/// Records how long a member scan took, `trigger` being `rescan` or `automatic`
+pub fn record_scan(trigger: &'static str, elapsed: Duration) {
+    SCANS
+        .lock()
+        .unwrap()
+        .entry(trigger)
+        .or_insert_with(|| Series::new(&SCAN_BUCKETS))
+        .observe(elapsed, true);
+}
+
+/// Records a `DynamoDB` request, see `db::timed`
+pub fn record_db(op: &'static str, elapsed: Duration, ok: bool) {
+    DB.lock()
+        .unwrap()
+        .entry(op)
+        .or_insert_with(|| Series::new(&DB_BUCKETS))
+        .observe(elapsed, ok);
+}
+
+pub async fn metrics(_: Authorized, Extension(state): Extension<http::State>) -> String {
+    let deployment = state.deployment;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "utv_info{{deployment=\"{}\",shards=\"{}\",version=\"{}\"}} 1",
+        deployment,
+        settings::shards()
+            .ok()
+            .flatten()
+            .map_or("all".to_string(), |s| s.to_string()),
+        env!("CARGO_PKG_VERSION")
+    );
+    for ((kind, name), series) in SERIES.lock().unwrap().iter() {
+        let labels = format!(
+            "deployment=\"{}\",kind=\"{}\",name=\"{}\"",
+            deployment, kind, name
+        );
+        let _ = writeln!(out, "utv_interactions_total{{{}}} {}", labels, series.count);
+        let _ = writeln!(
+            out,
+            "utv_interaction_errors_total{{{}}} {}",
+            labels, series.errors
+        );
+        series.write(&mut out, "utv_interaction_seconds", &labels);
+    }
+    for ((counter, label), n) in COUNTERS.lock().unwrap().iter() {
+        let _ = writeln!(
+            out,
+            "{}{{deployment=\"{}\",{}=\"{}\"}} {}",
+            counter.name(),
+            deployment,
+            counter.label(),
+            label,
+            n
+        );
+    }
+    for (trigger, series) in SCANS.lock().unwrap().iter() {
+        let labels = format!("deployment=\"{}\",trigger=\"{}\"", deployment, trigger);
+        series.write(&mut out, "utv_scan_seconds", &labels);
+    }
+    for (op, series) in DB.lock().unwrap().iter() {
+        let labels = format!("deployment=\"{}\",op=\"{}\"", deployment, op);
+        let _ = writeln!(out, "utv_db_errors_total{{{}}} {}", labels, series.errors);
+        series.write(&mut out, "utv_db_seconds", &labels);
+    }
+    out
+}
@@ -0,0 +1,10 @@
+//! Messages guilds write themselves, with `{name}` placeholders filled in when they're sent
+
+/// Replaces each `{name}` with its value; unknown placeholders are left as typed
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), value)
+        })
+}
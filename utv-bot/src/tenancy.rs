@@ -0,0 +1,192 @@
+//! Which guilds the bot serves, and `/tenant`, with which its owner approves them.
+//!
+//! By default the bot serves any guild that invites it. With `TENANCY=allowlist` it only serves
+//! guilds in `ALLOWED_GUILD_IDS` or approved with `/tenant add`, and leaves any other as soon as
+//! it sees it, when invited or on startup. Leaving deletes what the bot stored about the guild,
+//! like being kicked does. No guild is left while none is approved at all, or while the
+//! approved ones can't be read, so a missing configuration doesn't empty the bot. `/tenant` is
+//! for the bot's owner and only runs in a DM with the bot.
+
+use serenity::client::Context;
+use serenity::http::Http;
+use serenity::model::guild::Guild;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+use tracing::{info, warn};
+
+use crate::{db, handlers, owner, response, settings, sharding};
+
+/// Whether the bot may stay in the guild
+pub async fn allowed(db_client: &db::DynamoDB, guild_id: GuildId) -> bool {
+    if !settings::tenancy_allowlist().unwrap_or(false) {
+        return true;
+    }
+    let seeded = settings::allowed_guilds().unwrap_or_default();
+    if seeded.contains(&guild_id) {
+        return true;
+    }
+    match db_client.is_tenant(guild_id).await {
+        Some(true) => true,
+        Some(false) => seeded.is_empty() && db_client.tenants().await.is_empty(),
+        None => {
+            warn!("Cannot check whether {} is an approved tenant", guild_id);
+            true
+        }
+    }
+}
+
+/// Leaves the guild unless it's allowed, returning whether the bot left
+pub async fn enforce(db_client: &db::DynamoDB, http: &Http, guild: &Guild) -> bool {
+    if allowed(db_client, guild.id).await {
+        return false;
+    }
+    if let Err(why) = guild.id.leave(http).await {
+        warn!("Cannot leave unapproved guild {}: {}", guild.id, why);
+        return false;
+    }
+    info!(
+        "Left {} ({}), which isn't an approved tenant",
+        guild.name, guild.id
+    );
+    if let Some(channel) = settings::owner_log_channel().ok().flatten() {
+        let said = channel
+            .say(
+                http,
+                format!(
+                    "Left **{}** (`{}`), which isn't an approved tenant. \
+                     `/tenant add guild:{}` approves it before it invites the bot again.",
+                    guild.name, guild.id, guild.id
+                ),
+            )
+            .await;
+        if let Err(why) = said {
+            warn!("Cannot tell the owner about leaving {}: {}", guild.id, why);
+        }
+    }
+    true
+}
+
+pub async fn tenant(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    ctx: Context,
+) -> serenity::Result<()> {
+    let owner = owner::owner(&ctx.http).await?;
+    if command.user.id != owner {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Only the bot's owner can run this command.",
+        )
+        .await;
+    }
+    if command.guild_id.is_some() {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "Run `/tenant` in a DM with the bot.",
+        )
+        .await;
+    }
+    let (name, options) = match handlers::subcommand(&command) {
+        Some(sub) => sub,
+        None => return Ok(()),
+    };
+    let guild_id = handlers::option_str(options, "guild")
+        .and_then(|id| id.trim().parse().ok())
+        .map(GuildId);
+    let enabled = settings::tenancy_allowlist().unwrap_or(false);
+    let seeded = settings::allowed_guilds().unwrap_or_default();
+    match (name, guild_id) {
+        ("add", Some(guild_id)) => {
+            let tenant = db::Tenant {
+                guild_id,
+                added_by: command.user.id,
+                added_at: response::unix_now(),
+            };
+            let title = if !db_client.put_tenant(&tenant).await {
+                "Error: Couldn't approve the server, try again".to_string()
+            } else if enabled {
+                format!("Approved `{}`", guild_id)
+            } else {
+                format!(
+                    "Approved `{}`. Every server is served until `TENANCY=allowlist` is set.",
+                    guild_id
+                )
+            };
+            response::respond_title(&ctx, &command, true, title).await
+        }
+        ("remove", Some(guild_id)) => {
+            let title = match db_client.delete_tenant(guild_id).await {
+                None => "Error: Couldn't remove the server, try again".to_string(),
+                Some(_) if seeded.contains(&guild_id) => format!(
+                    "Removed `{}`, but it's still approved by `ALLOWED_GUILD_IDS`",
+                    guild_id
+                ),
+                Some(false) => format!("`{}` wasn't approved", guild_id),
+                Some(true) if !enabled => format!("Removed `{}`", guild_id),
+                // fails when the bot isn't in it
+                Some(true) => match guild_id.leave(&ctx.http).await {
+                    Ok(()) => format!("Removed `{}` and left it", guild_id),
+                    Err(_) => format!("Removed `{}`", guild_id),
+                },
+            };
+            response::respond_title(&ctx, &command, true, title).await
+        }
+        ("list", _) => list(db_client, &command, &ctx, enabled, &seeded).await,
+        _ => response::respond_title(&ctx, &command, true, "That's not a valid server id.").await,
+    }
+}
+
+async fn list(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    ctx: &Context,
+    enabled: bool,
+    seeded: &[GuildId],
+) -> serenity::Result<()> {
+    let tenants = db_client.tenants().await;
+    let mut approved = seeded
+        .iter()
+        .map(|guild_id| format!("`{}` from `ALLOWED_GUILD_IDS`", guild_id))
+        .collect::<Vec<_>>();
+    approved.extend(tenants.iter().map(|tenant| {
+        format!(
+            "`{}` by <@{}> {}",
+            tenant.guild_id,
+            tenant.added_by,
+            response::timestamp(tenant.added_at, response::TimestampStyle::Relative)
+        )
+    }));
+    // the guilds the bot would leave the next time it sees them
+    let unapproved = match sharding::guilds(&ctx.http).await {
+        Ok(guilds) => guilds
+            .iter()
+            .filter(|guild_id| {
+                !seeded.contains(guild_id) && !tenants.iter().any(|t| t.guild_id == **guild_id)
+            })
+            .map(|guild_id| format!("`{}`", guild_id))
+            .collect(),
+        Err(why) => vec![format!("Couldn't list the bot's servers: {}", why)],
+    };
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Tenants")
+            .description(if enabled {
+                "Only approved servers are served."
+            } else {
+                "`TENANCY` is `open`, so every server is served."
+            })
+            .field("Approved", response::field_lines(&approved, "None"), false)
+            .field(
+                "In but Not Approved",
+                response::field_lines(&unapproved, "None"),
+                false,
+            )
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
@@ -0,0 +1,166 @@
+//! Rate limits on verification emails, so `/verify` can't be spammed with random EIDs.
+//!
+//! Each request takes a token from the member's bucket and, in a guild, from the guild's. A
+//! member gets [`USER_BURST`] emails, then one every [`USER_REFILL_SECS`]. Running out starts a
+//! cooldown, which doubles each time it happens again within a day, up to a day. A guild gets
+//! [`GUILD_BURST`], then one every [`GUILD_REFILL_SECS`], so a wave of throwaway accounts can't
+//! flood the verification server either. After [`ALERT_STRIKES`] cooldowns in a day the
+//! guild's moderators are alerted, at most once a day per member.
+//!
+//! Buckets are kept in memory and written to the `rate_limits` table, so a restart doesn't reset
+//! them; they expire from it a day after their last use.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serenity::http::Http;
+use serenity::model::id::{GuildId, UserId};
+use tracing::warn;
+
+use crate::i18n::{self, Locale};
+use crate::{audit, cache, db, response};
+
+const USER_BURST: f64 = 3.0;
+const USER_REFILL_SECS: f64 = 10.0 * 60.0;
+const GUILD_BURST: f64 = 30.0;
+const GUILD_REFILL_SECS: f64 = 20.0;
+const BASE_COOLDOWN_SECS: i64 = 10 * 60;
+const DAY: i64 = 24 * 60 * 60;
+const ALERT_STRIKES: u32 = 3;
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<String, db::RateLimit>> = Mutex::new(HashMap::new());
+}
+
+/// Takes a verification email from the member's and guild's budgets, returning when the member
+/// may ask again instead when either ran out
+pub async fn take(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+) -> Option<i64> {
+    let now = response::unix_now();
+    let user_key = format!("user:{}", user_id);
+    let mut user = load(db_client, &user_key).await;
+    if user.blocked_until > now {
+        return Some(user.blocked_until);
+    }
+    refill(&mut user, now, USER_BURST, USER_REFILL_SECS);
+    if user.tokens < 1.0 {
+        if now - user.struck_at > DAY {
+            user.strikes = 0;
+        }
+        user.strikes += 1;
+        user.struck_at = now;
+        let cooldown = (BASE_COOLDOWN_SECS << (user.strikes - 1).min(8)).min(DAY);
+        user.blocked_until = now + cooldown;
+        let alert = user.strikes >= ALERT_STRIKES && now - user.alerted_at > DAY;
+        if alert {
+            user.alerted_at = now;
+        }
+        store(db_client, &user_key, user.clone()).await;
+        if let (true, Some(guild_id)) = (alert, guild_id) {
+            alert_moderators(db_client, http, guild_id, user_id, user.strikes).await;
+        }
+        return Some(user.blocked_until);
+    }
+
+    if let Some(guild_id) = guild_id {
+        let guild_key = format!("guild:{}", guild_id);
+        let mut guild = load(db_client, &guild_key).await;
+        refill(&mut guild, now, GUILD_BURST, GUILD_REFILL_SECS);
+        if guild.tokens < 1.0 {
+            // the member's token isn't taken, it's the guild's turn to wait
+            return Some(now + ((1.0 - guild.tokens) * GUILD_REFILL_SECS).ceil() as i64);
+        }
+        guild.tokens -= 1.0;
+        store(db_client, &guild_key, guild).await;
+    }
+    user.tokens -= 1.0;
+    store(db_client, &user_key, user).await;
+    None
+}
+
+/// The reply to a member who asked for too many emails
+pub fn limited_message(locale: Locale, retry_at: i64) -> String {
+    i18n::format(
+        locale,
+        "throttle.limited",
+        &[(
+            "when",
+            &response::timestamp(retry_at, response::TimestampStyle::Relative),
+        )],
+    )
+}
+
+fn refill(bucket: &mut db::RateLimit, now: i64, burst: f64, refill_secs: f64) {
+    // a bucket that was never used starts full
+    if bucket.updated_at == 0 {
+        bucket.tokens = burst;
+    }
+    let elapsed = (now - bucket.updated_at).max(0) as f64;
+    bucket.tokens = (bucket.tokens + elapsed / refill_secs).min(burst);
+    bucket.updated_at = now;
+}
+
+async fn load(db_client: &db::DynamoDB, key: &str) -> db::RateLimit {
+    if let Some(bucket) = BUCKETS.lock().unwrap().get(key) {
+        return bucket.clone();
+    }
+    db_client.get_rate_limit(key).await.unwrap_or_default()
+}
+
+async fn store(db_client: &db::DynamoDB, key: &str, bucket: db::RateLimit) {
+    if !db_client
+        .put_rate_limit(key, &bucket, bucket.updated_at + DAY)
+        .await
+    {
+        warn!("Cannot save the rate limit of {}", key);
+    }
+    let mut buckets = BUCKETS.lock().unwrap();
+    let now = bucket.updated_at;
+    buckets.insert(key.to_string(), bucket);
+    // the table has the rest, should they come back
+    if buckets.len() > 10_000 {
+        buckets.retain(|_, b| now - b.updated_at < DAY);
+    }
+}
+
+async fn alert_moderators(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    guild_id: GuildId,
+    user_id: UserId,
+    strikes: u32,
+) {
+    let reason = format!(
+        "Ran out of verification emails {} times in a day, possibly trying random EIDs",
+        strikes
+    );
+    if let Ok(bot_id) = cache::bot_id(http).await {
+        audit::record(
+            db_client,
+            guild_id,
+            bot_id,
+            "verify.rate_limited",
+            Some(user_id),
+            reason.clone(),
+        )
+        .await;
+    }
+    audit::post(
+        db_client,
+        http,
+        guild_id,
+        audit::Post {
+            action: "Repeated verification attempts",
+            member: user_id,
+            actor: None,
+            reason,
+            failed: false,
+        },
+    )
+    .await;
+}
@@ -0,0 +1,432 @@
+//! `/admin import` and `/admin export`: moving verified members in and out of the bot in bulk.
+//!
+//! Both use the same format, so an export of one instance imports into another: CSV with a
+//! `discord_id,eid,affiliation` header, several affiliations separated by `;`, or a JSON array of
+//! objects with those fields. Importing links accounts for every guild the bot is in without
+//! checking the directory, so only the bot's owner and `TRUSTED_ADMIN_IDS` may; exports carry
+//! plain EIDs only for them and are otherwise masked like `/admin bulk-lookup`.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::http::AttachmentType;
+use serenity::model::id::{GuildId, UserId};
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+    ApplicationCommandInteractionDataOptionValue,
+};
+use serenity::utils::Color;
+use tracing::info;
+
+use crate::{admin, audit, config, db, handlers, jobs, members, owner, response, settings, stats};
+
+/// every row is a few table writes, which have to finish before the interaction expires
+const MAX_IMPORT_ROWS: usize = 2000;
+const MAX_IMPORT_BYTES: u64 = 256 * 1024;
+/// characters of the EID hash exported when EIDs can't be decrypted
+const HASH_CHARS: usize = 16;
+
+/// One row of an import or export
+#[derive(Serialize, Deserialize)]
+struct Row {
+    discord_id: String,
+    eid: String,
+    #[serde(default, deserialize_with = "one_or_many")]
+    affiliation: Vec<String>,
+}
+
+/// What happened to a row of an import
+enum Outcome {
+    Added,
+    Skipped(&'static str),
+    Invalid(String),
+    Failed,
+}
+
+pub async fn import(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+    jobs: &jobs::Queue,
+) -> serenity::Result<()> {
+    if !trusted(command, ctx).await? {
+        return response::respond_title(
+            ctx,
+            command,
+            true,
+            "Only the bot's owner and trusted admins can import verified members.",
+        )
+        .await;
+    }
+    let key = match settings::encryption_key() {
+        Ok(Some(key)) => key,
+        _ => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Imports are not enabled on this instance of the bot.",
+            )
+            .await
+        }
+    };
+    let file = match handlers::option(options, "file") {
+        Some(ApplicationCommandInteractionDataOptionValue::Attachment(file))
+            if file.size <= MAX_IMPORT_BYTES =>
+        {
+            file
+        }
+        _ => {
+            return response::respond_title(
+                ctx,
+                command,
+                true,
+                "Attach a CSV or JSON file of `discord_id,eid,affiliation` rows, up to 256 KB",
+            )
+            .await
+        }
+    };
+    let dry_run = matches!(
+        handlers::option(options, "dry-run"),
+        Some(ApplicationCommandInteractionDataOptionValue::Boolean(true))
+    );
+    response::defer(ctx, command, true).await?;
+    let rows = match file.download().await.map(String::from_utf8) {
+        Ok(Ok(text)) => parse(&text),
+        _ => Err("Couldn't read that file, attach a CSV or JSON file.".to_string()),
+    };
+    let rows = match rows {
+        Ok(rows) if rows.len() <= MAX_IMPORT_ROWS => rows,
+        Ok(_) => Err(format!(
+            "Import at most {} rows at a time.",
+            MAX_IMPORT_ROWS
+        )),
+        Err(why) => Err(why),
+    };
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(why) => {
+            command
+                .create_followup_message(&ctx.http, |message| message.content(why))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // checked up front so a dry run reports the same skips a real import would
+    let encrypted = rows
+        .iter()
+        .map(|row| encrypt(&row.eid, &key))
+        .collect::<Vec<_>>();
+    let holders = db_client.users_by_encrypted_eid(&encrypted).await;
+    let (mut seen_ids, mut seen_eids) = (HashSet::new(), HashSet::new());
+    let mut outcomes = Vec::with_capacity(rows.len());
+    for (row, encrypted) in rows.iter().zip(&encrypted) {
+        let outcome = match validate(row) {
+            Err(why) => Outcome::Invalid(why),
+            Ok(_) if !seen_ids.insert(&row.discord_id) => Outcome::Skipped("duplicate account"),
+            Ok(_) if !seen_eids.insert(encrypted) => Outcome::Skipped("duplicate EID"),
+            Ok(user_id) => match holders.get(encrypted) {
+                Some(holder) if *holder == user_id => Outcome::Skipped("already verified"),
+                Some(_) => Outcome::Skipped("EID linked to another account"),
+                None if db_client.is_verified(user_id.0).await => {
+                    Outcome::Skipped("account verified with another EID")
+                }
+                None if dry_run => Outcome::Added,
+                None => {
+                    let claims = db::Claims {
+                        major: Vec::new(),
+                        school: Vec::new(),
+                        affiliation: row.affiliation.iter().map(|a| a.to_lowercase()).collect(),
+                        name: None,
+                    };
+                    match db_client.link_user(user_id, encrypted, &claims).await {
+                        db::LinkResult::Linked => Outcome::Added,
+                        db::LinkResult::AlreadyLinked => {
+                            Outcome::Skipped("account verified with another EID")
+                        }
+                        db::LinkResult::EidInUse => {
+                            Outcome::Skipped("EID linked to another account")
+                        }
+                        db::LinkResult::Failed => Outcome::Failed,
+                    }
+                }
+            },
+        };
+        outcomes.push(outcome);
+    }
+
+    let count = |f: fn(&Outcome) -> bool| outcomes.iter().filter(|o| f(o)).count();
+    let added = count(|o| matches!(o, Outcome::Added));
+    let skipped = count(|o| matches!(o, Outcome::Skipped(_)));
+    let invalid = count(|o| matches!(o, Outcome::Invalid(_)));
+    let failed = count(|o| matches!(o, Outcome::Failed));
+    if !dry_run {
+        if added > 0 {
+            stats::invalidate(guild_id);
+            // other guilds pick the new links up with their next scan
+            jobs.push(jobs::Job::Reconcile { guild_id, since: 0 });
+        }
+        audit::record(
+            db_client,
+            guild_id,
+            command.user.id,
+            "admin.import",
+            None,
+            format!(
+                "{} rows: {} added, {} skipped, {} invalid, {} failed",
+                rows.len(),
+                added,
+                skipped,
+                invalid,
+                failed
+            ),
+        )
+        .await;
+        info!(
+            "{} imported {} of {} rows in {}",
+            command.user.id,
+            added,
+            rows.len(),
+            guild_id
+        );
+    }
+
+    let mut csv = "line,discord_id,eid,status\n".to_string();
+    for (i, (row, outcome)) in rows.iter().zip(&outcomes).enumerate() {
+        let status = match outcome {
+            Outcome::Added if dry_run => "would be added".to_string(),
+            Outcome::Added => "added".to_string(),
+            Outcome::Skipped(why) => format!("skipped: {}", why),
+            Outcome::Invalid(why) => format!("invalid: {}", why),
+            Outcome::Failed => "failed, try again".to_string(),
+        };
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            i + 1,
+            row.discord_id,
+            admin::mask_eid(&row.eid),
+            status
+        ));
+    }
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(csv.into_bytes()),
+                    filename: "import.csv".to_string(),
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title(if dry_run { "Import Dry Run" } else { "Import" })
+                        .description(format!(
+                            "Of {} rows, {} {} added, {} skipped and {} invalid{}. The attached \
+                             report has a line per row; EIDs are masked.",
+                            rows.len(),
+                            added,
+                            if dry_run { "would be" } else { "were" },
+                            skipped,
+                            invalid,
+                            if failed > 0 {
+                                format!(", and {} failed and can be imported again", failed)
+                            } else {
+                                String::new()
+                            }
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+pub async fn export(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    options: &[ApplicationCommandInteractionDataOption],
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let plain = trusted(command, ctx).await?;
+    let key = settings::encryption_key().ok().flatten();
+    let json = handlers::option_str(options, "format") == Some("json");
+    response::defer(ctx, command, true).await?;
+
+    let in_guild = members::fetch_all(&ctx.http, guild_id)
+        .await?
+        .into_iter()
+        .map(|m| m.user.id)
+        .collect::<HashSet<_>>();
+    let mut records = db_client
+        .linked_records()
+        .await
+        .into_iter()
+        .filter(|(user_id, _)| in_guild.contains(user_id))
+        .collect::<Vec<_>>();
+    records.sort_by_key(|(user_id, _)| *user_id);
+    let rows = records
+        .into_iter()
+        .filter_map(|(user_id, record)| {
+            let encrypted = record.encrypted_eid?;
+            let eid = key
+                .as_ref()
+                .and_then(|key| {
+                    let encrypted = base64::decode(&encrypted).ok()?;
+                    utv_token::deterministic_aes::decrypt(&encrypted, key).ok()
+                })
+                .map(|eid| String::from_utf8_lossy(&eid).into_owned());
+            Some(Row {
+                discord_id: user_id.0.to_string(),
+                eid: match eid {
+                    Some(eid) if plain => eid,
+                    Some(eid) => admin::mask_eid(&eid),
+                    None => format!("hash:{}", &db::eid_hash(&encrypted)[..HASH_CHARS]),
+                },
+                affiliation: record.claims.map(|c| c.affiliation).unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "admin.export",
+        None,
+        format!(
+            "{} verified members, {}",
+            rows.len(),
+            if plain && key.is_some() {
+                "plain EIDs"
+            } else {
+                "masked EIDs"
+            }
+        ),
+    )
+    .await;
+
+    let (data, filename) = if json {
+        (
+            serde_json::to_vec_pretty(&rows).map_err(serenity::Error::Json)?,
+            format!("verified-{}.json", guild_id),
+        )
+    } else {
+        let mut csv = "discord_id,eid,affiliation\n".to_string();
+        for row in &rows {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                row.discord_id,
+                row.eid,
+                row.affiliation.join(";")
+            ));
+        }
+        (csv.into_bytes(), format!("verified-{}.csv", guild_id))
+    };
+    command
+        .create_followup_message(&ctx.http, |message| {
+            message
+                .add_file(AttachmentType::Bytes {
+                    data: Cow::from(data),
+                    filename,
+                })
+                .create_embed(|embed| {
+                    embed
+                        .title("Verified Members Export")
+                        .description(format!(
+                            "{} verified members of this server. {}",
+                            rows.len(),
+                            if plain && key.is_some() {
+                                "EIDs are in plain text, keep this file private; it can be \
+                                 imported with `/admin import`."
+                            } else {
+                                "EIDs are masked; only the bot's owner and trusted admins can \
+                                 export them in full."
+                            }
+                        ))
+                        .color(Color::from_rgb(191, 87, 0))
+                })
+        })
+        .await?;
+    Ok(())
+}
+
+async fn trusted(command: &ApplicationCommandInteraction, ctx: &Context) -> serenity::Result<bool> {
+    let owner = owner::owner(&ctx.http).await?;
+    let trusted = settings::trusted_admins().unwrap_or_default();
+    Ok(command.user.id == owner || trusted.contains(&command.user.id))
+}
+
+/// Reads rows from a JSON array, or from CSV with or without the header
+fn parse(text: &str) -> Result<Vec<Row>, String> {
+    if text.trim_start().starts_with('[') {
+        return serde_json::from_str(text)
+            .map_err(|why| format!("Couldn't read the JSON: {}", why));
+    }
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("discord_id"))
+        .map(|line| {
+            let mut fields = line.splitn(3, ',').map(str::trim);
+            Row {
+                discord_id: fields.next().unwrap_or_default().to_string(),
+                eid: fields.next().unwrap_or_default().to_string(),
+                affiliation: fields
+                    .next()
+                    .unwrap_or_default()
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|a| !a.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Checks a row's fields, lower casing its EID and affiliations like the directory does
+fn validate(row: &Row) -> Result<UserId, String> {
+    let user_id = match row.discord_id.parse::<u64>() {
+        Ok(id) if id > 0 => UserId(id),
+        _ => return Err(format!("`{}` is not a Discord id", row.discord_id)),
+    };
+    if row.eid.len() < 2 || !row.eid.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("not an EID".to_string());
+    }
+    if row.affiliation.is_empty() {
+        return Err("no affiliation".to_string());
+    }
+    if let Some(unknown) = row
+        .affiliation
+        .iter()
+        .find(|a| !config::AFFILIATIONS.contains(&a.to_lowercase().as_str()))
+    {
+        return Err(format!("unknown affiliation `{}`", unknown));
+    }
+    Ok(user_id)
+}
+
+/// EIDs are stored as entered at verification, which is almost always lower case
+fn encrypt(eid: &str, key: &[u8]) -> String {
+    base64::encode(utv_token::deterministic_aes::encrypt(
+        eid.to_lowercase().as_bytes(),
+        key,
+    ))
+}
+
+/// Accepts a single affiliation as well as a list, for hand-written JSON
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
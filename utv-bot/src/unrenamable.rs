@@ -0,0 +1,238 @@
+//! Verified members the bot can't rename.
+//!
+//! Discord doesn't let the bot change the nickname of the guild's owner or of members whose
+//! highest role is above its own, so moderators are often never decorated. Guilds choose what
+//! happens with `/config unrenamable`: nothing, a one-time DM asking the member to set the
+//! nickname themselves, or a list of them in the audit ledger at the end of each scan.
+//!
+//! Regardless of the policy, members whose roles or nickname couldn't be changed for lack of
+//! permissions are gathered during scans and reconciliations, and listed in a single warning
+//! at the end: in the audit channel if the guild has one, otherwise by DM to the guild's owner.
+//! The owner themselves is never renamed, so isn't listed.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serenity::http::{Http, HttpError};
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, UserId};
+use tracing::warn;
+
+use crate::config::GuildConfig;
+use crate::{audit, db};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    Skip,
+    Notify,
+    Report,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::Skip
+    }
+}
+
+impl Policy {
+    pub fn parse(name: &str) -> Option<Policy> {
+        match name {
+            "skip" => Some(Policy::Skip),
+            "notify" => Some(Policy::Notify),
+            "report" => Some(Policy::Report),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Policy::Skip => "Skipped silently",
+            Policy::Notify => "Asked once by DM to set their nickname themselves",
+            Policy::Report => "Listed in the audit ledger after each scan",
+        }
+    }
+}
+
+/// members named in one ledger entry or warning
+const MAX_LISTED: usize = 50;
+/// ownership is rarely transferred, so the owner isn't looked up for every member
+const OWNER_TTL: Duration = Duration::from_secs(60 * 60);
+
+lazy_static! {
+    /// members each guild's current scan couldn't rename, for `Policy::Report`
+    static ref FOUND: Mutex<HashMap<GuildId, BTreeSet<UserId>>> = Mutex::new(HashMap::new());
+    /// members whose roles or nickname couldn't be changed since the guild was last warned
+    static ref DENIED: Mutex<HashMap<GuildId, BTreeSet<UserId>>> = Mutex::new(HashMap::new());
+    static ref OWNERS: Mutex<HashMap<GuildId, (UserId, Instant)>> = Mutex::new(HashMap::new());
+}
+
+/// Whether a failed nickname edit was refused for lack of permissions
+pub fn is_forbidden(why: &serenity::Error) -> bool {
+    matches!(why, serenity::Error::Http(why) if matches!(&**why, HttpError::UnsuccessfulRequest(r) if r.status_code.as_u16() == 403))
+}
+
+/// The guild's owner, whose nickname no bot can change
+pub async fn owner(http: &Http, guild_id: GuildId) -> Option<UserId> {
+    if let Some((owner, at)) = OWNERS.lock().unwrap().get(&guild_id) {
+        if at.elapsed() < OWNER_TTL {
+            return Some(*owner);
+        }
+    }
+    match guild_id.to_partial_guild(http).await {
+        Ok(guild) => {
+            OWNERS
+                .lock()
+                .unwrap()
+                .insert(guild_id, (guild.owner_id, Instant::now()));
+            Some(guild.owner_id)
+        }
+        Err(why) => {
+            warn!("Cannot look up the owner of {}: {}", guild_id, why);
+            None
+        }
+    }
+}
+
+/// Notes a member whose roles or nickname couldn't be changed, for the next [`warn`]
+pub fn denied(guild_id: GuildId, user_id: UserId) {
+    DENIED
+        .lock()
+        .unwrap()
+        .entry(guild_id)
+        .or_default()
+        .insert(user_id);
+}
+
+/// Sends one warning listing the members noted with [`denied`] since the last one
+pub async fn warn(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId) {
+    let denied = match DENIED.lock().unwrap().remove(&guild_id) {
+        Some(denied) if !denied.is_empty() => denied,
+        _ => return,
+    };
+    let guild = match guild_id.to_partial_guild(http).await {
+        Ok(guild) => guild,
+        Err(why) => {
+            warn!("Cannot warn {} about permissions: {}", guild_id, why);
+            return;
+        }
+    };
+    let warning = format!(
+        "The bot couldn't update the roles or nickname of {} members in {}: {}{}. Check that it \
+         has the Manage Roles and Manage Nicknames permissions and that its role is above theirs.",
+        denied.len(),
+        guild.name,
+        denied
+            .iter()
+            .take(MAX_LISTED)
+            .map(|user| format!("<@{}>", user))
+            .collect::<Vec<_>>()
+            .join(", "),
+        if denied.len() > MAX_LISTED {
+            format!(" and {} more", denied.len() - MAX_LISTED)
+        } else {
+            String::new()
+        }
+    );
+    let sent = match db_client.get_guild_config(guild_id).await.audit_channel {
+        Some(channel) => channel
+            .send_message(http, |m| m.content(warning))
+            .await
+            .map(|_| ()),
+        None => match guild.owner_id.create_dm_channel(http).await {
+            Ok(dm) => dm.say(http, warning).await.map(|_| ()),
+            Err(why) => Err(why),
+        },
+    };
+    if let Err(why) = sent {
+        warn!("Cannot warn {} about permissions: {}", guild_id, why);
+    }
+}
+
+/// Applies the guild's policy to a verified member whose nickname couldn't be set to `nickname`
+pub async fn handle(
+    db_client: &db::DynamoDB,
+    http: &Http,
+    member: &Member,
+    nickname: &str,
+    config: &GuildConfig,
+) {
+    match config.unrenamable {
+        Policy::Skip => {}
+        Policy::Report => {
+            FOUND
+                .lock()
+                .unwrap()
+                .entry(member.guild_id)
+                .or_default()
+                .insert(member.user.id);
+        }
+        Policy::Notify => {
+            // the config is re-read, since it may have changed while the member was checked
+            let mut config = db_client.get_guild_config(member.guild_id).await;
+            if config.unrenamable_notified.contains(&member.user.id) {
+                return;
+            }
+            let guild = match member.guild_id.to_partial_guild(http).await {
+                Ok(guild) => guild.name,
+                Err(_) => "a server".to_string(),
+            };
+            let notice = format!(
+                "You're verified in {}, but your permissions there keep the bot from changing your \
+                 nickname. To show you're verified, set your nickname in that server to `{}`.",
+                guild, nickname
+            );
+            if let Err(why) = member
+                .user
+                .direct_message(http, |m| m.content(notice))
+                .await
+            {
+                warn!("Cannot DM {} about their nickname: {}", member.user.id, why);
+            }
+            // asked once even when the DM failed, rather than on every check
+            config.unrenamable_notified.push(member.user.id);
+            db_client.set_guild_config(member.guild_id, &config).await;
+        }
+    }
+}
+
+/// Lists the members the scan that just finished couldn't rename
+pub async fn report(db_client: &db::DynamoDB, http: &Http, guild_id: GuildId) {
+    let found = match FOUND.lock().unwrap().remove(&guild_id) {
+        Some(found) if !found.is_empty() => found,
+        _ => return,
+    };
+    let bot = match http.get_current_user().await {
+        Ok(bot) => bot.id,
+        Err(why) => {
+            warn!("Cannot report unrenamable members of {}: {}", guild_id, why);
+            return;
+        }
+    };
+    audit::record(
+        db_client,
+        guild_id,
+        bot,
+        "scan.unrenamable",
+        None,
+        format!(
+            "Couldn't decorate the nickname of {} verified members: {}{}",
+            found.len(),
+            found
+                .iter()
+                .take(MAX_LISTED)
+                .map(|user| format!("<@{}>", user))
+                .collect::<Vec<_>>()
+                .join(", "),
+            if found.len() > MAX_LISTED {
+                format!(" and {} more", found.len() - MAX_LISTED)
+            } else {
+                String::new()
+            }
+        ),
+    )
+    .await;
+}
@@ -0,0 +1,461 @@
+//! Outbound webhooks: verification events POSTed as JSON to URLs each guild adds with
+//! `/admin webhook add`, for other org tooling to react to.
+//!
+//! Events are `verified`, `unverified`, `expired` and `role-assigned`, each a JSON object with
+//! the guild, the member and the unix time it happened at, plus the role ids for
+//! `role-assigned`. Each webhook gets its own random secret when it's added, shown once to the
+//! admin who added it: the `X-Signature` header is the HMAC-SHA256 of the body under that secret,
+//! in unpadded URL-safe base64. It's never signed with `SHARED_KEY`, which receivers would need to
+//! check it and which mints verification tokens. Webhooks added before they had secrets get
+//! nothing until they're added again.
+//!
+//! The host is resolved before every attempt and the request is only sent to a public address,
+//! pinned for that attempt and without following redirects, so a name resolving to the bot's own
+//! network or the cloud metadata endpoint can't be reached. Deliveries run in the background and
+//! are retried [`MAX_ATTEMPTS`] times with growing delays when the endpoint is unreachable or
+//! answers with a server error or 429; other errors aren't retried. The last [`LOG_LEN`]
+//! deliveries of each guild are kept in memory for `/admin webhook list`, so retries still
+//! waiting and the log are lost on restart.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use rand::Rng;
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serenity::client::Context;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::model::interactions::application_command::{
+    ApplicationCommandInteraction, ApplicationCommandInteractionDataOption,
+};
+use serenity::utils::Color;
+use tracing::warn;
+
+use crate::{config, db, handlers, response};
+
+pub const MAX_WEBHOOKS: usize = 3;
+const MAX_ATTEMPTS: u32 = 5;
+/// the wait before the first retry, quadrupled for each one after
+const FIRST_RETRY: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(10);
+const LOG_LEN: usize = 20;
+
+lazy_static! {
+    static ref LOG: Mutex<HashMap<GuildId, VecDeque<Delivery>>> = Mutex::new(HashMap::new());
+}
+
+/// A URL events are sent to and the secret they're signed with
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "StoredWebhook")]
+pub struct Webhook {
+    pub url: String,
+    /// empty for webhooks added before they had secrets
+    pub secret: String,
+}
+
+/// Webhooks were stored as bare URLs before they had secrets
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredWebhook {
+    Signed { url: String, secret: String },
+    Url(String),
+}
+
+impl From<StoredWebhook> for Webhook {
+    fn from(stored: StoredWebhook) -> Self {
+        match stored {
+            StoredWebhook::Signed { url, secret } => Webhook { url, secret },
+            StoredWebhook::Url(url) => Webhook {
+                url,
+                secret: String::new(),
+            },
+        }
+    }
+}
+
+pub enum Event {
+    Verified,
+    Unverified,
+    Expired,
+    RoleAssigned(Vec<RoleId>),
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Verified => "verified",
+            Event::Unverified => "unverified",
+            Event::Expired => "expired",
+            Event::RoleAssigned(_) => "role-assigned",
+        }
+    }
+}
+
+/// A delivery as `/admin webhook list` shows it
+struct Delivery {
+    at: i64,
+    event: &'static str,
+    url: String,
+    attempts: u32,
+    /// the status the endpoint answered with, or why it couldn't be reached
+    outcome: Result<StatusCode, String>,
+}
+
+/// Sends the event to the guild's webhooks, if it has any, without waiting for the deliveries
+pub async fn emit(db_client: &db::DynamoDB, guild_id: GuildId, user_id: UserId, event: Event) {
+    let webhooks = db_client.get_guild_config(guild_id).await.webhooks;
+    if webhooks.is_empty() {
+        return;
+    }
+    let at = response::unix_now();
+    let mut body = json!({
+        "event": event.name(),
+        "guild_id": guild_id.to_string(),
+        "user_id": user_id.to_string(),
+        "at": at,
+    });
+    if let Event::RoleAssigned(roles) = &event {
+        body["roles"] = json!(roles.iter().map(|r| r.to_string()).collect::<Vec<_>>());
+    }
+    let body = body.to_string();
+    for webhook in webhooks {
+        let (body, event) = (body.clone(), event.name());
+        tokio::spawn(async move {
+            let (attempts, outcome) = if webhook.secret.is_empty() {
+                (
+                    0,
+                    Err("added before webhooks had secrets, remove and add it again".to_string()),
+                )
+            } else {
+                let signature = utv_token::signature(body.as_bytes(), webhook.secret.as_bytes());
+                deliver(&webhook.url, &body, &signature).await
+            };
+            if let Err(why) = &outcome {
+                warn!(
+                    "Cannot deliver {} to a webhook of {}: {}",
+                    event, guild_id, why
+                );
+            }
+            let mut log = LOG.lock().unwrap();
+            let deliveries = log.entry(guild_id).or_default();
+            deliveries.push_front(Delivery {
+                at,
+                event,
+                url: webhook.url,
+                attempts,
+                outcome,
+            });
+            deliveries.truncate(LOG_LEN);
+        });
+    }
+}
+
+/// Why a webhook's host can't be sent to
+enum Unreachable {
+    /// it resolves to an address that isn't public, which is never retried
+    Refused(String),
+    Unresolved(String),
+}
+
+/// A client that only reaches the URL's host at the address checked here, so the host can't be
+/// resolved to another one when the request is sent
+async fn client(url: &str) -> Result<reqwest::Client, Unreachable> {
+    let url = Url::parse(url).map_err(|why| Unreachable::Refused(why.to_string()))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| Unreachable::Refused("the URL has no host".to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|why| Unreachable::Unresolved(format!("cannot resolve {}: {}", host, why)))?
+        .collect::<Vec<_>>();
+    if let Some(address) = addresses.iter().find(|a| !is_public(a.ip())) {
+        return Err(Unreachable::Refused(format!(
+            "{} resolves to {}, which isn't a public address",
+            host,
+            address.ip()
+        )));
+    }
+    let address = addresses
+        .first()
+        .ok_or_else(|| Unreachable::Unresolved(format!("{} has no addresses", host)))?;
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .redirect(Policy::none())
+        .resolve(host, *address)
+        .build()
+        .map_err(|why| Unreachable::Refused(why.to_string()))
+}
+
+/// Whether the address is on the public internet, rather than the bot's host, its network or a
+/// link-local address like the cloud metadata endpoint
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || first == 0
+                // shared by carrier-grade NATs
+                || (first == 100 && (64..128).contains(&second)))
+        }
+        IpAddr::V6(ip) => {
+            if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+                return false;
+            }
+            if let Some(ip) = ip.to_ipv4() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            // unique local fc00::/7 and link-local fe80::/10
+            first & 0xfe00 != 0xfc00 && first & 0xffc0 != 0xfe80
+        }
+    }
+}
+
+/// POSTs the body until it's accepted, refused or out of attempts, returning the attempts made
+async fn deliver(url: &str, body: &str, signature: &str) -> (u32, Result<StatusCode, String>) {
+    let mut delay = FIRST_RETRY;
+    let mut attempt = 1;
+    loop {
+        let result = match client(url).await {
+            Ok(client) => client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header("X-Signature", signature)
+                .body(body.to_string())
+                .send()
+                .await
+                .map_err(|why| why.to_string()),
+            Err(Unreachable::Refused(why)) => return (attempt, Err(why)),
+            Err(Unreachable::Unresolved(why)) => Err(why),
+        };
+        let retry = match &result {
+            Ok(r) => r.status().is_server_error() || r.status() == StatusCode::TOO_MANY_REQUESTS,
+            Err(_) => true,
+        };
+        if !retry || attempt == MAX_ATTEMPTS {
+            return (
+                attempt,
+                match result {
+                    Ok(r) if r.status().is_success() => Ok(r.status()),
+                    Ok(r) => Err(format!("answered {}", r.status())),
+                    Err(why) => Err(why),
+                },
+            );
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 4;
+        attempt += 1;
+    }
+}
+
+/// Accepts https URLs of named hosts; where they resolve to is checked when sending
+fn valid_url(input: &str) -> Option<String> {
+    let url = Url::parse(input.trim()).ok()?;
+    // IP addresses aren't domains
+    match url.domain() {
+        Some(host) if url.scheme() == "https" && host != "localhost" => Some(url.to_string()),
+        _ => None,
+    }
+}
+
+/// `/admin webhook add|remove|list`
+pub async fn webhook(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    sub: &ApplicationCommandInteractionDataOption,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let url = handlers::option_str(&sub.options, "url");
+    let change = match (sub.name.as_str(), url) {
+        ("list", _) => return list(db_client, command, guild_id, ctx).await,
+        ("add", Some(url)) => match valid_url(url) {
+            Some(url) => {
+                if let Err(Unreachable::Refused(why) | Unreachable::Unresolved(why)) =
+                    client(&url).await
+                {
+                    return response::respond_title(ctx, command, true, why).await;
+                }
+                Change::Add(Webhook {
+                    url,
+                    secret: base64::encode_config(
+                        rand::thread_rng().gen::<[u8; 32]>(),
+                        base64::URL_SAFE_NO_PAD,
+                    ),
+                })
+            }
+            None => {
+                return response::respond_title(
+                    ctx,
+                    command,
+                    true,
+                    "Webhooks must be https URLs with a host name",
+                )
+                .await
+            }
+        },
+        ("remove", Some(url)) => {
+            Change::Remove(valid_url(url).unwrap_or_else(|| url.trim().to_string()))
+        }
+        _ => return Ok(()),
+    };
+    // shown once, and never written to the audit ledger
+    let mut added_secret = None;
+    let result = config::update(db_client, guild_id, command.user.id, |config| {
+        let webhooks = &mut config.webhooks;
+        Some(match change {
+            Change::Add(webhook) if webhooks.iter().any(|w| w.url == webhook.url) => {
+                "That webhook was already added"
+            }
+            Change::Add(_) if webhooks.len() >= MAX_WEBHOOKS => {
+                "This server has as many webhooks as it can, remove one first"
+            }
+            Change::Add(webhook) => {
+                added_secret = Some(webhook.secret.clone());
+                webhooks.push(webhook);
+                "Added the webhook, verification events will be sent to it"
+            }
+            Change::Remove(url) => {
+                let before = webhooks.len();
+                webhooks.retain(|w| w.url != url);
+                if webhooks.len() == before {
+                    "That webhook wasn't added"
+                } else {
+                    "Removed the webhook"
+                }
+            }
+        })
+        .map(str::to_string)
+    })
+    .await;
+    let title = match result {
+        Ok(summary) => summary,
+        Err(why) => {
+            added_secret = None;
+            why.to_string()
+        }
+    };
+    match added_secret {
+        Some(secret) => {
+            response::respond_embed(ctx, command, true, |embed| {
+                embed
+                    .title(title)
+                    .description(format!(
+                        "Deliveries are signed with this secret, which won't be shown again:\n\
+                         `{}`\nThe `X-Signature` header is the HMAC-SHA256 of the body under it, \
+                         in unpadded URL-safe base64.",
+                        secret
+                    ))
+                    .color(Color::from_rgb(191, 87, 0))
+            })
+            .await
+        }
+        None => response::respond_title(ctx, command, true, title).await,
+    }
+}
+
+enum Change {
+    Add(Webhook),
+    Remove(String),
+}
+
+async fn list(
+    db_client: &db::DynamoDB,
+    command: &ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: &Context,
+) -> serenity::Result<()> {
+    let webhooks = db_client.get_guild_config(guild_id).await.webhooks;
+    let webhooks = webhooks
+        .iter()
+        .map(|webhook| format!("`{}`", webhook.url))
+        .collect::<Vec<_>>();
+    let deliveries = LOG
+        .lock()
+        .unwrap()
+        .get(&guild_id)
+        .map(|log| {
+            log.iter()
+                .map(|d| {
+                    format!(
+                        "{} `{}` to `{}`: {}{}",
+                        response::timestamp(d.at, response::TimestampStyle::Relative),
+                        d.event,
+                        d.url,
+                        match &d.outcome {
+                            Ok(status) => status.to_string(),
+                            Err(why) => format!("failed, {}", why),
+                        },
+                        if d.attempts > 1 {
+                            format!(" after {} attempts", d.attempts)
+                        } else {
+                            String::new()
+                        }
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    response::respond_embed(ctx, command, true, |embed| {
+        embed
+            .title("Webhooks")
+            .field("URLs", response::field_lines(&webhooks, "None"), false)
+            .field(
+                "Recent Deliveries",
+                response::field_lines(&deliveries, "None since the bot started"),
+                false,
+            )
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_public_addresses() {
+        for ip in ["8.8.8.8", "2606:4700:4700::1111", "::ffff:1.1.1.1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn webhooks_stored_as_urls_have_no_secret() {
+        let webhooks: Vec<Webhook> = serde_json::from_str(
+            r#"["https://example.com/a", {"url": "https://example.com/b", "secret": "s"}]"#,
+        )
+        .unwrap();
+        assert_eq!(webhooks[0].secret, "");
+        assert_eq!(webhooks[1].url, "https://example.com/b");
+        assert_eq!(webhooks[1].secret, "s");
+    }
+}
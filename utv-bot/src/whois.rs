@@ -0,0 +1,182 @@
+//! `/whois` and `/lookup`: verification status for moderators.
+//!
+//! `/whois` shows what the bot stores about a member: whether and when they verified, their
+//! affiliation and their EID, masked when the bot has `ENCRYPTION_KEY` and otherwise as a hash.
+//! `/lookup` goes the other way, from an EID to the account that verified with it, naming it only
+//! when it's a member of the guild, like `/admin bulk-lookup`. Both are recorded in the audit
+//! ledger.
+
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::model::interactions::application_command::ApplicationCommandInteraction;
+use serenity::utils::Color;
+
+use crate::{admin, audit, db, handlers, response, settings};
+
+/// characters of the EID hash shown, enough to tell EIDs apart
+const HASH_CHARS: usize = 16;
+
+pub async fn whois(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let user = match handlers::option_user(&command.data.options, "user") {
+        Some(user) => user.clone(),
+        None => return response::respond_title(&ctx, &command, true, "Choose a member").await,
+    };
+    let record = db_client.get_user_record(user.id).await;
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "whois",
+        Some(user.id),
+        "",
+    )
+    .await;
+
+    let record = match record {
+        Some(record) => record,
+        None => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                format!("{} has never verified", user.name),
+            )
+            .await
+        }
+    };
+    let status = match (&record.encrypted_eid, record.expired_at) {
+        (Some(_), _) => "Verified".to_string(),
+        (None, Some(expired_at)) => format!(
+            "Expired {}",
+            response::timestamp(expired_at, response::TimestampStyle::Relative)
+        ),
+        (None, None) => "Not verified".to_string(),
+    };
+    let eid = record.encrypted_eid.as_deref().map(|encrypted| {
+        match settings::encryption_key().ok().flatten().and_then(|key| {
+            let encrypted = base64::decode(encrypted).ok()?;
+            utv_token::deterministic_aes::decrypt(&encrypted, &key).ok()
+        }) {
+            Some(eid) => format!("`{}`", admin::mask_eid(&String::from_utf8_lossy(&eid))),
+            None => format!("hash `{}`", &db::eid_hash(encrypted)[..HASH_CHARS]),
+        }
+    });
+    response::respond_embed(&ctx, &command, true, |embed| {
+        embed
+            .title(format!("Verification of {}", user.name))
+            .field("Status", status, true)
+            .field("EID", eid.unwrap_or_else(|| "None".to_string()), true)
+            .field(
+                "Verified",
+                match (&record.encrypted_eid, record.verified_at) {
+                    (Some(_), Some(at)) => response::datetime(at),
+                    (Some(_), None) => "Before verification dates were recorded".to_string(),
+                    (None, _) => "None".to_string(),
+                },
+                false,
+            );
+        if let Some(claims) = &record.claims {
+            embed.field("Affiliation", list(&claims.affiliation), false);
+            embed.field("School", list(&claims.school), true);
+            embed.field("Major", list(&claims.major), true);
+        }
+        if let Some(departed_at) = record.departed_at {
+            embed.field(
+                "Departed",
+                format!(
+                    "A directory re-check found an affiliation gone {}",
+                    response::timestamp(departed_at, response::TimestampStyle::Relative)
+                ),
+                false,
+            );
+        }
+        embed.color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+pub async fn lookup(
+    db_client: &db::DynamoDB,
+    command: ApplicationCommandInteraction,
+    guild_id: GuildId,
+    ctx: Context,
+) -> serenity::Result<()> {
+    if !handlers::is_admin(&command) {
+        return response::respond_title(
+            &ctx,
+            &command,
+            true,
+            "You must be an administrator to run this command.",
+        )
+        .await;
+    }
+    let key = match settings::encryption_key() {
+        Ok(Some(key)) => key,
+        _ => {
+            return response::respond_title(
+                &ctx,
+                &command,
+                true,
+                "EID lookups are not enabled on this instance of the bot.",
+            )
+            .await
+        }
+    };
+    // EIDs are stored as entered at verification, which is almost always lower case
+    let eid = match handlers::option_str(&command.data.options, "eid") {
+        Some(eid) if !eid.trim().is_empty() => eid.trim().to_lowercase(),
+        _ => return response::respond_title(&ctx, &command, true, "Enter an EID").await,
+    };
+    let encrypted = base64::encode(utv_token::deterministic_aes::encrypt(eid.as_bytes(), &key));
+    let user = db_client
+        .users_by_encrypted_eid(&[encrypted.clone()])
+        .await
+        .remove(&encrypted);
+    let in_guild = match user {
+        Some(user) => guild_id.member(&ctx.http, user).await.is_ok(),
+        None => false,
+    };
+    audit::record(
+        db_client,
+        guild_id,
+        command.user.id,
+        "lookup",
+        user.filter(|_| in_guild),
+        format!("EID {}", admin::mask_eid(&eid)),
+    )
+    .await;
+    let description = match user {
+        Some(user) if in_guild => format!("Verified as <@{}>", user),
+        Some(_) => "Verified by an account that isn't in this server".to_string(),
+        None => "Not verified".to_string(),
+    };
+    response::respond_embed(&ctx, &command, true, |embed| {
+        embed
+            .title(format!("EID {}", admin::mask_eid(&eid)))
+            .description(description)
+            .color(Color::from_rgb(191, 87, 0))
+    })
+    .await
+}
+
+fn list(values: &[String]) -> String {
+    if values.is_empty() {
+        "None".to_string()
+    } else {
+        values.join(", ")
+    }
+}
@@ -0,0 +1,56 @@
+import crypto from "crypto";
+import { PORTAL_VERIFICATION, signToken, VERIFICATION } from "./token";
+
+const BOT_URL = process.env.BOT_URL!;
+
+/** How long a token minted for the bot may be redeemed, in seconds */
+const TOKEN_SECS = 5 * 60;
+
+export type VerifyStatus =
+  | "linked"
+  | "already-linked"
+  | "eid-in-use"
+  | "invalid-token"
+  | "expired-token"
+  | "replayed-token"
+  | "banned"
+  | "bad-request"
+  | "bad-signature"
+  | "stale"
+  | "failed";
+
+/**
+ * Has the bot link a Discord account to an EID through its `POST /verify`, which records the
+ * link and updates the member's roles and nickname in every server right away.
+ *
+ * @param discord_id The Discord account the portal logged in
+ * @param encrypted_eid The base64 encrypted EID Qualtrics vouched for
+ * @param event The event QR code ticket the member arrived with, counted if they get verified
+ */
+export const verifyWithBot = async (
+  discord_id: string,
+  encrypted_eid: string,
+  event?: string
+): Promise<VerifyStatus> => {
+  const now = Math.floor(Date.now() / 1000);
+  // VerifiedClaims: encrypted_eid, major, school, affiliation, expires_at, issued_at, nonce, name
+  const token = signToken(VERIFICATION, [
+    Array.from(Buffer.from(encrypted_eid, "base64")),
+    [],
+    [],
+    [],
+    now + TOKEN_SECS,
+    now,
+    Array.from(crypto.randomBytes(16)),
+    null,
+  ]);
+  const res = await fetch(`${BOT_URL}/verify`, {
+    method: "POST",
+    body: signToken(PORTAL_VERIFICATION, [discord_id, token, now, event ?? null]),
+  });
+  try {
+    return (await res.json()).status as VerifyStatus;
+  } catch {
+    return "failed";
+  }
+};
@@ -6,6 +6,8 @@ declare module "iron-session" {
   interface IronSessionData {
     oauthState?: string;
     csrfToken?: string;
+    // the signed event QR code ticket the member arrived with, see the bot's /event-qr
+    eventTicket?: string;
     discordAuth?: {
       id: string;
       token: string;
@@ -1,5 +1,5 @@
 import crypto from "crypto";
-import {decode} from "@msgpack/msgpack";
+import {decode, encode} from "@msgpack/msgpack";
 
 export interface VerifiedClaims {
   encrypted_eid: number[],
@@ -10,15 +10,30 @@ export interface VerifiedClaims {
 
 const key = Buffer.from(process.env.SHARED_KEY!, "base64url");
 
+/** What verification tokens are signed for, matching `utv_token::VERIFICATION` */
+export const VERIFICATION = "verification";
+/** What verifications handed to the bot's POST /verify are signed for */
+export const PORTAL_VERIFICATION = "portal-verification";
+
+/**
+ * The HMAC-SHA256 of a payload signed for `purpose`: the purpose and a zero byte come before
+ * the payload, so a value signed for one purpose is never accepted as another.
+ */
+function tag(purpose: string, data: Buffer): Buffer {
+  const hmac = crypto.createHmac("sha256", key);
+  hmac.update(purpose);
+  hmac.update(Buffer.from([0]));
+  hmac.update(data);
+  return hmac.digest();
+}
+
 export function decodeToken(token: String): VerifiedClaims | false {
   const buf = Buffer.from(token, "base64url");
   const data = buf.slice(0, buf.length - 32);
   const inputHash = buf.slice(buf.length - 32);
 
-  const hmac = crypto.createHmac("sha256", key);
-  hmac.update(data);
-  const validHash = hmac.digest();
-  if (!crypto.timingSafeEqual(inputHash, validHash)) return false;
+  const validHash = tag(VERIFICATION, data);
+  if (inputHash.length !== validHash.length || !crypto.timingSafeEqual(inputHash, validHash)) return false;
 
   const [encrypted_eid, major, school, affiliation] = decode(data) as [number[], String[], String[], String[]];
   return {
@@ -28,3 +43,15 @@ export function decodeToken(token: String): VerifiedClaims | false {
     affiliation
   }
 }
+
+/**
+ * Signs a payload the way the bot's tokens are: msgpack followed by its HMAC-SHA256 under
+ * SHARED_KEY for the purpose, in unpadded URL-safe base64.
+ *
+ * @param purpose What the bot checks the payload for, e.g. {@link VERIFICATION}
+ * @param payload Fields in the order the bot declares them
+ */
+export function signToken(purpose: string, payload: unknown[]): string {
+  const data = Buffer.from(encode(payload));
+  return Buffer.concat([data, tag(purpose, data)]).toString("base64url");
+}
@@ -1,8 +1,15 @@
-import {AWSError} from "aws-sdk";
 import {withIronSessionApiRoute} from "iron-session/next";
 import {NextApiHandler} from "next";
 import {ironOptions} from "../../lib/config";
-import {becameVerified, docClient} from "../../lib/db";
+import {docClient} from "../../lib/db";
+import {verifyWithBot, VerifyStatus} from "../../lib/bot";
+
+const MESSAGES: { [status in VerifyStatus]?: [number, string] } = {
+    "linked": [200, "Created."],
+    "already-linked": [403, "Discord account already verified."],
+    "eid-in-use": [403, "This UT EID is already linked to another Discord account."],
+    "banned": [403, "This UT EID can't be verified."],
+};
 
 const handler: NextApiHandler = withIronSessionApiRoute(async (req, res) => {
     const {token, csrf_token} = req.body;
@@ -17,80 +24,44 @@ const handler: NextApiHandler = withIronSessionApiRoute(async (req, res) => {
         return;
     }
 
-    docClient.get(
-        {
-            TableName: "qualtrics_tokens",
-            Key: {
-                token,
-            }
+    // deleting it up front means a Qualtrics token verifies at most one account
+    const data = await docClient.delete({
+        TableName: "qualtrics_tokens",
+        Key: {
+            token,
         },
-        (err, data) => {
-            if (err || data === undefined || data === null || data === {}) {
-                res.status(403).send("Bad UT EID. Try signing in again.");
-                return;
-            }
-
-            let encrypted_eid = data.Item.encrypted_eid;
-            let expiry = new Date(data.Item.created_at);
-
-            if ((new Date() - expiry) > (5 * 60 * 1000)) {
-                res.status(403).send("Expired request. Try signing in again.");
-                return;
-            }
-
-            const tr = docClient.transactWrite({
-                TransactItems: [
-                    {
-                        Delete: {
-                            TableName: "qualtrics_tokens",
-                            Key: {
-                                token,
-                            },
-                        },
-                    },
-                    {
-                        Update: {
-                            TableName: "users",
-                            Key: {
-                                discord_id
-                            },
-                            UpdateExpression: "set encrypted_eid = :encrypted_eid, claims = :claims",
-                            ConditionExpression: "attribute_not_exists(discord_id) AND attribute_not_exists(encrypted_eid)",
-                            ExpressionAttributeValues: {":encrypted_eid": encrypted_eid, ":claims": JSON.stringify({})},
-                            // stringify empty claims for backwards compatibility
-                        },
-                    },
-                ],
-            });
+        ReturnValues: "ALL_OLD",
+    }).promise().catch((e) => {
+        console.log(e);
+        return undefined;
+    });
+    const item = data?.Attributes;
+    if (item == null || item.encrypted_eid == null) {
+        res.status(403).send("Bad UT EID. Try signing in again.");
+        return;
+    }
 
-            let cancellationReasons: AWSError[] = [];
-            tr.on("extractError", (r) => {
-                if (r.error) {
-                    cancellationReasons = JSON.parse(
-                        r.httpResponse.body.toString()
-                    ).CancellationReasons;
-                }
-            });
+    let expiry = new Date(item.created_at);
+    if ((new Date().getTime() - expiry.getTime()) > (5 * 60 * 1000)) {
+        res.status(403).send("Expired request. Try signing in again.");
+        return;
+    }
 
-            tr.promise().then(async () => {
-                await becameVerified(discord_id);
-                res.status(200).send("Created.");
-            }).catch((e) => {
-                console.log(e, cancellationReasons!);
-                if (cancellationReasons !== undefined) {
-                    if (cancellationReasons.length > 1 && cancellationReasons[1].Code == "ConditionalCheckFailed") {
-                        if (cancellationReasons![0].Code == "ConditionalCheckFailed") {
-                            res.status(403).send("Discord account already verified and bad UT EID.");
-                        } else {
-                            res.status(403).send("Discord account already verified.");
-                        }
-                    } else if (cancellationReasons.length > 0 && cancellationReasons[0].Code == "ConditionalCheckFailed") {
-                        res.status(403).send("Bad UT EID. Try signing in again.");
-                    }
-                }
-                res.status(403).send("Server error.");
-            });
-        });
+    // the bot records the link, and updates roles and nicknames in every server
+    const {eventTicket} = req.session;
+    const status = await verifyWithBot(discord_id, item.encrypted_eid, eventTicket).catch((e) => {
+        console.log(e);
+        return "failed" as VerifyStatus;
+    });
+    const [code, message] = MESSAGES[status] ?? [500, "Server error."];
+    if (code == 500) {
+        console.log("bot refused verification:", status);
+    }
+    if (eventTicket != null && status == "linked") {
+        delete req.session.eventTicket;
+        await req.session.save();
+    }
+    res.status(code).send(message);
 }, ironOptions);
 
 export default handler;
@@ -20,6 +20,13 @@ interface Props {
 }
 
 export const getServerSideProps = withIronSessionSsr(async (ctx) => {
+  // kept through the login and Qualtrics, so the verification counts towards the event
+  const { event } = ctx.query;
+  if (typeof event == "string") {
+    ctx.req.session.eventTicket = event;
+    await ctx.req.session.save();
+  }
+
   const { discordAuth } = ctx.req.session;
   if (discordAuth == null || discordAuth.expiresAt < Date.now() / 1000 + 60) {
     return {